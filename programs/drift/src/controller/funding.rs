@@ -12,9 +12,13 @@ use crate::get_then_update_id;
 use crate::math::amm;
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    FUNDING_RATE_BUFFER, FUNDING_RATE_OFFSET_DENOMINATOR, ONE_HOUR_I128, TWENTY_FOUR_HOUR,
+    FUNDING_RATE_BUFFER, FUNDING_RATE_OFFSET_DENOMINATOR, ONE_HOUR, ONE_HOUR_I128, TWENTY_FOUR_HOUR,
+};
+use crate::math::funding::{
+    calculate_accrued_funding_rate, calculate_funding_interval, calculate_funding_payment,
+    calculate_funding_price_spread, calculate_funding_rate_from_params,
+    calculate_funding_rate_long_short, calculate_funding_rate_long_short_for_interval,
 };
-use crate::math::funding::{calculate_funding_payment, calculate_funding_rate_long_short};
 use crate::math::helpers::on_the_hour_update;
 use crate::math::safe_math::SafeMath;
 use crate::math::stats::calculate_new_twap;
@@ -23,7 +27,7 @@ use crate::math::oracle;
 
 use crate::state::events::{FundingPaymentRecord, FundingRateRecord};
 use crate::state::oracle_map::OracleMap;
-use crate::state::perp_market::{PerpMarket, AMM};
+use crate::state::perp_market::{ContractType, PerpMarket, AMM};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::state::OracleGuardRails;
 use crate::state::user::User;
@@ -152,6 +156,128 @@ pub fn settle_funding_payments(
     Ok(())
 }
 
+/// Accrues funding for the seconds since the last accrual into the cumulative funding rates of a
+/// market with continuous funding, at its current mark and oracle twaps. There's no jump at
+/// funding period boundaries to trade around, and what a position owes only depends on how long
+/// it was open. Time where funding is paused isn't accrued. Returns whether funding was accrued
+pub fn accrue_funding(
+    market: &mut PerpMarket,
+    now: UnixTimestamp,
+    funding_paused: bool,
+) -> DriftResult<bool> {
+    if market.contract_type != ContractType::Perpetual || !market.amm.funding_params.is_continuous()
+    {
+        return Ok(false);
+    }
+
+    let last_funding_rate_ts = market.amm.last_funding_rate_ts;
+    let funding_interval = now.safe_sub(last_funding_rate_ts)?;
+    if funding_interval <= 0 {
+        return Ok(false);
+    }
+
+    if funding_paused {
+        market.amm.last_funding_rate_ts = now;
+        return Ok(false);
+    }
+
+    let mark_price_twap = market.amm.last_mark_price_twap;
+    let oracle_price_twap = market.amm.historical_oracle_data.last_oracle_price_twap;
+    let max_price_spread = market.get_max_price_divergence_for_funding_rate(oracle_price_twap)?;
+
+    let price_spread = calculate_funding_price_spread(
+        &market.amm,
+        mark_price_twap,
+        oracle_price_twap,
+        max_price_spread,
+    )?;
+
+    // accruals over a few seconds are fractions of FUNDING_RATE_PRECISION, the truncated part
+    // carries over so accruing often doesn't round funding down
+    let (funding_rate, funding_rate_remainder) = calculate_accrued_funding_rate(
+        price_spread,
+        funding_interval,
+        market.funding_rate_remainder,
+    )?;
+    market.funding_rate_remainder = funding_rate_remainder;
+
+    let funding_period = market.amm.funding_period.max(ONE_HOUR);
+    let (funding_rate_long, funding_rate_short, funding_imbalance_revenue) =
+        calculate_funding_rate_long_short_for_interval(
+            market,
+            funding_rate.cast()?,
+            funding_interval,
+            funding_period,
+        )?;
+
+    market.amm.cumulative_funding_rate_long = market
+        .amm
+        .cumulative_funding_rate_long
+        .safe_add(funding_rate_long)?;
+
+    market.amm.cumulative_funding_rate_short = market
+        .amm
+        .cumulative_funding_rate_short
+        .safe_add(funding_rate_short)?;
+
+    market.amm.net_unsettled_funding_pnl = market
+        .amm
+        .net_unsettled_funding_pnl
+        .safe_sub(funding_imbalance_revenue.cast()?)?;
+
+    // the last funding rates are per funding period, same as for periodic funding
+    market.amm.last_funding_rate =
+        scale_funding_rate_to_period(funding_rate.cast()?, funding_interval, funding_period)?;
+    market.amm.last_funding_rate_long =
+        scale_funding_rate_to_period(funding_rate_long, funding_interval, funding_period)?;
+    market.amm.last_funding_rate_short =
+        scale_funding_rate_to_period(funding_rate_short, funding_interval, funding_period)?;
+    market.amm.last_24h_avg_funding_rate = calculate_new_twap(
+        market.amm.last_funding_rate,
+        now,
+        market.amm.last_24h_avg_funding_rate,
+        last_funding_rate_ts,
+        TWENTY_FOUR_HOUR,
+    )?;
+
+    market.amm.last_funding_rate_ts = now;
+    market.amm.funding_params.last_funding_period = market.amm.funding_period.cast()?;
+
+    // one record per funding period
+    if last_funding_rate_ts.safe_div(funding_period)? < now.safe_div(funding_period)? {
+        emit!(FundingRateRecord {
+            ts: now,
+            record_id: get_then_update_id!(market, next_funding_rate_record_id),
+            market_index: market.market_index,
+            funding_rate: market.amm.last_funding_rate,
+            funding_rate_long: market.amm.last_funding_rate_long.cast()?,
+            funding_rate_short: market.amm.last_funding_rate_short.cast()?,
+            cumulative_funding_rate_long: market.amm.cumulative_funding_rate_long,
+            cumulative_funding_rate_short: market.amm.cumulative_funding_rate_short,
+            mark_price_twap,
+            oracle_price_twap,
+            period_revenue: market.amm.net_revenue_since_last_funding,
+            base_asset_amount_with_amm: market.amm.base_asset_amount_with_amm,
+            base_asset_amount_with_unsettled_lp: market.amm.base_asset_amount_with_unsettled_lp,
+        });
+
+        market.amm.net_revenue_since_last_funding = 0;
+    }
+
+    Ok(true)
+}
+
+fn scale_funding_rate_to_period(
+    funding_rate: i128,
+    funding_interval: i64,
+    funding_period: i64,
+) -> DriftResult<i64> {
+    funding_rate
+        .safe_mul(funding_period.cast()?)?
+        .safe_div(funding_interval.cast()?)?
+        .cast()
+}
+
 #[allow(clippy::comparison_chain)]
 pub fn update_funding_rate(
     market_index: u16,
@@ -163,6 +289,11 @@ pub fn update_funding_rate(
     funding_paused: bool,
     precomputed_reserve_price: Option<u64>,
 ) -> DriftResult<bool> {
+    // dated futures and options converge to their oracle through expiry settlement instead of funding
+    if market.contract_type != ContractType::Perpetual {
+        return Ok(false);
+    }
+
     let reserve_price = match precomputed_reserve_price {
         Some(reserve_price) => reserve_price,
         None => market.amm.reserve_price()?,
@@ -170,12 +301,19 @@ pub fn update_funding_rate(
     // Pause funding if oracle is invalid or if mark/oracle spread is too divergent
     let block_funding_rate_update = oracle::block_operation(
         market,
-        oracle_map.get_price_data(&market.amm.oracle)?,
+        oracle_map.get_price_data(market.oracle_id())?,
         guard_rails,
         reserve_price,
         slot,
     )?;
 
+    // continuous funding is already up to date whenever the amm is, just catch up
+    if market.amm.funding_params.is_continuous() {
+        let funding_paused = funding_paused || block_funding_rate_update;
+        accrue_funding(market, now, funding_paused)?;
+        return Ok(!funding_paused);
+    }
+
     let time_until_next_update = on_the_hour_update(
         now,
         market.amm.last_funding_rate_ts,
@@ -186,7 +324,7 @@ pub fn update_funding_rate(
         !funding_paused && !block_funding_rate_update && (time_until_next_update == 0);
 
     if valid_funding_update {
-        let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+        let oracle_price_data = oracle_map.get_price_data(market.oracle_id())?;
         let sanitize_clamp_denominator = market.get_sanitize_clamp_denominator()?;
 
         let oracle_price_twap = amm::update_oracle_price_twap(
@@ -222,31 +360,42 @@ pub fn update_funding_rate(
             sanitize_clamp_denominator,
         )?;
 
-        let period_adjustment = (24_i128)
-            .safe_mul(ONE_HOUR_I128)?
-            .safe_div(max(ONE_HOUR_I128, market.amm.funding_period as i128))?;
-        // funding period = 1 hour, window = 1 day
-        // low periodicity => quickly updating/settled funding rates => lower funding rate payment per interval
-        let price_spread = mid_price_twap.cast::<i64>()?.safe_sub(oracle_price_twap)?;
-
-        // add offset 1/FUNDING_RATE_OFFSET_DENOMINATOR*365. if FUNDING_RATE_OFFSET_DENOMINATOR = 5000 => 7.3% annualized rate
-        let price_spread_with_offset = price_spread.safe_add(
-            oracle_price_twap
-                .abs()
-                .safe_div(FUNDING_RATE_OFFSET_DENOMINATOR)?,
-        )?;
-
         // clamp price divergence based on contract tier for funding rate calculation
         let max_price_spread =
             market.get_max_price_divergence_for_funding_rate(oracle_price_twap)?;
-        let clamped_price_spread =
-            price_spread_with_offset.clamp(-max_price_spread, max_price_spread);
 
-        let funding_rate = clamped_price_spread
-            .cast::<i128>()?
-            .safe_mul(FUNDING_RATE_BUFFER.cast()?)?
-            .safe_div(period_adjustment.cast()?)?
-            .cast::<i64>()?;
+        let funding_rate = if market.amm.funding_params.enabled {
+            calculate_funding_rate_from_params(
+                &market.amm,
+                mid_price_twap,
+                oracle_price_twap,
+                max_price_spread,
+                calculate_funding_interval(&market.amm, now)?,
+            )?
+        } else {
+            let period_adjustment = (24_i128)
+                .safe_mul(ONE_HOUR_I128)?
+                .safe_div(max(ONE_HOUR_I128, market.amm.funding_period as i128))?;
+            // funding period = 1 hour, window = 1 day
+            // low periodicity => quickly updating/settled funding rates => lower funding rate payment per interval
+            let price_spread = mid_price_twap.cast::<i64>()?.safe_sub(oracle_price_twap)?;
+
+            // add offset 1/FUNDING_RATE_OFFSET_DENOMINATOR*365. if FUNDING_RATE_OFFSET_DENOMINATOR = 5000 => 7.3% annualized rate
+            let price_spread_with_offset = price_spread.safe_add(
+                oracle_price_twap
+                    .abs()
+                    .safe_div(FUNDING_RATE_OFFSET_DENOMINATOR)?,
+            )?;
+
+            let clamped_price_spread =
+                price_spread_with_offset.clamp(-max_price_spread, max_price_spread);
+
+            clamped_price_spread
+                .cast::<i128>()?
+                .safe_mul(FUNDING_RATE_BUFFER.cast()?)?
+                .safe_div(period_adjustment.cast()?)?
+                .cast::<i64>()?
+        };
 
         let (funding_rate_long, funding_rate_short, funding_imbalance_revenue) =
             calculate_funding_rate_long_short(market, funding_rate.cast()?)?;
@@ -285,6 +434,7 @@ pub fn update_funding_rate(
             .safe_sub(funding_imbalance_revenue.cast()?)?;
 
         market.amm.last_funding_rate_ts = now;
+        market.amm.funding_params.last_funding_period = market.amm.funding_period.cast()?;
 
         emit!(FundingRateRecord {
             ts: now,
@@ -11,21 +11,25 @@ use crate::error::ErrorCode;
 use crate::math::amm::calculate_net_user_pnl;
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    MAX_APR_PER_REVENUE_SETTLE_TO_INSURANCE_FUND_VAULT, ONE_YEAR, PERCENTAGE_PRECISION,
-    SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_DENOMINATOR,
+    IF_BOOST_SHARES_PER_WEIGHT_PRECISION, IF_TRANCHE_SHARE_FACTOR_PRECISION_U64,
+    MAX_APR_PER_REVENUE_SETTLE_TO_INSURANCE_FUND_VAULT, MIN_IF_TRANCHE_SHARE_FACTOR, ONE_YEAR,
+    PERCENTAGE_PRECISION, SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_DENOMINATOR,
     SHARE_OF_REVENUE_ALLOCATED_TO_INSURANCE_FUND_VAULT_NUMERATOR,
 };
 use crate::math::helpers::get_proportion_u128;
 use crate::math::helpers::on_the_hour_update;
 use crate::math::insurance::{
-    calculate_if_shares_lost, calculate_rebase_info, if_shares_to_vault_amount,
-    vault_amount_to_if_shares,
+    calculate_if_shares_lost, calculate_rebase_info, if_shares_to_share_tokens,
+    if_shares_to_vault_amount, share_tokens_to_if_shares, vault_amount_to_if_shares,
 };
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
 use crate::math::spot_withdraw::validate_spot_market_vault_amount;
 use crate::state::events::{InsuranceFundRecord, InsuranceFundStakeRecord, StakeAction};
-use crate::state::insurance_fund_stake::InsuranceFundStake;
+use crate::state::insurance_fund_stake::{
+    InsuranceFundJuniorTranche, InsuranceFundLockupTier, InsuranceFundStake,
+    InsuranceFundStakeLockup, InsuranceFundTranche,
+};
 use crate::state::perp_market::PerpMarket;
 use crate::state::spot_market::{SpotBalanceType, SpotMarket};
 use crate::state::state::State;
@@ -135,6 +139,11 @@ pub fn apply_rebase_to_insurance_fund(
             .insurance_fund
             .shares_base
             .safe_add(expo_diff.cast::<u128>()?)?;
+        spot_market.insurance_fund_total_boost_weight = spot_market
+            .insurance_fund_total_boost_weight
+            .cast::<u128>()?
+            .safe_div(rebase_divisor)?
+            .cast()?;
 
         msg!("rebasing insurance fund: expo_diff={}", expo_diff);
     }
@@ -188,6 +197,548 @@ pub fn apply_rebase_to_insurance_fund_stake(
     Ok(())
 }
 
+pub fn apply_rebase_to_insurance_fund_stake_lockup(
+    insurance_fund_stake_lockup: &mut InsuranceFundStakeLockup,
+    spot_market: &mut SpotMarket,
+) -> DriftResult {
+    if spot_market.insurance_fund.shares_base != insurance_fund_stake_lockup.if_base {
+        validate!(
+            spot_market.insurance_fund.shares_base > insurance_fund_stake_lockup.if_base,
+            ErrorCode::InvalidIFRebase,
+            "Rebase expo out of bounds"
+        )?;
+
+        let expo_diff = (spot_market.insurance_fund.shares_base
+            - insurance_fund_stake_lockup.if_base)
+            .cast::<u32>()?;
+
+        let rebase_divisor = 10_u128.pow(expo_diff);
+
+        insurance_fund_stake_lockup.if_base = spot_market.insurance_fund.shares_base;
+        insurance_fund_stake_lockup.boost_weight = insurance_fund_stake_lockup
+            .boost_weight
+            .cast::<u128>()?
+            .safe_div(rebase_divisor)?
+            .cast()?;
+    }
+
+    Ok(())
+}
+
+/// Credits a locked stake the boost shares minted for it since its last accrual. Once the lockup
+/// has ended its boost weight is settled out of the market and the shares minted for it after the
+/// end go to the protocol
+pub fn accrue_insurance_fund_stake_boost(
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    insurance_fund_stake_lockup: &mut InsuranceFundStakeLockup,
+    spot_market: &mut SpotMarket,
+    now: i64,
+) -> DriftResult<u128> {
+    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    apply_rebase_to_insurance_fund_stake_lockup(insurance_fund_stake_lockup, spot_market)?;
+
+    let cumulative_boost_shares_per_weight =
+        spot_market.insurance_fund_cumulative_boost_shares_per_weight;
+
+    let mut boost_shares = insurance_fund_stake_lockup
+        .boost_weight
+        .cast::<u128>()?
+        .safe_mul(
+            cumulative_boost_shares_per_weight
+                .safe_sub(insurance_fund_stake_lockup.last_cumulative_boost_shares_per_weight)?,
+        )?
+        .safe_div(IF_BOOST_SHARES_PER_WEIGHT_PRECISION)?;
+
+    if now > insurance_fund_stake_lockup.lockup_end_ts
+        && insurance_fund_stake_lockup.boost_weight > 0
+    {
+        // shares are minted without timestamps, so they're assumed to be minted evenly since the
+        // last accrual
+        let accrual_duration = now
+            .safe_sub(insurance_fund_stake_lockup.last_accrual_ts)?
+            .cast::<u128>()?;
+        let boosted_duration = insurance_fund_stake_lockup
+            .lockup_end_ts
+            .safe_sub(insurance_fund_stake_lockup.last_accrual_ts)?
+            .max(0)
+            .cast::<u128>()?;
+
+        let expired_boost_shares = boost_shares.safe_sub(
+            boost_shares
+                .safe_mul(boosted_duration)?
+                .safe_div(accrual_duration)?,
+        )?;
+        boost_shares = boost_shares.safe_sub(expired_boost_shares)?;
+
+        spot_market.insurance_fund.user_shares = spot_market
+            .insurance_fund
+            .user_shares
+            .safe_sub(expired_boost_shares)?;
+        spot_market.insurance_fund_total_boost_weight = spot_market
+            .insurance_fund_total_boost_weight
+            .safe_sub(insurance_fund_stake_lockup.boost_weight)?;
+        insurance_fund_stake_lockup.boost_weight = 0;
+    }
+
+    insurance_fund_stake_lockup.last_cumulative_boost_shares_per_weight =
+        cumulative_boost_shares_per_weight;
+    insurance_fund_stake_lockup.last_accrual_ts = now;
+
+    // the boost shares were added to the user shares when they were minted
+    insurance_fund_stake.increase_if_shares(boost_shares, spot_market)?;
+
+    Ok(boost_shares)
+}
+
+fn update_insurance_fund_stake_boost_weight(
+    insurance_fund_stake: &InsuranceFundStake,
+    insurance_fund_stake_lockup: &mut InsuranceFundStakeLockup,
+    spot_market: &mut SpotMarket,
+) -> DriftResult {
+    let boost_weight = insurance_fund_stake_lockup
+        .calculate_boost_weight(insurance_fund_stake.checked_if_shares(spot_market)?)?;
+
+    spot_market.insurance_fund_total_boost_weight = spot_market
+        .insurance_fund_total_boost_weight
+        .safe_sub(insurance_fund_stake_lockup.boost_weight)?
+        .safe_add(boost_weight)?;
+
+    insurance_fund_stake_lockup.boost_weight = boost_weight;
+
+    Ok(())
+}
+
+pub fn lock_insurance_fund_stake(
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    insurance_fund_stake_lockup: &mut InsuranceFundStakeLockup,
+    spot_market: &mut SpotMarket,
+    lockup_tier: InsuranceFundLockupTier,
+    now: i64,
+) -> DriftResult {
+    validate!(
+        lockup_tier != InsuranceFundLockupTier::None,
+        ErrorCode::InvalidInsuranceFundLockup,
+        "lockup tier must be set"
+    )?;
+
+    validate!(
+        insurance_fund_stake.lockup_tier == InsuranceFundLockupTier::None,
+        ErrorCode::InvalidInsuranceFundLockup,
+        "insurance fund stake already locked"
+    )?;
+
+    validate!(
+        insurance_fund_stake.tranche == InsuranceFundTranche::Senior,
+        ErrorCode::InvalidInsuranceFundLockup,
+        "junior insurance fund stakes can't be locked"
+    )?;
+
+    validate!(
+        insurance_fund_stake.last_withdraw_request_shares == 0,
+        ErrorCode::IFWithdrawRequestInProgress,
+        "withdraw request in progress"
+    )?;
+
+    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+
+    insurance_fund_stake_lockup.market_index = spot_market.market_index;
+    insurance_fund_stake_lockup.lockup_tier = lockup_tier;
+    insurance_fund_stake_lockup.lockup_end_ts = now.safe_add(lockup_tier.duration())?;
+    insurance_fund_stake_lockup.if_base = spot_market.insurance_fund.shares_base;
+    insurance_fund_stake_lockup.boost_weight = 0;
+    insurance_fund_stake_lockup.last_cumulative_boost_shares_per_weight =
+        spot_market.insurance_fund_cumulative_boost_shares_per_weight;
+    insurance_fund_stake_lockup.last_accrual_ts = now;
+
+    insurance_fund_stake.lockup_tier = lockup_tier;
+
+    update_insurance_fund_stake_boost_weight(
+        insurance_fund_stake,
+        insurance_fund_stake_lockup,
+        spot_market,
+    )?;
+
+    let if_shares = insurance_fund_stake.checked_if_shares(spot_market)?;
+
+    emit!(InsuranceFundStakeRecord {
+        ts: now,
+        user_authority: insurance_fund_stake.authority,
+        action: StakeAction::Lock,
+        amount: if_shares_to_vault_amount(
+            if_shares,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?,
+        market_index: spot_market.market_index,
+        insurance_vault_amount_before: insurance_vault_amount,
+        if_shares_before: if_shares,
+        user_if_shares_before: spot_market.insurance_fund.user_shares,
+        total_if_shares_before: spot_market.insurance_fund.total_shares,
+        if_shares_after: if_shares,
+        total_if_shares_after: spot_market.insurance_fund.total_shares,
+        user_if_shares_after: spot_market.insurance_fund.user_shares,
+    });
+
+    Ok(())
+}
+
+/// Adding to a locked stake restarts its lockup, so the added shares are locked for the tier's
+/// full duration before they can be removed
+pub fn extend_insurance_fund_stake_lockup(
+    insurance_fund_stake: &InsuranceFundStake,
+    insurance_fund_stake_lockup: &mut InsuranceFundStakeLockup,
+    spot_market: &mut SpotMarket,
+    now: i64,
+) -> DriftResult {
+    insurance_fund_stake_lockup.lockup_end_ts = insurance_fund_stake_lockup
+        .lockup_end_ts
+        .max(now.safe_add(insurance_fund_stake_lockup.lockup_tier.duration())?);
+
+    update_insurance_fund_stake_boost_weight(
+        insurance_fund_stake,
+        insurance_fund_stake_lockup,
+        spot_market,
+    )
+}
+
+pub fn unlock_insurance_fund_stake(
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    insurance_fund_stake_lockup: &mut InsuranceFundStakeLockup,
+    user_stats: &mut UserStats,
+    spot_market: &mut SpotMarket,
+    now: i64,
+) -> DriftResult {
+    validate!(
+        insurance_fund_stake_lockup.is_unlockable(now),
+        ErrorCode::InsuranceFundStakeLocked,
+        "insurance fund stake locked until {}",
+        insurance_fund_stake_lockup.lockup_end_ts
+    )?;
+
+    let if_shares_before = insurance_fund_stake.unchecked_if_shares();
+    let total_if_shares_before = spot_market.insurance_fund.total_shares;
+    let user_if_shares_before = spot_market.insurance_fund.user_shares;
+
+    let boost_shares = accrue_insurance_fund_stake_boost(
+        insurance_vault_amount,
+        insurance_fund_stake,
+        insurance_fund_stake_lockup,
+        spot_market,
+        now,
+    )?;
+
+    spot_market.insurance_fund_total_boost_weight =
+        spot_market
+            .insurance_fund_total_boost_weight
+            .safe_sub(insurance_fund_stake_lockup.boost_weight)?;
+    insurance_fund_stake_lockup.boost_weight = 0;
+
+    insurance_fund_stake.lockup_tier = InsuranceFundLockupTier::None;
+
+    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+
+    if spot_market.market_index == QUOTE_SPOT_MARKET_INDEX {
+        user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?;
+    } else if spot_market.market_index == GOV_SPOT_MARKET_INDEX {
+        user_stats.if_staked_gov_token_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?;
+    }
+
+    emit!(InsuranceFundStakeRecord {
+        ts: now,
+        user_authority: user_stats.authority,
+        action: StakeAction::Unlock,
+        amount: if_shares_to_vault_amount(
+            boost_shares,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?,
+        market_index: spot_market.market_index,
+        insurance_vault_amount_before: insurance_vault_amount,
+        if_shares_before,
+        user_if_shares_before,
+        total_if_shares_before,
+        if_shares_after,
+        total_if_shares_after: spot_market.insurance_fund.total_shares,
+        user_if_shares_after: spot_market.insurance_fund.user_shares,
+    });
+
+    Ok(())
+}
+
+pub fn apply_rebase_to_insurance_fund_junior_tranche(
+    insurance_fund_junior_tranche: &mut InsuranceFundJuniorTranche,
+    spot_market: &mut SpotMarket,
+) -> DriftResult {
+    if spot_market.insurance_fund.shares_base != insurance_fund_junior_tranche.if_base {
+        validate!(
+            spot_market.insurance_fund.shares_base > insurance_fund_junior_tranche.if_base,
+            ErrorCode::InvalidIFRebase,
+            "Rebase expo out of bounds"
+        )?;
+
+        let expo_diff = (spot_market.insurance_fund.shares_base
+            - insurance_fund_junior_tranche.if_base)
+            .cast::<u32>()?;
+
+        let rebase_divisor = 10_u128.pow(expo_diff);
+
+        insurance_fund_junior_tranche.if_base = spot_market.insurance_fund.shares_base;
+        insurance_fund_junior_tranche.total_shares = insurance_fund_junior_tranche
+            .total_shares
+            .safe_div(rebase_divisor)?;
+        insurance_fund_junior_tranche.boost_weight = insurance_fund_junior_tranche
+            .boost_weight
+            .cast::<u128>()?
+            .safe_div(rebase_divisor)?
+            .cast()?;
+    }
+
+    Ok(())
+}
+
+pub fn update_insurance_fund_junior_tranche_boost_weight(
+    insurance_fund_junior_tranche: &mut InsuranceFundJuniorTranche,
+    spot_market: &mut SpotMarket,
+) -> DriftResult {
+    let boost_weight = insurance_fund_junior_tranche.calculate_boost_weight()?;
+
+    spot_market.insurance_fund_total_boost_weight = spot_market
+        .insurance_fund_total_boost_weight
+        .safe_sub(insurance_fund_junior_tranche.boost_weight)?
+        .safe_add(boost_weight)?;
+
+    insurance_fund_junior_tranche.boost_weight = boost_weight;
+
+    Ok(())
+}
+
+/// Credits the junior tranche the boost shares minted for it since its last accrual, growing
+/// every junior stake through the share factor
+pub fn accrue_insurance_fund_junior_tranche_boost(
+    insurance_vault_amount: u64,
+    insurance_fund_junior_tranche: &mut InsuranceFundJuniorTranche,
+    spot_market: &mut SpotMarket,
+) -> DriftResult<u128> {
+    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
+    apply_rebase_to_insurance_fund_junior_tranche(insurance_fund_junior_tranche, spot_market)?;
+
+    let cumulative_boost_shares_per_weight =
+        spot_market.insurance_fund_cumulative_boost_shares_per_weight;
+
+    let boost_shares = insurance_fund_junior_tranche
+        .boost_weight
+        .cast::<u128>()?
+        .safe_mul(
+            cumulative_boost_shares_per_weight
+                .safe_sub(insurance_fund_junior_tranche.last_cumulative_boost_shares_per_weight)?,
+        )?
+        .safe_div(IF_BOOST_SHARES_PER_WEIGHT_PRECISION)?;
+
+    insurance_fund_junior_tranche.last_cumulative_boost_shares_per_weight =
+        cumulative_boost_shares_per_weight;
+
+    if boost_shares > 0 && insurance_fund_junior_tranche.total_shares > 0 {
+        let total_shares_after = insurance_fund_junior_tranche
+            .total_shares
+            .safe_add(boost_shares)?;
+
+        insurance_fund_junior_tranche.share_factor = insurance_fund_junior_tranche
+            .share_factor
+            .cast::<u128>()?
+            .safe_mul(total_shares_after)?
+            .safe_div(insurance_fund_junior_tranche.total_shares)?
+            .cast()?;
+        insurance_fund_junior_tranche.total_shares = total_shares_after;
+    }
+
+    update_insurance_fund_junior_tranche_boost_weight(insurance_fund_junior_tranche, spot_market)?;
+
+    Ok(boost_shares)
+}
+
+/// Brings a junior stake up to date with the tranche's losses and boosted revenue before it's
+/// used, returning its if shares
+pub fn update_insurance_fund_junior_stake(
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    insurance_fund_junior_tranche: &mut InsuranceFundJuniorTranche,
+    spot_market: &mut SpotMarket,
+) -> DriftResult<u128> {
+    validate!(
+        insurance_fund_stake.tranche == InsuranceFundTranche::Junior,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "insurance fund stake is not in the junior tranche"
+    )?;
+
+    accrue_insurance_fund_junior_tranche_boost(
+        insurance_vault_amount,
+        insurance_fund_junior_tranche,
+        spot_market,
+    )?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+
+    let if_shares = insurance_fund_junior_tranche.calculate_stake_shares(
+        insurance_fund_stake.checked_if_shares(spot_market)?,
+        insurance_fund_stake,
+    )?;
+
+    if insurance_fund_stake.junior_epoch != insurance_fund_junior_tranche.epoch {
+        // the tranche was wiped out along with the shares of any withdraw request
+        insurance_fund_stake.last_withdraw_request_shares = 0;
+        insurance_fund_stake.last_withdraw_request_value = 0;
+    } else {
+        insurance_fund_stake.last_withdraw_request_shares = insurance_fund_junior_tranche
+            .calculate_stake_shares(
+                insurance_fund_stake.last_withdraw_request_shares,
+                insurance_fund_stake,
+            )?;
+    }
+
+    insurance_fund_stake.update_if_shares(if_shares, spot_market)?;
+    insurance_fund_stake.junior_epoch = insurance_fund_junior_tranche.epoch;
+    insurance_fund_stake.junior_share_factor = insurance_fund_junior_tranche.share_factor;
+
+    Ok(if_shares)
+}
+
+/// Carries a junior stake's change in shares from adding or removing stake over to the tranche
+pub fn update_insurance_fund_junior_tranche_shares(
+    if_shares_before: u128,
+    insurance_fund_stake: &InsuranceFundStake,
+    insurance_fund_junior_tranche: &mut InsuranceFundJuniorTranche,
+    spot_market: &mut SpotMarket,
+) -> DriftResult {
+    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+
+    // junior stakes round their shares down, so their sum can't be more than the tranche's
+    insurance_fund_junior_tranche.total_shares = insurance_fund_junior_tranche
+        .total_shares
+        .safe_add(if_shares_after)?
+        .safe_sub(if_shares_before)?;
+
+    update_insurance_fund_junior_tranche_boost_weight(insurance_fund_junior_tranche, spot_market)
+}
+
+/// Burns the junior tranche's shares worth the loss so the senior share price holds. Whatever
+/// the junior tranche can't cover is lost by the senior shares pro rata
+pub fn apply_insurance_fund_loss_to_junior_tranche(
+    insurance_vault_amount: u64,
+    loss: u64,
+    insurance_fund_junior_tranche: &mut InsuranceFundJuniorTranche,
+    spot_market: &mut SpotMarket,
+) -> DriftResult<u128> {
+    accrue_insurance_fund_junior_tranche_boost(
+        insurance_vault_amount,
+        insurance_fund_junior_tranche,
+        spot_market,
+    )?;
+
+    if loss == 0 || insurance_fund_junior_tranche.total_shares == 0 {
+        return Ok(0);
+    }
+
+    let loss_shares = vault_amount_to_if_shares(
+        loss,
+        spot_market.insurance_fund.total_shares,
+        insurance_vault_amount,
+    )?;
+
+    let total_shares_after = insurance_fund_junior_tranche
+        .total_shares
+        .saturating_sub(loss_shares);
+
+    let share_factor = insurance_fund_junior_tranche
+        .share_factor
+        .cast::<u128>()?
+        .safe_mul(total_shares_after)?
+        .safe_div(insurance_fund_junior_tranche.total_shares)?;
+
+    let shares_burned = if share_factor < MIN_IF_TRANCHE_SHARE_FACTOR {
+        msg!(
+            "junior tranche wiped out in epoch {}",
+            insurance_fund_junior_tranche.epoch
+        );
+        insurance_fund_junior_tranche.epoch = insurance_fund_junior_tranche.epoch.safe_add(1)?;
+        insurance_fund_junior_tranche.share_factor = IF_TRANCHE_SHARE_FACTOR_PRECISION_U64;
+        insurance_fund_junior_tranche.total_shares
+    } else {
+        insurance_fund_junior_tranche.share_factor = share_factor.cast()?;
+        insurance_fund_junior_tranche
+            .total_shares
+            .safe_sub(total_shares_after)?
+    };
+
+    msg!(
+        "junior tranche burned {} if shares for loss of {}",
+        shares_burned,
+        loss
+    );
+
+    insurance_fund_junior_tranche.total_shares = insurance_fund_junior_tranche
+        .total_shares
+        .safe_sub(shares_burned)?;
+
+    spot_market.insurance_fund.total_shares = spot_market
+        .insurance_fund
+        .total_shares
+        .safe_sub(shares_burned)?;
+    spot_market.insurance_fund.user_shares = spot_market
+        .insurance_fund
+        .user_shares
+        .safe_sub(shares_burned)?;
+
+    update_insurance_fund_junior_tranche_boost_weight(insurance_fund_junior_tranche, spot_market)?;
+
+    Ok(shares_burned)
+}
+
+pub fn update_insurance_fund_stake_tranche(
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    insurance_fund_junior_tranche: &mut InsuranceFundJuniorTranche,
+    spot_market: &mut SpotMarket,
+    tranche: InsuranceFundTranche,
+) -> DriftResult {
+    if insurance_fund_stake.tranche == InsuranceFundTranche::Junior {
+        update_insurance_fund_junior_stake(
+            insurance_vault_amount,
+            insurance_fund_stake,
+            insurance_fund_junior_tranche,
+            spot_market,
+        )?;
+    } else {
+        apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
+        apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    }
+
+    validate!(
+        insurance_fund_stake.checked_if_shares(spot_market)? == 0
+            && insurance_fund_stake.last_withdraw_request_shares == 0
+            && insurance_fund_stake.lockup_tier == InsuranceFundLockupTier::None,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "insurance fund stake must be empty to change tranche"
+    )?;
+
+    insurance_fund_stake.tranche = tranche;
+    insurance_fund_stake.junior_epoch = insurance_fund_junior_tranche.epoch;
+    insurance_fund_stake.junior_share_factor = insurance_fund_junior_tranche.share_factor;
+
+    Ok(())
+}
+
 pub fn request_remove_insurance_fund_stake(
     n_shares: u128,
     insurance_vault_amount: u64,
@@ -196,6 +747,12 @@ pub fn request_remove_insurance_fund_stake(
     spot_market: &mut SpotMarket,
     now: i64,
 ) -> DriftResult {
+    validate!(
+        insurance_fund_stake.lockup_tier == InsuranceFundLockupTier::None,
+        ErrorCode::InsuranceFundStakeLocked,
+        "insurance fund stake must be unlocked before requesting removal"
+    )?;
+
     msg!("n_shares {}", n_shares);
     insurance_fund_stake.last_withdraw_request_shares = n_shares;
 
@@ -501,6 +1058,205 @@ pub fn admin_remove_insurance_fund_stake(
     Ok(withdraw_amount)
 }
 
+fn validate_insurance_fund_stake_wrappable(
+    insurance_fund_stake: &InsuranceFundStake,
+) -> DriftResult {
+    validate!(
+        insurance_fund_stake.tranche == InsuranceFundTranche::Senior,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "junior insurance fund stakes can't be wrapped"
+    )?;
+
+    validate!(
+        insurance_fund_stake.lockup_tier == InsuranceFundLockupTier::None,
+        ErrorCode::InsuranceFundStakeLocked,
+        "locked insurance fund stakes can't be wrapped"
+    )?;
+
+    validate!(
+        insurance_fund_stake.last_withdraw_request_shares == 0
+            && insurance_fund_stake.last_withdraw_request_value == 0,
+        ErrorCode::IFWithdrawRequestInProgress,
+        "withdraw request in progress"
+    )?;
+
+    Ok(())
+}
+
+/// Moves the stake's shares to the program's wrapped stake. Returns the share tokens to mint
+pub fn wrap_insurance_fund_stake(
+    n_shares: u128,
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    wrapped_insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    spot_market: &mut SpotMarket,
+    share_token_supply: u64,
+    now: i64,
+) -> DriftResult<u64> {
+    validate_insurance_fund_stake_wrappable(insurance_fund_stake)?;
+
+    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    apply_rebase_to_insurance_fund_stake(wrapped_insurance_fund_stake, spot_market)?;
+
+    let if_shares_before = insurance_fund_stake.checked_if_shares(spot_market)?;
+
+    validate!(
+        n_shares > 0 && n_shares <= if_shares_before,
+        ErrorCode::InsufficientIFShares,
+        "n_shares={} > if_shares_before={}",
+        n_shares,
+        if_shares_before
+    )?;
+
+    let share_tokens = if_shares_to_share_tokens(
+        n_shares,
+        wrapped_insurance_fund_stake.checked_if_shares(spot_market)?,
+        share_token_supply,
+    )?;
+
+    validate!(
+        share_tokens > 0,
+        ErrorCode::InsufficientIFShares,
+        "n_shares={} too small to mint share tokens",
+        n_shares
+    )?;
+
+    let amount = if_shares_to_vault_amount(
+        n_shares,
+        spot_market.insurance_fund.total_shares,
+        insurance_vault_amount,
+    )?;
+
+    insurance_fund_stake.decrease_if_shares(n_shares, spot_market)?;
+    insurance_fund_stake.cost_basis = insurance_fund_stake.cost_basis.safe_sub(amount.cast()?)?;
+
+    wrapped_insurance_fund_stake.increase_if_shares(n_shares, spot_market)?;
+    wrapped_insurance_fund_stake.cost_basis = wrapped_insurance_fund_stake
+        .cost_basis
+        .safe_add(amount.cast()?)?;
+
+    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+
+    if spot_market.market_index == QUOTE_SPOT_MARKET_INDEX {
+        user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?;
+    } else if spot_market.market_index == GOV_SPOT_MARKET_INDEX {
+        user_stats.if_staked_gov_token_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?;
+    }
+
+    emit!(InsuranceFundStakeRecord {
+        ts: now,
+        user_authority: user_stats.authority,
+        action: StakeAction::Wrap,
+        amount,
+        market_index: spot_market.market_index,
+        insurance_vault_amount_before: insurance_vault_amount,
+        if_shares_before,
+        user_if_shares_before: spot_market.insurance_fund.user_shares,
+        total_if_shares_before: spot_market.insurance_fund.total_shares,
+        if_shares_after,
+        total_if_shares_after: spot_market.insurance_fund.total_shares,
+        user_if_shares_after: spot_market.insurance_fund.user_shares,
+    });
+
+    Ok(share_tokens)
+}
+
+/// Moves the shares the burned share tokens are worth from the program's wrapped stake back to
+/// the stake. Returns the shares moved
+pub fn unwrap_insurance_fund_stake(
+    share_token_amount: u64,
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    wrapped_insurance_fund_stake: &mut InsuranceFundStake,
+    user_stats: &mut UserStats,
+    spot_market: &mut SpotMarket,
+    share_token_supply: u64,
+    now: i64,
+) -> DriftResult<u128> {
+    validate_insurance_fund_stake_wrappable(insurance_fund_stake)?;
+
+    apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
+    apply_rebase_to_insurance_fund_stake(insurance_fund_stake, spot_market)?;
+    apply_rebase_to_insurance_fund_stake(wrapped_insurance_fund_stake, spot_market)?;
+
+    let if_shares_before = insurance_fund_stake.checked_if_shares(spot_market)?;
+
+    let n_shares = share_tokens_to_if_shares(
+        share_token_amount,
+        wrapped_insurance_fund_stake.checked_if_shares(spot_market)?,
+        share_token_supply,
+    )?;
+
+    validate!(
+        n_shares > 0,
+        ErrorCode::InsufficientIFShares,
+        "share_token_amount={} too small to unwrap",
+        share_token_amount
+    )?;
+
+    let amount = if_shares_to_vault_amount(
+        n_shares,
+        spot_market.insurance_fund.total_shares,
+        insurance_vault_amount,
+    )?;
+
+    wrapped_insurance_fund_stake.decrease_if_shares(n_shares, spot_market)?;
+    wrapped_insurance_fund_stake.cost_basis = wrapped_insurance_fund_stake
+        .cost_basis
+        .safe_sub(amount.cast()?)?;
+
+    // reset cost basis if no shares
+    insurance_fund_stake.cost_basis = if if_shares_before == 0 {
+        amount.cast()?
+    } else {
+        insurance_fund_stake.cost_basis.safe_add(amount.cast()?)?
+    };
+    insurance_fund_stake.increase_if_shares(n_shares, spot_market)?;
+
+    let if_shares_after = insurance_fund_stake.checked_if_shares(spot_market)?;
+
+    if spot_market.market_index == QUOTE_SPOT_MARKET_INDEX {
+        user_stats.if_staked_quote_asset_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?;
+    } else if spot_market.market_index == GOV_SPOT_MARKET_INDEX {
+        user_stats.if_staked_gov_token_amount = if_shares_to_vault_amount(
+            if_shares_after,
+            spot_market.insurance_fund.total_shares,
+            insurance_vault_amount,
+        )?;
+    }
+
+    emit!(InsuranceFundStakeRecord {
+        ts: now,
+        user_authority: user_stats.authority,
+        action: StakeAction::Unwrap,
+        amount,
+        market_index: spot_market.market_index,
+        insurance_vault_amount_before: insurance_vault_amount,
+        if_shares_before,
+        user_if_shares_before: spot_market.insurance_fund.user_shares,
+        total_if_shares_before: spot_market.insurance_fund.total_shares,
+        if_shares_after,
+        total_if_shares_after: spot_market.insurance_fund.total_shares,
+        user_if_shares_after: spot_market.insurance_fund.user_shares,
+    });
+
+    Ok(n_shares)
+}
+
 pub fn transfer_protocol_insurance_fund_stake(
     insurance_vault_amount: u64,
     n_shares: u128,
@@ -510,6 +1266,12 @@ pub fn transfer_protocol_insurance_fund_stake(
     now: i64,
     signer_pubkey: Pubkey,
 ) -> DriftResult<u64> {
+    validate!(
+        target_insurance_fund_stake.tranche == InsuranceFundTranche::Senior,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "protocol if shares can only be transferred to senior stakes"
+    )?;
+
     apply_rebase_to_insurance_fund(insurance_vault_amount, spot_market)?;
 
     let total_if_shares_before = spot_market.insurance_fund.total_shares;
@@ -723,6 +1485,9 @@ pub fn settle_revenue_to_insurance_fund(
         .total_factor
         .safe_sub(spot_market.insurance_fund.user_factor)?;
 
+    // shares are minted at the price before the revenue lands in the vault
+    let total_if_shares_pre_settle = spot_market.insurance_fund.total_shares;
+
     // give protocol its cut
     if protocol_if_factor > 0 {
         let n_shares = vault_amount_to_if_shares(
@@ -737,6 +1502,19 @@ pub fn settle_revenue_to_insurance_fund(
             spot_market.insurance_fund.total_shares.safe_add(n_shares)?;
     }
 
+    if spot_market.insurance_fund.user_factor > 0 {
+        let user_revenue = insurance_fund_token_amount
+            .safe_mul(spot_market.insurance_fund.user_factor.cast()?)?
+            .safe_div(spot_market.insurance_fund.total_factor.cast()?)?;
+
+        mint_insurance_fund_boost_shares(
+            user_revenue,
+            total_if_shares_pre_settle,
+            insurance_vault_amount,
+            spot_market,
+        )?;
+    }
+
     let total_if_shares_before = spot_market.insurance_fund.total_shares;
 
     update_revenue_pool_balances(
@@ -762,6 +1540,54 @@ pub fn settle_revenue_to_insurance_fund(
     insurance_fund_token_amount.cast()
 }
 
+/// Locked stakes get the boosted part of the users' cut of settled revenue as shares minted to
+/// the market, which each lockup accrues by its boost weight
+pub fn mint_insurance_fund_boost_shares(
+    user_revenue: u64,
+    total_if_shares: u128,
+    insurance_vault_amount: u64,
+    spot_market: &mut SpotMarket,
+) -> DriftResult<u128> {
+    let total_boost_weight = spot_market
+        .insurance_fund_total_boost_weight
+        .cast::<u128>()?;
+    if total_boost_weight == 0 {
+        return Ok(0);
+    }
+
+    // boost weight is counted like extra shares when splitting the users' cut
+    let boost_revenue = user_revenue
+        .cast::<u128>()?
+        .safe_mul(total_boost_weight)?
+        .safe_div(
+            spot_market
+                .insurance_fund
+                .user_shares
+                .safe_add(total_boost_weight)?,
+        )?;
+
+    let n_shares = vault_amount_to_if_shares(
+        boost_revenue.cast()?,
+        total_if_shares,
+        insurance_vault_amount,
+    )?;
+
+    spot_market.insurance_fund.total_shares =
+        spot_market.insurance_fund.total_shares.safe_add(n_shares)?;
+    spot_market.insurance_fund.user_shares =
+        spot_market.insurance_fund.user_shares.safe_add(n_shares)?;
+
+    spot_market.insurance_fund_cumulative_boost_shares_per_weight = spot_market
+        .insurance_fund_cumulative_boost_shares_per_weight
+        .safe_add(
+            n_shares
+                .safe_mul(IF_BOOST_SHARES_PER_WEIGHT_PRECISION)?
+                .safe_div(total_boost_weight)?,
+        )?;
+
+    Ok(n_shares)
+}
+
 pub fn resolve_perp_pnl_deficit(
     vault_amount: u64,
     insurance_vault_amount: u64,
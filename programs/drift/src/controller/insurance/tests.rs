@@ -2,7 +2,8 @@ use anchor_lang::prelude::Pubkey;
 
 use crate::controller::insurance::*;
 use crate::math::constants::{
-    QUOTE_PRECISION, SPOT_BALANCE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION,
+    IF_TRANCHE_SHARE_FACTOR_PRECISION_U64, NINETY_DAY, QUOTE_PRECISION, SPOT_BALANCE_PRECISION,
+    SPOT_CUMULATIVE_INTEREST_PRECISION, THIRTY_DAY,
 };
 use crate::state::perp_market::PoolBalance;
 use crate::state::spot_market::InsuranceFund;
@@ -1619,3 +1620,763 @@ fn test_transfer_protocol_owned_stake() {
     )
     .is_err());
 }
+
+#[test]
+fn lock_and_unlock_if_stake() {
+    let mut if_balance = 0;
+    let mut if_stake_a = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut if_stake_b = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let amount = (1000 * QUOTE_PRECISION) as u64;
+    let mut spot_market = SpotMarket {
+        insurance_fund: InsuranceFund {
+            unstaking_period: 0,
+            ..InsuranceFund::default()
+        },
+        ..SpotMarket::default()
+    };
+
+    for if_stake in [&mut if_stake_a, &mut if_stake_b] {
+        add_insurance_fund_stake(
+            amount,
+            if_balance,
+            if_stake,
+            &mut user_stats,
+            &mut spot_market,
+            0,
+        )
+        .unwrap();
+        if_balance += amount;
+    }
+    assert_eq!(spot_market.insurance_fund.user_shares, 2 * amount as u128);
+
+    let mut lockup = InsuranceFundStakeLockup::default();
+    assert!(lock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_a,
+        &mut lockup,
+        &mut spot_market,
+        InsuranceFundLockupTier::None,
+        0,
+    )
+    .is_err());
+
+    lock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_a,
+        &mut lockup,
+        &mut spot_market,
+        InsuranceFundLockupTier::NinetyDays,
+        0,
+    )
+    .unwrap();
+    assert_eq!(lockup.lockup_end_ts, NINETY_DAY);
+    assert_eq!(lockup.boost_weight, amount / 4);
+    assert_eq!(spot_market.insurance_fund_total_boost_weight, amount / 4);
+    assert_eq!(if_stake_a.lockup_tier, InsuranceFundLockupTier::NinetyDays);
+
+    // can't lock twice or withdraw while locked
+    assert!(lock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_a,
+        &mut InsuranceFundStakeLockup::default(),
+        &mut spot_market,
+        InsuranceFundLockupTier::ThirtyDays,
+        0,
+    )
+    .is_err());
+    assert!(request_remove_insurance_fund_stake(
+        if_stake_a.unchecked_if_shares(),
+        if_balance,
+        &mut if_stake_a,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .is_err());
+
+    // users' cut of 225 is split over 2000 shares + 250 boost weight
+    let boost_shares = mint_insurance_fund_boost_shares(
+        (225 * QUOTE_PRECISION) as u64,
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+        &mut spot_market,
+    )
+    .unwrap();
+    if_balance += (225 * QUOTE_PRECISION) as u64;
+    assert_eq!(boost_shares, 25 * QUOTE_PRECISION);
+    assert_eq!(
+        spot_market.insurance_fund.user_shares,
+        2025 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        spot_market.insurance_fund_cumulative_boost_shares_per_weight,
+        100_000_000_000
+    );
+
+    assert!(unlock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_a,
+        &mut lockup,
+        &mut user_stats,
+        &mut spot_market,
+        NINETY_DAY - 1,
+    )
+    .is_err());
+
+    unlock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_a,
+        &mut lockup,
+        &mut user_stats,
+        &mut spot_market,
+        NINETY_DAY,
+    )
+    .unwrap();
+    assert_eq!(if_stake_a.unchecked_if_shares(), 1025 * QUOTE_PRECISION);
+    assert_eq!(if_stake_a.lockup_tier, InsuranceFundLockupTier::None);
+    assert_eq!(lockup.boost_weight, 0);
+    assert_eq!(spot_market.insurance_fund_total_boost_weight, 0);
+
+    let value_a = if_shares_to_vault_amount(
+        if_stake_a.unchecked_if_shares(),
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+    )
+    .unwrap();
+    let value_b = if_shares_to_vault_amount(
+        if_stake_b.unchecked_if_shares(),
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+    )
+    .unwrap();
+    assert!(value_a > value_b);
+    assert_eq!(value_a + value_b, if_balance - 1);
+
+    request_remove_insurance_fund_stake(
+        if_stake_a.unchecked_if_shares(),
+        if_balance,
+        &mut if_stake_a,
+        &mut user_stats,
+        &mut spot_market,
+        NINETY_DAY,
+    )
+    .unwrap();
+}
+
+#[test]
+fn add_to_locked_if_stake() {
+    let mut if_balance = 0;
+    let mut if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let amount = (1000 * QUOTE_PRECISION) as u64;
+    let mut spot_market = SpotMarket {
+        insurance_fund: InsuranceFund {
+            unstaking_period: 0,
+            ..InsuranceFund::default()
+        },
+        ..SpotMarket::default()
+    };
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    let mut lockup = InsuranceFundStakeLockup::default();
+    lock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake,
+        &mut lockup,
+        &mut spot_market,
+        InsuranceFundLockupTier::ThirtyDays,
+        0,
+    )
+    .unwrap();
+    assert_eq!(lockup.boost_weight, amount / 10);
+
+    mint_insurance_fund_boost_shares(
+        (110 * QUOTE_PRECISION) as u64,
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+        &mut spot_market,
+    )
+    .unwrap();
+    if_balance += (110 * QUOTE_PRECISION) as u64;
+
+    let now = THIRTY_DAY / 3;
+    let boost_shares = accrue_insurance_fund_stake_boost(
+        if_balance,
+        &mut if_stake,
+        &mut lockup,
+        &mut spot_market,
+        now,
+    )
+    .unwrap();
+    assert_eq!(boost_shares, 10 * QUOTE_PRECISION);
+    assert_eq!(if_stake.unchecked_if_shares(), 1010 * QUOTE_PRECISION);
+
+    // already accrued
+    let boost_shares = accrue_insurance_fund_stake_boost(
+        if_balance,
+        &mut if_stake,
+        &mut lockup,
+        &mut spot_market,
+        now,
+    )
+    .unwrap();
+    assert_eq!(boost_shares, 0);
+
+    add_insurance_fund_stake(
+        if_balance,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        now,
+    )
+    .unwrap();
+    if_balance *= 2;
+    assert_eq!(if_stake.unchecked_if_shares(), 2020 * QUOTE_PRECISION);
+
+    extend_insurance_fund_stake_lockup(&if_stake, &mut lockup, &mut spot_market, now).unwrap();
+    assert_eq!(lockup.lockup_end_ts, now + THIRTY_DAY);
+    assert_eq!(lockup.boost_weight, (202 * QUOTE_PRECISION) as u64);
+    assert_eq!(
+        spot_market.insurance_fund_total_boost_weight,
+        (202 * QUOTE_PRECISION) as u64
+    );
+
+    assert!(unlock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake,
+        &mut lockup,
+        &mut user_stats,
+        &mut spot_market,
+        THIRTY_DAY,
+    )
+    .is_err());
+}
+
+#[test]
+fn expired_if_stake_lockup_stops_boost() {
+    let mut if_balance = 0;
+    let mut if_stake_a = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut if_stake_b = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let amount = (1000 * QUOTE_PRECISION) as u64;
+    let mut spot_market = SpotMarket {
+        insurance_fund: InsuranceFund {
+            unstaking_period: 0,
+            ..InsuranceFund::default()
+        },
+        ..SpotMarket::default()
+    };
+
+    for if_stake in [&mut if_stake_a, &mut if_stake_b] {
+        add_insurance_fund_stake(
+            amount,
+            if_balance,
+            if_stake,
+            &mut user_stats,
+            &mut spot_market,
+            0,
+        )
+        .unwrap();
+        if_balance += amount;
+    }
+
+    let mut lockup = InsuranceFundStakeLockup::default();
+    lock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_a,
+        &mut lockup,
+        &mut spot_market,
+        InsuranceFundLockupTier::ThirtyDays,
+        0,
+    )
+    .unwrap();
+
+    // users' cut of 210 is split over 2000 shares + 100 boost weight
+    let boost_shares = mint_insurance_fund_boost_shares(
+        (210 * QUOTE_PRECISION) as u64,
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+        &mut spot_market,
+    )
+    .unwrap();
+    if_balance += (210 * QUOTE_PRECISION) as u64;
+    assert_eq!(boost_shares, 10 * QUOTE_PRECISION);
+
+    // half the shares were minted after the lockup ended, they go to the protocol
+    let now = 2 * THIRTY_DAY;
+    let boost_shares = accrue_insurance_fund_stake_boost(
+        if_balance,
+        &mut if_stake_a,
+        &mut lockup,
+        &mut spot_market,
+        now,
+    )
+    .unwrap();
+    assert_eq!(boost_shares, 5 * QUOTE_PRECISION);
+    assert_eq!(if_stake_a.unchecked_if_shares(), 1005 * QUOTE_PRECISION);
+    assert_eq!(
+        spot_market.insurance_fund.user_shares,
+        2005 * QUOTE_PRECISION
+    );
+    assert_eq!(lockup.boost_weight, 0);
+    assert_eq!(spot_market.insurance_fund_total_boost_weight, 0);
+
+    // no more boost once the expired weight is settled out
+    assert_eq!(
+        mint_insurance_fund_boost_shares(
+            (210 * QUOTE_PRECISION) as u64,
+            spot_market.insurance_fund.total_shares,
+            if_balance,
+            &mut spot_market,
+        )
+        .unwrap(),
+        0
+    );
+
+    unlock_insurance_fund_stake(
+        if_balance,
+        &mut if_stake_a,
+        &mut lockup,
+        &mut user_stats,
+        &mut spot_market,
+        now,
+    )
+    .unwrap();
+    assert_eq!(if_stake_a.unchecked_if_shares(), 1005 * QUOTE_PRECISION);
+    assert_eq!(if_stake_a.lockup_tier, InsuranceFundLockupTier::None);
+}
+
+fn add_junior_insurance_fund_stake(
+    amount: u64,
+    if_balance: u64,
+    if_stake: &mut InsuranceFundStake,
+    junior_tranche: &mut InsuranceFundJuniorTranche,
+    user_stats: &mut UserStats,
+    spot_market: &mut SpotMarket,
+) {
+    let if_shares_before =
+        update_insurance_fund_junior_stake(if_balance, if_stake, junior_tranche, spot_market)
+            .unwrap();
+    add_insurance_fund_stake(amount, if_balance, if_stake, user_stats, spot_market, 0).unwrap();
+    update_insurance_fund_junior_tranche_shares(
+        if_shares_before,
+        if_stake,
+        junior_tranche,
+        spot_market,
+    )
+    .unwrap();
+}
+
+#[test]
+fn junior_tranche_absorbs_losses_first() {
+    let mut if_balance = 0;
+    let mut senior_if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut junior_if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let amount = (1000 * QUOTE_PRECISION) as u64;
+    let mut spot_market = SpotMarket {
+        insurance_fund: InsuranceFund {
+            unstaking_period: 0,
+            ..InsuranceFund::default()
+        },
+        has_insurance_fund_junior_tranche: true,
+        ..SpotMarket::default()
+    };
+    let mut junior_tranche = InsuranceFundJuniorTranche::new(0, 0, 0);
+
+    update_insurance_fund_stake_tranche(
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut spot_market,
+        InsuranceFundTranche::Junior,
+    )
+    .unwrap();
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut senior_if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    add_junior_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut user_stats,
+        &mut spot_market,
+    );
+    if_balance += amount;
+    assert_eq!(junior_tranche.total_shares, amount as u128);
+
+    // stakes with shares can't change tranche
+    assert!(update_insurance_fund_stake_tranche(
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut spot_market,
+        InsuranceFundTranche::Senior,
+    )
+    .is_err());
+
+    // junior tranche covers the whole loss
+    let shares_burned = apply_insurance_fund_loss_to_junior_tranche(
+        if_balance,
+        amount / 2,
+        &mut junior_tranche,
+        &mut spot_market,
+    )
+    .unwrap();
+    if_balance -= amount / 2;
+    assert_eq!(shares_burned, amount as u128 / 2);
+    assert_eq!(
+        junior_tranche.share_factor,
+        IF_TRANCHE_SHARE_FACTOR_PRECISION_U64 / 2
+    );
+    assert_eq!(
+        spot_market.insurance_fund.total_shares,
+        3 * amount as u128 / 2
+    );
+
+    assert_eq!(
+        if_shares_to_vault_amount(
+            senior_if_stake.unchecked_if_shares(),
+            spot_market.insurance_fund.total_shares,
+            if_balance,
+        )
+        .unwrap(),
+        amount
+    );
+
+    let junior_if_shares = update_insurance_fund_junior_stake(
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut spot_market,
+    )
+    .unwrap();
+    assert_eq!(junior_if_shares, amount as u128 / 2);
+
+    // loss is more than the junior tranche has, senior tranche covers the rest
+    let shares_burned = apply_insurance_fund_loss_to_junior_tranche(
+        if_balance,
+        700 * QUOTE_PRECISION as u64,
+        &mut junior_tranche,
+        &mut spot_market,
+    )
+    .unwrap();
+    if_balance -= 700 * QUOTE_PRECISION as u64;
+    assert_eq!(shares_burned, amount as u128 / 2);
+    assert_eq!(junior_tranche.total_shares, 0);
+    assert_eq!(junior_tranche.epoch, 1);
+    assert_eq!(
+        junior_tranche.share_factor,
+        IF_TRANCHE_SHARE_FACTOR_PRECISION_U64
+    );
+
+    assert_eq!(
+        if_shares_to_vault_amount(
+            senior_if_stake.unchecked_if_shares(),
+            spot_market.insurance_fund.total_shares,
+            if_balance,
+        )
+        .unwrap(),
+        800 * QUOTE_PRECISION as u64
+    );
+
+    let junior_if_shares = update_insurance_fund_junior_stake(
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut spot_market,
+    )
+    .unwrap();
+    assert_eq!(junior_if_shares, 0);
+
+    // nothing left to burn
+    let shares_burned = apply_insurance_fund_loss_to_junior_tranche(
+        if_balance,
+        QUOTE_PRECISION as u64,
+        &mut junior_tranche,
+        &mut spot_market,
+    )
+    .unwrap();
+    assert_eq!(shares_burned, 0);
+
+    update_insurance_fund_stake_tranche(
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut spot_market,
+        InsuranceFundTranche::Senior,
+    )
+    .unwrap();
+    assert_eq!(junior_if_stake.tranche, InsuranceFundTranche::Senior);
+}
+
+#[test]
+fn junior_tranche_boosted_revenue() {
+    let mut if_balance = 0;
+    let mut senior_if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut junior_if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let amount = (1000 * QUOTE_PRECISION) as u64;
+    let mut spot_market = SpotMarket {
+        insurance_fund: InsuranceFund {
+            unstaking_period: 0,
+            ..InsuranceFund::default()
+        },
+        has_insurance_fund_junior_tranche: true,
+        ..SpotMarket::default()
+    };
+    // junior shares count for 50% more in revenue settles
+    let mut junior_tranche = InsuranceFundJuniorTranche::new(0, 500_000, 0);
+
+    update_insurance_fund_stake_tranche(
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut spot_market,
+        InsuranceFundTranche::Junior,
+    )
+    .unwrap();
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut senior_if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    add_junior_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut user_stats,
+        &mut spot_market,
+    );
+    if_balance += amount;
+    assert_eq!(junior_tranche.boost_weight, amount / 2);
+    assert_eq!(spot_market.insurance_fund_total_boost_weight, amount / 2);
+
+    // junior stakes can't be locked
+    assert!(lock_insurance_fund_stake(
+        if_balance,
+        &mut junior_if_stake,
+        &mut InsuranceFundStakeLockup::default(),
+        &mut spot_market,
+        InsuranceFundLockupTier::ThirtyDays,
+        0,
+    )
+    .is_err());
+
+    let boost_shares = mint_insurance_fund_boost_shares(
+        (250 * QUOTE_PRECISION) as u64,
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+        &mut spot_market,
+    )
+    .unwrap();
+    if_balance += (250 * QUOTE_PRECISION) as u64;
+    assert_eq!(boost_shares, 50 * QUOTE_PRECISION);
+
+    let boost_shares = accrue_insurance_fund_junior_tranche_boost(
+        if_balance,
+        &mut junior_tranche,
+        &mut spot_market,
+    )
+    .unwrap();
+    assert_eq!(boost_shares, 50 * QUOTE_PRECISION);
+    assert_eq!(junior_tranche.total_shares, 1050 * QUOTE_PRECISION);
+    assert_eq!(junior_tranche.share_factor, 1_050_000_000_000);
+    assert_eq!(junior_tranche.boost_weight, (525 * QUOTE_PRECISION) as u64);
+    assert_eq!(
+        spot_market.insurance_fund_total_boost_weight,
+        (525 * QUOTE_PRECISION) as u64
+    );
+
+    let junior_if_shares = update_insurance_fund_junior_stake(
+        if_balance,
+        &mut junior_if_stake,
+        &mut junior_tranche,
+        &mut spot_market,
+    )
+    .unwrap();
+    assert_eq!(junior_if_shares, 1050 * QUOTE_PRECISION);
+
+    let senior_value = if_shares_to_vault_amount(
+        senior_if_stake.unchecked_if_shares(),
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+    )
+    .unwrap();
+    let junior_value = if_shares_to_vault_amount(
+        junior_if_shares,
+        spot_market.insurance_fund.total_shares,
+        if_balance,
+    )
+    .unwrap();
+    assert!(junior_value > senior_value);
+}
+
+#[test]
+fn wrap_and_unwrap_if_stake() {
+    let mut if_balance = 0;
+    let mut if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut wrapped_if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut user_stats = UserStats::default();
+    let amount = (1000 * QUOTE_PRECISION) as u64;
+    let mut spot_market = SpotMarket {
+        insurance_fund: InsuranceFund {
+            unstaking_period: 0,
+            ..InsuranceFund::default()
+        },
+        ..SpotMarket::default()
+    };
+
+    add_insurance_fund_stake(
+        amount,
+        if_balance,
+        &mut if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+    )
+    .unwrap();
+    if_balance += amount;
+
+    assert!(wrap_insurance_fund_stake(
+        amount as u128 + 1,
+        if_balance,
+        &mut if_stake,
+        &mut wrapped_if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+        0,
+    )
+    .is_err());
+
+    // first share tokens are one to one with shares
+    let mut share_token_supply = wrap_insurance_fund_stake(
+        400 * QUOTE_PRECISION,
+        if_balance,
+        &mut if_stake,
+        &mut wrapped_if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+        0,
+    )
+    .unwrap();
+    assert_eq!(share_token_supply, (400 * QUOTE_PRECISION) as u64);
+    assert_eq!(if_stake.unchecked_if_shares(), 600 * QUOTE_PRECISION);
+    assert_eq!(
+        wrapped_if_stake.unchecked_if_shares(),
+        400 * QUOTE_PRECISION
+    );
+    assert_eq!(spot_market.insurance_fund.total_shares, amount as u128);
+    assert_eq!(
+        user_stats.if_staked_quote_asset_amount,
+        (600 * QUOTE_PRECISION) as u64
+    );
+
+    // insurance fund pays out 99% and rebases the shares by 10x
+    if_balance = (10 * QUOTE_PRECISION) as u64;
+
+    let share_tokens = wrap_insurance_fund_stake(
+        10 * QUOTE_PRECISION,
+        if_balance,
+        &mut if_stake,
+        &mut wrapped_if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        share_token_supply,
+        0,
+    )
+    .unwrap();
+    assert_eq!(spot_market.insurance_fund.shares_base, 1);
+    assert_eq!(
+        spot_market.insurance_fund.total_shares,
+        100 * QUOTE_PRECISION
+    );
+    assert_eq!(wrapped_if_stake.unchecked_if_shares(), 50 * QUOTE_PRECISION);
+    // supply didn't rebase, so each token is worth a tenth of a share
+    assert_eq!(share_tokens, (100 * QUOTE_PRECISION) as u64);
+    share_token_supply += share_tokens;
+
+    let mut other_if_stake = InsuranceFundStake::new(Pubkey::default(), 0, 0);
+    let mut other_user_stats = UserStats::default();
+
+    assert!(unwrap_insurance_fund_stake(
+        share_token_supply + 1,
+        if_balance,
+        &mut other_if_stake,
+        &mut wrapped_if_stake,
+        &mut other_user_stats,
+        &mut spot_market,
+        share_token_supply,
+        0,
+    )
+    .is_err());
+
+    let if_shares = unwrap_insurance_fund_stake(
+        share_token_supply,
+        if_balance,
+        &mut other_if_stake,
+        &mut wrapped_if_stake,
+        &mut other_user_stats,
+        &mut spot_market,
+        share_token_supply,
+        0,
+    )
+    .unwrap();
+    assert_eq!(if_shares, 50 * QUOTE_PRECISION);
+    assert_eq!(other_if_stake.unchecked_if_shares(), 50 * QUOTE_PRECISION);
+    assert_eq!(wrapped_if_stake.unchecked_if_shares(), 0);
+    assert_eq!(
+        other_user_stats.if_staked_quote_asset_amount,
+        (5 * QUOTE_PRECISION) as u64
+    );
+    assert_eq!(
+        user_stats.if_staked_quote_asset_amount,
+        (5 * QUOTE_PRECISION) as u64
+    );
+
+    // locked stakes can't be wrapped
+    if_stake.lockup_tier = InsuranceFundLockupTier::NinetyDays;
+    assert!(wrap_insurance_fund_stake(
+        QUOTE_PRECISION,
+        if_balance,
+        &mut if_stake,
+        &mut wrapped_if_stake,
+        &mut user_stats,
+        &mut spot_market,
+        0,
+        0,
+    )
+    .is_err());
+}
@@ -0,0 +1,277 @@
+use std::ops::DerefMut;
+
+use anchor_lang::prelude::*;
+
+use crate::controller::amm::update_pnl_pool_and_user_balance;
+use crate::controller::funding::settle_funding_payment;
+use crate::controller::orders::validate_market_within_price_band;
+use crate::controller::pnl::calculate_max_pnl_pool_excess;
+use crate::controller::position::{get_position_index, update_quote_asset_amount};
+use crate::controller::spot_balance::update_spot_market_cumulative_interest;
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::QUOTE_SPOT_MARKET_INDEX;
+use crate::math::margin::{meets_withdraw_margin_requirement, MarginRequirementType};
+use crate::math::oracle::{is_oracle_valid_for_action, DriftAction};
+use crate::math::safe_math::SafeMath;
+use crate::state::events::IsolatedPerpPositionTransferRecord;
+use crate::state::oracle_map::OracleMap;
+use crate::state::paused_operations::PerpOperation;
+use crate::state::perp_market::{MarketStatus, PerpMarket};
+use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+use crate::state::spot_market_map::SpotMarketMap;
+use crate::state::state::State;
+use crate::state::user::{MarketType, User};
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+/// Moves quote collateral between the user's cross margin account and the isolated perp position
+/// in market_index. A positive amount deposits into the isolated position, a negative amount
+/// withdraws from it
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_isolated_perp_position_deposit(
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    state: &State,
+    market_index: u16,
+    amount: i64,
+    now: i64,
+) -> DriftResult {
+    validate!(
+        amount != 0,
+        ErrorCode::InvalidIsolatedPerpPosition,
+        "amount must not be zero"
+    )?;
+
+    validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
+
+    validate!(
+        perp_market_map
+            .get_ref(&market_index)?
+            .quote_spot_market_index
+            == QUOTE_SPOT_MARKET_INDEX,
+        ErrorCode::InvalidIsolatedPerpPosition,
+        "perp market {} must be quoted in the quote spot market",
+        market_index
+    )?;
+
+    {
+        let quote_spot_market = &mut spot_market_map.get_quote_spot_market_mut()?;
+        let oracle_price_data = oracle_map.get_price_data(quote_spot_market.oracle_id())?;
+        update_spot_market_cumulative_interest(quote_spot_market, Some(oracle_price_data), now)?;
+    }
+
+    settle_funding_payment(
+        user,
+        user_key,
+        perp_market_map.get_ref_mut(&market_index)?.deref_mut(),
+        now,
+    )?;
+
+    let base_asset_amount = user
+        .get_perp_position(market_index)
+        .map_or(0, |position| position.base_asset_amount);
+    let (oracle_price, max_pnl_pool_excess) = validate_market_for_pnl_pool_transfer(
+        &perp_market_map.get_ref(&market_index)?,
+        &spot_market_map.get_quote_spot_market()?,
+        oracle_map,
+        state,
+        base_asset_amount,
+    )?;
+
+    let position_index = if amount > 0 {
+        validate!(
+            !user.is_being_liquidated(),
+            ErrorCode::UserIsBeingLiquidated,
+            "cant deposit into isolated perp position while being liquidated"
+        )?;
+
+        let position_index = get_position_index(&user.perp_positions, market_index)
+            .or_else(|_| user.add_perp_position(market_index))?;
+
+        if !user.is_isolated_perp_position(position_index) {
+            let position = &user.perp_positions[position_index];
+            validate!(
+                !position.is_open_position() && !position.has_open_order() && !position.is_lp(),
+                ErrorCode::InvalidIsolatedPerpPosition,
+                "perp position in market {} must be empty to become isolated",
+                market_index
+            )?;
+
+            user.update_isolated_perp_position(position_index, true);
+        }
+
+        let quote_spot_market = &mut spot_market_map.get_quote_spot_market_mut()?;
+        let quote_spot_position = user.get_quote_spot_position();
+        let quote_token_amount = if quote_spot_position.balance_type == SpotBalanceType::Deposit {
+            quote_spot_position.get_token_amount(quote_spot_market)?
+        } else {
+            0
+        };
+
+        validate!(
+            quote_token_amount >= amount.unsigned_abs().cast()?,
+            ErrorCode::InsufficientDeposit,
+            "quote deposit {} less than amount {}",
+            quote_token_amount,
+            amount
+        )?;
+
+        let mut perp_market = perp_market_map.get_ref_mut(&market_index)?;
+        update_pnl_pool_and_user_balance(
+            &mut perp_market,
+            quote_spot_market,
+            user,
+            -amount.cast::<i128>()?,
+        )?;
+        update_quote_asset_amount(
+            &mut user.perp_positions[position_index],
+            &mut perp_market,
+            amount,
+        )?;
+
+        position_index
+    } else {
+        let position_index = get_position_index(&user.perp_positions, market_index)?;
+
+        validate!(
+            user.is_isolated_perp_position(position_index),
+            ErrorCode::InvalidIsolatedPerpPosition,
+            "perp position in market {} is not isolated",
+            market_index
+        )?;
+
+        let position = &user.perp_positions[position_index];
+        let isolated_collateral = position
+            .quote_asset_amount
+            .safe_sub(position.quote_entry_amount)?;
+
+        validate!(
+            amount.unsigned_abs().cast::<i64>()? <= isolated_collateral,
+            ErrorCode::InsufficientCollateral,
+            "withdraw amount {} greater than isolated collateral {}",
+            amount.unsigned_abs(),
+            isolated_collateral
+        )?;
+
+        let claimable_pnl = position.get_claimable_pnl(oracle_price, max_pnl_pool_excess)?;
+        validate!(
+            amount.unsigned_abs().cast::<i128>()? <= claimable_pnl,
+            ErrorCode::PnlPoolCantSettleUser,
+            "withdraw amount {} greater than claimable pnl {}",
+            amount.unsigned_abs(),
+            claimable_pnl
+        )?;
+
+        let quote_spot_market = &mut spot_market_map.get_quote_spot_market_mut()?;
+        let mut perp_market = perp_market_map.get_ref_mut(&market_index)?;
+        update_pnl_pool_and_user_balance(
+            &mut perp_market,
+            quote_spot_market,
+            user,
+            amount.unsigned_abs().cast::<i128>()?,
+        )?;
+        update_quote_asset_amount(
+            &mut user.perp_positions[position_index],
+            &mut perp_market,
+            amount,
+        )?;
+
+        if user.perp_positions[position_index].is_available() {
+            user.update_isolated_perp_position(position_index, false);
+        }
+
+        position_index
+    };
+
+    meets_withdraw_margin_requirement(
+        user,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        MarginRequirementType::Initial,
+    )?;
+
+    emit!(IsolatedPerpPositionTransferRecord {
+        ts: now,
+        user: *user_key,
+        perp_market_index: market_index,
+        amount,
+        quote_asset_amount_after: user.perp_positions[position_index].quote_asset_amount,
+    });
+
+    Ok(())
+}
+
+/// Isolated collateral moves between the user's quote deposit and the market's pnl pool like
+/// settled pnl, so the market must pass the same checks as settle_pnl.
+/// Returns the oracle price and the pnl pool excess
+fn validate_market_for_pnl_pool_transfer(
+    perp_market: &PerpMarket,
+    quote_spot_market: &SpotMarket,
+    oracle_map: &mut OracleMap,
+    state: &State,
+    base_asset_amount: i64,
+) -> DriftResult<(i64, i128)> {
+    let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
+        MarketType::Perp,
+        perp_market.market_index,
+        perp_market.oracle_id(),
+        perp_market
+            .amm
+            .historical_oracle_data
+            .last_oracle_price_twap,
+        perp_market.get_max_confidence_interval_multiplier()?,
+        perp_market.get_validity_guard_rails_override(),
+    )?;
+    let oracle_price = perp_market.get_bounded_oracle_price(oracle_price_data.price);
+
+    validate_market_within_price_band(perp_market, state, oracle_price)?;
+
+    validate!(
+        is_oracle_valid_for_action(oracle_validity, Some(DriftAction::SettlePnl))?
+            && perp_market.is_price_divergence_ok_for_settle_pnl(oracle_price)?,
+        oracle_validity.get_error_code(),
+        "oracle invalid ({}) to settle pnl for market {}",
+        oracle_validity,
+        perp_market.market_index
+    )?;
+
+    validate!(
+        !perp_market.is_operation_paused(PerpOperation::SettlePnl),
+        ErrorCode::InvalidMarketStatusToSettlePnl,
+        "settle pnl paused for market {}",
+        perp_market.market_index
+    )?;
+
+    if base_asset_amount != 0 {
+        validate!(
+            !perp_market.is_operation_paused(PerpOperation::SettlePnlWithPosition)
+                && perp_market.status == MarketStatus::Active,
+            ErrorCode::InvalidMarketStatusToSettlePnl,
+            "cant settle pnl with position under current market {} status",
+            perp_market.market_index
+        )?;
+    } else {
+        validate!(
+            matches!(
+                perp_market.status,
+                MarketStatus::Active | MarketStatus::ReduceOnly
+            ),
+            ErrorCode::InvalidMarketStatusToSettlePnl,
+            "cant settle pnl under current market {} status",
+            perp_market.market_index
+        )?;
+    }
+
+    let max_pnl_pool_excess =
+        calculate_max_pnl_pool_excess(perp_market, quote_spot_market, oracle_price)?;
+
+    Ok((oracle_price, max_pnl_pool_excess))
+}
@@ -0,0 +1,279 @@
+use std::str::FromStr;
+
+use anchor_lang::Owner;
+use solana_program::pubkey::Pubkey;
+
+use crate::controller::isolated_position::transfer_isolated_perp_position_deposit;
+use crate::error::ErrorCode;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, BASE_PRECISION_I128, BASE_PRECISION_I64, LIQUIDATION_FEE_PRECISION,
+    PEG_PRECISION, QUOTE_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64,
+    QUOTE_SPOT_MARKET_INDEX, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+    SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+};
+use crate::state::oracle::{HistoricalOracleData, OracleSource};
+use crate::state::oracle_map::OracleMap;
+use crate::state::paused_operations::PerpOperation;
+use crate::state::perp_market::{MarketStatus, PerpMarket, PoolBalance, AMM};
+use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+use crate::state::spot_market_map::SpotMarketMap;
+use crate::state::state::State;
+use crate::state::user::{PerpPosition, SpotPosition, User};
+use crate::test_utils::*;
+use crate::test_utils::{get_positions, get_pyth_price, get_spot_positions};
+use crate::{create_account_info, create_anchor_account_info};
+
+#[test]
+pub fn deposit_and_withdraw_isolated_perp_position() {
+    let slot = 0;
+    let now = 0;
+
+    let mut oracle_price = get_pyth_price(100, 6);
+    let oracle_price_key =
+        Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+    let pyth_program = crate::ids::pyth_program::id();
+    create_account_info!(
+        oracle_price,
+        &oracle_price_key,
+        &pyth_program,
+        oracle_account_info
+    );
+    let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+    let state = State::default();
+
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+            bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+            ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+            ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            order_step_size: 10000000,
+            oracle: oracle_price_key,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price: oracle_price.agg.price,
+                last_oracle_price_twap_5min: oracle_price.agg.price,
+                last_oracle_price_twap: oracle_price.agg.price,
+                ..HistoricalOracleData::default()
+            },
+            ..AMM::default()
+        },
+        margin_ratio_initial: 1000,
+        margin_ratio_maintenance: 500,
+        status: MarketStatus::Active,
+        liquidator_fee: LIQUIDATION_FEE_PRECISION / 100,
+        pnl_pool: PoolBalance {
+            scaled_balance: (50 * SPOT_BALANCE_PRECISION),
+            market_index: QUOTE_SPOT_MARKET_INDEX,
+            ..PoolBalance::default()
+        },
+        ..PerpMarket::default()
+    };
+    create_anchor_account_info!(market, PerpMarket, market_account_info);
+    let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+    let mut spot_market = SpotMarket {
+        market_index: 0,
+        oracle_source: OracleSource::QuoteAsset,
+        cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+        decimals: 6,
+        initial_asset_weight: SPOT_WEIGHT_PRECISION,
+        maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+        deposit_balance: 150 * SPOT_BALANCE_PRECISION,
+        historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+        ..SpotMarket::default()
+    };
+    create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+    let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+    let mut user = User {
+        perp_positions: [PerpPosition::default(); 8],
+        spot_positions: get_spot_positions(SpotPosition {
+            market_index: 0,
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        }),
+        ..User::default()
+    };
+    let user_key = Pubkey::default();
+
+    transfer_isolated_perp_position_deposit(
+        &mut user,
+        &user_key,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &state,
+        0,
+        40 * QUOTE_PRECISION_I64,
+        now,
+    )
+    .unwrap();
+
+    assert!(user.is_isolated_perp_market(0));
+    assert_eq!(
+        user.get_perp_position(0).unwrap().quote_asset_amount,
+        40 * QUOTE_PRECISION_I64
+    );
+    assert_eq!(
+        user.get_quote_spot_position()
+            .get_token_amount(&spot_market_map.get_ref(&0).unwrap())
+            .unwrap(),
+        60 * QUOTE_PRECISION
+    );
+    assert_eq!(
+        market_map.get_ref(&0).unwrap().amm.quote_asset_amount,
+        40 * QUOTE_PRECISION_I128
+    );
+
+    // cant withdraw more than the isolated collateral
+    let result = transfer_isolated_perp_position_deposit(
+        &mut user,
+        &user_key,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &state,
+        0,
+        -41 * QUOTE_PRECISION_I64,
+        now,
+    );
+    assert_eq!(result, Err(ErrorCode::InsufficientCollateral));
+
+    // cant withdraw while settle pnl is paused
+    market_map.get_ref_mut(&0).unwrap().paused_operations = PerpOperation::SettlePnl as u8;
+    let result = transfer_isolated_perp_position_deposit(
+        &mut user,
+        &user_key,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &state,
+        0,
+        -40 * QUOTE_PRECISION_I64,
+        now,
+    );
+    assert_eq!(result, Err(ErrorCode::InvalidMarketStatusToSettlePnl));
+    market_map.get_ref_mut(&0).unwrap().paused_operations = 0;
+
+    transfer_isolated_perp_position_deposit(
+        &mut user,
+        &user_key,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &state,
+        0,
+        -40 * QUOTE_PRECISION_I64,
+        now,
+    )
+    .unwrap();
+
+    assert!(user.perp_positions[0].is_available());
+    assert_eq!(user.isolated_perp_positions, 0);
+    assert_eq!(
+        user.get_quote_spot_position()
+            .get_token_amount(&spot_market_map.get_ref(&0).unwrap())
+            .unwrap(),
+        100 * QUOTE_PRECISION
+    );
+    assert_eq!(market_map.get_ref(&0).unwrap().amm.quote_asset_amount, 0);
+}
+
+#[test]
+pub fn cant_isolate_open_cross_position() {
+    let slot = 0;
+    let now = 0;
+
+    let mut oracle_price = get_pyth_price(100, 6);
+    let oracle_price_key =
+        Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+    let pyth_program = crate::ids::pyth_program::id();
+    create_account_info!(
+        oracle_price,
+        &oracle_price_key,
+        &pyth_program,
+        oracle_account_info
+    );
+    let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+    let state = State::default();
+
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+            sqrt_k: 100 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 100 * PEG_PRECISION,
+            order_step_size: 10000000,
+            base_asset_amount_with_amm: BASE_PRECISION_I128,
+            base_asset_amount_long: BASE_PRECISION_I128,
+            oracle: oracle_price_key,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price: oracle_price.agg.price,
+                last_oracle_price_twap_5min: oracle_price.agg.price,
+                last_oracle_price_twap: oracle_price.agg.price,
+                ..HistoricalOracleData::default()
+            },
+            ..AMM::default()
+        },
+        margin_ratio_initial: 1000,
+        margin_ratio_maintenance: 500,
+        number_of_users_with_base: 1,
+        status: MarketStatus::Active,
+        ..PerpMarket::default()
+    };
+    create_anchor_account_info!(market, PerpMarket, market_account_info);
+    let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+    let mut spot_market = SpotMarket {
+        market_index: 0,
+        oracle_source: OracleSource::QuoteAsset,
+        cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+        decimals: 6,
+        initial_asset_weight: SPOT_WEIGHT_PRECISION,
+        maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+        deposit_balance: 100 * SPOT_BALANCE_PRECISION,
+        historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+        ..SpotMarket::default()
+    };
+    create_anchor_account_info!(spot_market, SpotMarket, spot_market_account_info);
+    let spot_market_map = SpotMarketMap::load_one(&spot_market_account_info, true).unwrap();
+
+    let mut user = User {
+        perp_positions: get_positions(PerpPosition {
+            market_index: 0,
+            base_asset_amount: BASE_PRECISION_I64,
+            quote_asset_amount: -100 * QUOTE_PRECISION_I64,
+            quote_entry_amount: -100 * QUOTE_PRECISION_I64,
+            ..PerpPosition::default()
+        }),
+        spot_positions: get_spot_positions(SpotPosition {
+            market_index: 0,
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: 100 * SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        }),
+        ..User::default()
+    };
+    let user_key = Pubkey::default();
+
+    let result = transfer_isolated_perp_position_deposit(
+        &mut user,
+        &user_key,
+        &market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        &state,
+        0,
+        10 * QUOTE_PRECISION_I64,
+        now,
+    );
+
+    assert_eq!(result, Err(ErrorCode::InvalidIsolatedPerpPosition));
+    assert!(!user.is_isolated_perp_market(0));
+}
@@ -19,7 +19,7 @@ use crate::controller::spot_balance::{
 use crate::controller::spot_position::update_spot_balances_and_cumulative_deposits;
 use crate::error::{DriftResult, ErrorCode};
 use crate::get_then_update_id;
-use crate::math::bankruptcy::is_user_bankrupt;
+use crate::math::bankruptcy::{is_isolated_perp_position_bankrupt, is_user_bankrupt};
 use crate::math::casting::Cast;
 use crate::math::constants::{
     LIQUIDATION_FEE_PRECISION_U128, LIQUIDATION_PCT_PRECISION, QUOTE_PRECISION,
@@ -128,16 +128,32 @@ pub fn liquidate_perp(
         now,
     )?;
 
+    // isolated positions are liquidated on their own collateral and never put the
+    // cross margin account into liquidation
+    let is_isolated_position = user.is_isolated_perp_market(market_index);
+
+    let margin_context = if is_isolated_position {
+        MarginContext::liquidation(liquidation_margin_buffer_ratio)
+            .isolated_perp_position(market_index)
+    } else {
+        MarginContext::liquidation(liquidation_margin_buffer_ratio)
+    }
+    .track_market_margin_requirement(MarketIdentifier::perp(market_index))?;
+
     let margin_calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
         user,
         perp_market_map,
         spot_market_map,
         oracle_map,
-        MarginContext::liquidation(liquidation_margin_buffer_ratio)
-            .track_market_margin_requirement(MarketIdentifier::perp(market_index))?,
+        margin_context,
     )?;
 
-    if !user.is_being_liquidated() && margin_calculation.meets_margin_requirement() {
+    if is_isolated_position {
+        if margin_calculation.meets_margin_requirement() {
+            msg!("margin calculation: {:?}", margin_calculation);
+            return Err(ErrorCode::SufficientCollateral);
+        }
+    } else if !user.is_being_liquidated() && margin_calculation.meets_margin_requirement() {
         msg!("margin calculation: {:?}", margin_calculation);
         return Err(ErrorCode::SufficientCollateral);
     } else if user.is_being_liquidated() && margin_calculation.can_exit_liquidation()? {
@@ -163,7 +179,13 @@ pub fn liquidate_perp(
             e
         })?;
 
-    let liquidation_id = user.enter_liquidation(slot)?;
+    let liquidation_id = if !is_isolated_position {
+        user.enter_liquidation(slot)?
+    } else if user.is_being_liquidated() {
+        user.next_liquidation_id.safe_sub(1)?
+    } else {
+        get_then_update_id!(user, next_liquidation_id)
+    };
     let mut margin_freed = 0_u64;
 
     let position_index = get_position_index(&user.perp_positions, market_index)?;
@@ -174,6 +196,12 @@ pub fn liquidate_perp(
         ErrorCode::PositionDoesntHaveOpenPositionOrOrders
     )?;
 
+    let (cancel_market_type, cancel_market_index) = if is_isolated_position {
+        (Some(MarketType::Perp), Some(market_index))
+    } else {
+        (None, None)
+    };
+
     let canceled_order_ids = orders::cancel_orders(
         user,
         user_key,
@@ -184,13 +212,13 @@ pub fn liquidate_perp(
         now,
         slot,
         OrderActionExplanation::Liquidation,
-        None,
-        None,
+        cancel_market_type,
+        cancel_market_index,
         None,
     )?;
 
     let mut market = perp_market_map.get_ref_mut(&market_index)?;
-    let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+    let oracle_price_data = oracle_map.get_price_data(market.oracle_id())?;
 
     update_amm_and_check_validity(
         &mut market,
@@ -204,7 +232,7 @@ pub fn liquidate_perp(
     let oracle_price = if market.status == MarketStatus::Settlement {
         market.expiry_price
     } else {
-        oracle_price_data.price
+        market.get_bounded_oracle_price(oracle_price_data.price)
     };
 
     drop(market);
@@ -240,8 +268,7 @@ pub fn liquidate_perp(
                 perp_market_map,
                 spot_market_map,
                 oracle_map,
-                MarginContext::liquidation(liquidation_margin_buffer_ratio)
-                    .track_market_margin_requirement(MarketIdentifier::perp(market_index))?,
+                margin_context,
             )?;
 
         let initial_margin_shortage = margin_calculation.margin_shortage()?;
@@ -250,9 +277,15 @@ pub fn liquidate_perp(
         margin_freed = initial_margin_shortage
             .saturating_sub(new_margin_shortage)
             .cast::<u64>()?;
-        user.increment_margin_freed(margin_freed)?;
 
-        if intermediate_margin_calculation.can_exit_liquidation()? {
+        let can_exit_liquidation = if is_isolated_position {
+            intermediate_margin_calculation.meets_margin_requirement()
+        } else {
+            user.increment_margin_freed(margin_freed)?;
+            intermediate_margin_calculation.can_exit_liquidation()?
+        };
+
+        if can_exit_liquidation {
             emit!(LiquidationRecord {
                 ts: now,
                 liquidation_id,
@@ -273,7 +306,9 @@ pub fn liquidate_perp(
                 ..LiquidationRecord::default()
             });
 
-            user.exit_liquidation();
+            if !is_isolated_position {
+                user.exit_liquidation();
+            }
             return Ok(());
         }
 
@@ -331,7 +366,9 @@ pub fn liquidate_perp(
 
     let market = perp_market_map.get_ref(&market_index)?;
     let quote_spot_market = spot_market_map.get_ref(&market.quote_spot_market_index)?;
-    let quote_oracle_price = oracle_map.get_price_data(&quote_spot_market.oracle)?.price;
+    let quote_oracle_price = oracle_map
+        .get_price_data(quote_spot_market.oracle_id())?
+        .price;
     let liquidator_fee = market.liquidator_fee;
     let if_liquidation_fee = calculate_perp_if_fee(
         intermediate_margin_calculation.tracked_market_margin_shortage(margin_shortage)?,
@@ -356,13 +393,17 @@ pub fn liquidate_perp(
     drop(market);
     drop(quote_spot_market);
 
-    let max_pct_allowed = calculate_max_pct_to_liquidate(
-        user,
-        margin_shortage,
-        slot,
-        initial_pct_to_liquidate,
-        liquidation_duration,
-    )?;
+    let max_pct_allowed = if is_isolated_position {
+        LIQUIDATION_PCT_PRECISION
+    } else {
+        calculate_max_pct_to_liquidate(
+            user,
+            margin_shortage,
+            slot,
+            initial_pct_to_liquidate,
+            liquidation_duration,
+        )?
+    };
     let max_base_asset_amount_allowed_to_be_transferred =
         base_asset_amount_to_cover_margin_shortage
             .cast::<u128>()?
@@ -510,21 +551,36 @@ pub fn liquidate_perp(
         )
     };
 
-    let margin_freed_for_perp_position = calculate_margin_freed(
-        user,
-        perp_market_map,
-        spot_market_map,
-        oracle_map,
-        liquidation_margin_buffer_ratio,
-        margin_shortage,
-    )?;
-    margin_freed = margin_freed.safe_add(margin_freed_for_perp_position)?;
-    user.increment_margin_freed(margin_freed_for_perp_position)?;
+    if is_isolated_position {
+        let margin_calculation_after =
+            calculate_margin_requirement_and_total_collateral_and_liability_info(
+                user,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                margin_context,
+            )?;
+        let margin_freed_for_perp_position = margin_shortage
+            .saturating_sub(margin_calculation_after.margin_shortage()?)
+            .cast::<u64>()?;
+        margin_freed = margin_freed.safe_add(margin_freed_for_perp_position)?;
+    } else {
+        let margin_freed_for_perp_position = calculate_margin_freed(
+            user,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            liquidation_margin_buffer_ratio,
+            margin_shortage,
+        )?;
+        margin_freed = margin_freed.safe_add(margin_freed_for_perp_position)?;
+        user.increment_margin_freed(margin_freed_for_perp_position)?;
 
-    if base_asset_amount >= base_asset_amount_to_cover_margin_shortage {
-        user.exit_liquidation();
-    } else if is_user_bankrupt(user) {
-        user.enter_bankruptcy();
+        if base_asset_amount >= base_asset_amount_to_cover_margin_shortage {
+            user.exit_liquidation();
+        } else if is_user_bankrupt(user) {
+            user.enter_bankruptcy();
+        }
     }
 
     let liquidator_meets_initial_margin_requirement =
@@ -617,6 +673,7 @@ pub fn liquidate_perp(
         taker_order_base_asset_amount: Some(base_asset_amount),
         taker_order_cumulative_base_asset_amount_filled: Some(base_asset_amount),
         taker_order_cumulative_quote_asset_amount_filled: Some(base_asset_value),
+        taker_order_trigger_price: None,
         maker: Some(*liquidator_key),
         maker_order_id: Some(liquidator_order_id),
         maker_order_direction: Some(user_existing_position_direction),
@@ -635,7 +692,7 @@ pub fn liquidate_perp(
         liquidator: *liquidator_key,
         margin_requirement: margin_calculation.margin_requirement,
         total_collateral: margin_calculation.total_collateral,
-        bankrupt: user.is_bankrupt(),
+        bankrupt: user.is_bankrupt() || is_isolated_perp_position_bankrupt(user, position_index),
         canceled_order_ids,
         margin_freed,
         liquidate_perp: LiquidatePerpRecord {
@@ -747,7 +804,7 @@ pub fn liquidate_spot(
     let (asset_amount, asset_price, asset_decimals, asset_weight, asset_liquidation_multiplier) = {
         let mut asset_market = spot_market_map.get_ref_mut(&asset_market_index)?;
         let (asset_price_data, validity_guard_rails) =
-            oracle_map.get_price_data_and_guard_rails(&asset_market.oracle)?;
+            oracle_map.get_price_data_and_guard_rails(asset_market.oracle_id())?;
 
         update_spot_market_and_check_validity(
             &mut asset_market,
@@ -796,7 +853,7 @@ pub fn liquidate_spot(
     ) = {
         let mut liability_market = spot_market_map.get_ref_mut(&liability_market_index)?;
         let (liability_price_data, validity_guard_rails) =
-            oracle_map.get_price_data_and_guard_rails(&liability_market.oracle)?;
+            oracle_map.get_price_data_and_guard_rails(liability_market.oracle_id())?;
 
         update_spot_market_and_check_validity(
             &mut liability_market,
@@ -1231,6 +1288,13 @@ pub fn liquidate_borrow_for_perp_pnl(
         "liquidator bankrupt",
     )?;
 
+    validate!(
+        !user.is_isolated_perp_market(perp_market_index),
+        ErrorCode::InvalidIsolatedPerpPosition,
+        "isolated perp position in market {} cant be liquidated for its pnl",
+        perp_market_index
+    )?;
+
     let perp_market = perp_market_map.get_ref(&perp_market_index)?;
 
     validate!(
@@ -1327,7 +1391,9 @@ pub fn liquidate_borrow_for_perp_pnl(
         let market = perp_market_map.get_ref(&perp_market_index)?;
 
         let quote_spot_market = spot_market_map.get_ref(&market.quote_spot_market_index)?;
-        let quote_price = oracle_map.get_price_data(&quote_spot_market.oracle)?.price;
+        let quote_price = oracle_map
+            .get_price_data(quote_spot_market.oracle_id())?
+            .price;
 
         let pnl_asset_weight =
             market.get_unrealized_asset_weight(pnl, MarginRequirementType::Maintenance)?;
@@ -1353,7 +1419,7 @@ pub fn liquidate_borrow_for_perp_pnl(
     ) = {
         let mut liability_market = spot_market_map.get_ref_mut(&liability_market_index)?;
         let (liability_price_data, validity_guard_rails) =
-            oracle_map.get_price_data_and_guard_rails(&liability_market.oracle)?;
+            oracle_map.get_price_data_and_guard_rails(liability_market.oracle_id())?;
 
         update_spot_market_and_check_validity(
             &mut liability_market,
@@ -1447,7 +1513,8 @@ pub fn liquidate_borrow_for_perp_pnl(
 
         if intermediate_margin_calculation.can_exit_liquidation()? {
             let market = perp_market_map.get_ref(&perp_market_index)?;
-            let market_oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
+            let market_oracle_price = market
+                .get_bounded_oracle_price(oracle_map.get_price_data(market.oracle_id())?.price);
 
             emit!(LiquidationRecord {
                 ts: now,
@@ -1638,7 +1705,7 @@ pub fn liquidate_borrow_for_perp_pnl(
 
     let market_oracle_price = {
         let market = perp_market_map.get_ref_mut(&perp_market_index)?;
-        oracle_map.get_price_data(&market.amm.oracle)?.price
+        market.get_bounded_oracle_price(oracle_map.get_price_data(market.oracle_id())?.price)
     };
 
     emit!(LiquidationRecord {
@@ -1700,6 +1767,13 @@ pub fn liquidate_perp_pnl_for_deposit(
         "liquidator bankrupt",
     )?;
 
+    validate!(
+        !user.is_isolated_perp_market(perp_market_index),
+        ErrorCode::InvalidIsolatedPerpPosition,
+        "isolated perp position in market {} cant be liquidated for its pnl",
+        perp_market_index
+    )?;
+
     let asset_spot_market = spot_market_map.get_ref(&asset_market_index)?;
 
     validate!(
@@ -1776,7 +1850,7 @@ pub fn liquidate_perp_pnl_for_deposit(
     ) = {
         let mut asset_market = spot_market_map.get_ref_mut(&asset_market_index)?;
         let (asset_price_data, validity_guard_rails) =
-            oracle_map.get_price_data_and_guard_rails(&asset_market.oracle)?;
+            oracle_map.get_price_data_and_guard_rails(asset_market.oracle_id())?;
 
         update_spot_market_and_check_validity(
             &mut asset_market,
@@ -1853,7 +1927,9 @@ pub fn liquidate_perp_pnl_for_deposit(
         let market = perp_market_map.get_ref(&perp_market_index)?;
 
         let quote_spot_market = spot_market_map.get_ref(&market.quote_spot_market_index)?;
-        let quote_price = oracle_map.get_price_data(&quote_spot_market.oracle)?.price;
+        let quote_price = oracle_map
+            .get_price_data(quote_spot_market.oracle_id())?
+            .price;
 
         (
             unsettled_pnl.unsigned_abs(),
@@ -1930,7 +2006,8 @@ pub fn liquidate_perp_pnl_for_deposit(
 
         if exiting_liq_territory || is_contract_tier_violation {
             let market = perp_market_map.get_ref(&perp_market_index)?;
-            let market_oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
+            let market_oracle_price = market
+                .get_bounded_oracle_price(oracle_map.get_price_data(market.oracle_id())?.price);
 
             emit!(LiquidationRecord {
                 ts: now,
@@ -2133,7 +2210,7 @@ pub fn liquidate_perp_pnl_for_deposit(
 
     let market_oracle_price = {
         let market = perp_market_map.get_ref_mut(&perp_market_index)?;
-        oracle_map.get_price_data(&market.amm.oracle)?.price
+        market.get_bounded_oracle_price(oracle_map.get_price_data(market.oracle_id())?.price)
     };
 
     emit!(LiquidationRecord {
@@ -2172,15 +2249,26 @@ pub fn resolve_perp_bankruptcy(
     now: i64,
     insurance_fund_vault_balance: u64,
 ) -> DriftResult<u64> {
-    if !user.is_bankrupt() && is_user_bankrupt(user) {
-        user.enter_bankruptcy();
-    }
+    let is_isolated_position = user.is_isolated_perp_market(market_index);
 
-    validate!(
-        user.is_bankrupt(),
-        ErrorCode::UserNotBankrupt,
-        "user not bankrupt",
-    )?;
+    if is_isolated_position {
+        let position_index = get_position_index(&user.perp_positions, market_index)?;
+        validate!(
+            is_isolated_perp_position_bankrupt(user, position_index),
+            ErrorCode::UserNotBankrupt,
+            "isolated perp position not bankrupt",
+        )?;
+    } else {
+        if !user.is_bankrupt() && is_user_bankrupt(user) {
+            user.enter_bankruptcy();
+        }
+
+        validate!(
+            user.is_bankrupt(),
+            ErrorCode::UserNotBankrupt,
+            "user not bankrupt",
+        )?;
+    }
 
     validate!(
         !liquidator.is_being_liquidated(),
@@ -2224,6 +2312,13 @@ pub fn resolve_perp_bankruptcy(
         "user must have negative pnl"
     )?;
 
+    let margin_context = if is_isolated_position {
+        MarginContext::standard(MarginRequirementType::Maintenance)
+            .isolated_perp_position(market_index)
+    } else {
+        MarginContext::standard(MarginRequirementType::Maintenance)
+    };
+
     let MarginCalculation {
         margin_requirement,
         total_collateral,
@@ -2233,7 +2328,7 @@ pub fn resolve_perp_bankruptcy(
         perp_market_map,
         spot_market_map,
         oracle_map,
-        MarginContext::standard(MarginRequirementType::Maintenance),
+        margin_context,
     )?;
 
     // spot market's insurance fund draw attempt here (before social loss)
@@ -2259,7 +2354,7 @@ pub fn resolve_perp_bankruptcy(
 
         // move if payment to pnl pool
         let spot_market = &mut spot_market_map.get_ref_mut(&QUOTE_SPOT_MARKET_INDEX)?;
-        let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+        let oracle_price_data = oracle_map.get_price_data(spot_market.oracle_id())?;
         update_spot_market_cumulative_interest(spot_market, Some(oracle_price_data), now)?;
 
         update_spot_balances(
@@ -2353,10 +2448,14 @@ pub fn resolve_perp_bankruptcy(
         )?;
 
         user.increment_total_socialized_loss(quote_asset_amount.unsigned_abs())?;
+
+        if is_isolated_position && user.perp_positions[position_index].is_available() {
+            user.update_isolated_perp_position(position_index, false);
+        }
     }
 
     // exit bankruptcy
-    if !is_user_bankrupt(user) {
+    if !is_isolated_position && !is_user_bankrupt(user) {
         user.exit_bankruptcy();
     }
 
@@ -2480,7 +2579,7 @@ pub fn resolve_spot_bankruptcy(
 
     {
         let mut spot_market = spot_market_map.get_ref_mut(&market_index)?;
-        let oracle_price_data = &oracle_map.get_price_data(&spot_market.oracle)?;
+        let oracle_price_data = &oracle_map.get_price_data(spot_market.oracle_id())?;
         let quote_social_loss = get_token_value(
             -borrow_amount.cast()?,
             spot_market.decimals,
@@ -1,8 +1,10 @@
 pub mod amm;
 pub mod funding;
 pub mod insurance;
+pub mod isolated_position;
 pub mod liquidation;
 pub mod lp;
+pub mod oracle_circuit_breaker;
 pub mod orders;
 pub mod pda;
 pub mod pnl;
@@ -11,3 +13,4 @@ pub mod repeg;
 pub mod spot_balance;
 pub mod spot_position;
 pub mod token;
+pub mod twap_order;
@@ -0,0 +1,168 @@
+use anchor_lang::prelude::*;
+use solana_program::msg;
+
+use crate::error::DriftResult;
+use crate::math::casting::Cast;
+use crate::math::oracle::{is_oracle_valid_for_action, OracleValidity};
+use crate::state::events::{OracleCircuitBreakerAction, OracleCircuitBreakerRecord};
+use crate::state::oracle_circuit_breaker::OracleCircuitBreaker;
+use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market::PerpMarket;
+use crate::state::spot_market::SpotMarket;
+use crate::state::user::MarketType;
+
+#[cfg(test)]
+mod tests;
+
+pub fn update_perp_market_oracle_circuit_breaker(
+    perp_market: &mut PerpMarket,
+    circuit_breaker: &mut OracleCircuitBreaker,
+    oracle_map: &mut OracleMap,
+    now: i64,
+) -> DriftResult {
+    let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
+        MarketType::Perp,
+        perp_market.market_index,
+        perp_market.oracle_id(),
+        perp_market
+            .amm
+            .historical_oracle_data
+            .last_oracle_price_twap,
+        perp_market.get_max_confidence_interval_multiplier()?,
+        perp_market.get_validity_guard_rails_override(),
+    )?;
+    let oracle_price = oracle_price_data.price;
+    let oracle_price_twap_5min = perp_market
+        .amm
+        .historical_oracle_data
+        .last_oracle_price_twap_5min;
+
+    update_oracle_circuit_breaker(
+        circuit_breaker,
+        &mut perp_market.paused_operations,
+        oracle_price,
+        oracle_price_twap_5min,
+        oracle_validity,
+        now,
+    )
+}
+
+pub fn update_spot_market_oracle_circuit_breaker(
+    spot_market: &mut SpotMarket,
+    circuit_breaker: &mut OracleCircuitBreaker,
+    oracle_map: &mut OracleMap,
+    now: i64,
+) -> DriftResult {
+    let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
+        MarketType::Spot,
+        spot_market.market_index,
+        spot_market.oracle_id(),
+        spot_market.historical_oracle_data.last_oracle_price_twap,
+        spot_market.get_max_confidence_interval_multiplier()?,
+        spot_market.get_validity_guard_rails_override(),
+    )?;
+    let oracle_price = oracle_price_data.price;
+    let oracle_price_twap_5min = spot_market
+        .historical_oracle_data
+        .last_oracle_price_twap_5min;
+
+    update_oracle_circuit_breaker(
+        circuit_breaker,
+        &mut spot_market.paused_operations,
+        oracle_price,
+        oracle_price_twap_5min,
+        oracle_validity,
+        now,
+    )
+}
+
+/// Trips the breaker if a valid oracle has moved too far from the 5 min oracle twap, pausing the
+/// market's operations, or resets it once the cooldown has ended. Updates the market's paused
+/// operations
+pub fn update_oracle_circuit_breaker(
+    circuit_breaker: &mut OracleCircuitBreaker,
+    market_paused_operations: &mut u8,
+    oracle_price: i64,
+    oracle_price_twap_5min: i64,
+    oracle_validity: OracleValidity,
+    now: i64,
+) -> DriftResult {
+    if circuit_breaker.is_tripped() {
+        if !circuit_breaker.can_reset(now) {
+            msg!(
+                "circuit breaker tripped until {}",
+                circuit_breaker.tripped_until_ts
+            );
+            return Ok(());
+        }
+
+        reset_oracle_circuit_breaker(circuit_breaker, market_paused_operations, oracle_price, now);
+    }
+
+    // an invalid oracle can't trip the breaker
+    if !is_oracle_valid_for_action(oracle_validity, None)? {
+        msg!(
+            "{} {} circuit breaker skipped, oracle validity {}",
+            circuit_breaker.market_type,
+            circuit_breaker.market_index,
+            oracle_validity
+        );
+        return Ok(());
+    }
+
+    if let Some(price_move) = circuit_breaker.update(oracle_price, oracle_price_twap_5min)? {
+        *market_paused_operations = circuit_breaker.trip(*market_paused_operations, now)?;
+
+        msg!(
+            "{} {} circuit breaker tripped, oracle moved {} from 5 min twap {} to {}",
+            circuit_breaker.market_type,
+            circuit_breaker.market_index,
+            price_move,
+            oracle_price_twap_5min,
+            oracle_price
+        );
+
+        emit!(OracleCircuitBreakerRecord {
+            ts: now,
+            market_index: circuit_breaker.market_index,
+            market_type: circuit_breaker.market_type,
+            action: OracleCircuitBreakerAction::Trip,
+            oracle_price,
+            oracle_price_twap_5min,
+            price_move: price_move.cast()?,
+            paused_operations: circuit_breaker.tripped_paused_operations,
+            tripped_until_ts: circuit_breaker.tripped_until_ts,
+        });
+    }
+
+    Ok(())
+}
+
+pub fn reset_oracle_circuit_breaker(
+    circuit_breaker: &mut OracleCircuitBreaker,
+    market_paused_operations: &mut u8,
+    oracle_price: i64,
+    now: i64,
+) {
+    let unpaused_operations = circuit_breaker.tripped_paused_operations;
+
+    *market_paused_operations = circuit_breaker.reset(*market_paused_operations);
+
+    msg!(
+        "{} {} circuit breaker reset",
+        circuit_breaker.market_type,
+        circuit_breaker.market_index
+    );
+
+    emit!(OracleCircuitBreakerRecord {
+        ts: now,
+        market_index: circuit_breaker.market_index,
+        market_type: circuit_breaker.market_type,
+        action: OracleCircuitBreakerAction::Reset,
+        oracle_price,
+        oracle_price_twap_5min: 0,
+        price_move: 0,
+        paused_operations: unpaused_operations,
+        tripped_until_ts: 0,
+    });
+}
@@ -0,0 +1,100 @@
+mod update_oracle_circuit_breaker {
+    use crate::controller::oracle_circuit_breaker::update_oracle_circuit_breaker;
+    use crate::math::constants::{PERCENTAGE_PRECISION, PRICE_PRECISION_I64};
+    use crate::math::oracle::OracleValidity;
+    use crate::state::oracle_circuit_breaker::OracleCircuitBreaker;
+    use crate::state::paused_operations::PerpOperation;
+
+    fn circuit_breaker() -> OracleCircuitBreaker {
+        OracleCircuitBreaker {
+            max_price_move: PERCENTAGE_PRECISION as u32 / 10, // 10%
+            cooldown: 600,
+            paused_operations: PerpOperation::AmmFill as u8 | PerpOperation::Fill as u8,
+            ..OracleCircuitBreaker::default()
+        }
+    }
+
+    #[test]
+    fn invalid_oracle_doesnt_trip() {
+        let mut circuit_breaker = circuit_breaker();
+        let mut market_paused_operations = 0;
+
+        for oracle_validity in [
+            OracleValidity::StaleForAMM,
+            OracleValidity::InsufficientDataPoints,
+            OracleValidity::TooUncertain,
+            OracleValidity::TooVolatile,
+        ] {
+            update_oracle_circuit_breaker(
+                &mut circuit_breaker,
+                &mut market_paused_operations,
+                50 * PRICE_PRECISION_I64,
+                100 * PRICE_PRECISION_I64,
+                oracle_validity,
+                0,
+            )
+            .unwrap();
+
+            assert!(!circuit_breaker.is_tripped());
+            assert_eq!(market_paused_operations, 0);
+        }
+
+        update_oracle_circuit_breaker(
+            &mut circuit_breaker,
+            &mut market_paused_operations,
+            50 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64,
+            OracleValidity::Valid,
+            0,
+        )
+        .unwrap();
+
+        assert!(circuit_breaker.is_tripped());
+        assert_eq!(
+            market_paused_operations,
+            PerpOperation::AmmFill as u8 | PerpOperation::Fill as u8
+        );
+    }
+
+    #[test]
+    fn resets_after_cooldown() {
+        let mut circuit_breaker = circuit_breaker();
+        let mut market_paused_operations = 0;
+
+        update_oracle_circuit_breaker(
+            &mut circuit_breaker,
+            &mut market_paused_operations,
+            88 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64,
+            OracleValidity::Valid,
+            0,
+        )
+        .unwrap();
+        assert_eq!(circuit_breaker.tripped_until_ts, 600);
+
+        // still within the cooldown
+        update_oracle_circuit_breaker(
+            &mut circuit_breaker,
+            &mut market_paused_operations,
+            100 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64,
+            OracleValidity::Valid,
+            599,
+        )
+        .unwrap();
+        assert!(circuit_breaker.is_tripped());
+
+        // resets even if the oracle is invalid, but can't trip again
+        update_oracle_circuit_breaker(
+            &mut circuit_breaker,
+            &mut market_paused_operations,
+            50 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64,
+            OracleValidity::StaleForAMM,
+            600,
+        )
+        .unwrap();
+        assert!(!circuit_breaker.is_tripped());
+        assert_eq!(market_paused_operations, 0);
+    }
+}
@@ -11,7 +11,7 @@ use crate::controller::funding::settle_funding_payment;
 use crate::controller::lp::burn_lp_shares;
 use crate::controller::position;
 use crate::controller::position::{
-    add_new_position, decrease_open_bids_and_asks, get_position_index, increase_open_bids_and_asks,
+    decrease_open_bids_and_asks, get_position_index, increase_open_bids_and_asks,
     update_lp_market_position, update_position_and_market, update_quote_asset_amount,
     PositionDirection,
 };
@@ -30,7 +30,9 @@ use crate::load_mut;
 use crate::math::amm_jit::calculate_amm_jit_liquidity;
 use crate::math::auction::{calculate_auction_params_for_trigger_order, calculate_auction_prices};
 use crate::math::casting::Cast;
-use crate::math::constants::{BASE_PRECISION_U64, PERP_DECIMALS, QUOTE_SPOT_MARKET_INDEX};
+use crate::math::constants::{
+    BASE_PRECISION_U64, PERCENTAGE_PRECISION_U64, PERP_DECIMALS, QUOTE_SPOT_MARKET_INDEX,
+};
 use crate::math::fees::{determine_user_fee_tier, ExternalFillFees, FillFees};
 use crate::math::fulfillment::{
     determine_perp_fulfillment_methods, determine_spot_fulfillment_methods,
@@ -45,7 +47,8 @@ use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::{get_signed_token_amount, get_token_amount};
 use crate::math::{amm, fees, margin::*, orders::*};
 use crate::state::order_params::{
-    ModifyOrderParams, ModifyOrderPolicy, OrderParams, PlaceOrderOptions, PostOnlyParam,
+    FillRequirement, ModifyOrderParams, ModifyOrderPolicy, OrderParams, PlaceOrderOptions,
+    PostOnlyParam, TrailingStopOffset,
 };
 
 use crate::math::amm::calculate_amm_available_liquidity;
@@ -54,7 +57,8 @@ use crate::math::safe_unwrap::SafeUnwrap;
 use crate::math::spot_swap::select_margin_type_for_swap;
 use crate::print_error;
 use crate::state::events::{
-    emit_stack, get_order_action_record, LPAction, LPRecord, OrderActionRecord, OrderRecord,
+    emit_stack, get_order_action_record, HeartbeatAction, HeartbeatRecord, LPAction, LPRecord,
+    OrderActionRecord, OrderRecord,
 };
 use crate::state::events::{OrderAction, OrderActionExplanation};
 use crate::state::fill_mode::FillMode;
@@ -72,9 +76,9 @@ use crate::state::state::FeeStructure;
 use crate::state::state::*;
 use crate::state::traits::Size;
 use crate::state::user::{
-    AssetType, Order, OrderStatus, OrderTriggerCondition, OrderType, UserStats,
+    AssetType, Order, OrderBitFlag, OrderStatus, OrderTriggerCondition, OrderType, UserStats,
 };
-use crate::state::user::{MarketType, User};
+use crate::state::user::{MarketType, TimeInForce, User};
 use crate::state::user_map::{UserMap, UserStatsMap};
 use crate::validate;
 use crate::validation;
@@ -173,7 +177,7 @@ pub fn place_perp_order(
     )?;
 
     let position_index = get_position_index(&user.perp_positions, market_index)
-        .or_else(|_| add_new_position(&mut user.perp_positions, market_index))?;
+        .or_else(|_| user.add_perp_position(market_index))?;
 
     // Increment open orders for existing position
     let (existing_position_direction, order_base_asset_amount) = {
@@ -208,7 +212,7 @@ pub fn place_perp_order(
         (existing_position_direction, base_asset_amount)
     };
 
-    let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+    let oracle_price_data = oracle_map.get_price_data(market.oracle_id())?;
 
     // updates auction params for crossing limit orders w/out auction duration
     params.update_perp_auction_params(market, oracle_price_data.price)?;
@@ -220,18 +224,21 @@ pub fn place_perp_order(
         state.min_perp_auction_duration,
     )?;
 
-    let max_ts = match params.max_ts {
-        Some(max_ts) => max_ts,
-        None => match params.order_type {
-            OrderType::Market | OrderType::Oracle => {
-                now.safe_add(30_i64.max((auction_duration / 2) as i64))?
-            }
-            _ => 0_i64,
-        },
+    let default_max_ts = match params.order_type {
+        OrderType::Market | OrderType::Oracle => {
+            now.safe_add(30_i64.max((auction_duration / 2) as i64))?
+        }
+        _ => 0_i64,
     };
+    let (max_ts, time_in_force) = get_max_ts_and_time_in_force(&params, default_max_ts)?;
 
-    if max_ts != 0 && max_ts < now {
-        msg!("max_ts ({}) < now ({}), skipping order", max_ts, now);
+    if time_in_force.is_expired(max_ts, now, slot)? {
+        msg!(
+            "max_ts ({}) passed, skipping order. now {} slot {}",
+            max_ts,
+            now,
+            slot
+        );
         return Ok(());
     }
 
@@ -241,7 +248,18 @@ pub fn place_perp_order(
         "must be perp order"
     )?;
 
-    let new_order = Order {
+    let (oracle_price_offset, bit_flags) =
+        get_trailing_stop_order_fields(&params, options.bit_flags)?;
+    let (display_base_asset_amount, hidden_base_asset_amount, bit_flags) =
+        get_iceberg_order_fields(
+            &params,
+            order_base_asset_amount,
+            market.amm.order_step_size,
+            bit_flags,
+        )?;
+    validate_fill_requirement_params(&params, order_base_asset_amount, time_in_force)?;
+
+    let mut new_order = Order {
         status: OrderStatus::Open,
         order_type: params.order_type,
         market_type: params.market_type,
@@ -256,7 +274,7 @@ pub fn place_perp_order(
             &market.amm,
         )?,
         existing_position_direction,
-        base_asset_amount: order_base_asset_amount,
+        base_asset_amount: display_base_asset_amount,
         base_asset_amount_filled: 0,
         quote_asset_amount_filled: 0,
         direction: params.direction,
@@ -268,16 +286,32 @@ pub fn place_perp_order(
         )?,
         trigger_condition: params.trigger_condition,
         post_only: params.post_only != PostOnlyParam::None,
-        oracle_price_offset: params.oracle_price_offset.unwrap_or(0),
+        oracle_price_offset,
         immediate_or_cancel: params.immediate_or_cancel,
         auction_start_price,
         auction_end_price,
         auction_duration,
         max_ts,
-        padding: [0; 3],
+        bit_flags,
+        bracket_id: options.bracket_id,
+        time_in_force,
     };
 
-    let valid_oracle_price = Some(oracle_map.get_price_data(&market.amm.oracle)?.price);
+    if new_order.is_iceberg() {
+        new_order.auction_end_price = hidden_base_asset_amount.cast()?;
+    }
+
+    if new_order.is_trailing_stop() {
+        if let Some(trigger_price) = calculate_trailing_stop_trigger_price(
+            &new_order,
+            oracle_price_data.price.unsigned_abs(),
+            market.amm.order_tick_size,
+        )? {
+            new_order.trigger_price = trigger_price;
+        }
+    }
+
+    let valid_oracle_price = Some(oracle_map.get_price_data(market.oracle_id())?.price);
     match validate_order(&new_order, market, valid_oracle_price, slot) {
         Ok(()) => {}
         Err(ErrorCode::PlacePostOnlyLimitFailure)
@@ -373,7 +407,7 @@ pub fn place_perp_order(
         taker_order,
         maker,
         maker_order,
-        oracle_map.get_price_data(&market.amm.oracle)?.price,
+        oracle_map.get_price_data(market.oracle_id())?.price,
     )?;
     emit_stack::<_, { OrderActionRecord::SIZE }>(order_action_record)?;
 
@@ -389,6 +423,331 @@ pub fn place_perp_order(
     Ok(())
 }
 
+/// Places a parent order with take profit and/or stop loss legs. The legs are reduce only trigger
+/// orders that can't be triggered until the parent fills, and filling or canceling one leg cancels
+/// the other
+pub fn place_perp_bracket_order(
+    state: &State,
+    user: &mut User,
+    user_key: Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    params: OrderParams,
+    take_profit_params: Option<OrderParams>,
+    stop_loss_params: Option<OrderParams>,
+) -> DriftResult {
+    validate!(
+        take_profit_params.is_some() || stop_loss_params.is_some(),
+        ErrorCode::InvalidBracketOrder,
+        "bracket must have a take profit or stop loss"
+    )?;
+
+    validate!(
+        matches!(
+            params.order_type,
+            OrderType::Market | OrderType::Limit | OrderType::Oracle
+        ),
+        ErrorCode::InvalidBracketOrder,
+        "bracket parent can not be a trigger order"
+    )?;
+
+    let (take_profit_condition, stop_loss_condition) = match params.direction {
+        PositionDirection::Long => (OrderTriggerCondition::Above, OrderTriggerCondition::Below),
+        PositionDirection::Short => (OrderTriggerCondition::Below, OrderTriggerCondition::Above),
+    };
+
+    let mut legs = Vec::with_capacity(2);
+    for (leg_params, trigger_condition) in [
+        (take_profit_params, take_profit_condition),
+        (stop_loss_params, stop_loss_condition),
+    ] {
+        if let Some(mut leg_params) = leg_params {
+            validate!(
+                matches!(
+                    leg_params.order_type,
+                    OrderType::TriggerMarket | OrderType::TriggerLimit
+                ) && leg_params.market_type == params.market_type
+                    && leg_params.market_index == params.market_index
+                    && leg_params.direction == params.direction.opposite()
+                    && leg_params.trigger_condition == trigger_condition
+                    && !leg_params.immediate_or_cancel,
+                ErrorCode::InvalidBracketOrder,
+                "bracket legs must be trigger orders that close the parent in market {}",
+                params.market_index
+            )?;
+
+            leg_params.reduce_only = true;
+            legs.push(leg_params);
+        }
+    }
+
+    let bracket_id = user.get_free_bracket_id();
+
+    let parent_order_id = user.next_order_id;
+    place_perp_order(
+        state,
+        user,
+        user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock,
+        params,
+        PlaceOrderOptions::default().bracket(bracket_id, OrderBitFlag::BracketParent as u8),
+    )?;
+
+    if user.get_order_index(parent_order_id).is_err() {
+        msg!("bracket parent was not placed, skipping legs");
+        return Ok(());
+    }
+
+    let mut leg_bit_flags = OrderBitFlag::BracketLeg as u8 | OrderBitFlag::BracketPending as u8;
+    if legs.len() > 1 {
+        leg_bit_flags |= OrderBitFlag::BracketHasSibling as u8;
+    }
+
+    for leg_params in legs {
+        let leg_order_id = user.next_order_id;
+        place_perp_order(
+            state,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            clock,
+            leg_params,
+            PlaceOrderOptions::default().bracket(bracket_id, leg_bit_flags),
+        )?;
+
+        validate!(
+            user.get_order_index(leg_order_id).is_ok(),
+            ErrorCode::InvalidBracketOrder,
+            "bracket leg could not be placed"
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Trailing stops store their offset in oracle_price_offset until they're triggered
+fn get_trailing_stop_order_fields(params: &OrderParams, bit_flags: u8) -> DriftResult<(i32, u8)> {
+    let trailing_stop_offset = match params.trailing_stop_offset {
+        Some(trailing_stop_offset) => trailing_stop_offset,
+        None => return Ok((params.oracle_price_offset.unwrap_or(0), bit_flags)),
+    };
+
+    validate!(
+        params.order_type == OrderType::TriggerMarket && params.oracle_price_offset.is_none(),
+        ErrorCode::InvalidTrailingStopOrder,
+        "trailing stop must be a trigger market order without an oracle price offset"
+    )?;
+
+    let (offset, trailing_stop_bit_flags) = match trailing_stop_offset {
+        TrailingStopOffset::Price(offset) => (offset, OrderBitFlag::TrailingStop as u8),
+        TrailingStopOffset::Percentage(offset) => {
+            validate!(
+                offset.cast::<u64>()? < PERCENTAGE_PRECISION_U64,
+                ErrorCode::InvalidTrailingStopOrder,
+                "trailing stop percentage must be less than 100%"
+            )?;
+
+            (
+                offset,
+                OrderBitFlag::TrailingStop as u8 | OrderBitFlag::TrailingStopPercentage as u8,
+            )
+        }
+    };
+
+    validate!(
+        offset > 0,
+        ErrorCode::InvalidTrailingStopOrder,
+        "trailing stop offset must be greater than 0"
+    )?;
+
+    Ok((offset.cast()?, bit_flags | trailing_stop_bit_flags))
+}
+
+/// Good til slot orders store the slot they expire after in max_ts
+fn get_max_ts_and_time_in_force(
+    params: &OrderParams,
+    default_max_ts: i64,
+) -> DriftResult<(i64, TimeInForce)> {
+    match params.max_slot {
+        Some(max_slot) => {
+            validate!(
+                params.max_ts.is_none(),
+                ErrorCode::InvalidOrderMaxTs,
+                "max_ts and max_slot can not both be set"
+            )?;
+
+            Ok((max_slot.cast()?, TimeInForce::GoodTilSlot))
+        }
+        None => Ok((
+            params.max_ts.unwrap_or(default_max_ts),
+            TimeInForce::GoodTilTime,
+        )),
+    }
+}
+
+/// Fill requirements are only enforced by place_and_take, so they're limited to immediate or cancel
+/// orders that take
+fn validate_fill_requirement_params(
+    params: &OrderParams,
+    base_asset_amount: u64,
+    time_in_force: TimeInForce,
+) -> DriftResult {
+    let fill_requirement = match params.fill_requirement {
+        Some(fill_requirement) => fill_requirement,
+        None => return Ok(()),
+    };
+
+    validate!(
+        params.order_type == OrderType::Limit
+            && params.immediate_or_cancel
+            && params.post_only == PostOnlyParam::None,
+        ErrorCode::InvalidFillRequirement,
+        "fill requirement is only supported for immediate or cancel limit orders that take"
+    )?;
+
+    validate!(
+        time_in_force == TimeInForce::GoodTilTime,
+        ErrorCode::InvalidFillRequirement,
+        "fill requirement can not be used with max_slot"
+    )?;
+
+    if let FillRequirement::MinimumFill(min_fill_base_asset_amount) = fill_requirement {
+        validate!(
+            min_fill_base_asset_amount > 0 && min_fill_base_asset_amount <= base_asset_amount,
+            ErrorCode::InvalidFillRequirement,
+            "minimum fill ({}) must be greater than 0 and at most the order size ({})",
+            min_fill_base_asset_amount,
+            base_asset_amount
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Reverts a place and take whose fill left a fill or kill or minimum fill order filled below its
+/// minimum fill
+pub fn validate_place_and_take_fill_requirement(
+    params: &OrderParams,
+    order_base_asset_amount: u64,
+    base_asset_amount_filled: u64,
+) -> DriftResult {
+    match params.fill_requirement {
+        Some(fill_requirement) => validate_fill_requirement(
+            fill_requirement.get_min_fill_base_asset_amount(order_base_asset_amount),
+            order_base_asset_amount,
+            base_asset_amount_filled,
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Iceberg orders only show display_base_asset_amount, the hidden rest is stored in
+/// auction_end_price since icebergs are post only and never have an auction.
+/// Returns the shown and hidden base asset amounts
+fn get_iceberg_order_fields(
+    params: &OrderParams,
+    base_asset_amount: u64,
+    step_size: u64,
+    bit_flags: u8,
+) -> DriftResult<(u64, u64, u8)> {
+    let display_base_asset_amount = match params.display_base_asset_amount {
+        Some(display_base_asset_amount) => {
+            standardize_base_asset_amount(display_base_asset_amount, step_size)?
+        }
+        None => return Ok((base_asset_amount, 0, bit_flags)),
+    };
+
+    validate!(
+        params.order_type == OrderType::Limit
+            && params.post_only != PostOnlyParam::None
+            && !params.reduce_only
+            && !params.immediate_or_cancel,
+        ErrorCode::InvalidIcebergOrder,
+        "iceberg must be a post only limit order that isn't reduce only or immediate or cancel"
+    )?;
+
+    validate!(
+        display_base_asset_amount >= step_size,
+        ErrorCode::InvalidIcebergOrder,
+        "display_base_asset_amount ({}) must be at least the step size ({})",
+        display_base_asset_amount,
+        step_size
+    )?;
+
+    if display_base_asset_amount >= base_asset_amount {
+        return Ok((base_asset_amount, 0, bit_flags));
+    }
+
+    Ok((
+        display_base_asset_amount,
+        base_asset_amount.safe_sub(display_base_asset_amount)?,
+        bit_flags | OrderBitFlag::Iceberg as u8,
+    ))
+}
+
+/// Ratchets the trigger price of the user's untriggered trailing stops in the market towards the
+/// oracle price. Returns whether any trigger price moved
+pub fn update_trailing_stop_orders(
+    user: &mut User,
+    user_key: &Pubkey,
+    market_index: u16,
+    oracle_price: i64,
+    tick_size: u64,
+    now: i64,
+) -> DriftResult<bool> {
+    let mut updated = false;
+    for order_index in 0..user.orders.len() {
+        let order = &user.orders[order_index];
+        if order.status != OrderStatus::Open
+            || order.market_type != MarketType::Perp
+            || order.market_index != market_index
+            || !order.is_trailing_stop()
+            || order.triggered()
+        {
+            continue;
+        }
+
+        if let Some(trigger_price) =
+            calculate_trailing_stop_trigger_price(order, oracle_price.unsigned_abs(), tick_size)?
+        {
+            user.orders[order_index].trigger_price = trigger_price;
+            updated = true;
+
+            let order_action_record = get_order_action_record(
+                now,
+                OrderAction::Trigger,
+                OrderActionExplanation::TrailingStopUpdated,
+                market_index,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(*user_key),
+                Some(user.orders[order_index]),
+                None,
+                None,
+                oracle_price,
+            )?;
+            emit_stack::<_, { OrderActionRecord::SIZE }>(order_action_record)?;
+        }
+    }
+
+    Ok(updated)
+}
+
 fn get_auction_params(
     params: &OrderParams,
     oracle_price_data: &OraclePriceData,
@@ -600,7 +959,7 @@ pub fn cancel_order(
     spot_market_map: &SpotMarketMap,
     oracle_map: &mut OracleMap,
     now: i64,
-    _slot: u64,
+    slot: u64,
     explanation: OrderActionExplanation,
     filler_key: Option<&Pubkey>,
     filler_reward: u64,
@@ -618,10 +977,12 @@ pub fn cancel_order(
 
     validate!(order_status == OrderStatus::Open, ErrorCode::OrderNotOpen)?;
 
-    let oracle = if is_perp_order {
-        perp_market_map.get_ref(&order_market_index)?.amm.oracle
+    let canceled_order = user.orders[order_index];
+
+    let oracle_id = if is_perp_order {
+        perp_market_map.get_ref(&order_market_index)?.oracle_id()
     } else {
-        spot_market_map.get_ref(&order_market_index)?.oracle
+        spot_market_map.get_ref(&order_market_index)?.oracle_id()
     };
 
     if !skip_log {
@@ -647,7 +1008,7 @@ pub fn cancel_order(
             taker_order,
             maker,
             maker_order,
-            oracle_map.get_price_data(&oracle)?.price,
+            oracle_map.get_price_data(oracle_id)?.price,
         )?;
         emit_stack::<_, { OrderActionRecord::SIZE }>(order_action_record)?;
     }
@@ -659,8 +1020,9 @@ pub fn cancel_order(
 
         // only decrease open/bids ask if it's not a trigger order or if it's been triggered
         if !user.orders[order_index].must_be_triggered() || user.orders[order_index].triggered() {
-            let base_asset_amount_unfilled =
-                user.orders[order_index].get_base_asset_amount_unfilled(None)?;
+            let base_asset_amount_unfilled = user.orders[order_index]
+                .get_base_asset_amount_unfilled(None)?
+                .safe_add(user.orders[order_index].get_hidden_base_asset_amount()?)?;
             position::decrease_open_bids_and_asks(
                 &mut user.perp_positions[position_index],
                 &order_direction,
@@ -675,8 +1037,9 @@ pub fn cancel_order(
 
         // only decrease open/bids ask if it's not a trigger order or if it's been triggered
         if !user.orders[order_index].must_be_triggered() || user.orders[order_index].triggered() {
-            let base_asset_amount_unfilled =
-                user.orders[order_index].get_base_asset_amount_unfilled(None)?;
+            let base_asset_amount_unfilled = user.orders[order_index]
+                .get_base_asset_amount_unfilled(None)?
+                .safe_add(user.orders[order_index].get_hidden_base_asset_amount()?)?;
             decrease_spot_open_bids_and_asks(
                 &mut user.spot_positions[spot_position_index],
                 &order_direction,
@@ -687,9 +1050,152 @@ pub fn cancel_order(
         user.orders[order_index] = Order::default();
     }
 
+    if canceled_order.is_bracket_order() {
+        update_bracket_orders_after_cancel(
+            &canceled_order,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// If the parent is canceled before filling, its legs are canceled with it. Otherwise the legs are
+/// activated to protect the partial fill. A canceled leg cancels its sibling
+fn update_bracket_orders_after_cancel(
+    canceled_order: &Order,
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+) -> DriftResult {
+    if canceled_order.is_bracket_parent() && canceled_order.base_asset_amount_filled > 0 {
+        activate_bracket_legs(user, canceled_order.bracket_id);
+        return Ok(());
+    }
+
+    let explanation = if canceled_order.is_bracket_parent() {
+        OrderActionExplanation::BracketParentCanceled
+    } else {
+        OrderActionExplanation::BracketSiblingCanceled
+    };
+
+    for order_index in 0..user.orders.len() {
+        let order = &user.orders[order_index];
+        if order.status != OrderStatus::Open
+            || order.bracket_id != canceled_order.bracket_id
+            || !order.is_bracket_leg()
+        {
+            continue;
+        }
+
+        cancel_order(
+            order_index,
+            user,
+            user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+            explanation,
+            None,
+            0,
+            false,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Fully filled orders are removed without going through cancel_order, so after a fill the user's
+/// brackets are checked for legs whose parent has filled (activated) and legs whose sibling has
+/// filled (canceled). Brackets are perp only and liquidations cancel the user's orders through
+/// cancel_order, so only perp fills need the check
+pub fn update_bracket_orders_after_fill(
+    user: &mut User,
+    user_key: &Pubkey,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+    slot: u64,
+) -> DriftResult {
+    for order_index in 0..user.orders.len() {
+        let order = user.orders[order_index];
+        if order.status != OrderStatus::Open || !order.is_bracket_leg() {
+            continue;
+        }
+
+        let is_open_in_bracket = |i: usize, other: &Order| {
+            i != order_index
+                && other.status == OrderStatus::Open
+                && other.bracket_id == order.bracket_id
+        };
+
+        if order.is_pending_bracket_leg() {
+            let parent_open = user
+                .orders
+                .iter()
+                .enumerate()
+                .any(|(i, other)| is_open_in_bracket(i, other) && other.is_bracket_parent());
+
+            if !parent_open {
+                user.orders[order_index].remove_bit_flag(OrderBitFlag::BracketPending);
+            }
+
+            continue;
+        }
+
+        if order.is_bit_flag_set(OrderBitFlag::BracketHasSibling) {
+            let sibling_open = user
+                .orders
+                .iter()
+                .enumerate()
+                .any(|(i, other)| is_open_in_bracket(i, other) && other.is_bracket_leg());
+
+            if !sibling_open {
+                cancel_order(
+                    order_index,
+                    user,
+                    user_key,
+                    perp_market_map,
+                    spot_market_map,
+                    oracle_map,
+                    now,
+                    slot,
+                    OrderActionExplanation::BracketSiblingFilled,
+                    None,
+                    0,
+                    false,
+                )?;
+            }
+        }
+    }
+
     Ok(())
 }
 
+fn activate_bracket_legs(user: &mut User, bracket_id: u8) {
+    for order in user.orders.iter_mut() {
+        if order.status == OrderStatus::Open
+            && order.bracket_id == bracket_id
+            && order.is_pending_bracket_leg()
+        {
+            order.remove_bit_flag(OrderBitFlag::BracketPending);
+        }
+    }
+}
+
 pub enum ModifyOrderId {
     UserOrderId(u8),
     OrderId(u32),
@@ -737,6 +1243,15 @@ pub fn modify_order(
 
     let existing_order = user.orders[order_index];
 
+    if existing_order.is_bracket_order() {
+        // the replacement order takes over the existing order's place in the bracket
+        user.orders[order_index].bracket_id = 0;
+
+        if existing_order.is_bracket_parent() && existing_order.base_asset_amount_filled > 0 {
+            activate_bracket_legs(&mut user, existing_order.bracket_id);
+        }
+    }
+
     cancel_order(
         order_index,
         &mut user,
@@ -757,6 +1272,16 @@ pub fn modify_order(
     let order_params =
         merge_modify_order_params_with_existing_order(&existing_order, &modify_order_params)?;
 
+    // trailing stop and iceberg flags are derived from the merged params
+    let options = PlaceOrderOptions::default().bracket(
+        existing_order.bracket_id,
+        existing_order.bit_flags
+            & !(OrderBitFlag::TrailingStop as u8
+                | OrderBitFlag::TrailingStopPercentage as u8
+                | OrderBitFlag::Iceberg as u8),
+    );
+    let new_order_id = user.next_order_id;
+
     if order_params.market_type == MarketType::Perp {
         place_perp_order(
             state,
@@ -767,7 +1292,7 @@ pub fn modify_order(
             oracle_map,
             clock,
             order_params,
-            PlaceOrderOptions::default(),
+            options,
         )?;
     } else {
         place_spot_order(
@@ -779,7 +1304,20 @@ pub fn modify_order(
             oracle_map,
             clock,
             order_params,
-            PlaceOrderOptions::default(),
+            options,
+        )?;
+    }
+
+    if existing_order.is_bracket_order() && user.get_order_index(new_order_id).is_err() {
+        update_bracket_orders_after_cancel(
+            &existing_order,
+            &mut user,
+            &user_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            clock.unix_timestamp,
+            clock.slot,
         )?;
     }
 
@@ -796,9 +1334,18 @@ fn merge_modify_order_params_with_existing_order(
         .direction
         .unwrap_or(existing_order.direction);
     let user_order_id = existing_order.user_order_id;
-    let base_asset_amount = modify_order_params
-        .base_asset_amount
-        .unwrap_or(existing_order.get_base_asset_amount_unfilled(None)?);
+    let hidden_base_asset_amount = existing_order.get_hidden_base_asset_amount()?;
+    let base_asset_amount = modify_order_params.base_asset_amount.unwrap_or(
+        existing_order
+            .get_base_asset_amount_unfilled(None)?
+            .safe_add(hidden_base_asset_amount)?,
+    );
+    // the shown size of an iceberg order is kept at its unfilled base_asset_amount
+    let display_base_asset_amount = if hidden_base_asset_amount > 0 {
+        Some(existing_order.get_base_asset_amount_unfilled(None)?)
+    } else {
+        None
+    };
     let price = modify_order_params.price.unwrap_or(existing_order.price);
     let market_index = existing_order.market_index;
     let reduce_only = modify_order_params
@@ -812,7 +1359,15 @@ fn merge_modify_order_params_with_existing_order(
             PostOnlyParam::None
         });
     let immediate_or_cancel = false;
-    let max_ts = modify_order_params.max_ts.or(Some(existing_order.max_ts));
+    let (max_ts, max_slot) = if modify_order_params.max_ts.is_some() {
+        (modify_order_params.max_ts, None)
+    } else if modify_order_params.max_slot.is_some() {
+        (None, modify_order_params.max_slot)
+    } else if existing_order.time_in_force == TimeInForce::GoodTilSlot {
+        (None, Some(existing_order.max_ts.cast()?))
+    } else {
+        (Some(existing_order.max_ts), None)
+    };
     let trigger_price = modify_order_params
         .trigger_price
         .or(Some(existing_order.trigger_price));
@@ -827,9 +1382,28 @@ fn merge_modify_order_params_with_existing_order(
                     OrderTriggerCondition::Below
                 }
             });
-    let oracle_price_offset = modify_order_params
-        .oracle_price_offset
-        .or(Some(existing_order.oracle_price_offset));
+    // for untriggered trailing stops, oracle_price_offset modifies the trailing offset
+    let is_trailing_stop = existing_order.is_trailing_stop() && !existing_order.triggered();
+    let oracle_price_offset = if is_trailing_stop {
+        None
+    } else {
+        modify_order_params
+            .oracle_price_offset
+            .or(Some(existing_order.oracle_price_offset))
+    };
+    let trailing_stop_offset = if is_trailing_stop {
+        let offset = modify_order_params
+            .oracle_price_offset
+            .unwrap_or(existing_order.oracle_price_offset)
+            .unsigned_abs();
+        if existing_order.is_bit_flag_set(OrderBitFlag::TrailingStopPercentage) {
+            Some(TrailingStopOffset::Percentage(offset))
+        } else {
+            Some(TrailingStopOffset::Price(offset))
+        }
+    } else {
+        None
+    };
     let (auction_duration, auction_start_price, auction_end_price) =
         if modify_order_params.auction_duration.is_some()
             && modify_order_params.auction_start_price.is_some()
@@ -862,6 +1436,10 @@ fn merge_modify_order_params_with_existing_order(
         auction_duration,
         auction_start_price,
         auction_end_price,
+        trailing_stop_offset,
+        display_base_asset_amount,
+        max_slot,
+        fill_requirement: None,
     })
 }
 
@@ -980,15 +1558,18 @@ pub fn fill_perp_order(
         let (oracle_price_data, _oracle_validity) = oracle_map.get_price_data_and_validity(
             MarketType::Perp,
             market.market_index,
-            &market.amm.oracle,
+            market.oracle_id(),
             market.amm.historical_oracle_data.last_oracle_price_twap,
             market.get_max_confidence_interval_multiplier()?,
+            market.get_validity_guard_rails_override(),
         )?;
 
         amm_is_available &=
             is_oracle_valid_for_action(_oracle_validity, Some(DriftAction::FillOrderAmm))?;
         amm_is_available &= !market.is_operation_paused(PerpOperation::AmmFill);
         amm_is_available &= !market.has_too_much_drawdown()?;
+        // option markets only match against other orders, the amm curve can't price them
+        amm_is_available &= !market.is_option();
 
         reserve_price_before = market.amm.reserve_price()?;
         oracle_price = oracle_price_data.price;
@@ -1064,7 +1645,7 @@ pub fn fill_perp_order(
 
     validate_perp_fill_possible(state, user, order_index, slot, makers_and_referrer.0.len())?;
 
-    let should_expire_order = should_expire_order_before_fill(user, order_index, now)?;
+    let should_expire_order = should_expire_order_before_fill(user, order_index, now, slot)?;
 
     let position_index =
         get_position_index(&user.perp_positions, user.orders[order_index].market_index)?;
@@ -1232,6 +1813,40 @@ pub fn fill_perp_order(
         )?;
     }
 
+    let tick_size = perp_market_map.get_ref(&market_index)?.amm.order_tick_size;
+
+    update_bracket_orders_after_fill(
+        user,
+        &user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        now,
+        slot,
+    )?;
+    update_trailing_stop_orders(user, &user_key, market_index, oracle_price, tick_size, now)?;
+
+    for (maker_key, _, _) in maker_orders_info.iter() {
+        let mut maker = makers_and_referrer.get_ref_mut(maker_key)?;
+        update_bracket_orders_after_fill(
+            &mut maker,
+            maker_key,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            now,
+            slot,
+        )?;
+        update_trailing_stop_orders(
+            &mut maker,
+            maker_key,
+            market_index,
+            oracle_price,
+            tick_size,
+            now,
+        )?;
+    }
+
     user.update_last_active_slot(slot);
 
     Ok(base_asset_amount)
@@ -1371,7 +1986,7 @@ fn get_maker_orders_info(
                 )?
             };
 
-            let should_expire_order = should_expire_order(&maker, maker_order_index, now)?;
+            let should_expire_order = should_expire_order(&maker, maker_order_index, now, slot)?;
 
             let existing_base_asset_amount = maker
                 .get_perp_position(maker.orders[maker_order_index].market_index)?
@@ -1529,16 +2144,21 @@ fn fulfill_perp_order(
     let user_order_position_decreasing =
         determine_if_user_order_is_position_decreasing(user, market_index, user_order_index)?;
 
-    let limit_price = fill_mode.get_limit_price(
-        &user.orders[user_order_index],
-        valid_oracle_price,
-        slot,
-        perp_market_map.get_ref(&market_index)?.amm.order_tick_size,
-    )?;
+    let limit_price = {
+        let market = perp_market_map.get_ref(&market_index)?;
+        let limit_price = fill_mode.get_limit_price(
+            &user.orders[user_order_index],
+            valid_oracle_price,
+            slot,
+            market.amm.order_tick_size,
+        )?;
+
+        market.get_bounded_limit_price(limit_price, user.orders[user_order_index].direction)
+    };
 
     let fulfillment_methods = {
         let market = perp_market_map.get_ref(&market_index)?;
-        let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
+        let oracle_price = oracle_map.get_price_data(market.oracle_id())?.price;
 
         determine_perp_fulfillment_methods(
             &user.orders[user_order_index],
@@ -2130,7 +2750,7 @@ pub fn fulfill_perp_order_with_amm(
         taker_order,
         maker,
         maker_order,
-        oracle_map.get_price_data(&market.amm.oracle)?.price,
+        oracle_map.get_price_data(market.oracle_id())?.price,
     )?;
     emit_stack::<_, { OrderActionRecord::SIZE }>(order_action_record)?;
 
@@ -2156,7 +2776,7 @@ pub fn credit_filler_perp_pnl(
 ) -> DriftResult {
     if filler_reward > 0 {
         let position_index = get_position_index(&filler.perp_positions, market.market_index)
-            .or_else(|_| add_new_position(&mut filler.perp_positions, market.market_index))?;
+            .or_else(|_| filler.add_perp_position(market.market_index))?;
 
         controller::position::update_quote_asset_amount(
             &mut filler.perp_positions[position_index],
@@ -2205,7 +2825,7 @@ pub fn fulfill_perp_order_with_match(
         return Ok((0_u64, 0_u64, 0_u64));
     }
 
-    let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
+    let oracle_price = oracle_map.get_price_data(market.oracle_id())?.price;
     let taker_direction: PositionDirection = taker.orders[taker_order_index].direction;
 
     let taker_price = if let Some(taker_limit_price) = taker_limit_price {
@@ -2217,7 +2837,7 @@ pub fn fulfill_perp_order_with_match(
             &taker_direction,
             amm_available_liquidity,
             oracle_price,
-            taker.orders[taker_order_index].seconds_til_expiry(now),
+            taker.orders[taker_order_index].seconds_til_expiry(now, slot),
         )?
     };
 
@@ -2227,12 +2847,15 @@ pub fn fulfill_perp_order_with_match(
     let taker_base_asset_amount = taker.orders[taker_order_index]
         .get_base_asset_amount_unfilled(Some(taker_existing_position))?;
 
-    let maker_price = maker.orders[maker_order_index].force_get_limit_price(
-        Some(oracle_price),
-        None,
-        slot,
-        market.amm.order_tick_size,
-    )?;
+    let taker_price = market.get_bounded_fill_price(taker_price);
+
+    let maker_price =
+        market.get_bounded_fill_price(maker.orders[maker_order_index].force_get_limit_price(
+            Some(oracle_price),
+            None,
+            slot,
+            market.amm.order_tick_size,
+        )?);
     let maker_direction = maker.orders[maker_order_index].direction;
     let maker_existing_position = maker
         .get_perp_position(market.market_index)?
@@ -2467,9 +3090,8 @@ pub fn fulfill_perp_order_with_match(
     if let Some(filler) = filler {
         if filler_reward > 0 {
             let filler_position_index =
-                get_position_index(&filler.perp_positions, market.market_index).or_else(|_| {
-                    add_new_position(&mut filler.perp_positions, market.market_index)
-                })?;
+                get_position_index(&filler.perp_positions, market.market_index)
+                    .or_else(|_| filler.add_perp_position(market.market_index))?;
 
             controller::position::update_quote_asset_amount(
                 &mut filler.perp_positions[filler_position_index],
@@ -2543,7 +3165,7 @@ pub fn fulfill_perp_order_with_match(
         Some(taker.orders[taker_order_index]),
         Some(*maker_key),
         Some(maker.orders[maker_order_index]),
-        oracle_map.get_price_data(&market.amm.oracle)?.price,
+        oracle_map.get_price_data(market.oracle_id())?.price,
     )?;
     emit_stack::<_, { OrderActionRecord::SIZE }>(order_action_record)?;
 
@@ -2579,6 +3201,8 @@ pub fn update_order_after_fill(
         .quote_asset_amount_filled
         .safe_add(quote_asset_amount)?;
 
+    order.refresh_iceberg(base_asset_amount)?;
+
     if order.get_base_asset_amount_unfilled(None)? == 0 {
         order.status = OrderStatus::Filled;
     }
@@ -2642,6 +3266,12 @@ pub fn trigger_order(
         "Order is already triggered"
     )?;
 
+    validate!(
+        !user.orders[order_index].is_pending_bracket_leg(),
+        ErrorCode::OrderNotTriggerable,
+        "Bracket parent has not filled"
+    )?;
+
     validate!(
         market_type == MarketType::Perp,
         ErrorCode::InvalidOrderMarketType,
@@ -2662,12 +3292,13 @@ pub fn trigger_order(
     let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
         MarketType::Perp,
         perp_market.market_index,
-        &perp_market.amm.oracle,
+        perp_market.oracle_id(),
         perp_market
             .amm
             .historical_oracle_data
             .last_oracle_price_twap,
         perp_market.get_max_confidence_interval_multiplier()?,
+        perp_market.get_validity_guard_rails_override(),
     )?;
 
     let is_oracle_valid =
@@ -2677,10 +3308,27 @@ pub fn trigger_order(
 
     let oracle_price = oracle_price_data.price;
 
+    let is_trailing_stop = user.orders[order_index].is_trailing_stop();
+    let trailing_stop_updated = is_trailing_stop
+        && update_trailing_stop_orders(
+            user,
+            &user_key,
+            market_index,
+            oracle_price,
+            perp_market.amm.order_tick_size,
+            now,
+        )?;
+
     let can_trigger = order_satisfies_trigger_condition(
         &user.orders[order_index],
         oracle_price.unsigned_abs().cast()?,
     )?;
+
+    // persist the new trail level even though the order can't be triggered yet
+    if !can_trigger && trailing_stop_updated {
+        return Ok(());
+    }
+
     validate!(can_trigger, ErrorCode::OrderDidNotSatisfyTriggerCondition)?;
 
     let worst_case_base_asset_amount_before = user
@@ -2725,7 +3373,11 @@ pub fn trigger_order(
     let order_action_record = get_order_action_record(
         now,
         OrderAction::Trigger,
-        OrderActionExplanation::None,
+        if is_trailing_stop {
+            OrderActionExplanation::TrailingStopTriggered
+        } else {
+            OrderActionExplanation::None
+        },
         market_index,
         Some(filler_key),
         None,
@@ -2799,6 +3451,11 @@ fn update_trigger_order_params(
 
     order.slot = slot;
 
+    // trail offset is no longer needed and would otherwise be read as an oracle price offset
+    if order.is_trailing_stop() {
+        order.oracle_price_offset = 0;
+    }
+
     let (auction_duration, auction_start_price, auction_end_price) =
         calculate_auction_params_for_trigger_order(
             order,
@@ -2935,6 +3592,93 @@ pub fn force_cancel_orders(
     Ok(())
 }
 
+/// Cancels all of a user's orders once their dead man's switch times out and pays the keeper a
+/// flat reward
+pub fn cancel_orders_on_heartbeat_timeout(
+    state: &State,
+    user_account_loader: &AccountLoader<User>,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user_account_loader.key();
+
+    validate!(
+        filler_key != user_key,
+        ErrorCode::InvalidHeartbeat,
+        "filler can not cancel its own orders"
+    )?;
+
+    let user = &mut load_mut!(user_account_loader)?;
+    let filler = &mut load_mut!(filler)?;
+
+    validate!(
+        user.is_heartbeat_expired(now)?,
+        ErrorCode::HeartbeatNotExpired,
+        "last heartbeat {} with timeout {}s, now {}",
+        user.last_heartbeat_ts,
+        user.heartbeat_timeout,
+        now
+    )?;
+
+    let canceled_order_ids = cancel_orders(
+        user,
+        &user_key,
+        Some(&filler_key),
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        now,
+        slot,
+        OrderActionExplanation::HeartbeatTimeout,
+        None,
+        None,
+        None,
+    )?;
+
+    let filler_reward = if canceled_order_ids.is_empty() {
+        0
+    } else {
+        let quote_spot_market = &mut spot_market_map.get_quote_spot_market_mut()?;
+        // the reward can't exceed the user's quote deposit
+        let max_filler_reward = user
+            .get_quote_spot_position()
+            .get_signed_token_amount(quote_spot_market)?
+            .max(0)
+            .cast::<u64>()?;
+
+        pay_keeper_flat_reward_for_spot(
+            user,
+            Some(filler),
+            quote_spot_market,
+            state
+                .spot_fee_structure
+                .flat_filler_fee
+                .min(max_filler_reward),
+            slot,
+        )?
+    };
+
+    emit!(HeartbeatRecord {
+        ts: now,
+        user: user_key,
+        action: HeartbeatAction::CancelOrders,
+        last_heartbeat_ts: user.last_heartbeat_ts,
+        heartbeat_timeout: user.heartbeat_timeout,
+        canceled_order_ids,
+        filler: Some(filler_key),
+        filler_reward: Some(filler_reward),
+    });
+
+    Ok(())
+}
+
 pub fn can_reward_user_with_perp_pnl(user: &mut Option<&mut User>, market_index: u16) -> bool {
     match user.as_mut() {
         Some(user) => user.force_get_perp_position_mut(market_index).is_ok(),
@@ -2993,12 +3737,12 @@ pub fn burn_user_lp_shares_for_risk_reduction(
 
     let mut market = perp_market_map.get_ref_mut(&market_index)?;
 
-    let quote_oracle = spot_market_map
+    let quote_oracle_id = spot_market_map
         .get_ref(&market.quote_spot_market_index)?
-        .oracle;
-    let quote_oracle_price = oracle_map.get_price_data(&quote_oracle)?.price;
+        .oracle_id();
+    let quote_oracle_price = oracle_map.get_price_data(quote_oracle_id)?.price;
 
-    let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+    let oracle_price_data = oracle_map.get_price_data(market.oracle_id())?;
 
     let oracle_price = if market.status == MarketStatus::Settlement {
         market.expiry_price
@@ -3160,6 +3904,18 @@ pub fn place_spot_order(
 
     validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
 
+    validate!(
+        params.trailing_stop_offset.is_none(),
+        ErrorCode::InvalidTrailingStopOrder,
+        "trailing stops are only supported for perp orders"
+    )?;
+
+    validate!(
+        options.bracket_id == 0,
+        ErrorCode::InvalidBracketOrder,
+        "brackets are only supported for perp orders"
+    )?;
+
     if options.try_expire_orders {
         expire_orders(
             user,
@@ -3180,16 +3936,19 @@ pub fn place_spot_order(
         )?;
     }
 
-    let max_ts = match params.max_ts {
-        Some(max_ts) => max_ts,
-        None => match params.order_type {
-            OrderType::Market | OrderType::Oracle => now.safe_add(30)?,
-            _ => 0_i64,
-        },
+    let default_max_ts = match params.order_type {
+        OrderType::Market | OrderType::Oracle => now.safe_add(30)?,
+        _ => 0_i64,
     };
+    let (max_ts, time_in_force) = get_max_ts_and_time_in_force(&params, default_max_ts)?;
 
-    if max_ts != 0 && max_ts < now {
-        msg!("max_ts ({}) < now ({}), skipping order", max_ts, now);
+    if time_in_force.is_expired(max_ts, now, slot)? {
+        msg!(
+            "max_ts ({}) passed, skipping order. now {} slot {}",
+            max_ts,
+            now,
+            slot
+        );
         return Ok(());
     }
 
@@ -3230,7 +3989,7 @@ pub fn place_spot_order(
     let token_amount = user.spot_positions[spot_position_index].get_token_amount(spot_market)?;
     let signed_token_amount = get_signed_token_amount(token_amount, &balance_type)?;
 
-    let oracle_price_data = *oracle_map.get_price_data(&spot_market.oracle)?;
+    let oracle_price_data = *oracle_map.get_price_data(spot_market.oracle_id())?;
 
     // Increment open orders for existing position
     let (existing_position_direction, order_base_asset_amount) = {
@@ -3295,7 +4054,16 @@ pub fn place_spot_order(
         "must be spot order"
     )?;
 
-    let new_order = Order {
+    let (display_base_asset_amount, hidden_base_asset_amount, bit_flags) =
+        get_iceberg_order_fields(
+            &params,
+            order_base_asset_amount,
+            step_size,
+            options.bit_flags,
+        )?;
+    validate_fill_requirement_params(&params, order_base_asset_amount, time_in_force)?;
+
+    let mut new_order = Order {
         status: OrderStatus::Open,
         order_type: params.order_type,
         market_type: params.market_type,
@@ -3305,7 +4073,7 @@ pub fn place_spot_order(
         market_index: params.market_index,
         price: standardize_price(params.price, spot_market.order_tick_size, params.direction)?,
         existing_position_direction,
-        base_asset_amount: order_base_asset_amount,
+        base_asset_amount: display_base_asset_amount,
         base_asset_amount_filled: 0,
         quote_asset_amount_filled: 0,
         direction: params.direction,
@@ -3323,9 +4091,15 @@ pub fn place_spot_order(
         auction_end_price,
         auction_duration,
         max_ts,
-        padding: [0; 3],
+        bit_flags,
+        bracket_id: options.bracket_id,
+        time_in_force,
     };
 
+    if new_order.is_iceberg() {
+        new_order.auction_end_price = hidden_base_asset_amount.cast()?;
+    }
+
     validate_spot_order(
         &new_order,
         spot_market.order_step_size,
@@ -3448,7 +4222,18 @@ pub fn fill_spot_order(
     );
 
     {
-        let spot_market = spot_market_map.get_ref(&order_market_index)?;
+        let spot_market = &mut spot_market_map.get_ref_mut(&order_market_index)?;
+        if spot_market.has_oracle_circuit_breaker {
+            let circuit_breaker =
+                oracle_map.get_oracle_circuit_breaker(MarketType::Spot, order_market_index)?;
+            controller::oracle_circuit_breaker::update_spot_market_oracle_circuit_breaker(
+                spot_market,
+                &mut load_mut!(circuit_breaker)?,
+                oracle_map,
+                now,
+            )?;
+        }
+
         validate!(
             spot_market.fills_enabled(),
             ErrorCode::MarketFillOrderPaused,
@@ -3507,7 +4292,11 @@ pub fn fill_spot_order(
     };
 
     let oracle_price = oracle_map
-        .get_price_data(&spot_market_map.get_ref_mut(&order_market_index)?.oracle)?
+        .get_price_data(
+            spot_market_map
+                .get_ref_mut(&order_market_index)?
+                .oracle_id(),
+        )?
         .price;
     let maker_order_info = get_spot_maker_orders_info(
         perp_market_map,
@@ -3527,11 +4316,11 @@ pub fn fill_spot_order(
 
     {
         let mut quote_market = spot_market_map.get_quote_spot_market_mut()?;
-        let oracle_price_data = oracle_map.get_price_data(&quote_market.oracle)?;
+        let oracle_price_data = oracle_map.get_price_data(quote_market.oracle_id())?;
         update_spot_market_cumulative_interest(&mut quote_market, Some(oracle_price_data), now)?;
 
         let mut base_market = spot_market_map.get_ref_mut(&order_market_index)?;
-        let oracle_price_data = oracle_map.get_price_data(&base_market.oracle)?;
+        let oracle_price_data = oracle_map.get_price_data(base_market.oracle_id())?;
         update_spot_market_cumulative_interest(&mut base_market, Some(oracle_price_data), now)?;
 
         let oracle_too_divergent_with_twap_5min = is_oracle_too_divergent_with_twap_5min(
@@ -3555,7 +4344,7 @@ pub fn fill_spot_order(
         }
     }
 
-    let should_expire_order = should_expire_order_before_fill(user, order_index, now)?;
+    let should_expire_order = should_expire_order_before_fill(user, order_index, now, slot)?;
 
     let should_cancel_reduce_only = if user.orders[order_index].reduce_only {
         let market_index = user.orders[order_index].market_index;
@@ -3635,7 +4424,7 @@ pub fn fill_spot_order(
             spot_market.get_precision(),
         )?;
 
-        let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?.price;
+        let oracle_price = oracle_map.get_price_data(spot_market.oracle_id())?.price;
         let oracle_twap_5min = spot_market
             .historical_oracle_data
             .last_oracle_price_twap_5min;
@@ -3808,7 +4597,7 @@ fn get_spot_maker_orders_info(
                 )?
             };
 
-            let should_expire_order = should_expire_order(&maker, maker_order_index, now)?;
+            let should_expire_order = should_expire_order(&maker, maker_order_index, now, slot)?;
 
             let should_cancel_reduce_only_order = should_cancel_reduce_only_order(
                 &maker.orders[maker_order_index],
@@ -3918,7 +4707,7 @@ fn fulfill_spot_order(
     }
 
     // todo come up with fallback price
-    let oracle_price = oracle_map.get_price_data(&base_market.oracle)?.price;
+    let oracle_price = oracle_map.get_price_data(base_market.oracle_id())?.price;
     let limit_price = user.orders[user_order_index].get_limit_price(
         Some(oracle_price),
         None,
@@ -4018,8 +4807,8 @@ fn fulfill_spot_order(
         .force_get_spot_position_mut(base_market_index)?
         .get_signed_token_amount(&base_market)?;
 
-    let quote_price = oracle_map.get_price_data(&quote_market.oracle)?.price;
-    let base_price = oracle_map.get_price_data(&base_market.oracle)?.price;
+    let quote_price = oracle_map.get_price_data(quote_market.oracle_id())?.price;
+    let base_price = oracle_map.get_price_data(base_market.oracle_id())?.price;
 
     let strict_quote_price = StrictOraclePrice::new(
         quote_price,
@@ -4228,7 +5017,7 @@ pub fn fulfill_spot_order_with_match(
     }
 
     let market_index = taker.orders[taker_order_index].market_index;
-    let oracle_price = oracle_map.get_price_data(&base_market.oracle)?.price;
+    let oracle_price = oracle_map.get_price_data(base_market.oracle_id())?.price;
     let taker_price = match taker.orders[taker_order_index].get_limit_price(
         Some(oracle_price),
         None,
@@ -4527,7 +5316,7 @@ pub fn fulfill_spot_order_with_match(
         Some(taker.orders[taker_order_index]),
         Some(*maker_key),
         Some(maker.orders[maker_order_index]),
-        oracle_map.get_price_data(&base_market.oracle)?.price,
+        oracle_map.get_price_data(base_market.oracle_id())?.price,
     )?;
     emit_stack::<_, { OrderActionRecord::SIZE }>(order_action_record)?;
 
@@ -4563,7 +5352,7 @@ pub fn fulfill_spot_order_with_external_market(
     fee_structure: &FeeStructure,
     fulfillment_params: &mut dyn SpotFulfillmentParams,
 ) -> DriftResult<(u64, u64)> {
-    let oracle_price = oracle_map.get_price_data(&base_market.oracle)?.price;
+    let oracle_price = oracle_map.get_price_data(base_market.oracle_id())?.price;
     let taker_price = taker.orders[taker_order_index].get_limit_price(
         Some(oracle_price),
         None,
@@ -4869,9 +5658,10 @@ pub fn trigger_spot_order(
     let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
         MarketType::Spot,
         spot_market.market_index,
-        &spot_market.oracle,
+        spot_market.oracle_id(),
         spot_market.historical_oracle_data.last_oracle_price_twap,
         spot_market.get_max_confidence_interval_multiplier()?,
+        spot_market.get_validity_guard_rails_override(),
     )?;
     let strict_oracle_price = StrictOraclePrice {
         current: oracle_price_data.price,
@@ -5022,7 +5812,7 @@ pub fn expire_orders(
     slot: u64,
 ) -> DriftResult {
     for order_index in 0..user.orders.len() {
-        if !should_expire_order(user, order_index, now)? {
+        if !should_expire_order(user, order_index, now, slot)? {
             continue;
         }
 
@@ -5044,3 +5834,86 @@ pub fn expire_orders(
 
     Ok(())
 }
+
+/// Expires the stale orders of every user in user_map. The filler earns a flat fee for each order
+/// expired, paid from the user's quote balance. What a sweep pays for a user is capped at their
+/// quote deposits so it can't leave them borrowing
+pub fn sweep_expired_orders(
+    state: &State,
+    user_map: &UserMap,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let filler = &mut load_mut!(filler)?;
+
+    for (user_key, user_account_loader) in user_map.0.iter() {
+        if *user_key == filler_key {
+            msg!("filler can not sweep its own orders");
+            continue;
+        }
+
+        let user = &mut load_mut!(user_account_loader)?;
+
+        if user.is_being_liquidated() || user.is_bankrupt() {
+            continue;
+        }
+
+        let max_total_fee = {
+            let quote_spot_market = spot_market_map.get_quote_spot_market()?;
+            user.get_quote_spot_position()
+                .get_signed_token_amount(&quote_spot_market)?
+                .max(0)
+                .cast::<u64>()?
+        };
+        let mut total_fee = 0_u64;
+
+        for order_index in 0..user.orders.len() {
+            if !should_expire_order(user, order_index, now, slot)? {
+                continue;
+            }
+
+            // the record logs the fee actually paid once the cap kicks in
+            let fee = match user.orders[order_index].market_type {
+                MarketType::Spot => state.spot_fee_structure.flat_filler_fee,
+                MarketType::Perp => state.perp_fee_structure.flat_filler_fee,
+            }
+            .min(max_total_fee.safe_sub(total_fee)?);
+
+            total_fee = total_fee.safe_add(fee)?;
+
+            cancel_order(
+                order_index,
+                user,
+                user_key,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
+                now,
+                slot,
+                OrderActionExplanation::OrderExpired,
+                Some(&filler_key),
+                fee,
+                false,
+            )?;
+        }
+
+        if total_fee > 0 {
+            pay_keeper_flat_reward_for_spot(
+                user,
+                Some(filler),
+                spot_market_map.get_quote_spot_market_mut()?.deref_mut(),
+                total_fee,
+                slot,
+            )?;
+        }
+    }
+
+    Ok(())
+}
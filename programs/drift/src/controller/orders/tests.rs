@@ -1731,6 +1731,7 @@ pub mod fulfill_order_with_maker_order {
                 &oracle_price_key,
                 market.amm.historical_oracle_data.last_oracle_price_twap,
                 market.get_max_confidence_interval_multiplier().unwrap(),
+                market.get_validity_guard_rails_override(),
             )
             .unwrap();
 
@@ -12119,3 +12120,462 @@ mod update_maker_fills_map {
         assert_eq!(*map.get(&maker_key).unwrap(), -2 * fill as i64);
     }
 }
+
+pub mod bracket_orders {
+    use std::str::FromStr;
+
+    use crate::controller::orders::{cancel_order, update_bracket_orders_after_fill};
+    use crate::controller::position::PositionDirection;
+    use crate::create_account_info;
+    use crate::create_anchor_account_info;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, BASE_PRECISION_U64, PEG_PRECISION,
+        PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
+    };
+    use crate::state::events::OrderActionExplanation;
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::perp_market::{PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::SpotMarket;
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{
+        MarketType, OrderBitFlag, OrderStatus, OrderTriggerCondition, OrderType, User,
+    };
+    use crate::test_utils::*;
+    use crate::test_utils::{create_account_info, get_positions, get_pyth_price};
+
+    use super::*;
+
+    fn get_bracket_orders(parent_base_asset_amount_filled: u64) -> [Order; 32] {
+        let leg_bit_flags = OrderBitFlag::BracketLeg as u8
+            | OrderBitFlag::BracketPending as u8
+            | OrderBitFlag::BracketHasSibling as u8;
+
+        let mut orders = [Order::default(); 32];
+        orders[0] = Order {
+            market_index: 0,
+            order_id: 1,
+            status: OrderStatus::Open,
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            base_asset_amount_filled: parent_base_asset_amount_filled,
+            price: 100 * PRICE_PRECISION_U64,
+            bit_flags: OrderBitFlag::BracketParent as u8,
+            bracket_id: 1,
+            ..Order::default()
+        };
+        orders[1] = Order {
+            market_index: 0,
+            order_id: 2,
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            reduce_only: true,
+            trigger_price: 110 * PRICE_PRECISION_U64,
+            trigger_condition: OrderTriggerCondition::Above,
+            bit_flags: leg_bit_flags,
+            bracket_id: 1,
+            ..Order::default()
+        };
+        orders[2] = Order {
+            market_index: 0,
+            order_id: 3,
+            status: OrderStatus::Open,
+            order_type: OrderType::TriggerMarket,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            base_asset_amount: BASE_PRECISION_U64,
+            reduce_only: true,
+            trigger_price: 90 * PRICE_PRECISION_U64,
+            trigger_condition: OrderTriggerCondition::Below,
+            bit_flags: leg_bit_flags,
+            bracket_id: 1,
+            ..Order::default()
+        };
+
+        orders
+    }
+
+    #[test]
+    fn cancel_parent_and_legs() {
+        let slot = 0_u64;
+        let now = 0_i64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price: oracle_price.agg.price,
+                    last_oracle_price_twap: oracle_price.agg.price,
+                    last_oracle_price_twap_5min: oracle_price.agg.price,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_one(&usdc_spot_market_account_info, true).unwrap();
+
+        let user_key = Pubkey::default();
+
+        // canceling the parent before it fills cancels both legs
+        let mut user = User {
+            orders: get_bracket_orders(0),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 3,
+                open_bids: BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+
+        cancel_order(
+            0,
+            &mut user,
+            &user_key,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::None,
+            None,
+            0,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(user.orders[0], Order::default());
+        assert_eq!(user.orders[1], Order::default());
+        assert_eq!(user.orders[2], Order::default());
+        assert_eq!(user.perp_positions[0].open_orders, 0);
+        assert_eq!(user.perp_positions[0].open_bids, 0);
+
+        // canceling a partially filled parent leaves the legs to protect the fill
+        let mut user = User {
+            orders: get_bracket_orders(BASE_PRECISION_U64 / 2),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64 / 2,
+                open_orders: 3,
+                open_bids: BASE_PRECISION_I64 / 2,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+
+        cancel_order(
+            0,
+            &mut user,
+            &user_key,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::None,
+            None,
+            0,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(user.orders[0], Order::default());
+        assert!(user.orders[1].is_bracket_leg());
+        assert!(!user.orders[1].is_pending_bracket_leg());
+        assert!(user.orders[2].is_bracket_leg());
+        assert!(!user.orders[2].is_pending_bracket_leg());
+        assert_eq!(user.perp_positions[0].open_orders, 2);
+
+        // canceling one leg cancels its sibling
+        cancel_order(
+            2,
+            &mut user,
+            &user_key,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            now,
+            slot,
+            OrderActionExplanation::None,
+            None,
+            0,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(user.orders[1], Order::default());
+        assert_eq!(user.orders[2], Order::default());
+        assert_eq!(user.perp_positions[0].open_orders, 0);
+    }
+
+    #[test]
+    fn parent_and_leg_fills() {
+        let slot = 0_u64;
+        let now = 0_i64;
+
+        let mut oracle_price = get_pyth_price(100, 6);
+        let oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            oracle_price,
+            &oracle_price_key,
+            &pyth_program,
+            oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                order_step_size: 1000,
+                order_tick_size: 1,
+                oracle: oracle_price_key,
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            deposit_balance: SPOT_BALANCE_PRECISION,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_one(&usdc_spot_market_account_info, true).unwrap();
+
+        let user_key = Pubkey::default();
+
+        // parent fully filled and removed from the orders
+        let mut orders = get_bracket_orders(0);
+        orders[0] = Order::default();
+        let mut user = User {
+            orders,
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
+                open_orders: 2,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+
+        update_bracket_orders_after_fill(
+            &mut user,
+            &user_key,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            now,
+            slot,
+        )
+        .unwrap();
+
+        assert!(!user.orders[1].is_pending_bracket_leg());
+        assert!(!user.orders[2].is_pending_bracket_leg());
+        assert_eq!(user.perp_positions[0].open_orders, 2);
+
+        // take profit fully filled and removed from the orders
+        user.orders[1] = Order::default();
+        user.perp_positions[0].base_asset_amount = 0;
+        user.perp_positions[0].open_orders = 1;
+
+        update_bracket_orders_after_fill(
+            &mut user,
+            &user_key,
+            &market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            now,
+            slot,
+        )
+        .unwrap();
+
+        assert_eq!(user.orders[2], Order::default());
+        assert_eq!(user.perp_positions[0].open_orders, 0);
+    }
+}
+
+pub mod iceberg_orders {
+    use crate::controller::orders::fulfill_perp_order_with_match;
+    use crate::controller::position::PositionDirection;
+    use crate::math::constants::{
+        BASE_PRECISION_I64, BASE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64,
+    };
+    use crate::state::perp_market::PerpMarket;
+    use crate::state::user::{OrderBitFlag, OrderType, User, UserStats};
+    use crate::test_utils::{get_orders, get_positions};
+
+    use super::*;
+
+    #[test]
+    fn fill_refreshes_display_size() {
+        let mut taker = User {
+            orders: get_orders(Order {
+                market_index: 0,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                base_asset_amount: 2 * BASE_PRECISION_U64,
+                slot: 0,
+                auction_start_price: 100 * PRICE_PRECISION_I64,
+                auction_end_price: 200 * PRICE_PRECISION_I64,
+                auction_duration: 5,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_bids: 2 * BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+
+        // shows 1 with 2 hidden
+        let mut maker = User {
+            orders: get_orders(Order {
+                market_index: 0,
+                post_only: true,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                base_asset_amount: BASE_PRECISION_U64,
+                auction_end_price: 2 * BASE_PRECISION_I64,
+                bit_flags: OrderBitFlag::Iceberg as u8,
+                price: 100 * PRICE_PRECISION_U64,
+                ..Order::default()
+            }),
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                open_orders: 1,
+                open_asks: -3 * BASE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            ..User::default()
+        };
+
+        let mut market = PerpMarket::default_test();
+
+        let now = 1_i64;
+        let slot = 1_u64;
+
+        let fee_structure = get_fee_structure();
+
+        let (taker_key, maker_key, filler_key) = get_user_keys();
+
+        let mut taker_stats = UserStats::default();
+        let mut maker_stats = UserStats::default();
+
+        let taker_limit_price = taker.orders[0]
+            .get_limit_price(None, None, slot, market.amm.order_tick_size)
+            .unwrap();
+
+        let (_, _, base_asset_amount_filled) = fulfill_perp_order_with_match(
+            &mut market,
+            &mut taker,
+            &mut taker_stats,
+            0,
+            &taker_key,
+            &mut maker,
+            &mut Some(&mut maker_stats),
+            0,
+            &maker_key,
+            &mut None,
+            &mut None,
+            &filler_key,
+            &mut None,
+            &mut None,
+            0,
+            None,
+            taker_limit_price,
+            now,
+            slot,
+            &fee_structure,
+            &mut get_oracle_map(),
+        )
+        .unwrap();
+
+        // only the shown size fills
+        assert_eq!(base_asset_amount_filled, BASE_PRECISION_U64);
+        assert_eq!(
+            taker.orders[0]
+                .get_base_asset_amount_unfilled(None)
+                .unwrap(),
+            BASE_PRECISION_U64
+        );
+
+        let maker_order = &maker.orders[0];
+        assert_eq!(maker_order.base_asset_amount, 2 * BASE_PRECISION_U64);
+        assert_eq!(maker_order.base_asset_amount_filled, BASE_PRECISION_U64);
+        assert_eq!(
+            maker_order.get_base_asset_amount_unfilled(None).unwrap(),
+            BASE_PRECISION_U64
+        );
+        assert_eq!(
+            maker_order.get_hidden_base_asset_amount().unwrap(),
+            BASE_PRECISION_U64
+        );
+
+        // hidden size stays in open asks for margin
+        let maker_position = &maker.perp_positions[0];
+        assert_eq!(maker_position.base_asset_amount, -BASE_PRECISION_I64);
+        assert_eq!(maker_position.open_asks, -2 * BASE_PRECISION_I64);
+        assert_eq!(maker_position.open_orders, 1);
+    }
+}
@@ -29,10 +29,10 @@ use crate::state::margin_calculation::MarginContext;
 use crate::state::events::{OrderActionExplanation, SettlePnlExplanation, SettlePnlRecord};
 use crate::state::oracle_map::OracleMap;
 use crate::state::paused_operations::PerpOperation;
-use crate::state::perp_market::MarketStatus;
+use crate::state::perp_market::{MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::settle_pnl_mode::SettlePnlMode;
-use crate::state::spot_market::{SpotBalance, SpotBalanceType};
+use crate::state::spot_market::{SpotBalance, SpotBalanceType, SpotMarket};
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::State;
 use crate::state::user::{MarketType, User};
@@ -48,6 +48,38 @@ mod tests;
 #[cfg(test)]
 mod delisting;
 
+/// The pnl pool (with a buffer from the fee pool) in excess of the users' net pnl
+pub fn calculate_max_pnl_pool_excess(
+    perp_market: &PerpMarket,
+    spot_market: &SpotMarket,
+    oracle_price: i64,
+) -> DriftResult<i128> {
+    let pnl_pool_token_amount = get_token_amount(
+        perp_market.pnl_pool.scaled_balance,
+        spot_market,
+        perp_market.pnl_pool.balance_type(),
+    )?;
+
+    let fraction_of_fee_pool_token_amount = get_token_amount(
+        perp_market.amm.fee_pool.scaled_balance,
+        spot_market,
+        perp_market.amm.fee_pool.balance_type(),
+    )?
+    .safe_div(5)?;
+
+    // add a buffer from fee pool for pnl pool balance
+    let pnl_tokens_available: i128 = pnl_pool_token_amount
+        .safe_add(fraction_of_fee_pool_token_amount)?
+        .cast()?;
+
+    let net_user_pnl = calculate_net_user_pnl(&perp_market.amm, oracle_price)?;
+    if net_user_pnl < pnl_tokens_available {
+        pnl_tokens_available.safe_sub(net_user_pnl.max(0))
+    } else {
+        Ok(0)
+    }
+}
+
 pub fn settle_pnl(
     market_index: u16,
     user: &mut User,
@@ -70,7 +102,8 @@ pub fn settle_pnl(
 
     let mut market = perp_market_map.get_ref_mut(&market_index)?;
 
-    let oracle_price = oracle_map.get_price_data(&market.amm.oracle)?.price;
+    let oracle_price =
+        market.get_bounded_oracle_price(oracle_map.get_price_data(market.oracle_id())?.price);
 
     validate_market_within_price_band(&market, state, oracle_price)?;
 
@@ -79,6 +112,20 @@ pub fn settle_pnl(
     drop(market);
 
     let position_index = get_position_index(&user.perp_positions, market_index)?;
+
+    // isolated collateral lives in the position's quote_asset_amount, settling would move it into the cross account
+    if user.is_isolated_perp_position(position_index) {
+        let msg = format!(
+            "Cant settle pnl for isolated perp position in Market = {}",
+            market_index
+        );
+        return mode.result(
+            ErrorCode::CantSettlePnlForIsolatedPerpPosition,
+            market_index,
+            &msg,
+        );
+    }
+
     let unrealized_pnl = user.perp_positions[position_index].get_unrealized_pnl(oracle_price)?;
 
     // cannot settle negative pnl this way on a user who is in liquidation territory
@@ -159,12 +206,13 @@ pub fn settle_pnl(
             let (_, oracle_validity) = oracle_map.get_price_data_and_validity(
                 MarketType::Perp,
                 perp_market.market_index,
-                &perp_market.amm.oracle,
+                perp_market.oracle_id(),
                 perp_market
                     .amm
                     .historical_oracle_data
                     .last_oracle_price_twap,
                 perp_market.get_max_confidence_interval_multiplier()?,
+                perp_market.get_validity_guard_rails_override(),
             )?;
 
             if !is_oracle_valid_for_action(oracle_validity, Some(DriftAction::SettlePnl))?
@@ -244,30 +292,8 @@ pub fn settle_pnl(
         );
     }
 
-    let pnl_pool_token_amount = get_token_amount(
-        perp_market.pnl_pool.scaled_balance,
-        spot_market,
-        perp_market.pnl_pool.balance_type(),
-    )?;
-
-    let fraction_of_fee_pool_token_amount = get_token_amount(
-        perp_market.amm.fee_pool.scaled_balance,
-        spot_market,
-        perp_market.amm.fee_pool.balance_type(),
-    )?
-    .safe_div(5)?;
-
-    // add a buffer from fee pool for pnl pool balance
-    let pnl_tokens_available: i128 = pnl_pool_token_amount
-        .safe_add(fraction_of_fee_pool_token_amount)?
-        .cast()?;
-
-    let net_user_pnl = calculate_net_user_pnl(&perp_market.amm, oracle_price)?;
-    let max_pnl_pool_excess = if net_user_pnl < pnl_tokens_available {
-        pnl_tokens_available.safe_sub(net_user_pnl.max(0))?
-    } else {
-        0
-    };
+    let max_pnl_pool_excess =
+        calculate_max_pnl_pool_excess(perp_market, spot_market, oracle_price)?;
 
     let user_unsettled_pnl: i128 =
         user.perp_positions[position_index].get_claimable_pnl(oracle_price, max_pnl_pool_excess)?;
@@ -508,6 +508,7 @@ pub fn update_position_with_base_asset_amount(
     )?;
 
     controller::amm::update_spread_reserves(&mut market.amm)?;
+    market.update_bounded_spread_reserves()?;
 
     Ok((quote_asset_amount, quote_asset_amount_surplus, pnl))
 }
@@ -1,3 +1,4 @@
+use std::cell::Ref;
 use std::cmp::min;
 
 use anchor_lang::prelude::AccountInfo;
@@ -5,6 +6,8 @@ use anchor_lang::prelude::*;
 use solana_program::msg;
 
 use crate::controller::amm::update_spreads;
+use crate::controller::funding::accrue_funding;
+use crate::controller::oracle_circuit_breaker::update_perp_market_oracle_circuit_breaker;
 use crate::controller::spot_balance::update_spot_balances;
 use crate::error::ErrorCode;
 use crate::error::*;
@@ -24,8 +27,11 @@ use crate::math::repeg;
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_token_amount;
 
+use crate::state::load_ref::load_ref;
+use crate::state::option_market::OptionMarket;
 use crate::state::oracle::OraclePriceData;
 use crate::state::oracle_map::OracleMap;
+use crate::state::paused_operations::PerpOperation;
 use crate::state::perp_market::{MarketStatus, PerpMarket};
 use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_market::SpotBalanceType;
@@ -109,7 +115,8 @@ pub fn update_amms(
     let updated = true; // todo
     for (_key, market_account_loader) in perp_market_map.0.iter_mut() {
         let market = &mut load_mut!(market_account_loader)?;
-        let oracle_price_data = &oracle_map.get_price_data(&market.amm.oracle)?;
+        update_oracle_circuit_breaker(market, oracle_map, now)?;
+        let oracle_price_data = &oracle_map.get_price_data(market.oracle_id())?;
         _update_amm(market, oracle_price_data, state, now, clock_slot)?;
     }
 
@@ -124,7 +131,8 @@ pub fn update_amm(
     clock: &Clock,
 ) -> DriftResult<i128> {
     let market = &mut perp_market_map.get_ref_mut(&market_index)?;
-    let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle)?;
+    update_oracle_circuit_breaker(market, oracle_map, clock.unix_timestamp)?;
+    let oracle_price_data = oracle_map.get_price_data(market.oracle_id())?;
 
     let cost_of_update = _update_amm(
         market,
@@ -137,6 +145,27 @@ pub fn update_amm(
     Ok(cost_of_update)
 }
 
+/// Evaluated before the amm update so the oracle move is measured against the 5 min oracle twap
+/// from before this update
+fn update_oracle_circuit_breaker(
+    market: &mut PerpMarket,
+    oracle_map: &mut OracleMap,
+    now: i64,
+) -> DriftResult {
+    if !market.has_oracle_circuit_breaker {
+        return Ok(());
+    }
+
+    let circuit_breaker =
+        oracle_map.get_oracle_circuit_breaker(MarketType::Perp, market.market_index)?;
+    update_perp_market_oracle_circuit_breaker(
+        market,
+        &mut load_mut!(circuit_breaker)?,
+        oracle_map,
+        now,
+    )
+}
+
 pub fn _update_amm(
     market: &mut PerpMarket,
     oracle_price_data: &OraclePriceData,
@@ -151,12 +180,20 @@ pub fn _update_amm(
         return Ok(0);
     }
 
+    let oracle_price_data = &OraclePriceData {
+        price: market.get_bounded_oracle_price(oracle_price_data.price),
+        ..*oracle_price_data
+    };
+
     let oracle_validity = oracle::oracle_validity(
         MarketType::Perp,
         market.market_index,
         market.amm.historical_oracle_data.last_oracle_price_twap,
         oracle_price_data,
-        &state.oracle_guard_rails.validity,
+        &state
+            .oracle_guard_rails
+            .validity
+            .with_override(market.get_validity_guard_rails_override()),
         market.get_max_confidence_interval_multiplier()?,
         true,
     )?;
@@ -195,6 +232,14 @@ pub fn _update_amm(
 
     let reserve_price_after = market.amm.reserve_price()?;
 
+    // continuous funding accrues at the twaps in place since the last update, before they move
+    if market.amm.funding_params.is_continuous() {
+        let funding_paused = state.funding_paused()?
+            || market.is_operation_paused(PerpOperation::UpdateFunding)
+            || !is_oracle_valid_for_action(oracle_validity, Some(DriftAction::UpdateFunding))?;
+        accrue_funding(market, now, funding_paused)?;
+    }
+
     if is_oracle_valid_for_action(oracle_validity, Some(DriftAction::UpdateTwap))? {
         let sanitize_clamp_denominator = market.get_sanitize_clamp_denominator()?;
 
@@ -205,6 +250,8 @@ pub fn _update_amm(
             Some(reserve_price_after),
             sanitize_clamp_denominator,
         )?;
+
+        market.update_settlement_price_twap(oracle_price_data.price, now)?;
     }
 
     if is_oracle_valid_for_action(oracle_validity, Some(DriftAction::FillOrderAmm))? {
@@ -217,6 +264,7 @@ pub fn _update_amm(
     }
 
     update_spreads(&mut market.amm, reserve_price_after)?;
+    market.update_bounded_spread_reserves()?;
 
     Ok(amm_update_cost)
 }
@@ -239,7 +287,10 @@ pub fn update_amm_and_check_validity(
         market.market_index,
         risk_ema_price,
         oracle_price_data,
-        &state.oracle_guard_rails.validity,
+        &state
+            .oracle_guard_rails
+            .validity
+            .with_override(market.get_validity_guard_rails_override()),
         market.get_max_confidence_interval_multiplier()?,
         false,
     )?;
@@ -301,7 +352,7 @@ pub fn apply_cost_to_market(
 pub fn settle_expired_market(
     market_index: u16,
     market_map: &PerpMarketMap,
-    _oracle_map: &mut OracleMap,
+    oracle_map: &mut OracleMap,
     spot_market_map: &SpotMarketMap,
     _state: &State,
     clock: &Clock,
@@ -409,16 +460,35 @@ pub fn settle_expired_market(
         "Only support bank.decimals == QUOTE_PRECISION"
     )?;
 
-    let target_expiry_price = market.amm.historical_oracle_data.last_oracle_price_twap;
+    // options expire worthless or at their intrinsic value against the settlement window twap
+    let target_expiry_price = if market.is_option() {
+        let option_market_account_info = oracle_map.get_account_info(&market.amm.oracle)?;
+        let option_market: Ref<OptionMarket> =
+            load_ref(&option_market_account_info).or(Err(ErrorCode::UnableToLoadOracle))?;
+        option_market.get_settlement_price()?
+    } else if market.is_prediction_market() {
+        let oracle_price = oracle_map.get_price_data(market.oracle_id())?.price;
+        market.get_prediction_market_settlement_price(oracle_price)?
+    } else {
+        market.get_target_expiry_price()?
+    };
+
     validate!(
-        target_expiry_price > 0,
+        target_expiry_price > 0
+            || ((market.is_option() || market.is_prediction_market()) && target_expiry_price == 0),
         ErrorCode::MarketSettlementTargetPriceInvalid,
         "target_expiry_price <= 0 {}",
         target_expiry_price
     )?;
 
-    let expiry_price =
-        amm::calculate_expiry_price(&market.amm, target_expiry_price, pnl_pool_amount)?;
+    // prediction markets settle at exactly their outcome
+    let expiry_price = if market.is_prediction_market() {
+        target_expiry_price
+    } else if market.is_option() {
+        amm::calculate_expiry_price(&market.amm, target_expiry_price, pnl_pool_amount)?.max(0)
+    } else {
+        amm::calculate_expiry_price(&market.amm, target_expiry_price, pnl_pool_amount)?
+    };
 
     market.expiry_price = expiry_price;
     market.status = MarketStatus::Settlement;
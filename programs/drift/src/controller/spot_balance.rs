@@ -400,7 +400,7 @@ pub fn update_spot_market_and_check_validity(
         spot_market.market_index,
         risk_ema_price,
         oracle_price_data,
-        validity_guard_rails,
+        &validity_guard_rails.with_override(spot_market.get_validity_guard_rails_override()),
         spot_market.get_max_confidence_interval_multiplier()?,
         false,
     )?;
@@ -1,6 +1,6 @@
 use crate::signer::get_signer_seeds;
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, CloseAccount, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Burn, CloseAccount, Mint, MintTo, Token, TokenAccount, Transfer};
 
 pub fn send_from_program_vault<'info>(
     token_program: &Program<'info, Token>,
@@ -57,3 +57,40 @@ pub fn close_vault<'info>(
     let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signers);
     token::close_account(cpi_context)
 }
+
+pub fn mint_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    mint: &Account<'info, Mint>,
+    to: &Account<'info, TokenAccount>,
+    authority: &AccountInfo<'info>,
+    nonce: u8,
+    amount: u64,
+) -> Result<()> {
+    let signature_seeds = get_signer_seeds(&nonce);
+    let signers = &[&signature_seeds[..]];
+    let cpi_accounts = MintTo {
+        mint: mint.to_account_info().clone(),
+        to: to.to_account_info().clone(),
+        authority: authority.to_account_info().clone(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, signers);
+    token::mint_to(cpi_context, amount)
+}
+
+pub fn burn_tokens<'info>(
+    token_program: &Program<'info, Token>,
+    mint: &Account<'info, Mint>,
+    from: &Account<'info, TokenAccount>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Burn {
+        mint: mint.to_account_info().clone(),
+        from: from.to_account_info().clone(),
+        authority: authority.to_account_info().clone(),
+    };
+    let cpi_program = token_program.to_account_info();
+    let cpi_context = CpiContext::new(cpi_program, cpi_accounts);
+    token::burn(cpi_context, amount)
+}
@@ -0,0 +1,226 @@
+use anchor_lang::prelude::*;
+
+use crate::controller::orders::{pay_keeper_flat_reward_for_perps, place_perp_order};
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::safe_math::SafeMath;
+use crate::state::events::{TwapOrderAction, TwapOrderRecord};
+use crate::state::oracle_map::OracleMap;
+use crate::state::order_params::{OrderParams, PlaceOrderOptions, TwapOrderParams};
+use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::spot_market_map::SpotMarketMap;
+use crate::state::state::State;
+use crate::state::twap_order::{TwapOrder, TwapOrderStatus, UserTwapOrders};
+use crate::state::user::{MarketType, OrderBitFlag, OrderType, User};
+use crate::{get_then_update_id, load_mut, validate};
+
+pub fn place_twap_order(
+    user: &User,
+    user_key: &Pubkey,
+    user_twap_orders: &mut UserTwapOrders,
+    perp_market_map: &PerpMarketMap,
+    params: TwapOrderParams,
+    now: i64,
+) -> DriftResult {
+    validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
+
+    if user.is_reduce_only() {
+        validate!(
+            params.reduce_only,
+            ErrorCode::UserReduceOnly,
+            "order must be reduce only"
+        )?;
+    }
+
+    validate!(
+        params.num_slices > 0 && params.interval > 0,
+        ErrorCode::InvalidTwapOrder,
+        "num_slices ({}) and interval ({}) must be greater than 0",
+        params.num_slices,
+        params.interval
+    )?;
+
+    {
+        let perp_market = perp_market_map.get_ref(&params.market_index)?;
+
+        validate!(
+            params.base_asset_amount > 0
+                && params.base_asset_amount % perp_market.amm.order_step_size == 0,
+            ErrorCode::InvalidTwapOrder,
+            "base_asset_amount ({}) must be a multiple of the order step size ({})",
+            params.base_asset_amount,
+            perp_market.amm.order_step_size
+        )?;
+
+        validate!(
+            params
+                .base_asset_amount
+                .safe_div(params.num_slices.cast()?)?
+                >= perp_market.amm.min_order_size,
+            ErrorCode::InvalidTwapOrder,
+            "slices must be at least the min order size ({})",
+            perp_market.amm.min_order_size
+        )?;
+
+        validate!(
+            params.price % perp_market.amm.order_tick_size == 0,
+            ErrorCode::InvalidTwapOrder,
+            "price ({}) must be a multiple of the order tick size ({})",
+            params.price,
+            perp_market.amm.order_tick_size
+        )?;
+    }
+
+    let twap_order_index = user_twap_orders.get_free_twap_order_index()?;
+    let twap_id = get_then_update_id!(user_twap_orders, next_twap_id);
+
+    let twap_order = TwapOrder {
+        base_asset_amount: params.base_asset_amount,
+        price: params.price,
+        next_slice_ts: now,
+        interval: params.interval,
+        twap_id,
+        market_index: params.market_index,
+        num_slices: params.num_slices,
+        status: TwapOrderStatus::Open,
+        direction: params.direction,
+        reduce_only: params.reduce_only,
+        ..TwapOrder::default()
+    };
+    user_twap_orders.twap_orders[twap_order_index] = twap_order;
+
+    emit!(TwapOrderRecord {
+        ts: now,
+        user: *user_key,
+        action: TwapOrderAction::Place,
+        twap_order,
+        filler: None,
+        filler_reward: None,
+    });
+
+    Ok(())
+}
+
+/// Stops a twap order from releasing further slices. Slices already released stay open until they
+/// fill, expire or are canceled
+pub fn cancel_twap_order(
+    user_key: &Pubkey,
+    user_twap_orders: &mut UserTwapOrders,
+    twap_id: u32,
+    now: i64,
+) -> DriftResult {
+    let twap_order_index = user_twap_orders.get_twap_order_index(twap_id)?;
+    let twap_order = user_twap_orders.twap_orders[twap_order_index];
+    user_twap_orders.twap_orders[twap_order_index] = TwapOrder::default();
+
+    emit!(TwapOrderRecord {
+        ts: now,
+        user: *user_key,
+        action: TwapOrderAction::Cancel,
+        twap_order,
+        filler: None,
+        filler_reward: None,
+    });
+
+    Ok(())
+}
+
+/// Places the next slice of a twap order as a market order and pays the keeper a flat reward.
+/// Each slice expires when the next one is due
+pub fn release_twap_slice(
+    twap_id: u32,
+    state: &State,
+    user: &AccountLoader<User>,
+    user_twap_orders: &mut UserTwapOrders,
+    spot_market_map: &SpotMarketMap,
+    perp_market_map: &PerpMarketMap,
+    oracle_map: &mut OracleMap,
+    filler: &AccountLoader<User>,
+    clock: &Clock,
+) -> DriftResult {
+    let now = clock.unix_timestamp;
+    let slot = clock.slot;
+
+    let filler_key = filler.key();
+    let user_key = user.key();
+    let user = &mut load_mut!(user)?;
+
+    let twap_order_index = user_twap_orders.get_twap_order_index(twap_id)?;
+    let twap_order = &mut user_twap_orders.twap_orders[twap_order_index];
+
+    validate!(
+        now >= twap_order.next_slice_ts,
+        ErrorCode::InvalidTwapOrder,
+        "next slice can not be released until {}",
+        twap_order.next_slice_ts
+    )?;
+
+    let market_index = twap_order.market_index;
+    let order_step_size = perp_market_map.get_ref(&market_index)?.amm.order_step_size;
+    let base_asset_amount = twap_order.get_next_slice_base_asset_amount(order_step_size)?;
+
+    let order_id = user.next_order_id;
+    let params = OrderParams {
+        order_type: OrderType::Market,
+        market_type: MarketType::Perp,
+        direction: twap_order.direction,
+        base_asset_amount,
+        price: twap_order.price,
+        market_index,
+        reduce_only: twap_order.reduce_only,
+        max_ts: Some(now.safe_add(twap_order.interval)?),
+        ..OrderParams::default()
+    };
+
+    place_perp_order(
+        state,
+        user,
+        user_key,
+        perp_market_map,
+        spot_market_map,
+        oracle_map,
+        clock,
+        params,
+        PlaceOrderOptions::default().set_bit_flag(OrderBitFlag::TwapSlice),
+    )?;
+
+    validate!(
+        user.get_order(order_id).is_some(),
+        ErrorCode::InvalidTwapOrder,
+        "slice was not placed"
+    )?;
+
+    twap_order.record_slice(base_asset_amount, order_id, now)?;
+
+    let is_filler_taker = user_key == filler_key;
+    let mut filler = if !is_filler_taker {
+        Some(load_mut!(filler)?)
+    } else {
+        None
+    };
+
+    let filler_reward = pay_keeper_flat_reward_for_perps(
+        user,
+        filler.as_deref_mut(),
+        &mut perp_market_map.get_ref_mut(&market_index)?,
+        state.perp_fee_structure.flat_filler_fee,
+        slot,
+    )?;
+
+    emit!(TwapOrderRecord {
+        ts: now,
+        user: user_key,
+        action: TwapOrderAction::ReleaseSlice,
+        twap_order: *twap_order,
+        filler: Some(filler_key),
+        filler_reward: Some(filler_reward),
+    });
+
+    if twap_order.is_complete() {
+        *twap_order = TwapOrder::default();
+    }
+
+    user.update_last_active_slot(slot);
+
+    Ok(())
+}
@@ -557,6 +557,70 @@ pub enum ErrorCode {
     OracleWrongWriteAuthority,
     #[msg("Oracle vaa owner must be wormhole program")]
     OracleWrongVaaOwner,
+    #[msg("Invalid Isolated Perp Position")]
+    InvalidIsolatedPerpPosition,
+    #[msg("Cant settle pnl for isolated perp position")]
+    CantSettlePnlForIsolatedPerpPosition,
+    #[msg("Portfolio margin not supported for lp positions")]
+    PortfolioMarginLpNotSupported,
+    #[msg("Invalid bracket order")]
+    InvalidBracketOrder,
+    #[msg("Invalid trailing stop order")]
+    InvalidTrailingStopOrder,
+    #[msg("Invalid twap order")]
+    InvalidTwapOrder,
+    #[msg("Invalid iceberg order")]
+    InvalidIcebergOrder,
+    #[msg("Invalid fill requirement")]
+    InvalidFillRequirement,
+    #[msg("Fill or kill order was not filled in full")]
+    FillOrKillOrderNotFilled,
+    #[msg("Order filled less than its minimum fill")]
+    MinimumFillNotMet,
+    #[msg("Invalid scale order")]
+    InvalidScaleOrder,
+    #[msg("User heartbeat has not timed out")]
+    HeartbeatNotExpired,
+    #[msg("Invalid oracle guard rails override")]
+    InvalidOracleGuardRailsOverride,
+    #[msg("Invalid dated future params")]
+    InvalidFutureParams,
+    #[msg("Invalid perp market rollover")]
+    InvalidPerpMarketRollover,
+    #[msg("Invalid option market")]
+    InvalidOptionMarket,
+    #[msg("Invalid prediction market order")]
+    InvalidPredictionMarketOrder,
+    #[msg("Invalid prediction market")]
+    InvalidPredictionMarket,
+    #[msg("Invalid funding params")]
+    InvalidFundingParams,
+    #[msg("Invalid delegate permissions")]
+    InvalidDelegatePermissions,
+    #[msg("Delegate action not allowed")]
+    DelegateActionNotAllowed,
+    #[msg("Withdraw guard not found")]
+    WithdrawGuardNotFound,
+    #[msg("Withdraw destination not allowlisted")]
+    WithdrawDestinationNotAllowlisted,
+    #[msg("Invalid withdraw guard request")]
+    InvalidWithdrawGuardRequest,
+    #[msg("Withdraw guard request timelocked")]
+    WithdrawGuardRequestTimelocked,
+    #[msg("Invalid insurance fund lockup")]
+    InvalidInsuranceFundLockup,
+    #[msg("Insurance fund stake locked")]
+    InsuranceFundStakeLocked,
+    #[msg("Invalid insurance fund tranche")]
+    InvalidInsuranceFundTranche,
+    #[msg("Invalid heartbeat")]
+    InvalidHeartbeat,
+    #[msg("Oracle aggregate not found")]
+    OracleAggregateNotFound,
+    #[msg("Invalid oracle circuit breaker params")]
+    InvalidOracleCircuitBreakerParams,
+    #[msg("Oracle circuit breaker not found")]
+    OracleCircuitBreakerNotFound,
 }
 
 #[macro_export]
@@ -1,3 +1,4 @@
+use std::cell::Ref;
 use std::convert::identity;
 use std::mem::size_of;
 
@@ -15,11 +16,11 @@ use crate::instructions::constraints::*;
 use crate::math::casting::Cast;
 use crate::math::constants::{
     DEFAULT_LIQUIDATION_MARGIN_BUFFER_RATIO, FEE_POOL_TO_REVENUE_POOL_THRESHOLD,
-    IF_FACTOR_PRECISION, INSURANCE_A_MAX, INSURANCE_B_MAX, INSURANCE_C_MAX,
+    IF_BOOST_PRECISION, IF_FACTOR_PRECISION, INSURANCE_A_MAX, INSURANCE_B_MAX, INSURANCE_C_MAX,
     INSURANCE_SPECULATIVE_MAX, LIQUIDATION_FEE_PRECISION, MAX_CONCENTRATION_COEFFICIENT,
-    MAX_SQRT_K, MAX_UPDATE_K_PRICE_CHANGE, PERCENTAGE_PRECISION, QUOTE_SPOT_MARKET_INDEX,
-    SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_IMF_PRECISION, SPOT_WEIGHT_PRECISION, THIRTEEN_DAY,
-    TWENTY_FOUR_HOUR,
+    MAX_PREDICTION_MARKET_PRICE, MAX_SQRT_K, MAX_UPDATE_K_PRICE_CHANGE, PERCENTAGE_PRECISION,
+    QUOTE_SPOT_MARKET_INDEX, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_IMF_PRECISION,
+    SPOT_WEIGHT_PRECISION, THIRTEEN_DAY, TWENTY_FOUR_HOUR,
 };
 use crate::math::cp_curve::get_update_k_result;
 use crate::math::orders::is_multiple_of_step_size;
@@ -33,22 +34,32 @@ use crate::state::fulfillment_params::phoenix::PhoenixMarketContext;
 use crate::state::fulfillment_params::phoenix::PhoenixV1FulfillmentConfig;
 use crate::state::fulfillment_params::serum::SerumContext;
 use crate::state::fulfillment_params::serum::SerumV3FulfillmentConfig;
-use crate::state::insurance_fund_stake::ProtocolIfSharesTransferConfig;
+use crate::state::insurance_fund_stake::{
+    InsuranceFundJuniorTranche, InsuranceFundStake, ProtocolIfSharesTransferConfig,
+};
+use crate::state::load_ref::load_ref;
+use crate::state::option_market::{OptionMarket, OptionMarketParams};
 use crate::state::oracle::{
-    get_oracle_price, get_prelaunch_price, get_pyth_price, get_switchboard_price,
-    HistoricalIndexData, HistoricalOracleData, OraclePriceData, OracleSource, PrelaunchOracle,
-    PrelaunchOracleParams,
+    get_option_market_price, get_oracle_price, get_prelaunch_price, get_pyth_price,
+    get_switchboard_on_demand_price, get_switchboard_price, HistoricalIndexData,
+    HistoricalOracleData, OracleAggregate, OracleAggregationMethod, OraclePriceData, OracleSource,
+    PrelaunchOracle, PrelaunchOracleParams,
 };
+use crate::state::oracle_circuit_breaker::{OracleCircuitBreaker, OracleCircuitBreakerParams};
+use crate::state::oracle_map::OracleMap;
 use crate::state::paused_operations::{InsuranceFundOperation, PerpOperation, SpotOperation};
 use crate::state::perp_market::{
-    ContractTier, ContractType, InsuranceClaim, MarketStatus, PerpMarket, PoolBalance, AMM,
+    ContractTier, ContractType, FundingParams, FundingPremiumIndex, InsuranceClaim, MarketStatus,
+    PerpMarket, PoolBalance, PredictionMarketResolution, AMM,
 };
 use crate::state::spot_market::{
     AssetTier, InsuranceFund, SpotBalanceType, SpotFulfillmentConfigStatus, SpotMarket,
 };
-use crate::state::state::{ExchangeStatus, FeeStructure, OracleGuardRails, State};
+use crate::state::state::{
+    ExchangeStatus, FeeStructure, OracleGuardRails, State, ValidityGuardRailsOverride,
+};
 use crate::state::traits::Size;
-use crate::state::user::UserStats;
+use crate::state::user::{MarketType, UserStats};
 use crate::validate;
 use crate::validation::fee_structure::validate_fee_structure;
 use crate::validation::margin::{validate_margin, validate_margin_weights};
@@ -285,7 +296,17 @@ pub fn handle_initialize_spot_market(
         fuel_boost_borrows: 0,
         fuel_boost_taker: 0,
         fuel_boost_maker: 0,
-        padding: [0; 43],
+        padding1: 0,
+        oracle_slots_before_stale_for_amm: 0,
+        oracle_confidence_interval_max_size: 0,
+        oracle_slots_before_stale_for_margin: 0,
+        padding2: [0; 2],
+        insurance_fund_cumulative_boost_shares_per_weight: 0,
+        insurance_fund_total_boost_weight: 0,
+        has_insurance_fund_junior_tranche: false,
+        has_oracle_aggregate: false,
+        has_oracle_circuit_breaker: false,
+        padding: [0; 5],
         insurance_fund: InsuranceFund {
             vault: *ctx.accounts.insurance_fund_vault.to_account_info().key,
             unstaking_period: THIRTEEN_DAY,
@@ -682,6 +703,48 @@ pub fn handle_initialize_perp_market(
             } = get_pyth_price(&ctx.accounts.oracle, clock_slot, 1, true)?;
             (oracle_price, oracle_delay, QUOTE_PRECISION_I64)
         }
+        OracleSource::SwitchboardOnDemand => {
+            let OraclePriceData {
+                price: oracle_price,
+                delay: oracle_delay,
+                ..
+            } = get_switchboard_on_demand_price(&ctx.accounts.oracle, clock_slot)?;
+
+            (oracle_price, oracle_delay, oracle_price)
+        }
+        OracleSource::EuropeanOption => {
+            let OraclePriceData {
+                price: oracle_price,
+                delay: oracle_delay,
+                ..
+            } = get_option_market_price(&ctx.accounts.oracle, clock_slot)?;
+
+            (oracle_price, oracle_delay, oracle_price)
+        }
+    };
+
+    // option markets trade the premium of their option market and expire with it
+    let (contract_type, expiry_ts) = if oracle_source == OracleSource::EuropeanOption {
+        let option_market: Ref<OptionMarket> =
+            load_ref(&ctx.accounts.oracle).or(Err(ErrorCode::InvalidOptionMarket))?;
+
+        validate!(
+            option_market.perp_market_index == market_index,
+            ErrorCode::InvalidOptionMarket,
+            "option market perp_market_index {} != market_index {}",
+            option_market.perp_market_index,
+            market_index
+        )?;
+
+        validate!(
+            amm_jit_intensity == 0 && curve_update_intensity == 0,
+            ErrorCode::InvalidOptionMarket,
+            "option markets can't use the amm"
+        )?;
+
+        (ContractType::Option, option_market.expiry_ts)
+    } else {
+        (ContractType::Perpetual, 0)
     };
 
     validate_margin(
@@ -701,7 +764,7 @@ pub fn handle_initialize_perp_market(
     )?;
 
     **perp_market = PerpMarket {
-        contract_type: ContractType::Perpetual,
+        contract_type,
         contract_tier,
         status: if active_status {
             MarketStatus::Active
@@ -710,7 +773,7 @@ pub fn handle_initialize_perp_market(
         },
         name,
         expiry_price: 0,
-        expiry_ts: 0,
+        expiry_ts,
         pubkey: *perp_market_pubkey,
         market_index,
         number_of_users_with_base: 0,
@@ -739,7 +802,20 @@ pub fn handle_initialize_perp_market(
         fuel_boost_position: 0,
         fuel_boost_taker: 0,
         fuel_boost_maker: 0,
-        padding: [0; 43],
+        padding1: 0,
+        oracle_slots_before_stale_for_amm: 0,
+        oracle_confidence_interval_max_size: 0,
+        oracle_slots_before_stale_for_margin: 0,
+        rollover_market_index: 0,
+        settlement_price_twap: 0,
+        settlement_price_twap_ts: 0,
+        settlement_window: 0,
+        rollover_period: 0,
+        prediction_market_resolution: PredictionMarketResolution::default(),
+        has_oracle_aggregate: false,
+        has_oracle_circuit_breaker: false,
+        padding2: 0,
+        funding_rate_remainder: 0,
         amm: AMM {
             oracle: *ctx.accounts.oracle.key,
             oracle_source,
@@ -845,6 +921,73 @@ pub fn handle_initialize_perp_market(
     Ok(())
 }
 
+#[access_control(
+    valid_oracle_for_perp_market(&ctx.accounts.oracle, &ctx.accounts.perp_market)
+)]
+pub fn handle_initialize_perp_market_rollover(
+    ctx: Context<InitializePerpMarketRollover>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    validate!(
+        perp_market.is_future() && perp_market.rollover_period != 0,
+        ErrorCode::InvalidPerpMarketRollover,
+        "perp market {} doesn't roll over",
+        perp_market.market_index
+    )?;
+
+    validate!(
+        perp_market.rollover_market_index == 0,
+        ErrorCode::InvalidPerpMarketRollover,
+        "perp market {} already rolled over to {}",
+        perp_market.market_index,
+        perp_market.rollover_market_index
+    )?;
+
+    let settlement_window_start_ts = perp_market.get_settlement_window_start_ts()?;
+    validate!(
+        now >= settlement_window_start_ts,
+        ErrorCode::InvalidPerpMarketRollover,
+        "perp market {} can't roll over before {}",
+        perp_market.market_index,
+        settlement_window_start_ts
+    )?;
+
+    let oracle_price_data = get_oracle_price(
+        &perp_market.amm.oracle_source,
+        &ctx.accounts.oracle,
+        clock.slot,
+    )?;
+
+    let state = &mut ctx.accounts.state;
+    let market_index = state.number_of_markets;
+    let rollover_perp_market = &mut ctx.accounts.rollover_perp_market.load_init()?;
+
+    **rollover_perp_market = perp_market.get_rollover_market(
+        ctx.accounts.rollover_perp_market.key(),
+        market_index,
+        &oracle_price_data,
+        now,
+        clock.slot,
+    )?;
+
+    msg!(
+        "perp market {} rolled over to {} expiring at {} with status {:?}",
+        perp_market.market_index,
+        market_index,
+        rollover_perp_market.expiry_ts,
+        rollover_perp_market.status
+    );
+
+    perp_market.rollover_market_index = market_index;
+
+    safe_increment!(state.number_of_markets, 1);
+
+    Ok(())
+}
+
 pub fn handle_delete_initialized_perp_market(
     ctx: Context<DeleteInitializedPerpMarket>,
     market_index: u16,
@@ -1065,6 +1208,168 @@ pub fn handle_update_perp_market_expiry(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_future_params(
+    ctx: Context<AdminUpdatePerpMarket>,
+    expiry_ts: i64,
+    settlement_window: u32,
+    rollover_period: u32,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    // perpetuals with positions have accrued funding, so only empty markets can become futures
+    validate!(
+        perp_market.is_future()
+            || (perp_market.number_of_users == 0 && perp_market.number_of_users_with_base == 0),
+        ErrorCode::InvalidFutureParams,
+        "perp market {} has users, can't become a dated future",
+        perp_market.market_index
+    )?;
+
+    validate!(
+        now < expiry_ts,
+        ErrorCode::InvalidFutureParams,
+        "expiry ts {} must be later than now {}",
+        expiry_ts,
+        now
+    )?;
+
+    validate!(
+        settlement_window.cast::<i64>()? <= expiry_ts.safe_sub(now)?,
+        ErrorCode::InvalidFutureParams,
+        "settlement window {} starts before now",
+        settlement_window
+    )?;
+
+    validate!(
+        rollover_period == 0 || settlement_window < rollover_period,
+        ErrorCode::InvalidFutureParams,
+        "settlement window {} must be shorter than rollover period {}",
+        settlement_window,
+        rollover_period
+    )?;
+
+    msg!(
+        "perp_market.contract_type {:?} -> {:?}",
+        perp_market.contract_type,
+        ContractType::Future
+    );
+    msg!(
+        "perp_market.expiry_ts {} -> {}",
+        perp_market.expiry_ts,
+        expiry_ts
+    );
+    msg!(
+        "perp_market.settlement_window {} -> {}",
+        perp_market.settlement_window,
+        settlement_window
+    );
+    msg!(
+        "perp_market.rollover_period {} -> {}",
+        perp_market.rollover_period,
+        rollover_period
+    );
+
+    perp_market.contract_type = ContractType::Future;
+    perp_market.expiry_ts = expiry_ts;
+    perp_market.settlement_window = settlement_window;
+    perp_market.rollover_period = rollover_period;
+    perp_market.settlement_price_twap = 0;
+    perp_market.settlement_price_twap_ts = 0;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_initialize_prediction_market(ctx: Context<AdminUpdatePerpMarket>) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    validate!(
+        perp_market.status == MarketStatus::Initialized
+            && perp_market.contract_type == ContractType::Perpetual,
+        ErrorCode::InvalidPredictionMarket,
+        "perp market {} must be an initialized perpetual to become a prediction market",
+        perp_market.market_index
+    )?;
+
+    let reserve_price = perp_market.amm.reserve_price()?;
+    validate!(
+        reserve_price <= MAX_PREDICTION_MARKET_PRICE
+            && perp_market.amm.order_tick_size < MAX_PREDICTION_MARKET_PRICE,
+        ErrorCode::InvalidPredictionMarket,
+        "reserve price {} and tick size {} must be below {}",
+        reserve_price,
+        perp_market.amm.order_tick_size,
+        MAX_PREDICTION_MARKET_PRICE
+    )?;
+
+    msg!(
+        "perp_market.contract_type {:?} -> {:?}",
+        perp_market.contract_type,
+        ContractType::Prediction
+    );
+
+    perp_market.contract_type = ContractType::Prediction;
+    perp_market.update_bounded_spread_reserves()?;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_resolve_perp_prediction_market(
+    ctx: Context<AdminUpdatePerpMarket>,
+    resolution: PredictionMarketResolution,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    validate!(
+        perp_market.is_prediction_market(),
+        ErrorCode::InvalidPredictionMarket,
+        "perp market {} isn't a prediction market",
+        perp_market.market_index
+    )?;
+
+    validate!(
+        perp_market.status != MarketStatus::Settlement,
+        ErrorCode::InvalidPredictionMarket,
+        "perp market {} is already settled",
+        perp_market.market_index
+    )?;
+
+    msg!(
+        "perp_market.prediction_market_resolution {:?} -> {:?}",
+        perp_market.prediction_market_resolution,
+        resolution
+    );
+
+    perp_market.prediction_market_resolution = resolution;
+
+    // a resolved market can be settled right away
+    if resolution != PredictionMarketResolution::Unresolved
+        && (perp_market.expiry_ts == 0 || perp_market.expiry_ts > now)
+    {
+        msg!("perp_market.expiry_ts {} -> {}", perp_market.expiry_ts, now);
+        msg!(
+            "perp_market.status {:?} -> {:?}",
+            perp_market.status,
+            MarketStatus::ReduceOnly
+        );
+
+        perp_market.expiry_ts = now;
+        perp_market.status = MarketStatus::ReduceOnly;
+    }
+
+    Ok(())
+}
+
 #[access_control(
     perp_market_valid(&ctx.accounts.perp_market)
 )]
@@ -1889,7 +2194,11 @@ pub fn handle_update_perp_market_funding_period(
 ) -> Result<()> {
     let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
 
-    validate!(funding_period >= 0, ErrorCode::DefaultError)?;
+    // the period is tracked as a u32 in the market's funding params
+    validate!(
+        funding_period >= 0 && funding_period.cast::<u32>().is_ok(),
+        ErrorCode::DefaultError
+    )?;
 
     msg!(
         "perp_market.amm.funding_period: {:?} -> {:?}",
@@ -1901,6 +2210,41 @@ pub fn handle_update_perp_market_funding_period(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_funding_params(
+    ctx: Context<AdminUpdatePerpMarket>,
+    enabled: bool,
+    premium_index: FundingPremiumIndex,
+    interest_rate: i16,
+    premium_clamp_threshold: u16,
+    premium_clamp_slope: u8,
+    continuous: bool,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    let funding_params = FundingParams {
+        enabled,
+        premium_index,
+        interest_rate,
+        premium_clamp_threshold,
+        premium_clamp_slope,
+        continuous,
+        ..perp_market.amm.funding_params
+    };
+    funding_params.validate()?;
+
+    msg!(
+        "perp_market.amm.funding_params: {:?} -> {:?}",
+        perp_market.amm.funding_params,
+        funding_params
+    );
+
+    perp_market.amm.funding_params = funding_params;
+    Ok(())
+}
+
 #[access_control(
     perp_market_valid(&ctx.accounts.perp_market)
 )]
@@ -2210,6 +2554,32 @@ pub fn handle_update_spot_market_paused_operations(
     Ok(())
 }
 
+#[access_control(
+    spot_market_valid(&ctx.accounts.spot_market)
+)]
+pub fn handle_update_spot_market_oracle_guard_rails_override(
+    ctx: Context<AdminUpdateSpotMarket>,
+    guard_rails_override: ValidityGuardRailsOverride,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+
+    guard_rails_override.validate(&ctx.accounts.state.oracle_guard_rails.validity)?;
+
+    msg!(
+        "spot_market.oracle guard rails override: {:?} -> {:?}",
+        spot_market.get_validity_guard_rails_override(),
+        guard_rails_override
+    );
+
+    spot_market.oracle_slots_before_stale_for_amm = guard_rails_override.slots_before_stale_for_amm;
+    spot_market.oracle_slots_before_stale_for_margin =
+        guard_rails_override.slots_before_stale_for_margin;
+    spot_market.oracle_confidence_interval_max_size =
+        guard_rails_override.confidence_interval_max_size;
+
+    Ok(())
+}
+
 #[access_control(
     spot_market_valid(&ctx.accounts.spot_market)
 )]
@@ -2499,6 +2869,32 @@ pub fn handle_update_perp_market_paused_operations(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_oracle_guard_rails_override(
+    ctx: Context<AdminUpdatePerpMarket>,
+    guard_rails_override: ValidityGuardRailsOverride,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+
+    guard_rails_override.validate(&ctx.accounts.state.oracle_guard_rails.validity)?;
+
+    msg!(
+        "perp_market.oracle guard rails override: {:?} -> {:?}",
+        perp_market.get_validity_guard_rails_override(),
+        guard_rails_override
+    );
+
+    perp_market.oracle_slots_before_stale_for_amm = guard_rails_override.slots_before_stale_for_amm;
+    perp_market.oracle_slots_before_stale_for_margin =
+        guard_rails_override.slots_before_stale_for_margin;
+    perp_market.oracle_confidence_interval_max_size =
+        guard_rails_override.confidence_interval_max_size;
+
+    Ok(())
+}
+
 #[access_control(
     perp_market_valid(&ctx.accounts.perp_market)
 )]
@@ -3476,6 +3872,88 @@ pub fn handle_update_protocol_if_shares_transfer_config(
     Ok(())
 }
 
+pub fn handle_initialize_insurance_fund_junior_tranche(
+    ctx: Context<InitializeInsuranceFundJuniorTranche>,
+    market_index: u16,
+    revenue_boost: u32,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let mut junior_tranche = ctx.accounts.insurance_fund_junior_tranche.load_init()?;
+
+    validate!(
+        revenue_boost.cast::<u128>()? <= IF_BOOST_PRECISION,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "revenue_boost must be <= 100%"
+    )?;
+
+    *junior_tranche = InsuranceFundJuniorTranche::new(
+        market_index,
+        revenue_boost,
+        spot_market.insurance_fund.shares_base,
+    );
+    junior_tranche.last_cumulative_boost_shares_per_weight =
+        spot_market.insurance_fund_cumulative_boost_shares_per_weight;
+
+    msg!("spot_market.has_insurance_fund_junior_tranche: false -> true");
+    spot_market.has_insurance_fund_junior_tranche = true;
+
+    Ok(())
+}
+
+pub fn handle_update_insurance_fund_junior_tranche_revenue_boost(
+    ctx: Context<AdminUpdateInsuranceFundJuniorTranche>,
+    market_index: u16,
+    revenue_boost: u32,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let junior_tranche = &mut load_mut!(ctx.accounts.insurance_fund_junior_tranche)?;
+
+    validate!(
+        revenue_boost.cast::<u128>()? <= IF_BOOST_PRECISION,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "revenue_boost must be <= 100%"
+    )?;
+
+    // the tranche earns the old boost up to now
+    controller::insurance::accrue_insurance_fund_junior_tranche_boost(
+        ctx.accounts.insurance_fund_vault.amount,
+        junior_tranche,
+        spot_market,
+    )?;
+
+    msg!(
+        "junior_tranche.revenue_boost: {} -> {}",
+        junior_tranche.revenue_boost,
+        revenue_boost
+    );
+    junior_tranche.revenue_boost = revenue_boost;
+
+    controller::insurance::update_insurance_fund_junior_tranche_boost_weight(
+        junior_tranche,
+        spot_market,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_initialize_insurance_fund_share_mint(
+    ctx: Context<InitializeInsuranceFundShareMint>,
+    market_index: u16,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let mut wrapped_insurance_fund_stake = ctx
+        .accounts
+        .wrapped_insurance_fund_stake
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    // the program's stake holds the shares behind the share tokens
+    *wrapped_insurance_fund_stake =
+        InsuranceFundStake::new(ctx.accounts.drift_signer.key(), market_index, now);
+
+    Ok(())
+}
+
 pub fn handle_initialize_prelaunch_oracle(
     ctx: Context<InitializePrelaunchOracle>,
     params: PrelaunchOracleParams,
@@ -3489,6 +3967,18 @@ pub fn handle_initialize_prelaunch_oracle(
     if let Some(max_price) = params.max_price {
         oracle.max_price = max_price;
     }
+    if let Some(reference_oracle) = params.reference_oracle {
+        oracle.reference_oracle = reference_oracle;
+        oracle.reference_oracle_source = params
+            .reference_oracle_source
+            .ok_or(ErrorCode::InvalidOracle)?;
+    }
+    if let Some(reference_weight) = params.reference_weight {
+        oracle.reference_weight = reference_weight;
+    }
+    if let Some(slots_before_transition) = params.slots_before_transition {
+        oracle.slots_before_transition = slots_before_transition;
+    }
 
     oracle.validate()?;
 
@@ -3528,10 +4018,56 @@ pub fn handle_update_prelaunch_oracle_params(
         msg!("max price: unchanged")
     }
 
-    oracle.validate()?;
+    if let Some(reference_oracle) = params.reference_oracle {
+        let reference_oracle_source = params
+            .reference_oracle_source
+            .ok_or(ErrorCode::InvalidOracle)?;
 
-    Ok(())
-}
+        msg!(
+            "reference oracle: {:?} -> {:?}",
+            oracle.reference_oracle,
+            reference_oracle
+        );
+        msg!(
+            "reference oracle source: {:?} -> {:?}",
+            oracle.reference_oracle_source,
+            reference_oracle_source
+        );
+
+        oracle.reference_oracle = reference_oracle;
+        oracle.reference_oracle_source = reference_oracle_source;
+        // the new reference has to prove itself before the market transitions to it
+        oracle.reference_valid_since_slot = 0;
+    } else {
+        msg!("reference oracle: unchanged")
+    }
+
+    if let Some(reference_weight) = params.reference_weight {
+        msg!(
+            "reference weight: {:?} -> {:?}",
+            oracle.reference_weight,
+            reference_weight
+        );
+        oracle.reference_weight = reference_weight;
+    } else {
+        msg!("reference weight: unchanged")
+    }
+
+    if let Some(slots_before_transition) = params.slots_before_transition {
+        msg!(
+            "slots before transition: {:?} -> {:?}",
+            oracle.slots_before_transition,
+            slots_before_transition
+        );
+        oracle.slots_before_transition = slots_before_transition;
+    } else {
+        msg!("slots before transition: unchanged")
+    }
+
+    oracle.validate()?;
+
+    Ok(())
+}
 
 pub fn handle_delete_prelaunch_oracle(
     ctx: Context<DeletePrelaunchOracle>,
@@ -3548,6 +4084,369 @@ pub fn handle_delete_prelaunch_oracle(
     Ok(())
 }
 
+pub fn handle_initialize_option_market(
+    ctx: Context<InitializeOptionMarket>,
+    params: OptionMarketParams,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    validate!(
+        params.expiry_ts > now
+            && params.settlement_window.cast::<i64>()? < params.expiry_ts.safe_sub(now)?,
+        ErrorCode::InvalidOptionMarket,
+        "expiry_ts {} must be more than settlement_window {} after now {}",
+        params.expiry_ts,
+        params.settlement_window,
+        now
+    )?;
+
+    let underlying_price_data = get_oracle_price(
+        &params.underlying_oracle_source,
+        &ctx.accounts.underlying_oracle,
+        clock.slot,
+    )?;
+
+    let mut option_market = ctx.accounts.option_market.load_init()?;
+
+    *option_market = OptionMarket {
+        underlying_oracle: ctx.accounts.underlying_oracle.key(),
+        strike: params.strike,
+        expiry_ts: params.expiry_ts,
+        implied_volatility: params.implied_volatility,
+        settlement_window: params.settlement_window,
+        perp_market_index: params.perp_market_index,
+        option_type: params.option_type,
+        underlying_oracle_source: params.underlying_oracle_source,
+        ..OptionMarket::default()
+    };
+
+    option_market.validate()?;
+    option_market.update(&underlying_price_data, now, clock.slot)?;
+
+    Ok(())
+}
+
+pub fn handle_update_option_market_implied_volatility(
+    ctx: Context<AdminUpdateOptionMarket>,
+    implied_volatility: u64,
+) -> Result<()> {
+    let mut option_market = ctx.accounts.option_market.load_mut()?;
+
+    msg!(
+        "implied_volatility: {:?} -> {:?}",
+        option_market.implied_volatility,
+        implied_volatility
+    );
+
+    option_market.implied_volatility = implied_volatility;
+    option_market.validate()?;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_initialize_perp_market_oracle_aggregate<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, InitializePerpMarketOracleAggregate<'info>>,
+    _market_index: u16,
+    method: OracleAggregationMethod,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let mut oracle_aggregate = ctx.accounts.oracle_aggregate.load_init()?;
+
+    // the market's prices are read from the aggregate from now on
+    perp_market.has_oracle_aggregate = true;
+
+    update_oracle_aggregate(
+        &mut oracle_aggregate,
+        MarketType::Perp,
+        perp_market.market_index,
+        perp_market.amm.oracle,
+        method,
+        ctx.remaining_accounts,
+        Clock::get()?.slot,
+    )
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_oracle_aggregate<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, UpdatePerpMarketOracleAggregate<'info>>,
+    _market_index: u16,
+    method: OracleAggregationMethod,
+) -> Result<()> {
+    let perp_market = load!(ctx.accounts.perp_market)?;
+    let mut oracle_aggregate = ctx.accounts.oracle_aggregate.load_mut()?;
+
+    update_oracle_aggregate(
+        &mut oracle_aggregate,
+        MarketType::Perp,
+        perp_market.market_index,
+        perp_market.amm.oracle,
+        method,
+        ctx.remaining_accounts,
+        Clock::get()?.slot,
+    )
+}
+
+#[access_control(
+    spot_market_valid(&ctx.accounts.spot_market)
+)]
+pub fn handle_initialize_spot_market_oracle_aggregate<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, InitializeSpotMarketOracleAggregate<'info>>,
+    _market_index: u16,
+    method: OracleAggregationMethod,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let mut oracle_aggregate = ctx.accounts.oracle_aggregate.load_init()?;
+
+    // the market's prices are read from the aggregate from now on
+    spot_market.has_oracle_aggregate = true;
+
+    update_oracle_aggregate(
+        &mut oracle_aggregate,
+        MarketType::Spot,
+        spot_market.market_index,
+        spot_market.oracle,
+        method,
+        ctx.remaining_accounts,
+        Clock::get()?.slot,
+    )
+}
+
+#[access_control(
+    spot_market_valid(&ctx.accounts.spot_market)
+)]
+pub fn handle_update_spot_market_oracle_aggregate<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, UpdateSpotMarketOracleAggregate<'info>>,
+    _market_index: u16,
+    method: OracleAggregationMethod,
+) -> Result<()> {
+    let spot_market = load!(ctx.accounts.spot_market)?;
+    let mut oracle_aggregate = ctx.accounts.oracle_aggregate.load_mut()?;
+
+    update_oracle_aggregate(
+        &mut oracle_aggregate,
+        MarketType::Spot,
+        spot_market.market_index,
+        spot_market.oracle,
+        method,
+        ctx.remaining_accounts,
+        Clock::get()?.slot,
+    )
+}
+
+/// Sets the aggregate's secondary oracles to the remaining accounts, checking each can be read
+fn update_oracle_aggregate<'c: 'info, 'info>(
+    oracle_aggregate: &mut OracleAggregate,
+    market_type: MarketType,
+    market_index: u16,
+    primary_oracle: Pubkey,
+    method: OracleAggregationMethod,
+    remaining_accounts: &'c [AccountInfo<'info>],
+    slot: u64,
+) -> Result<()> {
+    validate!(
+        remaining_accounts.len() <= OracleAggregate::MAX_SECONDARY_ORACLES,
+        ErrorCode::DefaultError,
+        "at most {} secondary oracles",
+        OracleAggregate::MAX_SECONDARY_ORACLES
+    )?;
+
+    let mut oracle_map = OracleMap::load(&mut remaining_accounts.iter().peekable(), slot, None)?;
+
+    let mut secondary_oracles = [Pubkey::default(); OracleAggregate::MAX_SECONDARY_ORACLES];
+    for (i, account_info) in remaining_accounts.iter().enumerate() {
+        let oracle = account_info.key();
+
+        validate!(
+            oracle != primary_oracle && !secondary_oracles[..i].contains(&oracle),
+            ErrorCode::DefaultError,
+            "secondary oracle {} is the primary oracle or a duplicate",
+            oracle
+        )?;
+
+        oracle_map.get_price_data(&oracle)?;
+
+        secondary_oracles[i] = oracle;
+    }
+
+    msg!(
+        "{} {} oracle aggregate primary oracle {} -> {}",
+        market_type,
+        market_index,
+        oracle_aggregate.primary_oracle,
+        primary_oracle
+    );
+
+    msg!(
+        "secondary oracles {:?} -> {:?}",
+        oracle_aggregate.get_oracles()[1..].to_vec(),
+        secondary_oracles[..remaining_accounts.len()].to_vec()
+    );
+
+    msg!("method {:?} -> {:?}", oracle_aggregate.method, method);
+
+    oracle_aggregate.primary_oracle = primary_oracle;
+    oracle_aggregate.secondary_oracles = secondary_oracles;
+    oracle_aggregate.num_secondary_oracles = remaining_accounts.len().cast()?;
+    oracle_aggregate.market_index = market_index;
+    oracle_aggregate.market_type = market_type;
+    oracle_aggregate.method = method;
+
+    Ok(())
+}
+
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+)]
+pub fn handle_initialize_perp_market_circuit_breaker(
+    ctx: Context<InitializePerpMarketCircuitBreaker>,
+    params: OracleCircuitBreakerParams,
+) -> Result<()> {
+    params.validate()?;
+
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let mut circuit_breaker = ctx.accounts.circuit_breaker.load_init()?;
+
+    // amm updates and fills require the breaker from now on
+    perp_market.has_oracle_circuit_breaker = true;
+
+    circuit_breaker.market_index = perp_market.market_index;
+    circuit_breaker.market_type = MarketType::Perp;
+    update_circuit_breaker_params(&mut circuit_breaker, params);
+
+    Ok(())
+}
+
+pub fn handle_update_perp_market_circuit_breaker_params(
+    ctx: Context<AdminUpdatePerpMarketCircuitBreaker>,
+    params: OracleCircuitBreakerParams,
+) -> Result<()> {
+    params.validate()?;
+
+    let mut circuit_breaker = load_mut!(ctx.accounts.circuit_breaker)?;
+    update_circuit_breaker_params(&mut circuit_breaker, params);
+
+    Ok(())
+}
+
+pub fn handle_reset_perp_market_circuit_breaker(
+    ctx: Context<AdminUpdatePerpMarketCircuitBreaker>,
+) -> Result<()> {
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let circuit_breaker = &mut load_mut!(ctx.accounts.circuit_breaker)?;
+
+    validate!(
+        circuit_breaker.is_tripped(),
+        ErrorCode::DefaultError,
+        "circuit breaker isn't tripped"
+    )?;
+
+    controller::oracle_circuit_breaker::reset_oracle_circuit_breaker(
+        circuit_breaker,
+        &mut perp_market.paused_operations,
+        perp_market.amm.historical_oracle_data.last_oracle_price,
+        Clock::get()?.unix_timestamp,
+    );
+
+    PerpOperation::log_all_operations_paused(perp_market.paused_operations);
+
+    Ok(())
+}
+
+#[access_control(
+    spot_market_valid(&ctx.accounts.spot_market)
+)]
+pub fn handle_initialize_spot_market_circuit_breaker(
+    ctx: Context<InitializeSpotMarketCircuitBreaker>,
+    params: OracleCircuitBreakerParams,
+) -> Result<()> {
+    params.validate()?;
+
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let mut circuit_breaker = ctx.accounts.circuit_breaker.load_init()?;
+
+    // fills require the breaker from now on
+    spot_market.has_oracle_circuit_breaker = true;
+
+    circuit_breaker.market_index = spot_market.market_index;
+    circuit_breaker.market_type = MarketType::Spot;
+    update_circuit_breaker_params(&mut circuit_breaker, params);
+
+    Ok(())
+}
+
+pub fn handle_update_spot_market_circuit_breaker_params(
+    ctx: Context<AdminUpdateSpotMarketCircuitBreaker>,
+    params: OracleCircuitBreakerParams,
+) -> Result<()> {
+    params.validate()?;
+
+    let mut circuit_breaker = load_mut!(ctx.accounts.circuit_breaker)?;
+    update_circuit_breaker_params(&mut circuit_breaker, params);
+
+    Ok(())
+}
+
+pub fn handle_reset_spot_market_circuit_breaker(
+    ctx: Context<AdminUpdateSpotMarketCircuitBreaker>,
+) -> Result<()> {
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let circuit_breaker = &mut load_mut!(ctx.accounts.circuit_breaker)?;
+
+    validate!(
+        circuit_breaker.is_tripped(),
+        ErrorCode::DefaultError,
+        "circuit breaker isn't tripped"
+    )?;
+
+    controller::oracle_circuit_breaker::reset_oracle_circuit_breaker(
+        circuit_breaker,
+        &mut spot_market.paused_operations,
+        spot_market.historical_oracle_data.last_oracle_price,
+        Clock::get()?.unix_timestamp,
+    );
+
+    SpotOperation::log_all_operations_paused(spot_market.paused_operations);
+
+    Ok(())
+}
+
+fn update_circuit_breaker_params(
+    circuit_breaker: &mut OracleCircuitBreaker,
+    params: OracleCircuitBreakerParams,
+) {
+    msg!(
+        "{} {} circuit breaker",
+        circuit_breaker.market_type,
+        circuit_breaker.market_index
+    );
+
+    msg!(
+        "max_price_move {} -> {}",
+        circuit_breaker.max_price_move,
+        params.max_price_move
+    );
+    msg!(
+        "cooldown {} -> {}",
+        circuit_breaker.cooldown,
+        params.cooldown
+    );
+    msg!(
+        "paused_operations {} -> {}",
+        circuit_breaker.paused_operations,
+        params.paused_operations
+    );
+
+    circuit_breaker.max_price_move = params.max_price_move;
+    circuit_breaker.cooldown = params.cooldown;
+    circuit_breaker.paused_operations = params.paused_operations;
+}
+
 pub fn handle_initialize_pyth_pull_oracle(
     ctx: Context<InitPythPullPriceFeed>,
     feed_id: [u8; 32],
@@ -3818,6 +4717,31 @@ pub struct InitializePerpMarket<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct InitializePerpMarketRollover<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        init,
+        seeds = [b"perp_market", state.number_of_markets.to_le_bytes().as_ref()],
+        space = PerpMarket::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub rollover_perp_market: AccountLoader<'info, PerpMarket>,
+    /// CHECK: checked in `initialize_perp_market_rollover`
+    pub oracle: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct DeleteInitializedPerpMarket<'info> {
     #[account(mut)]
@@ -4019,6 +4943,105 @@ pub struct UpdateProtocolIfSharesTransferConfig<'info> {
     pub state: Box<Account<'info, State>>,
 }
 
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializeInsuranceFundJuniorTranche<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        init,
+        seeds = [b"insurance_fund_junior_tranche", market_index.to_le_bytes().as_ref()],
+        space = InsuranceFundJuniorTranche::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub insurance_fund_junior_tranche: AccountLoader<'info, InsuranceFundJuniorTranche>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct AdminUpdateInsuranceFundJuniorTranche<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_junior_tranche", market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_junior_tranche: AccountLoader<'info, InsuranceFundJuniorTranche>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16)]
+pub struct InitializeInsuranceFundShareMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        constraint = insurance_fund_vault.mint.eq(&spot_market_mint.key())
+    )]
+    pub spot_market_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        seeds = [b"insurance_fund_share_mint".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+        payer = admin,
+        mint::decimals = spot_market_mint.decimals,
+        mint::authority = drift_signer
+    )]
+    pub insurance_fund_share_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        seeds = [b"insurance_fund_stake", drift_signer.key.as_ref(), market_index.to_le_bytes().as_ref()],
+        space = InsuranceFundStake::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub wrapped_insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: program signer
+    pub drift_signer: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
 #[derive(Accounts)]
 #[instruction(params: PrelaunchOracleParams,)]
 pub struct InitializePrelaunchOracle<'info> {
@@ -4084,6 +5107,210 @@ pub struct DeletePrelaunchOracle<'info> {
     pub state: Box<Account<'info, State>>,
 }
 
+#[derive(Accounts)]
+#[instruction(params: OptionMarketParams,)]
+pub struct InitializeOptionMarket<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        seeds = [b"option_market".as_ref(), params.perp_market_index.to_le_bytes().as_ref()],
+        space = OptionMarket::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub option_market: AccountLoader<'info, OptionMarket>,
+    /// CHECK: checked in `initialize_option_market`
+    pub underlying_oracle: AccountInfo<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminUpdateOptionMarket<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub option_market: AccountLoader<'info, OptionMarket>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct InitializePerpMarketOracleAggregate<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        seeds = [b"perp_oracle_aggregate".as_ref(), market_index.to_le_bytes().as_ref()],
+        space = OracleAggregate::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub oracle_aggregate: AccountLoader<'info, OracleAggregate>,
+    #[account(
+        mut,
+        constraint = perp_market.load()?.market_index == market_index
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct UpdatePerpMarketOracleAggregate<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"perp_oracle_aggregate".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub oracle_aggregate: AccountLoader<'info, OracleAggregate>,
+    #[account(
+        constraint = perp_market.load()?.market_index == market_index
+    )]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct InitializeSpotMarketOracleAggregate<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        seeds = [b"spot_oracle_aggregate".as_ref(), market_index.to_le_bytes().as_ref()],
+        space = OracleAggregate::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub oracle_aggregate: AccountLoader<'info, OracleAggregate>,
+    #[account(
+        mut,
+        constraint = spot_market.load()?.market_index == market_index
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct UpdateSpotMarketOracleAggregate<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"spot_oracle_aggregate".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub oracle_aggregate: AccountLoader<'info, OracleAggregate>,
+    #[account(
+        constraint = spot_market.load()?.market_index == market_index
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePerpMarketCircuitBreaker<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        seeds = [b"perp_circuit_breaker".as_ref(), perp_market.load()?.market_index.to_le_bytes().as_ref()],
+        space = OracleCircuitBreaker::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub circuit_breaker: AccountLoader<'info, OracleCircuitBreaker>,
+    #[account(mut)]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminUpdatePerpMarketCircuitBreaker<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"perp_circuit_breaker".as_ref(), perp_market.load()?.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub circuit_breaker: AccountLoader<'info, OracleCircuitBreaker>,
+    #[account(mut)]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeSpotMarketCircuitBreaker<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        seeds = [b"spot_circuit_breaker".as_ref(), spot_market.load()?.market_index.to_le_bytes().as_ref()],
+        space = OracleCircuitBreaker::SIZE,
+        bump,
+        payer = admin
+    )]
+    pub circuit_breaker: AccountLoader<'info, OracleCircuitBreaker>,
+    #[account(mut)]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminUpdateSpotMarketCircuitBreaker<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"spot_circuit_breaker".as_ref(), spot_market.load()?.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub circuit_breaker: AccountLoader<'info, OracleCircuitBreaker>,
+    #[account(mut)]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        has_one = admin
+    )]
+    pub state: Box<Account<'info, State>>,
+}
+
 #[derive(Accounts)]
 #[instruction(feed_id : [u8; 32])]
 pub struct InitPythPullPriceFeed<'info> {
@@ -1,10 +1,18 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::token::{Mint, Token, TokenAccount};
+use std::iter::Peekable;
+use std::slice::Iter;
 
 use crate::controller::insurance::transfer_protocol_insurance_fund_stake;
-use crate::error::ErrorCode;
+use crate::error::{DriftResult, ErrorCode};
 use crate::instructions::constraints::*;
-use crate::state::insurance_fund_stake::{InsuranceFundStake, ProtocolIfSharesTransferConfig};
+use crate::instructions::optional_accounts::{
+    get_insurance_fund_junior_tranche, get_insurance_fund_stake_lockup,
+};
+use crate::state::insurance_fund_stake::{
+    InsuranceFundJuniorTranche, InsuranceFundLockupTier, InsuranceFundStake,
+    InsuranceFundStakeLockup, InsuranceFundTranche, ProtocolIfSharesTransferConfig,
+};
 use crate::state::paused_operations::InsuranceFundOperation;
 use crate::state::perp_market::MarketStatus;
 use crate::state::spot_market::SpotMarket;
@@ -41,8 +49,8 @@ pub fn handle_initialize_insurance_fund_stake(
     Ok(())
 }
 
-pub fn handle_add_insurance_fund_stake(
-    ctx: Context<AddInsuranceFundStake>,
+pub fn handle_add_insurance_fund_stake<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, AddInsuranceFundStake<'info>>,
     market_index: u16,
     amount: u64,
 ) -> Result<()> {
@@ -52,10 +60,12 @@ pub fn handle_add_insurance_fund_stake(
 
     let clock = Clock::get()?;
     let now = clock.unix_timestamp;
+    let insurance_fund_stake_key = ctx.accounts.insurance_fund_stake.key();
     let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
     let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
     let state = &ctx.accounts.state;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
 
     validate!(
         !spot_market.is_insurance_fund_operation_paused(InsuranceFundOperation::Add),
@@ -76,13 +86,6 @@ pub fn handle_add_insurance_fund_stake(
         spot_market.market_index
     )?;
 
-    validate!(
-        insurance_fund_stake.last_withdraw_request_shares == 0
-            && insurance_fund_stake.last_withdraw_request_value == 0,
-        ErrorCode::IFWithdrawRequestInProgress,
-        "withdraw request in progress"
-    )?;
-
     {
         controller::insurance::attempt_settle_revenue_to_insurance_fund(
             &ctx.accounts.spot_market_vault,
@@ -103,6 +106,38 @@ pub fn handle_add_insurance_fund_stake(
         )?;
     }
 
+    let junior_tranche = update_junior_insurance_fund_stake(
+        remaining_accounts_iter,
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        spot_market,
+    )?;
+
+    validate!(
+        insurance_fund_stake.last_withdraw_request_shares == 0
+            && insurance_fund_stake.last_withdraw_request_value == 0,
+        ErrorCode::IFWithdrawRequestInProgress,
+        "withdraw request in progress"
+    )?;
+
+    let lockup = if insurance_fund_stake.lockup_tier != InsuranceFundLockupTier::None {
+        let lockup =
+            get_insurance_fund_stake_lockup(remaining_accounts_iter, &insurance_fund_stake_key)?;
+
+        // credit the boost earned on the old shares before adding new ones
+        controller::insurance::accrue_insurance_fund_stake_boost(
+            ctx.accounts.insurance_fund_vault.amount,
+            insurance_fund_stake,
+            &mut *load_mut!(lockup)?,
+            spot_market,
+            now,
+        )?;
+
+        Some(lockup)
+    } else {
+        None
+    };
+
     controller::insurance::add_insurance_fund_stake(
         amount,
         ctx.accounts.insurance_fund_vault.amount,
@@ -112,6 +147,24 @@ pub fn handle_add_insurance_fund_stake(
         clock.unix_timestamp,
     )?;
 
+    if let Some(lockup) = lockup {
+        controller::insurance::extend_insurance_fund_stake_lockup(
+            insurance_fund_stake,
+            &mut *load_mut!(lockup)?,
+            spot_market,
+            now,
+        )?;
+    }
+
+    if let Some((junior_tranche, if_shares_before)) = junior_tranche {
+        controller::insurance::update_insurance_fund_junior_tranche_shares(
+            if_shares_before,
+            insurance_fund_stake,
+            &mut *load_mut!(junior_tranche)?,
+            spot_market,
+        )?;
+    }
+
     controller::token::receive(
         &ctx.accounts.token_program,
         &ctx.accounts.user_token_account,
@@ -123,8 +176,8 @@ pub fn handle_add_insurance_fund_stake(
     Ok(())
 }
 
-pub fn handle_request_remove_insurance_fund_stake(
-    ctx: Context<RequestRemoveInsuranceFundStake>,
+pub fn handle_request_remove_insurance_fund_stake<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, RequestRemoveInsuranceFundStake<'info>>,
     market_index: u16,
     amount: u64,
 ) -> Result<()> {
@@ -132,6 +185,7 @@ pub fn handle_request_remove_insurance_fund_stake(
     let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
     let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
 
     validate!(
         !spot_market.is_insurance_fund_operation_paused(InsuranceFundOperation::RequestRemove),
@@ -145,6 +199,13 @@ pub fn handle_request_remove_insurance_fund_stake(
         "insurance_fund_stake does not match market_index"
     )?;
 
+    let junior_tranche = update_junior_insurance_fund_stake(
+        remaining_accounts_iter,
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        spot_market,
+    )?;
+
     validate!(
         insurance_fund_stake.last_withdraw_request_shares == 0,
         ErrorCode::IFWithdrawRequestInProgress,
@@ -175,11 +236,20 @@ pub fn handle_request_remove_insurance_fund_stake(
         clock.unix_timestamp,
     )?;
 
+    if let Some((junior_tranche, if_shares_before)) = junior_tranche {
+        controller::insurance::update_insurance_fund_junior_tranche_shares(
+            if_shares_before,
+            insurance_fund_stake,
+            &mut *load_mut!(junior_tranche)?,
+            spot_market,
+        )?;
+    }
+
     Ok(())
 }
 
-pub fn handle_cancel_request_remove_insurance_fund_stake(
-    ctx: Context<RequestRemoveInsuranceFundStake>,
+pub fn handle_cancel_request_remove_insurance_fund_stake<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, RequestRemoveInsuranceFundStake<'info>>,
     market_index: u16,
 ) -> Result<()> {
     let clock = Clock::get()?;
@@ -187,6 +257,7 @@ pub fn handle_cancel_request_remove_insurance_fund_stake(
     let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
     let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
 
     validate!(
         insurance_fund_stake.market_index == market_index,
@@ -194,6 +265,13 @@ pub fn handle_cancel_request_remove_insurance_fund_stake(
         "insurance_fund_stake does not match market_index"
     )?;
 
+    let junior_tranche = update_junior_insurance_fund_stake(
+        remaining_accounts_iter,
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        spot_market,
+    )?;
+
     validate!(
         insurance_fund_stake.last_withdraw_request_shares != 0,
         ErrorCode::NoIFWithdrawRequestInProgress,
@@ -208,14 +286,23 @@ pub fn handle_cancel_request_remove_insurance_fund_stake(
         now,
     )?;
 
+    if let Some((junior_tranche, if_shares_before)) = junior_tranche {
+        controller::insurance::update_insurance_fund_junior_tranche_shares(
+            if_shares_before,
+            insurance_fund_stake,
+            &mut *load_mut!(junior_tranche)?,
+            spot_market,
+        )?;
+    }
+
     Ok(())
 }
 
 #[access_control(
     withdraw_not_paused(&ctx.accounts.state)
 )]
-pub fn handle_remove_insurance_fund_stake(
-    ctx: Context<RemoveInsuranceFundStake>,
+pub fn handle_remove_insurance_fund_stake<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, RemoveInsuranceFundStake<'info>>,
     market_index: u16,
 ) -> Result<()> {
     let clock = Clock::get()?;
@@ -224,6 +311,7 @@ pub fn handle_remove_insurance_fund_stake(
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
     let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
     let state = &ctx.accounts.state;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
 
     validate!(
         !spot_market.is_insurance_fund_operation_paused(InsuranceFundOperation::Remove),
@@ -244,6 +332,13 @@ pub fn handle_remove_insurance_fund_stake(
         "spot market utilization above health threshold"
     )?;
 
+    let junior_tranche = update_junior_insurance_fund_stake(
+        remaining_accounts_iter,
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        spot_market,
+    )?;
+
     let amount = controller::insurance::remove_insurance_fund_stake(
         ctx.accounts.insurance_fund_vault.amount,
         insurance_fund_stake,
@@ -252,6 +347,15 @@ pub fn handle_remove_insurance_fund_stake(
         now,
     )?;
 
+    if let Some((junior_tranche, if_shares_before)) = junior_tranche {
+        controller::insurance::update_insurance_fund_junior_tranche_shares(
+            if_shares_before,
+            insurance_fund_stake,
+            &mut *load_mut!(junior_tranche)?,
+            spot_market,
+        )?;
+    }
+
     controller::token::send_from_program_vault(
         &ctx.accounts.token_program,
         &ctx.accounts.insurance_fund_vault,
@@ -274,6 +378,203 @@ pub fn handle_remove_insurance_fund_stake(
     Ok(())
 }
 
+pub fn handle_update_insurance_fund_stake_tranche(
+    ctx: Context<UpdateInsuranceFundStakeTranche>,
+    market_index: u16,
+    tranche: InsuranceFundTranche,
+) -> Result<()> {
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let junior_tranche = &mut load_mut!(ctx.accounts.insurance_fund_junior_tranche)?;
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    controller::insurance::update_insurance_fund_stake_tranche(
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        junior_tranche,
+        spot_market,
+        tranche,
+    )?;
+
+    Ok(())
+}
+
+/// Junior stakes are brought up to date with their tranche before they're used. Returns the
+/// tranche and the stake's shares so the tranche can be updated after
+fn update_junior_insurance_fund_stake<'a>(
+    remaining_accounts_iter: &mut Peekable<Iter<'a, AccountInfo<'a>>>,
+    insurance_vault_amount: u64,
+    insurance_fund_stake: &mut InsuranceFundStake,
+    spot_market: &mut SpotMarket,
+) -> DriftResult<Option<(AccountLoader<'a, InsuranceFundJuniorTranche>, u128)>> {
+    if insurance_fund_stake.tranche != InsuranceFundTranche::Junior {
+        return Ok(None);
+    }
+
+    let junior_tranche =
+        get_insurance_fund_junior_tranche(remaining_accounts_iter, spot_market.market_index)?;
+
+    let if_shares = controller::insurance::update_insurance_fund_junior_stake(
+        insurance_vault_amount,
+        insurance_fund_stake,
+        &mut *load_mut!(junior_tranche)?,
+        spot_market,
+    )?;
+
+    Ok(Some((junior_tranche, if_shares)))
+}
+
+pub fn handle_lock_insurance_fund_stake(
+    ctx: Context<LockInsuranceFundStake>,
+    market_index: u16,
+    lockup_tier: InsuranceFundLockupTier,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let insurance_fund_stake_key = ctx.accounts.insurance_fund_stake.key();
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let mut lockup = ctx
+        .accounts
+        .insurance_fund_stake_lockup
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    lockup.insurance_fund_stake = insurance_fund_stake_key;
+    lockup.payer = ctx.accounts.payer.key();
+
+    controller::insurance::lock_insurance_fund_stake(
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        &mut lockup,
+        spot_market,
+        lockup_tier,
+        now,
+    )?;
+
+    Ok(())
+}
+
+/// Permissionless crank, so keepers can unlock stakes as their lockups end and stop their boost
+/// weight from diluting the locked stakes' boost
+pub fn handle_unlock_insurance_fund_stake(
+    ctx: Context<UnlockInsuranceFundStake>,
+    market_index: u16,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let lockup = &mut load_mut!(ctx.accounts.insurance_fund_stake_lockup)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    controller::insurance::unlock_insurance_fund_stake(
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        lockup,
+        user_stats,
+        spot_market,
+        now,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_wrap_insurance_fund_stake(
+    ctx: Context<WrapInsuranceFundStake>,
+    market_index: u16,
+    shares: u128,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let wrapped_insurance_fund_stake = &mut load_mut!(ctx.accounts.wrapped_insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let state = &ctx.accounts.state;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    let share_tokens = controller::insurance::wrap_insurance_fund_stake(
+        shares,
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        wrapped_insurance_fund_stake,
+        user_stats,
+        spot_market,
+        ctx.accounts.insurance_fund_share_mint.supply,
+        now,
+    )?;
+
+    controller::token::mint_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.insurance_fund_share_mint,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.drift_signer,
+        state.signer_nonce,
+        share_tokens,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_unwrap_insurance_fund_stake(
+    ctx: Context<UnwrapInsuranceFundStake>,
+    market_index: u16,
+    amount: u64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let insurance_fund_stake = &mut load_mut!(ctx.accounts.insurance_fund_stake)?;
+    let wrapped_insurance_fund_stake = &mut load_mut!(ctx.accounts.wrapped_insurance_fund_stake)?;
+    let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+
+    validate!(
+        insurance_fund_stake.market_index == market_index,
+        ErrorCode::IncorrectSpotMarketAccountPassed,
+        "insurance_fund_stake does not match market_index"
+    )?;
+
+    controller::insurance::unwrap_insurance_fund_stake(
+        amount,
+        ctx.accounts.insurance_fund_vault.amount,
+        insurance_fund_stake,
+        wrapped_insurance_fund_stake,
+        user_stats,
+        spot_market,
+        ctx.accounts.insurance_fund_share_mint.supply,
+        now,
+    )?;
+
+    controller::token::burn_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.insurance_fund_share_mint,
+        &ctx.accounts.user_token_account,
+        &ctx.accounts.authority,
+        amount,
+    )?;
+
+    Ok(())
+}
+
 pub fn handle_transfer_protocol_if_shares(
     ctx: Context<TransferProtocolIfShares>,
     market_index: u16,
@@ -457,6 +758,211 @@ pub struct RemoveInsuranceFundStake<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct UpdateInsuranceFundStakeTranche<'info> {
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_junior_tranche", market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_junior_tranche: AccountLoader<'info, InsuranceFundJuniorTranche>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct LockInsuranceFundStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        init,
+        seeds = [b"insurance_fund_stake_lockup", insurance_fund_stake.key().as_ref()],
+        space = InsuranceFundStakeLockup::SIZE,
+        bump,
+        payer = payer
+    )]
+    pub insurance_fund_stake_lockup: AccountLoader<'info, InsuranceFundStakeLockup>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct UnlockInsuranceFundStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_stake_lockup", insurance_fund_stake.key().as_ref()],
+        bump,
+        has_one = insurance_fund_stake,
+        has_one = payer,
+        close = payer
+    )]
+    pub insurance_fund_stake_lockup: AccountLoader<'info, InsuranceFundStakeLockup>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    /// CHECK: checked against the insurance fund stake and user stats
+    pub authority: AccountInfo<'info>,
+    /// CHECK: receives the lockup's rent, checked against the lockup
+    #[account(mut)]
+    pub payer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct WrapInsuranceFundStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_stake", drift_signer.key.as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub wrapped_insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_share_mint".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_share_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = insurance_fund_share_mint,
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(market_index: u16,)]
+pub struct UnwrapInsuranceFundStake<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(
+        mut,
+        seeds = [b"spot_market", market_index.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_stake", drift_signer.key.as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub wrapped_insurance_fund_stake: AccountLoader<'info, InsuranceFundStake>,
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub user_stats: AccountLoader<'info, UserStats>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"insurance_fund_vault".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"insurance_fund_share_mint".as_ref(), market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub insurance_fund_share_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = insurance_fund_share_mint,
+        token::authority = authority
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        constraint = state.signer.eq(&drift_signer.key())
+    )]
+    /// CHECK: forced drift_signer
+    pub drift_signer: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
 #[derive(Accounts)]
 #[instruction(market_index: u16,)]
 pub struct TransferProtocolIfShares<'info> {
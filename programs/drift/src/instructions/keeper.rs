@@ -1,12 +1,16 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Token, TokenAccount};
+use std::cell::Ref;
 
 use crate::error::ErrorCode;
 use crate::instructions::constraints::*;
-use crate::instructions::optional_accounts::{load_maps, AccountMaps};
+use crate::instructions::optional_accounts::{
+    get_insurance_fund_junior_tranche, load_maps, AccountMaps,
+};
 use crate::math::constants::QUOTE_SPOT_MARKET_INDEX;
 use crate::math::insurance::if_shares_to_vault_amount;
 use crate::math::margin::{calculate_user_equity, meets_settle_pnl_maintenance_margin_requirement};
+use crate::math::oracle::{is_oracle_valid_for_action, oracle_validity, DriftAction};
 use crate::math::orders::{estimate_price_from_side, find_bids_and_asks_from_users};
 use crate::math::spot_withdraw::validate_spot_market_vault_amount;
 use crate::optional_accounts::update_prelaunch_oracle;
@@ -15,6 +19,10 @@ use crate::state::fulfillment_params::drift::MatchFulfillmentParams;
 use crate::state::fulfillment_params::phoenix::PhoenixFulfillmentParams;
 use crate::state::fulfillment_params::serum::SerumFulfillmentParams;
 use crate::state::insurance_fund_stake::InsuranceFundStake;
+use crate::state::load_ref::load_ref;
+use crate::state::option_market::OptionMarket;
+use crate::state::oracle::{get_oracle_price, HistoricalOracleData, PrelaunchOracle};
+use crate::state::oracle_circuit_breaker::OracleCircuitBreaker;
 use crate::state::oracle_map::OracleMap;
 use crate::state::paused_operations::PerpOperation;
 use crate::state::perp_market::{MarketStatus, PerpMarket};
@@ -29,6 +37,7 @@ use crate::state::spot_market_map::{
     get_writable_spot_market_set, get_writable_spot_market_set_from_many,
 };
 use crate::state::state::State;
+use crate::state::twap_order::UserTwapOrders;
 use crate::state::user::{MarketType, OrderStatus, User, UserStats};
 use crate::state::user_map::{load_user_map, load_user_maps, UserMap, UserStatsMap};
 use crate::validation::user::validate_user_is_idle;
@@ -321,6 +330,85 @@ pub fn handle_trigger_order<'c: 'info, 'info>(
     Ok(())
 }
 
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_release_twap_slice<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, ReleaseTwapSlice<'info>>,
+    twap_id: u32,
+) -> Result<()> {
+    let clock = Clock::get()?;
+
+    let mut user_twap_orders = load_mut!(ctx.accounts.user_twap_orders)?;
+
+    // the keeper reward is paid from the slice's perp market
+    let market_index =
+        user_twap_orders.twap_orders[user_twap_orders.get_twap_order_index(twap_id)?].market_index;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &get_writable_perp_market_set(market_index),
+        &MarketSet::new(),
+        clock.slot,
+        Some(ctx.accounts.state.oracle_guard_rails),
+    )?;
+
+    controller::twap_order::release_twap_slice(
+        twap_id,
+        &ctx.accounts.state,
+        &ctx.accounts.user,
+        &mut user_twap_orders,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &ctx.accounts.filler,
+        &clock,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_sweep_expired_orders<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, SweepExpiredOrders<'info>>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &get_writable_spot_market_set(QUOTE_SPOT_MARKET_INDEX),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let user_map = load_user_map(remaining_accounts_iter, true)?;
+
+    controller::orders::sweep_expired_orders(
+        state,
+        &user_map,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &ctx.accounts.filler,
+        &clock,
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
@@ -352,6 +440,40 @@ pub fn handle_force_cancel_orders<'c: 'info, 'info>(
     Ok(())
 }
 
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_cancel_orders_on_heartbeat_timeout<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, ForceCancelOrder>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &get_writable_spot_market_set(QUOTE_SPOT_MARKET_INDEX),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    controller::orders::cancel_orders_on_heartbeat_timeout(
+        state,
+        &ctx.accounts.user,
+        &spot_market_map,
+        &perp_market_map,
+        &mut oracle_map,
+        &ctx.accounts.filler,
+        &clock,
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
@@ -930,13 +1052,14 @@ pub fn handle_resolve_perp_pnl_deficit<'c: 'info, 'info>(
 
     validate!(spot_market_index == 0, ErrorCode::InvalidSpotMarketAccount)?;
     let state = &ctx.accounts.state;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
 
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &get_writable_perp_market_set(perp_market_index),
         &get_writable_spot_market_set(spot_market_index),
         clock.slot,
@@ -999,7 +1122,7 @@ pub fn handle_resolve_perp_pnl_deficit<'c: 'info, 'info>(
             "Market is in settlement mode",
         )?;
 
-        let oracle_price = oracle_map.get_price_data(&perp_market.amm.oracle)?.price;
+        let oracle_price = oracle_map.get_price_data(perp_market.oracle_id())?.price;
         controller::orders::validate_market_within_price_band(perp_market, state, oracle_price)?;
 
         controller::insurance::resolve_perp_pnl_deficit(
@@ -1020,6 +1143,20 @@ pub fn handle_resolve_perp_pnl_deficit<'c: 'info, 'info>(
             ctx.accounts.insurance_fund_vault.amount
         )?;
 
+        {
+            let spot_market = &mut spot_market_map.get_ref_mut(&spot_market_index)?;
+            if spot_market.has_insurance_fund_junior_tranche {
+                let junior_tranche =
+                    get_insurance_fund_junior_tranche(remaining_accounts_iter, spot_market_index)?;
+                controller::insurance::apply_insurance_fund_loss_to_junior_tranche(
+                    ctx.accounts.insurance_fund_vault.amount,
+                    pay_from_insurance,
+                    &mut load_mut!(junior_tranche)?,
+                    spot_market,
+                )?;
+            }
+        }
+
         controller::token::send_from_program_vault(
             &ctx.accounts.token_program,
             &ctx.accounts.insurance_fund_vault,
@@ -1068,13 +1205,14 @@ pub fn handle_resolve_perp_bankruptcy<'c: 'info, 'info>(
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
     let state = &ctx.accounts.state;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
 
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &get_writable_perp_market_set(market_index),
         &get_writable_spot_market_set(quote_spot_market_index),
         clock.slot,
@@ -1124,6 +1262,22 @@ pub fn handle_resolve_perp_bankruptcy<'c: 'info, 'info>(
             ctx.accounts.insurance_fund_vault.amount
         )?;
 
+        {
+            let spot_market = &mut spot_market_map.get_ref_mut(&quote_spot_market_index)?;
+            if spot_market.has_insurance_fund_junior_tranche {
+                let junior_tranche = get_insurance_fund_junior_tranche(
+                    remaining_accounts_iter,
+                    quote_spot_market_index,
+                )?;
+                controller::insurance::apply_insurance_fund_loss_to_junior_tranche(
+                    ctx.accounts.insurance_fund_vault.amount,
+                    pay_from_insurance,
+                    &mut load_mut!(junior_tranche)?,
+                    spot_market,
+                )?;
+            }
+        }
+
         controller::token::send_from_program_vault(
             &ctx.accounts.token_program,
             &ctx.accounts.insurance_fund_vault,
@@ -1174,13 +1328,14 @@ pub fn handle_resolve_spot_bankruptcy<'c: 'info, 'info>(
 
     let user = &mut load_mut!(ctx.accounts.user)?;
     let liquidator = &mut load_mut!(ctx.accounts.liquidator)?;
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
 
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(market_index),
         clock.slot,
@@ -1222,6 +1377,20 @@ pub fn handle_resolve_spot_bankruptcy<'c: 'info, 'info>(
     )?;
 
     if pay_from_insurance > 0 {
+        {
+            let spot_market = &mut spot_market_map.get_ref_mut(&market_index)?;
+            if spot_market.has_insurance_fund_junior_tranche {
+                let junior_tranche =
+                    get_insurance_fund_junior_tranche(remaining_accounts_iter, market_index)?;
+                controller::insurance::apply_insurance_fund_loss_to_junior_tranche(
+                    ctx.accounts.insurance_fund_vault.amount,
+                    pay_from_insurance,
+                    &mut load_mut!(junior_tranche)?,
+                    spot_market,
+                )?;
+            }
+        }
+
         controller::token::send_from_program_vault(
             &ctx.accounts.token_program,
             &ctx.accounts.insurance_fund_vault,
@@ -1271,7 +1440,7 @@ pub fn handle_update_funding_rate(
         Some(state.oracle_guard_rails),
     )?;
 
-    let oracle_price_data = &oracle_map.get_price_data(&perp_market.amm.oracle)?;
+    let oracle_price_data = &oracle_map.get_price_data(perp_market.oracle_id())?;
     controller::repeg::_update_amm(perp_market, oracle_price_data, state, now, clock_slot)?;
 
     validate!(
@@ -1313,15 +1482,83 @@ pub fn handle_update_funding_rate(
     Ok(())
 }
 
+#[access_control(
+    perp_market_valid(&ctx.accounts.perp_market)
+    valid_oracle_for_perp_market(&ctx.accounts.oracle, &ctx.accounts.perp_market)
+)]
+pub fn handle_update_perp_market_circuit_breaker(
+    ctx: Context<UpdatePerpMarketCircuitBreaker>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
+    let circuit_breaker = &mut load_mut!(ctx.accounts.circuit_breaker)?;
+
+    let mut oracle_map = OracleMap::load_one(
+        &ctx.accounts.oracle,
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    controller::oracle_circuit_breaker::update_perp_market_oracle_circuit_breaker(
+        perp_market,
+        circuit_breaker,
+        &mut oracle_map,
+        clock.unix_timestamp,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    spot_market_valid(&ctx.accounts.spot_market)
+    valid_oracle_for_spot_market(&ctx.accounts.oracle, &ctx.accounts.spot_market)
+)]
+pub fn handle_update_spot_market_circuit_breaker(
+    ctx: Context<UpdateSpotMarketCircuitBreaker>,
+) -> Result<()> {
+    let clock = Clock::get()?;
+    let state = &ctx.accounts.state;
+    let spot_market = &mut load_mut!(ctx.accounts.spot_market)?;
+    let circuit_breaker = &mut load_mut!(ctx.accounts.circuit_breaker)?;
+
+    let mut oracle_map = OracleMap::load_one(
+        &ctx.accounts.oracle,
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    controller::oracle_circuit_breaker::update_spot_market_oracle_circuit_breaker(
+        spot_market,
+        circuit_breaker,
+        &mut oracle_map,
+        clock.unix_timestamp,
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     valid_oracle_for_perp_market(&ctx.accounts.oracle, &ctx.accounts.perp_market)
 )]
-pub fn handle_update_prelaunch_oracle(ctx: Context<UpdatePrelaunchOracle>) -> Result<()> {
+pub fn handle_update_prelaunch_oracle<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, UpdatePrelaunchOracle<'info>>,
+) -> Result<()> {
     let clock = Clock::get()?;
     let clock_slot = clock.slot;
-    let oracle_map = OracleMap::load_one(&ctx.accounts.oracle, clock_slot, None)?;
+    let now = clock.unix_timestamp;
 
-    let perp_market = &load!(ctx.accounts.perp_market)?;
+    // the reference oracle, if the prelaunch oracle has one, is passed in the remaining accounts
+    let oracle_account_infos = std::iter::once(ctx.accounts.oracle.clone())
+        .chain(ctx.remaining_accounts.iter().cloned())
+        .collect::<Vec<AccountInfo<'info>>>();
+    let mut oracle_map = OracleMap::load(
+        &mut oracle_account_infos.iter().peekable(),
+        clock_slot,
+        Some(ctx.accounts.state.oracle_guard_rails),
+    )?;
+
+    let perp_market = &mut load_mut!(ctx.accounts.perp_market)?;
 
     validate!(
         perp_market.amm.oracle_source == OracleSource::Prelaunch,
@@ -1329,7 +1566,104 @@ pub fn handle_update_prelaunch_oracle(ctx: Context<UpdatePrelaunchOracle>) -> Re
         "wrong oracle source"
     )?;
 
-    update_prelaunch_oracle(perp_market, &oracle_map, clock_slot)?;
+    let oracle: Ref<PrelaunchOracle> =
+        load_ref(&ctx.accounts.oracle).or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    validate!(
+        !oracle.has_reference_oracle() || oracle_map.contains(&oracle.reference_oracle),
+        ErrorCode::OracleNotFound,
+        "reference oracle {} not passed",
+        oracle.reference_oracle
+    )?;
+
+    drop(oracle);
+
+    update_prelaunch_oracle(perp_market, &mut oracle_map, clock_slot)?;
+
+    let oracle: Ref<PrelaunchOracle> =
+        load_ref(&ctx.accounts.oracle).or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    if oracle.should_transition(clock_slot)? {
+        let reference_price_data = *oracle_map.get_price_data(&oracle.reference_oracle)?;
+
+        msg!(
+            "reference oracle valid since slot {}, transitioning perp market {}",
+            oracle.reference_valid_since_slot,
+            perp_market.market_index
+        );
+
+        msg!(
+            "perp_market.amm.oracle: {:?} -> {:?}",
+            perp_market.amm.oracle,
+            oracle.reference_oracle
+        );
+
+        msg!(
+            "perp_market.amm.oracle_source: {:?} -> {:?}",
+            perp_market.amm.oracle_source,
+            oracle.reference_oracle_source
+        );
+
+        perp_market.amm.oracle = oracle.reference_oracle;
+        perp_market.amm.oracle_source = oracle.reference_oracle_source;
+
+        // the prelaunch price history isn't continuous with the reference, so it's restarted
+        // from the reference's current price
+        perp_market.amm.historical_oracle_data = HistoricalOracleData {
+            last_oracle_price_twap_ts: now,
+            ..HistoricalOracleData::default_with_current_oracle(reference_price_data)
+        };
+    }
+
+    Ok(())
+}
+
+pub fn handle_update_option_market(ctx: Context<UpdateOptionMarket>) -> Result<()> {
+    let clock = Clock::get()?;
+    let option_market = &mut load_mut!(ctx.accounts.option_market)?;
+
+    validate!(
+        ctx.accounts.underlying_oracle.key() == option_market.underlying_oracle,
+        ErrorCode::InvalidOracle,
+        "underlying oracle {} != option market underlying oracle {}",
+        ctx.accounts.underlying_oracle.key(),
+        option_market.underlying_oracle
+    )?;
+
+    let underlying_price_data = get_oracle_price(
+        &option_market.underlying_oracle_source,
+        &ctx.accounts.underlying_oracle,
+        clock.slot,
+    )?;
+
+    // the option has no underlying twap, so volatility is checked against the last read
+    let last_underlying_price = if option_market.underlying_price > 0 {
+        option_market.underlying_price
+    } else {
+        underlying_price_data.price
+    };
+
+    let underlying_oracle_validity = oracle_validity(
+        MarketType::Perp,
+        option_market.perp_market_index,
+        last_underlying_price,
+        &underlying_price_data,
+        &ctx.accounts.state.oracle_guard_rails.validity,
+        1,
+        true,
+    )?;
+
+    // the option market is read as an oracle in margin calculations
+    validate!(
+        is_oracle_valid_for_action(underlying_oracle_validity, Some(DriftAction::MarginCalc))?,
+        ErrorCode::InvalidOracle,
+        "Invalid underlying oracle ({:?} vs last price={}) for option market of perp market index={}",
+        underlying_price_data,
+        last_underlying_price,
+        option_market.perp_market_index
+    )?;
+
+    option_market.update(&underlying_price_data, clock.unix_timestamp, clock.slot)?;
 
     Ok(())
 }
@@ -1366,7 +1700,7 @@ pub fn handle_update_perp_bid_ask_twap<'c: 'info, 'info>(
         min_if_stake
     )?;
 
-    let oracle_price_data = oracle_map.get_price_data(&perp_market.amm.oracle)?;
+    let oracle_price_data = oracle_map.get_price_data(perp_market.oracle_id())?;
     controller::repeg::_update_amm(perp_market, oracle_price_data, state, now, slot)?;
 
     let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
@@ -1516,7 +1850,7 @@ pub fn handle_update_spot_market_cumulative_interest(
         Some(state.oracle_guard_rails),
     )?;
 
-    let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+    let oracle_price_data = oracle_map.get_price_data(spot_market.oracle_id())?;
 
     if !state.funding_paused()? {
         controller::spot_balance::update_spot_market_cumulative_interest(
@@ -1669,6 +2003,36 @@ pub struct TriggerOrder<'info> {
     pub user: AccountLoader<'info, User>,
 }
 
+#[derive(Accounts)]
+pub struct ReleaseTwapSlice<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&filler, &authority)?
+    )]
+    pub filler: AccountLoader<'info, User>,
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        seeds = [b"user_twap_orders", user.key().as_ref()],
+        bump,
+    )]
+    pub user_twap_orders: AccountLoader<'info, UserTwapOrders>,
+}
+
+#[derive(Accounts)]
+pub struct SweepExpiredOrders<'info> {
+    pub state: Box<Account<'info, State>>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&filler, &authority)?
+    )]
+    pub filler: AccountLoader<'info, User>,
+}
+
 #[derive(Accounts)]
 pub struct ForceCancelOrder<'info> {
     pub state: Box<Account<'info, State>>,
@@ -1940,6 +2304,36 @@ pub struct UpdateFundingRate<'info> {
     pub oracle: AccountInfo<'info>,
 }
 
+#[derive(Accounts)]
+pub struct UpdatePerpMarketCircuitBreaker<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub perp_market: AccountLoader<'info, PerpMarket>,
+    #[account(
+        mut,
+        seeds = [b"perp_circuit_breaker".as_ref(), perp_market.load()?.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub circuit_breaker: AccountLoader<'info, OracleCircuitBreaker>,
+    /// CHECK: checked in `update_perp_market_circuit_breaker` ix constraint
+    pub oracle: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSpotMarketCircuitBreaker<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub spot_market: AccountLoader<'info, SpotMarket>,
+    #[account(
+        mut,
+        seeds = [b"spot_circuit_breaker".as_ref(), spot_market.load()?.market_index.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub circuit_breaker: AccountLoader<'info, OracleCircuitBreaker>,
+    /// CHECK: checked in `update_spot_market_circuit_breaker` ix constraint
+    pub oracle: AccountInfo<'info>,
+}
+
 #[derive(Accounts)]
 pub struct UpdatePerpBidAskTwap<'info> {
     pub state: Box<Account<'info, State>>,
@@ -2008,8 +2402,18 @@ pub struct UpdateUserGovTokenInsuranceStake<'info> {
 #[derive(Accounts)]
 pub struct UpdatePrelaunchOracle<'info> {
     pub state: Box<Account<'info, State>>,
+    #[account(mut)]
     pub perp_market: AccountLoader<'info, PerpMarket>,
     #[account(mut)]
     /// CHECK: checked in ix
     pub oracle: AccountInfo<'info>,
 }
+
+#[derive(Accounts)]
+pub struct UpdateOptionMarket<'info> {
+    pub state: Box<Account<'info, State>>,
+    #[account(mut)]
+    pub option_market: AccountLoader<'info, OptionMarket>,
+    /// CHECK: checked in ix
+    pub underlying_oracle: AccountInfo<'info>,
+}
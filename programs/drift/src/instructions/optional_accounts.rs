@@ -3,6 +3,8 @@ use std::cell::RefMut;
 
 use crate::error::ErrorCode::UnableToLoadOracle;
 use crate::math::safe_unwrap::SafeUnwrap;
+use crate::state::delegate_permissions::DelegatePermissions;
+use crate::state::insurance_fund_stake::{InsuranceFundJuniorTranche, InsuranceFundStakeLockup};
 use crate::state::load_ref::load_ref_mut;
 use crate::state::oracle::PrelaunchOracle;
 use crate::state::oracle_map::OracleMap;
@@ -11,12 +13,14 @@ use crate::state::perp_market_map::{MarketSet, PerpMarketMap};
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::state::OracleGuardRails;
 use crate::state::traits::Size;
-use crate::state::user::{User, UserStats};
-use crate::{validate, OracleSource};
+use crate::state::user::{MarketType, User, UserStats};
+use crate::state::withdraw_guard::WithdrawGuard;
+use crate::{load, validate, OracleSource};
 use anchor_lang::accounts::account::Account;
 use anchor_lang::prelude::AccountInfo;
 use anchor_lang::prelude::AccountLoader;
-use anchor_lang::Discriminator;
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{Discriminator, Key};
 use anchor_spl::token::TokenAccount;
 use arrayref::array_ref;
 use solana_program::account_info::next_account_info;
@@ -38,14 +42,14 @@ pub fn load_maps<'a, 'b>(
     slot: u64,
     oracle_guard_rails: Option<OracleGuardRails>,
 ) -> DriftResult<AccountMaps<'a>> {
-    let oracle_map = OracleMap::load(account_info_iter, slot, oracle_guard_rails)?;
+    let mut oracle_map = OracleMap::load(account_info_iter, slot, oracle_guard_rails)?;
     let spot_market_map = SpotMarketMap::load(writable_spot_markets, account_info_iter)?;
     let perp_market_map = PerpMarketMap::load(writable_perp_markets, account_info_iter)?;
 
     for perp_market_index in writable_perp_markets.iter() {
         update_prelaunch_oracle(
             perp_market_map.get_ref(perp_market_index)?.deref(),
-            &oracle_map,
+            &mut oracle_map,
             slot,
         )?;
     }
@@ -59,7 +63,7 @@ pub fn load_maps<'a, 'b>(
 
 pub fn update_prelaunch_oracle(
     perp_market: &PerpMarket,
-    oracle_map: &OracleMap,
+    oracle_map: &mut OracleMap,
     slot: u64,
 ) -> DriftResult {
     if perp_market.amm.oracle_source != OracleSource::Prelaunch {
@@ -71,7 +75,40 @@ pub fn update_prelaunch_oracle(
     let mut oracle: RefMut<PrelaunchOracle> =
         load_ref_mut(&oracle_account_info).or(Err(UnableToLoadOracle))?;
 
-    oracle.update(perp_market, slot)?;
+    let reference_price_data = if oracle.has_reference_oracle() {
+        // without the reference the price would fall back to the mark twap alone
+        if !oracle_map.contains(&oracle.reference_oracle) {
+            msg!(
+                "reference oracle {} not passed, skipping prelaunch oracle update",
+                oracle.reference_oracle
+            );
+            return Ok(());
+        }
+
+        let (reference_price_data, reference_validity) = oracle_map.get_price_data_and_validity(
+            MarketType::Perp,
+            perp_market.market_index,
+            &oracle.reference_oracle,
+            perp_market
+                .amm
+                .historical_oracle_data
+                .last_oracle_price_twap,
+            perp_market.get_max_confidence_interval_multiplier()?,
+            perp_market.get_validity_guard_rails_override(),
+        )?;
+
+        Some((*reference_price_data, reference_validity))
+    } else {
+        None
+    };
+
+    oracle.update(
+        perp_market,
+        reference_price_data
+            .as_ref()
+            .map(|(price_data, validity)| (price_data, *validity)),
+        slot,
+    )?;
 
     Ok(())
 }
@@ -179,6 +216,141 @@ pub fn get_referrer_and_referrer_stats<'a>(
     Ok((Some(referrer), Some(referrer_stats)))
 }
 
+/// The user's authority and delegate can act for the user without limits, any other signer needs
+/// its DelegatePermissions for the user passed after the market accounts
+pub fn get_signer_delegate_permissions<'a>(
+    account_info_iter: &mut Peekable<Iter<'a, AccountInfo<'a>>>,
+    user: &AccountLoader<User>,
+    signer: &Pubkey,
+) -> DriftResult<Option<DelegatePermissions>> {
+    {
+        let user = load!(user)?;
+        if user.authority.eq(signer)
+            || (user.delegate.eq(signer) && !user.delegate.eq(&Pubkey::default()))
+        {
+            return Ok(None);
+        }
+    }
+
+    let delegate_permissions_account_info = next_account_info(account_info_iter).or_else(|_| {
+        msg!("signer {} is not the user's authority or delegate", signer);
+        Err(ErrorCode::DelegateActionNotAllowed)
+    })?;
+
+    let delegate_permissions_loader: AccountLoader<DelegatePermissions> =
+        AccountLoader::try_from(delegate_permissions_account_info).map_err(|e| {
+            msg!("Unable to deserialize delegate permissions");
+            msg!("{:?}", e);
+            ErrorCode::DelegateActionNotAllowed
+        })?;
+
+    let delegate_permissions = *load!(delegate_permissions_loader)?;
+
+    validate!(
+        delegate_permissions.user == user.key() && delegate_permissions.delegate == *signer,
+        ErrorCode::DelegateActionNotAllowed,
+        "delegate permissions are for user {} and delegate {}",
+        delegate_permissions.user,
+        delegate_permissions.delegate
+    )?;
+
+    Ok(Some(delegate_permissions))
+}
+
+/// Users with a withdraw guard pass it after the market accounts of their withdrawals
+pub fn get_withdraw_guard<'a>(
+    account_info_iter: &mut Peekable<Iter<'a, AccountInfo<'a>>>,
+    user_key: &Pubkey,
+) -> DriftResult<AccountLoader<'a, WithdrawGuard>> {
+    let withdraw_guard_account_info =
+        next_account_info(account_info_iter).or(Err(ErrorCode::WithdrawGuardNotFound))?;
+
+    validate!(
+        withdraw_guard_account_info.is_writable,
+        ErrorCode::WithdrawGuardNotFound,
+        "withdraw guard must be writable"
+    )?;
+
+    let withdraw_guard: AccountLoader<WithdrawGuard> =
+        AccountLoader::try_from(withdraw_guard_account_info).map_err(|e| {
+            msg!("Unable to deserialize withdraw guard");
+            msg!("{:?}", e);
+            ErrorCode::WithdrawGuardNotFound
+        })?;
+
+    validate!(
+        load!(withdraw_guard)?.user == *user_key,
+        ErrorCode::WithdrawGuardNotFound,
+        "withdraw guard is not for user {}",
+        user_key
+    )?;
+
+    Ok(withdraw_guard)
+}
+
+/// Locked insurance fund stakes pass their lockup after the instruction's accounts
+pub fn get_insurance_fund_stake_lockup<'a>(
+    account_info_iter: &mut Peekable<Iter<'a, AccountInfo<'a>>>,
+    insurance_fund_stake_key: &Pubkey,
+) -> DriftResult<AccountLoader<'a, InsuranceFundStakeLockup>> {
+    let lockup_account_info =
+        next_account_info(account_info_iter).or(Err(ErrorCode::InvalidInsuranceFundLockup))?;
+
+    validate!(
+        lockup_account_info.is_writable,
+        ErrorCode::InvalidInsuranceFundLockup,
+        "insurance fund stake lockup must be writable"
+    )?;
+
+    let lockup: AccountLoader<InsuranceFundStakeLockup> =
+        AccountLoader::try_from(lockup_account_info).map_err(|e| {
+            msg!("Unable to deserialize insurance fund stake lockup");
+            msg!("{:?}", e);
+            ErrorCode::InvalidInsuranceFundLockup
+        })?;
+
+    validate!(
+        load!(lockup)?.insurance_fund_stake == *insurance_fund_stake_key,
+        ErrorCode::InvalidInsuranceFundLockup,
+        "lockup is not for insurance fund stake {}",
+        insurance_fund_stake_key
+    )?;
+
+    Ok(lockup)
+}
+
+/// Junior stakes, and insurance fund draws on markets with a junior tranche, pass the market's
+/// junior tranche after the instruction's accounts
+pub fn get_insurance_fund_junior_tranche<'a>(
+    account_info_iter: &mut Peekable<Iter<'a, AccountInfo<'a>>>,
+    market_index: u16,
+) -> DriftResult<AccountLoader<'a, InsuranceFundJuniorTranche>> {
+    let junior_tranche_account_info =
+        next_account_info(account_info_iter).or(Err(ErrorCode::InvalidInsuranceFundTranche))?;
+
+    validate!(
+        junior_tranche_account_info.is_writable,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "insurance fund junior tranche must be writable"
+    )?;
+
+    let junior_tranche: AccountLoader<InsuranceFundJuniorTranche> =
+        AccountLoader::try_from(junior_tranche_account_info).map_err(|e| {
+            msg!("Unable to deserialize insurance fund junior tranche");
+            msg!("{:?}", e);
+            ErrorCode::InvalidInsuranceFundTranche
+        })?;
+
+    validate!(
+        load!(junior_tranche)?.market_index == market_index,
+        ErrorCode::InvalidInsuranceFundTranche,
+        "junior tranche is not for market {}",
+        market_index
+    )?;
+
+    Ok(junior_tranche)
+}
+
 pub fn get_whitelist_token<'a>(
     account_info_iter: &mut Peekable<Iter<'a, AccountInfo<'a>>>,
 ) -> DriftResult<Account<'a, TokenAccount>> {
@@ -11,17 +11,19 @@ use crate::controller::spot_position::{
     charge_withdraw_fee, update_spot_balances_and_cumulative_deposits,
     update_spot_balances_and_cumulative_deposits_with_limits,
 };
-use crate::error::ErrorCode;
+use crate::error::{DriftResult, ErrorCode};
 use crate::ids::{
     jupiter_mainnet_3, jupiter_mainnet_4, jupiter_mainnet_6, marinade_mainnet, serum_program,
 };
 use crate::instructions::constraints::*;
 use crate::instructions::optional_accounts::{
-    get_referrer_and_referrer_stats, get_whitelist_token, load_maps, AccountMaps,
+    get_referrer_and_referrer_stats, get_signer_delegate_permissions, get_whitelist_token,
+    get_withdraw_guard, load_maps, AccountMaps,
 };
 use crate::instructions::SpotFulfillmentType;
 use crate::load_mut;
 use crate::math::casting::Cast;
+use crate::math::constants::PERP_DECIMALS;
 use crate::math::liquidation::is_user_being_liquidated;
 use crate::math::margin::{
     calculate_max_withdrawable_amount, meets_place_order_margin_requirement,
@@ -35,31 +37,40 @@ use crate::math_error;
 use crate::print_error;
 use crate::safe_decrement;
 use crate::safe_increment;
+use crate::state::delegate_permissions::{
+    DelegateAction, DelegatePermissions, DelegatePermissionsParams,
+};
 use crate::state::events::{
-    DepositDirection, DepositExplanation, DepositRecord, LPAction, LPRecord, NewUserRecord,
-    OrderActionExplanation, SwapRecord,
+    DepositDirection, DepositExplanation, DepositRecord, HeartbeatAction, HeartbeatRecord,
+    LPAction, LPRecord, NewUserRecord, OrderActionExplanation, SwapRecord,
 };
 use crate::state::fill_mode::FillMode;
 use crate::state::fulfillment_params::drift::MatchFulfillmentParams;
 use crate::state::fulfillment_params::phoenix::PhoenixFulfillmentParams;
 use crate::state::fulfillment_params::serum::SerumFulfillmentParams;
 use crate::state::oracle::StrictOraclePrice;
+use crate::state::oracle_map::OracleMap;
 use crate::state::order_params::{
-    ModifyOrderParams, OrderParams, PlaceOrderOptions, PostOnlyParam,
+    ModifyOrderParams, OrderParams, PlaceOrderOptions, PostOnlyParam, ScaleOrderParams,
+    TwapOrderParams,
 };
 use crate::state::paused_operations::PerpOperation;
 use crate::state::perp_market::MarketStatus;
-use crate::state::perp_market_map::{get_writable_perp_market_set, MarketSet};
+use crate::state::perp_market_map::{get_writable_perp_market_set, MarketSet, PerpMarketMap};
 use crate::state::spot_fulfillment_params::SpotFulfillmentParams;
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::{
-    get_writable_spot_market_set, get_writable_spot_market_set_from_many,
+    get_writable_spot_market_set, get_writable_spot_market_set_from_many, SpotMarketMap,
 };
 use crate::state::state::State;
 use crate::state::traits::Size;
-use crate::state::user::{MarketType, OrderType, ReferrerName, User, UserStats};
+use crate::state::twap_order::UserTwapOrders;
+use crate::state::user::{
+    MarketType, Order, OrderStatus, OrderType, ReferrerName, User, UserStats, UserStatus,
+};
 use crate::state::user_map::{load_user_maps, UserMap, UserStatsMap};
+use crate::state::withdraw_guard::{WithdrawGuard, WithdrawGuardAction, MAX_WITHDRAW_DESTINATIONS};
 use crate::validate;
 use crate::validation::user::validate_user_deletion;
 use crate::validation::whitelist::validate_whitelist_token;
@@ -222,6 +233,22 @@ pub fn handle_initialize_user_stats<'c: 'info, 'info>(
     Ok(())
 }
 
+pub fn handle_initialize_user_twap_orders(ctx: Context<InitializeUserTwapOrders>) -> Result<()> {
+    let mut user_twap_orders = ctx
+        .accounts
+        .user_twap_orders
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    *user_twap_orders = UserTwapOrders {
+        user: ctx.accounts.user.key(),
+        next_twap_id: 1,
+        ..UserTwapOrders::default()
+    };
+
+    Ok(())
+}
+
 pub fn handle_initialize_referrer_name(
     ctx: Context<InitializeReferrerName>,
     name: [u8; 32],
@@ -286,7 +313,7 @@ pub fn handle_deposit<'c: 'info, 'info>(
     validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
 
     let mut spot_market = spot_market_map.get_ref_mut(&market_index)?;
-    let oracle_price_data = &oracle_map.get_price_data(&spot_market.oracle)?.clone();
+    let oracle_price_data = &oracle_map.get_price_data(spot_market.oracle_id())?.clone();
 
     validate!(
         !matches!(spot_market.status, MarketStatus::Initialized),
@@ -434,12 +461,13 @@ pub fn handle_withdraw<'c: 'info, 'info>(
     let slot = clock.slot;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &get_writable_spot_market_set(market_index),
         clock.slot,
@@ -448,9 +476,29 @@ pub fn handle_withdraw<'c: 'info, 'info>(
 
     validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
 
+    if user.is_withdraw_guarded() {
+        let withdraw_guard = get_withdraw_guard(remaining_accounts_iter, &user_key)?;
+        let mut withdraw_guard = load_mut!(withdraw_guard)?;
+
+        let spot_market = spot_market_map.get_ref(&market_index)?;
+        let oracle_price = oracle_map.get_price_data(spot_market.oracle_id())?.price;
+        let withdraw_value = get_token_value(amount.cast()?, spot_market.decimals, oracle_price)?
+            .unsigned_abs()
+            .min(u64::MAX.cast()?)
+            .cast::<u64>()?;
+
+        withdraw_guard.validate_withdraw(
+            &ctx.accounts.user_token_account.key(),
+            market_index,
+            amount,
+            withdraw_value,
+            now,
+        )?;
+    }
+
     let spot_market_is_reduce_only = {
         let spot_market = &mut spot_market_map.get_ref_mut(&market_index)?;
-        let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+        let oracle_price_data = oracle_map.get_price_data(spot_market.oracle_id())?;
 
         controller::spot_balance::update_spot_market_cumulative_interest(
             spot_market,
@@ -493,7 +541,7 @@ pub fn handle_withdraw<'c: 'info, 'info>(
         };
 
         let spot_market = &mut spot_market_map.get_ref_mut(&market_index)?;
-        let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+        let oracle_price_data = oracle_map.get_price_data(spot_market.oracle_id())?;
 
         if user.qualifies_for_withdraw_fee(&user_stats, slot) {
             let fee =
@@ -538,7 +586,7 @@ pub fn handle_withdraw<'c: 'info, 'info>(
     user.update_last_active_slot(slot);
 
     let mut spot_market = spot_market_map.get_ref_mut(&market_index)?;
-    let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?.price;
+    let oracle_price = oracle_map.get_price_data(spot_market.oracle_id())?.price;
 
     let is_borrow = user
         .get_spot_position(market_index)
@@ -623,6 +671,11 @@ pub fn handle_transfer_deposit<'c: 'info, 'info>(
         ErrorCode::UserBankrupt,
         "from_user bankrupt"
     )?;
+    validate!(
+        !from_user.is_withdraw_guarded(),
+        ErrorCode::WithdrawDestinationNotAllowlisted,
+        "from_user has a withdraw guard, transfers out have to be withdrawals"
+    )?;
 
     validate!(
         from_user_key != to_user_key,
@@ -644,7 +697,7 @@ pub fn handle_transfer_deposit<'c: 'info, 'info>(
 
     {
         let spot_market = &mut spot_market_map.get_ref_mut(&market_index)?;
-        let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+        let oracle_price_data = oracle_map.get_price_data(spot_market.oracle_id())?;
         controller::spot_balance::update_spot_market_cumulative_interest(
             spot_market,
             Some(oracle_price_data),
@@ -654,7 +707,7 @@ pub fn handle_transfer_deposit<'c: 'info, 'info>(
 
     let oracle_price = {
         let spot_market = &spot_market_map.get_ref(&market_index)?;
-        oracle_map.get_price_data(&spot_market.oracle)?.price
+        oracle_map.get_price_data(spot_market.oracle_id())?.price
     };
 
     {
@@ -791,6 +844,50 @@ pub fn handle_transfer_deposit<'c: 'info, 'info>(
     Ok(())
 }
 
+#[access_control(
+    deposit_not_paused(&ctx.accounts.state)
+    withdraw_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_transfer_isolated_perp_position_deposit<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, TransferIsolatedPerpPositionDeposit<'info>>,
+    perp_market_index: u16,
+    amount: i64,
+) -> anchor_lang::Result<()> {
+    let user_key = ctx.accounts.user.key();
+    let user = &mut load_mut!(ctx.accounts.user)?;
+    let state = &ctx.accounts.state;
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &get_writable_perp_market_set(perp_market_index),
+        &get_writable_spot_market_set(QUOTE_SPOT_MARKET_INDEX),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    controller::isolated_position::transfer_isolated_perp_position_deposit(
+        user,
+        &user_key,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        state,
+        perp_market_index,
+        amount,
+        now,
+    )?;
+
+    user.update_last_active_slot(clock.slot);
+
+    Ok(())
+}
+
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
@@ -801,18 +898,34 @@ pub fn handle_place_perp_order<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate_delegate_orders(
+        &delegate_permissions,
+        &[params],
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock.unix_timestamp,
+    )?;
+
     if params.immediate_or_cancel {
         msg!("immediate_or_cancel order must be in place_and_make or place_and_take");
         return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
@@ -836,6 +949,123 @@ pub fn handle_place_perp_order<'c: 'info, 'info>(
     Ok(())
 }
 
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_perp_bracket_order<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, PlaceOrder>,
+    params: OrderParams,
+    take_profit_params: Option<OrderParams>,
+    stop_loss_params: Option<OrderParams>,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate_delegate_orders(
+        &delegate_permissions,
+        &[Some(params), take_profit_params, stop_loss_params]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>(),
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock.unix_timestamp,
+    )?;
+
+    if params.immediate_or_cancel {
+        msg!("immediate_or_cancel order must be in place_and_make or place_and_take");
+        return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
+    }
+
+    let user_key = ctx.accounts.user.key();
+    let mut user = load_mut!(ctx.accounts.user)?;
+
+    controller::orders::place_perp_bracket_order(
+        &ctx.accounts.state,
+        &mut user,
+        user_key,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+        params,
+        take_profit_params,
+        stop_loss_params,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_twap_order<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, PlaceTwapOrder>,
+    params: TwapOrderParams,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let AccountMaps {
+        perp_market_map, ..
+    } = load_maps(
+        &mut ctx.remaining_accounts.iter().peekable(),
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let user_key = ctx.accounts.user.key();
+    let user = load!(ctx.accounts.user)?;
+    let mut user_twap_orders = load_mut!(ctx.accounts.user_twap_orders)?;
+
+    controller::twap_order::place_twap_order(
+        &user,
+        &user_key,
+        &mut user_twap_orders,
+        &perp_market_map,
+        params,
+        clock.unix_timestamp,
+    )?;
+
+    Ok(())
+}
+
+pub fn handle_cancel_twap_order(ctx: Context<CancelTwapOrder>, twap_id: u32) -> Result<()> {
+    let user_key = ctx.accounts.user.key();
+    let mut user_twap_orders = load_mut!(ctx.accounts.user_twap_orders)?;
+
+    controller::twap_order::cancel_twap_order(
+        &user_key,
+        &mut user_twap_orders,
+        twap_id,
+        Clock::get()?.unix_timestamp,
+    )?;
+
+    Ok(())
+}
+
 #[access_control(
     exchange_not_paused(&ctx.accounts.state)
 )]
@@ -846,23 +1076,37 @@ pub fn handle_cancel_order<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
     let order_id = match order_id {
         Some(order_id) => order_id,
         None => load!(ctx.accounts.user)?.get_last_order_id(),
     };
 
+    validate_delegate_cancels(
+        &delegate_permissions,
+        &ctx.accounts.user,
+        clock.unix_timestamp,
+        |order| order.order_id == order_id,
+    )?;
+
     controller::orders::cancel_order_by_order_id(
         order_id,
         &ctx.accounts.user,
@@ -885,18 +1129,32 @@ pub fn handle_cancel_order_by_user_id<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate_delegate_cancels(
+        &delegate_permissions,
+        &ctx.accounts.user,
+        clock.unix_timestamp,
+        |order| order.user_order_id == user_order_id,
+    )?;
+
     controller::orders::cancel_order_by_user_order_id(
         user_order_id,
         &ctx.accounts.user,
@@ -919,18 +1177,32 @@ pub fn handle_cancel_orders_by_ids<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate_delegate_cancels(
+        &delegate_permissions,
+        &ctx.accounts.user,
+        clock.unix_timestamp,
+        |order| order_ids.contains(&order.order_id),
+    )?;
+
     for order_id in order_ids {
         controller::orders::cancel_order_by_order_id(
             order_id,
@@ -957,18 +1229,40 @@ pub fn handle_cancel_orders<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate_delegate_cancels(
+        &delegate_permissions,
+        &ctx.accounts.user,
+        clock.unix_timestamp,
+        |order| {
+            let in_market = match (market_type, market_index) {
+                (Some(market_type), Some(market_index)) => {
+                    order.market_type == market_type && order.market_index == market_index
+                }
+                _ => true,
+            };
+            in_market && direction.map_or(true, |direction| order.direction == direction)
+        },
+    )?;
+
     let user_key = ctx.accounts.user.key();
     let mut user = load_mut!(ctx.accounts.user)?;
 
@@ -1001,18 +1295,31 @@ pub fn handle_modify_order<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate!(
+        delegate_permissions.is_none(),
+        ErrorCode::DelegateActionNotAllowed,
+        "delegates with scoped permissions can't modify orders"
+    )?;
+
     let order_id = match order_id {
         Some(order_id) => order_id,
         None => load!(ctx.accounts.user)?.get_last_order_id(),
@@ -1043,18 +1350,31 @@ pub fn handle_modify_order_by_user_order_id<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate!(
+        delegate_permissions.is_none(),
+        ErrorCode::DelegateActionNotAllowed,
+        "delegates with scoped permissions can't modify orders"
+    )?;
+
     controller::orders::modify_order(
         ModifyOrderId::UserOrderId(user_order_id),
         modify_order_params,
@@ -1079,63 +1399,250 @@ pub fn handle_place_orders<'c: 'info, 'info>(
     let clock = &Clock::get()?;
     let state = &ctx.accounts.state;
 
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         clock.slot,
         Some(state.oracle_guard_rails),
     )?;
 
-    validate!(
-        params.len() <= 32,
-        ErrorCode::DefaultError,
-        "max 32 order params"
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
     )?;
 
-    let user_key = ctx.accounts.user.key();
-    let mut user = load_mut!(ctx.accounts.user)?;
-
-    let num_orders = params.len();
-    for (i, params) in params.iter().enumerate() {
-        validate!(
-            !params.immediate_or_cancel,
-            ErrorCode::InvalidOrderIOC,
-            "immediate_or_cancel order must be in place_and_make or place_and_take"
-        )?;
+    validate_delegate_orders(
+        &delegate_permissions,
+        &params,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock.unix_timestamp,
+    )?;
 
-        // only enforce margin on last order and only try to expire on first order
-        let options = PlaceOrderOptions {
-            enforce_margin_check: i == num_orders - 1,
-            try_expire_orders: i == 0,
+    place_orders(
+        state,
+        &ctx.accounts.user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+        &params,
+    )?;
+
+    Ok(())
+}
+
+#[access_control(
+    exchange_not_paused(&ctx.accounts.state)
+)]
+pub fn handle_place_scale_orders<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, PlaceOrder>,
+    params: ScaleOrderParams,
+) -> Result<()> {
+    let clock = &Clock::get()?;
+    let state = &ctx.accounts.state;
+
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        clock.slot,
+        Some(state.oracle_guard_rails),
+    )?;
+
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    let (tick_size, step_size) = if params.market_type == MarketType::Perp {
+        let perp_market = perp_market_map.get_ref(&params.market_index)?;
+        (
+            perp_market.amm.order_tick_size,
+            perp_market.amm.order_step_size,
+        )
+    } else {
+        let spot_market = spot_market_map.get_ref(&params.market_index)?;
+        (spot_market.order_tick_size, spot_market.order_step_size)
+    };
+
+    let order_params = params.get_order_params(tick_size, step_size)?;
+
+    validate_delegate_orders(
+        &delegate_permissions,
+        &order_params,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock.unix_timestamp,
+    )?;
+
+    place_orders(
+        state,
+        &ctx.accounts.user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        clock,
+        &order_params,
+    )?;
+
+    Ok(())
+}
+
+/// Checks the orders a delegate with scoped permissions places are within them. Orders are valued
+/// at the greater of their limit price and the oracle price, market and oracle offset orders at
+/// the oracle price
+fn validate_delegate_orders(
+    delegate_permissions: &Option<DelegatePermissions>,
+    params: &[OrderParams],
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    now: i64,
+) -> DriftResult {
+    let delegate_permissions = match delegate_permissions {
+        Some(delegate_permissions) => delegate_permissions,
+        None => return Ok(()),
+    };
+
+    for params in params.iter() {
+        let (oracle_id, decimals) = match params.market_type {
+            MarketType::Perp => (
+                perp_market_map.get_ref(&params.market_index)?.oracle_id(),
+                PERP_DECIMALS,
+            ),
+            MarketType::Spot => {
+                let spot_market = spot_market_map.get_ref(&params.market_index)?;
+                (spot_market.oracle_id(), spot_market.decimals)
+            }
+        };
+
+        let oracle_price = oracle_map.get_price_data(oracle_id)?.price;
+        let price = if matches!(
+            params.order_type,
+            OrderType::Market | OrderType::TriggerMarket | OrderType::Oracle
+        ) || params.oracle_price_offset.is_some()
+        {
+            oracle_price
+        } else {
+            oracle_price.max(params.price.cast()?)
+        };
+
+        let order_notional = get_token_value(params.base_asset_amount.cast()?, decimals, price)?
+            .unsigned_abs()
+            .min(u64::MAX.cast()?)
+            .cast::<u64>()?;
+
+        delegate_permissions.validate_order(
+            params.market_type,
+            params.market_index,
+            params.reduce_only,
+            order_notional,
+            now,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks a delegate with scoped permissions can cancel each of the user's open orders that
+/// is_canceled matches, it can only cancel orders in its allowed markets
+fn validate_delegate_cancels(
+    delegate_permissions: &Option<DelegatePermissions>,
+    user: &AccountLoader<User>,
+    now: i64,
+    is_canceled: impl Fn(&Order) -> bool,
+) -> DriftResult {
+    let delegate_permissions = match delegate_permissions {
+        Some(delegate_permissions) => delegate_permissions,
+        None => return Ok(()),
+    };
+
+    delegate_permissions.validate_action(DelegateAction::CancelOrder, now)?;
+
+    let user = load!(user)?;
+    for order in user
+        .orders
+        .iter()
+        .filter(|order| order.status == OrderStatus::Open && is_canceled(order))
+    {
+        delegate_permissions.validate_cancel(order.market_type, order.market_index, now)?;
+    }
+
+    Ok(())
+}
+
+fn place_orders(
+    state: &State,
+    user: &AccountLoader<User>,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    clock: &Clock,
+    params: &[OrderParams],
+) -> DriftResult {
+    validate!(
+        params.len() <= 32,
+        ErrorCode::DefaultError,
+        "max 32 order params"
+    )?;
+
+    let user_key = user.key();
+    let mut user = load_mut!(user)?;
+
+    let num_orders = params.len();
+    for (i, params) in params.iter().enumerate() {
+        validate!(
+            !params.immediate_or_cancel,
+            ErrorCode::InvalidOrderIOC,
+            "immediate_or_cancel order must be in place_and_make or place_and_take"
+        )?;
+
+        // only enforce margin on last order and only try to expire on first order
+        let options = PlaceOrderOptions {
+            enforce_margin_check: i == num_orders - 1,
+            try_expire_orders: i == 0,
             risk_increasing: false,
             explanation: OrderActionExplanation::None,
+            bit_flags: 0,
+            bracket_id: 0,
         };
 
         if params.market_type == MarketType::Perp {
             controller::orders::place_perp_order(
-                &ctx.accounts.state,
+                state,
                 &mut user,
                 user_key,
-                &perp_market_map,
-                &spot_market_map,
-                &mut oracle_map,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
                 clock,
                 *params,
                 options,
             )?;
         } else {
             controller::orders::place_spot_order(
-                &ctx.accounts.state,
+                state,
                 &mut user,
                 user_key,
-                &perp_market_map,
-                &spot_market_map,
-                &mut oracle_map,
+                perp_market_map,
+                spot_market_map,
+                oracle_map,
                 clock,
                 *params,
                 options,
@@ -1207,8 +1714,11 @@ pub fn handle_place_and_take_perp_order<'c: 'info, 'info>(
 
     let user = &mut ctx.accounts.user;
     let order_id = load!(user)?.get_last_order_id();
+    let order_base_asset_amount = load!(user)?
+        .get_order(order_id)
+        .map_or(0, |order| order.base_asset_amount);
 
-    controller::orders::fill_perp_order(
+    let base_asset_amount_filled = controller::orders::fill_perp_order(
         order_id,
         &ctx.accounts.state,
         user,
@@ -1225,6 +1735,12 @@ pub fn handle_place_and_take_perp_order<'c: 'info, 'info>(
         FillMode::PlaceAndTake,
     )?;
 
+    controller::orders::validate_place_and_take_fill_requirement(
+        &params,
+        order_base_asset_amount,
+        base_asset_amount_filled,
+    )?;
+
     let order_exists = load!(ctx.accounts.user)?
         .orders
         .iter()
@@ -1348,18 +1864,34 @@ pub fn handle_place_spot_order<'c: 'info, 'info>(
     ctx: Context<'_, '_, 'c, 'info, PlaceOrder>,
     params: OrderParams,
 ) -> Result<()> {
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
     let AccountMaps {
         perp_market_map,
         spot_market_map,
         mut oracle_map,
     } = load_maps(
-        &mut ctx.remaining_accounts.iter().peekable(),
+        remaining_accounts_iter,
         &MarketSet::new(),
         &MarketSet::new(),
         Clock::get()?.slot,
         None,
     )?;
 
+    let delegate_permissions = get_signer_delegate_permissions(
+        remaining_accounts_iter,
+        &ctx.accounts.user,
+        ctx.accounts.authority.key,
+    )?;
+
+    validate_delegate_orders(
+        &delegate_permissions,
+        &[params],
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        Clock::get()?.unix_timestamp,
+    )?;
+
     if params.immediate_or_cancel {
         msg!("immediate_or_cancel order must be in place_and_make or place_and_take");
         return Err(print_error!(ErrorCode::InvalidOrderIOC)().into());
@@ -1472,8 +2004,11 @@ pub fn handle_place_and_take_spot_order<'c: 'info, 'info>(
 
     let user = &mut ctx.accounts.user;
     let order_id = load!(user)?.get_last_order_id();
+    let order_base_asset_amount = load!(user)?
+        .get_order(order_id)
+        .map_or(0, |order| order.base_asset_amount);
 
-    controller::orders::fill_spot_order(
+    let base_asset_amount_filled = controller::orders::fill_spot_order(
         order_id,
         &ctx.accounts.state,
         user,
@@ -1490,6 +2025,12 @@ pub fn handle_place_and_take_spot_order<'c: 'info, 'info>(
         fulfillment_params.as_mut(),
     )?;
 
+    controller::orders::validate_place_and_take_fill_requirement(
+        &params,
+        order_base_asset_amount,
+        base_asset_amount_filled,
+    )?;
+
     let order_exists = load!(ctx.accounts.user)?
         .orders
         .iter()
@@ -1675,6 +2216,10 @@ pub fn handle_add_perp_lp_shares<'c: 'info, 'info>(
     )?;
 
     validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
+    validate!(
+        !user.is_portfolio_margin_enabled(),
+        ErrorCode::PortfolioMarginLpNotSupported
+    )?;
     math::liquidation::validate_user_not_being_liquidated(
         user,
         &perp_market_map,
@@ -1898,6 +2443,176 @@ pub fn handle_update_user_delegate(
     Ok(())
 }
 
+pub fn handle_initialize_delegate_permissions(
+    ctx: Context<InitializeDelegatePermissions>,
+    delegate: Pubkey,
+    params: DelegatePermissionsParams,
+) -> Result<()> {
+    let mut delegate_permissions = ctx
+        .accounts
+        .delegate_permissions
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    *delegate_permissions = DelegatePermissions {
+        user: ctx.accounts.user.key(),
+        delegate,
+        ..DelegatePermissions::default()
+    };
+
+    delegate_permissions.update(params)?;
+
+    Ok(())
+}
+
+pub fn handle_update_delegate_permissions(
+    ctx: Context<UpdateDelegatePermissions>,
+    params: DelegatePermissionsParams,
+) -> Result<()> {
+    let mut delegate_permissions = load_mut!(ctx.accounts.delegate_permissions)?;
+    delegate_permissions.update(params)?;
+    Ok(())
+}
+
+pub fn handle_delete_delegate_permissions(_ctx: Context<DeleteDelegatePermissions>) -> Result<()> {
+    Ok(())
+}
+
+pub fn handle_initialize_withdraw_guard(
+    ctx: Context<InitializeWithdrawGuard>,
+    timelock: i64,
+    withdraw_threshold: u64,
+    destinations: Vec<Pubkey>,
+) -> Result<()> {
+    validate!(
+        timelock > 0,
+        ErrorCode::InvalidWithdrawGuardRequest,
+        "withdraw guard timelock must be > 0"
+    )?;
+
+    validate!(
+        destinations.len() <= MAX_WITHDRAW_DESTINATIONS,
+        ErrorCode::InvalidWithdrawGuardRequest,
+        "withdraw guard allows at most {} destinations",
+        MAX_WITHDRAW_DESTINATIONS
+    )?;
+
+    let mut withdraw_guard = ctx
+        .accounts
+        .withdraw_guard
+        .load_init()
+        .or(Err(ErrorCode::UnableToLoadAccountLoader))?;
+
+    *withdraw_guard = WithdrawGuard {
+        user: ctx.accounts.user.key(),
+        timelock,
+        withdraw_threshold,
+        ..WithdrawGuard::default()
+    };
+
+    for destination in destinations {
+        withdraw_guard.add_destination(destination)?;
+    }
+
+    let mut user = load_mut!(ctx.accounts.user)?;
+    user.add_user_status(UserStatus::WithdrawGuard);
+
+    Ok(())
+}
+
+pub fn handle_request_withdraw_guard_action(
+    ctx: Context<UpdateWithdrawGuard>,
+    action: WithdrawGuardAction,
+    destination: Pubkey,
+    market_index: u16,
+    amount: u64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let mut withdraw_guard = load_mut!(ctx.accounts.withdraw_guard)?;
+
+    withdraw_guard.request(action, destination, market_index, amount, now)?;
+
+    Ok(())
+}
+
+pub fn handle_cancel_withdraw_guard_request(ctx: Context<UpdateWithdrawGuard>) -> Result<()> {
+    let mut withdraw_guard = load_mut!(ctx.accounts.withdraw_guard)?;
+
+    withdraw_guard.cancel_request()?;
+
+    Ok(())
+}
+
+pub fn handle_execute_withdraw_guard_request(ctx: Context<UpdateWithdrawGuard>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let mut withdraw_guard = load_mut!(ctx.accounts.withdraw_guard)?;
+
+    withdraw_guard.execute_request(now)?;
+
+    Ok(())
+}
+
+pub fn handle_delete_withdraw_guard(ctx: Context<DeleteWithdrawGuard>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let mut withdraw_guard = load_mut!(ctx.accounts.withdraw_guard)?;
+
+    withdraw_guard.validate_remove(now)?;
+
+    let mut user = load_mut!(ctx.accounts.user)?;
+    user.remove_user_status(UserStatus::WithdrawGuard);
+
+    Ok(())
+}
+
+pub fn handle_update_user_heartbeat_timeout(
+    ctx: Context<UpdateUser>,
+    _sub_account_id: u16,
+    heartbeat_timeout: u16,
+) -> Result<()> {
+    let user_key = ctx.accounts.user.key();
+    let mut user = load_mut!(ctx.accounts.user)?;
+    let now = Clock::get()?.unix_timestamp;
+
+    user.heartbeat_timeout = heartbeat_timeout;
+    user.last_heartbeat_ts = now;
+
+    emit!(HeartbeatRecord {
+        ts: now,
+        user: user_key,
+        action: HeartbeatAction::UpdateTimeout,
+        last_heartbeat_ts: user.last_heartbeat_ts,
+        heartbeat_timeout: user.heartbeat_timeout,
+        ..HeartbeatRecord::default()
+    });
+
+    Ok(())
+}
+
+pub fn handle_update_user_heartbeat(ctx: Context<UpdateUserHeartbeat>) -> Result<()> {
+    let user_key = ctx.accounts.user.key();
+    let mut user = load_mut!(ctx.accounts.user)?;
+    let now = Clock::get()?.unix_timestamp;
+
+    validate!(
+        user.heartbeat_timeout != 0,
+        ErrorCode::InvalidHeartbeat,
+        "dead man's switch is disarmed"
+    )?;
+
+    user.last_heartbeat_ts = now;
+
+    emit!(HeartbeatRecord {
+        ts: now,
+        user: user_key,
+        action: HeartbeatAction::Heartbeat,
+        last_heartbeat_ts: user.last_heartbeat_ts,
+        heartbeat_timeout: user.heartbeat_timeout,
+        ..HeartbeatRecord::default()
+    });
+
+    Ok(())
+}
+
 pub fn handle_update_user_reduce_only(
     ctx: Context<UpdateUser>,
     _sub_account_id: u16,
@@ -1924,6 +2639,43 @@ pub fn handle_update_user_advanced_lp(
     Ok(())
 }
 
+pub fn handle_update_user_portfolio_margin<'c: 'info, 'info>(
+    ctx: Context<'_, '_, 'c, 'info, UpdateUser<'info>>,
+    _sub_account_id: u16,
+    portfolio_margin: bool,
+) -> Result<()> {
+    let remaining_accounts_iter = &mut ctx.remaining_accounts.iter().peekable();
+    let AccountMaps {
+        perp_market_map,
+        spot_market_map,
+        mut oracle_map,
+        ..
+    } = load_maps(
+        remaining_accounts_iter,
+        &MarketSet::new(),
+        &MarketSet::new(),
+        Clock::get()?.slot,
+        None,
+    )?;
+
+    let mut user = load_mut!(ctx.accounts.user)?;
+
+    validate!(!user.is_being_liquidated(), ErrorCode::LiquidationsOngoing)?;
+
+    user.update_portfolio_margin_status(portfolio_margin)?;
+
+    // switching margin modes cant leave the user below their initial margin requirement
+    meets_withdraw_margin_requirement(
+        &user,
+        &perp_market_map,
+        &spot_market_map,
+        &mut oracle_map,
+        MarginRequirementType::Initial,
+    )?;
+
+    Ok(())
+}
+
 pub fn handle_delete_user(ctx: Context<DeleteUser>) -> Result<()> {
     let user = &load!(ctx.accounts.user)?;
     let user_stats = &mut load_mut!(ctx.accounts.user_stats)?;
@@ -2073,6 +2825,27 @@ pub struct InitializeUserStats<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct InitializeUserTwapOrders<'info> {
+    #[account(
+        init,
+        seeds = [b"user_twap_orders", user.key().as_ref()],
+        space = UserTwapOrders::SIZE,
+        bump,
+        payer = payer
+    )]
+    pub user_twap_orders: AccountLoader<'info, UserTwapOrders>,
+    #[account(
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(
     name: [u8; 32],
@@ -2217,24 +2990,64 @@ pub struct TransferDeposit<'info> {
 }
 
 #[derive(Accounts)]
-pub struct PlaceOrder<'info> {
+pub struct TransferIsolatedPerpPositionDeposit<'info> {
     pub state: Box<Account<'info, State>>,
     #[account(
         mut,
-        constraint = can_sign_for_user(&user, &authority)?
+        has_one = authority,
     )]
     pub user: AccountLoader<'info, User>,
     pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CancelOrder<'info> {
+pub struct PlaceTwapOrder<'info> {
     pub state: Box<Account<'info, State>>,
+    #[account(
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
     #[account(
         mut,
+        seeds = [b"user_twap_orders", user.key().as_ref()],
+        bump,
+    )]
+    pub user_twap_orders: AccountLoader<'info, UserTwapOrders>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTwapOrder<'info> {
+    #[account(
         constraint = can_sign_for_user(&user, &authority)?
     )]
     pub user: AccountLoader<'info, User>,
+    #[account(
+        mut,
+        seeds = [b"user_twap_orders", user.key().as_ref()],
+        bump,
+    )]
+    pub user_twap_orders: AccountLoader<'info, UserTwapOrders>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    pub state: Box<Account<'info, State>>,
+    /// The authority is checked in the handler, delegates with scoped permissions pass them after
+    /// the market accounts
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    pub state: Box<Account<'info, State>>,
+    /// The authority is checked in the handler, delegates with scoped permissions pass them after
+    /// the market accounts
+    #[account(mut)]
+    pub user: AccountLoader<'info, User>,
     pub authority: Signer<'info>,
 }
 
@@ -2309,6 +3122,113 @@ pub struct UpdateUser<'info> {
     pub authority: Signer<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct InitializeDelegatePermissions<'info> {
+    #[account(
+        init,
+        seeds = [b"delegate_permissions", user.key().as_ref(), delegate.as_ref()],
+        space = DelegatePermissions::SIZE,
+        bump,
+        payer = payer
+    )]
+    pub delegate_permissions: AccountLoader<'info, DelegatePermissions>,
+    #[account(has_one = authority)]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDelegatePermissions<'info> {
+    #[account(
+        mut,
+        has_one = user
+    )]
+    pub delegate_permissions: AccountLoader<'info, DelegatePermissions>,
+    #[account(has_one = authority)]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteDelegatePermissions<'info> {
+    #[account(
+        mut,
+        has_one = user,
+        close = authority
+    )]
+    pub delegate_permissions: AccountLoader<'info, DelegatePermissions>,
+    #[account(has_one = authority)]
+    pub user: AccountLoader<'info, User>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeWithdrawGuard<'info> {
+    #[account(
+        init,
+        seeds = [b"withdraw_guard", user.key().as_ref()],
+        space = WithdrawGuard::SIZE,
+        bump,
+        payer = payer
+    )]
+    pub withdraw_guard: AccountLoader<'info, WithdrawGuard>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateWithdrawGuard<'info> {
+    #[account(
+        mut,
+        has_one = user
+    )]
+    pub withdraw_guard: AccountLoader<'info, WithdrawGuard>,
+    #[account(has_one = authority)]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteWithdrawGuard<'info> {
+    #[account(
+        mut,
+        has_one = user,
+        close = authority
+    )]
+    pub withdraw_guard: AccountLoader<'info, WithdrawGuard>,
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub user: AccountLoader<'info, User>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateUserHeartbeat<'info> {
+    #[account(
+        mut,
+        constraint = can_sign_for_user(&user, &authority)?
+    )]
+    pub user: AccountLoader<'info, User>,
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct DeleteUser<'info> {
     #[account(
@@ -2425,6 +3345,12 @@ pub fn handle_begin_swap<'c: 'info, 'info>(
 
     validate!(!user.is_bankrupt(), ErrorCode::UserBankrupt)?;
 
+    validate!(
+        !user.is_withdraw_guarded(),
+        ErrorCode::WithdrawDestinationNotAllowlisted,
+        "withdraw guarded users can't swap"
+    )?;
+
     math::liquidation::validate_user_not_being_liquidated(
         &mut user,
         &perp_market_map,
@@ -2449,7 +3375,7 @@ pub fn handle_begin_swap<'c: 'info, 'info>(
         "begin_swap ended in invalid state"
     )?;
 
-    let in_oracle_data = oracle_map.get_price_data(&in_spot_market.oracle)?;
+    let in_oracle_data = oracle_map.get_price_data(in_spot_market.oracle_id())?;
     controller::spot_balance::update_spot_market_cumulative_interest(
         &mut in_spot_market,
         Some(in_oracle_data),
@@ -2472,7 +3398,7 @@ pub fn handle_begin_swap<'c: 'info, 'info>(
         "begin_swap ended in invalid state"
     )?;
 
-    let out_oracle_data = oracle_map.get_price_data(&out_spot_market.oracle)?;
+    let out_oracle_data = oracle_map.get_price_data(out_spot_market.oracle_id())?;
     controller::spot_balance::update_spot_market_cumulative_interest(
         &mut out_spot_market,
         Some(out_oracle_data),
@@ -2685,12 +3611,12 @@ pub fn handle_end_swap<'c: 'info, 'info>(
         "the in_spot_market must have a flash loan amount set"
     )?;
 
-    let in_oracle_data = oracle_map.get_price_data(&in_spot_market.oracle)?;
+    let in_oracle_data = oracle_map.get_price_data(in_spot_market.oracle_id())?;
     let in_oracle_price = in_oracle_data.price;
 
     let mut out_spot_market = spot_market_map.get_ref_mut(&out_market_index)?;
 
-    let out_oracle_data = oracle_map.get_price_data(&out_spot_market.oracle)?;
+    let out_oracle_data = oracle_map.get_price_data(out_spot_market.oracle_id())?;
     let out_oracle_price = out_oracle_data.price;
 
     let in_vault = &mut ctx.accounts.in_spot_market_vault;
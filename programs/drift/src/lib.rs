@@ -11,15 +11,24 @@ use math::{bn, constants::*};
 use state::oracle::OracleSource;
 
 use crate::controller::position::PositionDirection;
-use crate::state::oracle::PrelaunchOracleParams;
-use crate::state::order_params::{ModifyOrderParams, OrderParams};
-use crate::state::perp_market::{ContractTier, MarketStatus};
+use crate::state::delegate_permissions::DelegatePermissionsParams;
+use crate::state::insurance_fund_stake::{InsuranceFundLockupTier, InsuranceFundTranche};
+use crate::state::option_market::OptionMarketParams;
+use crate::state::oracle::{OracleAggregationMethod, PrelaunchOracleParams};
+use crate::state::oracle_circuit_breaker::OracleCircuitBreakerParams;
+use crate::state::order_params::{
+    ModifyOrderParams, OrderParams, ScaleOrderParams, TwapOrderParams,
+};
+use crate::state::perp_market::{
+    ContractTier, FundingPremiumIndex, MarketStatus, PredictionMarketResolution,
+};
 use crate::state::settle_pnl_mode::SettlePnlMode;
 use crate::state::spot_market::AssetTier;
 use crate::state::spot_market::SpotFulfillmentConfigStatus;
 use crate::state::state::FeeStructure;
 use crate::state::state::*;
 use crate::state::user::MarketType;
+use crate::state::withdraw_guard::WithdrawGuardAction;
 
 pub mod controller;
 pub mod error;
@@ -66,6 +75,10 @@ pub mod drift {
         handle_initialize_referrer_name(ctx, name)
     }
 
+    pub fn initialize_user_twap_orders(ctx: Context<InitializeUserTwapOrders>) -> Result<()> {
+        handle_initialize_user_twap_orders(ctx)
+    }
+
     pub fn deposit<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, Deposit<'info>>,
         market_index: u16,
@@ -92,6 +105,14 @@ pub mod drift {
         handle_transfer_deposit(ctx, market_index, amount)
     }
 
+    pub fn transfer_isolated_perp_position_deposit<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, TransferIsolatedPerpPositionDeposit<'info>>,
+        perp_market_index: u16,
+        amount: i64,
+    ) -> anchor_lang::Result<()> {
+        handle_transfer_isolated_perp_position_deposit(ctx, perp_market_index, amount)
+    }
+
     pub fn place_perp_order<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, PlaceOrder>,
         params: OrderParams,
@@ -99,6 +120,26 @@ pub mod drift {
         handle_place_perp_order(ctx, params)
     }
 
+    pub fn place_perp_bracket_order<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, PlaceOrder>,
+        params: OrderParams,
+        take_profit_params: Option<OrderParams>,
+        stop_loss_params: Option<OrderParams>,
+    ) -> Result<()> {
+        handle_place_perp_bracket_order(ctx, params, take_profit_params, stop_loss_params)
+    }
+
+    pub fn place_twap_order<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, PlaceTwapOrder>,
+        params: TwapOrderParams,
+    ) -> Result<()> {
+        handle_place_twap_order(ctx, params)
+    }
+
+    pub fn cancel_twap_order(ctx: Context<CancelTwapOrder>, twap_id: u32) -> Result<()> {
+        handle_cancel_twap_order(ctx, twap_id)
+    }
+
     pub fn cancel_order<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, CancelOrder>,
         order_id: Option<u32>,
@@ -203,6 +244,13 @@ pub mod drift {
         handle_place_orders(ctx, params)
     }
 
+    pub fn place_scale_orders<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, PlaceOrder>,
+        params: ScaleOrderParams,
+    ) -> Result<()> {
+        handle_place_scale_orders(ctx, params)
+    }
+
     pub fn begin_swap<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, Swap<'info>>,
         in_market_index: u16,
@@ -284,6 +332,68 @@ pub mod drift {
         handle_update_user_delegate(ctx, _sub_account_id, delegate)
     }
 
+    pub fn initialize_delegate_permissions(
+        ctx: Context<InitializeDelegatePermissions>,
+        delegate: Pubkey,
+        params: DelegatePermissionsParams,
+    ) -> Result<()> {
+        handle_initialize_delegate_permissions(ctx, delegate, params)
+    }
+
+    pub fn update_delegate_permissions(
+        ctx: Context<UpdateDelegatePermissions>,
+        params: DelegatePermissionsParams,
+    ) -> Result<()> {
+        handle_update_delegate_permissions(ctx, params)
+    }
+
+    pub fn delete_delegate_permissions(ctx: Context<DeleteDelegatePermissions>) -> Result<()> {
+        handle_delete_delegate_permissions(ctx)
+    }
+
+    pub fn initialize_withdraw_guard(
+        ctx: Context<InitializeWithdrawGuard>,
+        timelock: i64,
+        withdraw_threshold: u64,
+        destinations: Vec<Pubkey>,
+    ) -> Result<()> {
+        handle_initialize_withdraw_guard(ctx, timelock, withdraw_threshold, destinations)
+    }
+
+    pub fn request_withdraw_guard_action(
+        ctx: Context<UpdateWithdrawGuard>,
+        action: WithdrawGuardAction,
+        destination: Pubkey,
+        market_index: u16,
+        amount: u64,
+    ) -> Result<()> {
+        handle_request_withdraw_guard_action(ctx, action, destination, market_index, amount)
+    }
+
+    pub fn cancel_withdraw_guard_request(ctx: Context<UpdateWithdrawGuard>) -> Result<()> {
+        handle_cancel_withdraw_guard_request(ctx)
+    }
+
+    pub fn execute_withdraw_guard_request(ctx: Context<UpdateWithdrawGuard>) -> Result<()> {
+        handle_execute_withdraw_guard_request(ctx)
+    }
+
+    pub fn delete_withdraw_guard(ctx: Context<DeleteWithdrawGuard>) -> Result<()> {
+        handle_delete_withdraw_guard(ctx)
+    }
+
+    pub fn update_user_heartbeat_timeout(
+        ctx: Context<UpdateUser>,
+        _sub_account_id: u16,
+        heartbeat_timeout: u16,
+    ) -> Result<()> {
+        handle_update_user_heartbeat_timeout(ctx, _sub_account_id, heartbeat_timeout)
+    }
+
+    pub fn update_user_heartbeat(ctx: Context<UpdateUserHeartbeat>) -> Result<()> {
+        handle_update_user_heartbeat(ctx)
+    }
+
     pub fn update_user_reduce_only(
         ctx: Context<UpdateUser>,
         _sub_account_id: u16,
@@ -300,6 +410,14 @@ pub mod drift {
         handle_update_user_advanced_lp(ctx, _sub_account_id, advanced_lp)
     }
 
+    pub fn update_user_portfolio_margin<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, UpdateUser<'info>>,
+        _sub_account_id: u16,
+        portfolio_margin: bool,
+    ) -> Result<()> {
+        handle_update_user_portfolio_margin(ctx, _sub_account_id, portfolio_margin)
+    }
+
     pub fn delete_user<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, DeleteUser>,
     ) -> Result<()> {
@@ -340,12 +458,31 @@ pub mod drift {
         handle_trigger_order(ctx, order_id)
     }
 
+    pub fn release_twap_slice<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, ReleaseTwapSlice<'info>>,
+        twap_id: u32,
+    ) -> Result<()> {
+        handle_release_twap_slice(ctx, twap_id)
+    }
+
+    pub fn sweep_expired_orders<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, SweepExpiredOrders<'info>>,
+    ) -> Result<()> {
+        handle_sweep_expired_orders(ctx)
+    }
+
     pub fn force_cancel_orders<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, ForceCancelOrder<'info>>,
     ) -> Result<()> {
         handle_force_cancel_orders(ctx)
     }
 
+    pub fn cancel_orders_on_heartbeat_timeout<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, ForceCancelOrder<'info>>,
+    ) -> Result<()> {
+        handle_cancel_orders_on_heartbeat_timeout(ctx)
+    }
+
     pub fn update_user_idle<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, UpdateUserIdle<'info>>,
     ) -> Result<()> {
@@ -494,10 +631,28 @@ pub mod drift {
         handle_update_funding_rate(ctx, market_index)
     }
 
-    pub fn update_prelaunch_oracle(ctx: Context<UpdatePrelaunchOracle>) -> Result<()> {
+    pub fn update_perp_market_circuit_breaker(
+        ctx: Context<UpdatePerpMarketCircuitBreaker>,
+    ) -> Result<()> {
+        handle_update_perp_market_circuit_breaker(ctx)
+    }
+
+    pub fn update_spot_market_circuit_breaker(
+        ctx: Context<UpdateSpotMarketCircuitBreaker>,
+    ) -> Result<()> {
+        handle_update_spot_market_circuit_breaker(ctx)
+    }
+
+    pub fn update_prelaunch_oracle<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, UpdatePrelaunchOracle<'info>>,
+    ) -> Result<()> {
         handle_update_prelaunch_oracle(ctx)
     }
 
+    pub fn update_option_market(ctx: Context<UpdateOptionMarket>) -> Result<()> {
+        handle_update_option_market(ctx)
+    }
+
     pub fn update_perp_bid_ask_twap<'c: 'info, 'info>(
         ctx: Context<'_, '_, 'c, 'info, UpdatePerpBidAskTwap<'info>>,
     ) -> Result<()> {
@@ -545,36 +700,75 @@ pub mod drift {
         handle_initialize_insurance_fund_stake(ctx, market_index)
     }
 
-    pub fn add_insurance_fund_stake(
-        ctx: Context<AddInsuranceFundStake>,
+    pub fn add_insurance_fund_stake<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, AddInsuranceFundStake<'info>>,
         market_index: u16,
         amount: u64,
     ) -> Result<()> {
         handle_add_insurance_fund_stake(ctx, market_index, amount)
     }
 
-    pub fn request_remove_insurance_fund_stake(
-        ctx: Context<RequestRemoveInsuranceFundStake>,
+    pub fn request_remove_insurance_fund_stake<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, RequestRemoveInsuranceFundStake<'info>>,
         market_index: u16,
         amount: u64,
     ) -> Result<()> {
         handle_request_remove_insurance_fund_stake(ctx, market_index, amount)
     }
 
-    pub fn cancel_request_remove_insurance_fund_stake(
-        ctx: Context<RequestRemoveInsuranceFundStake>,
+    pub fn cancel_request_remove_insurance_fund_stake<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, RequestRemoveInsuranceFundStake<'info>>,
         market_index: u16,
     ) -> Result<()> {
         handle_cancel_request_remove_insurance_fund_stake(ctx, market_index)
     }
 
-    pub fn remove_insurance_fund_stake(
-        ctx: Context<RemoveInsuranceFundStake>,
+    pub fn remove_insurance_fund_stake<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, RemoveInsuranceFundStake<'info>>,
         market_index: u16,
     ) -> Result<()> {
         handle_remove_insurance_fund_stake(ctx, market_index)
     }
 
+    pub fn update_insurance_fund_stake_tranche(
+        ctx: Context<UpdateInsuranceFundStakeTranche>,
+        market_index: u16,
+        tranche: InsuranceFundTranche,
+    ) -> Result<()> {
+        handle_update_insurance_fund_stake_tranche(ctx, market_index, tranche)
+    }
+
+    pub fn lock_insurance_fund_stake(
+        ctx: Context<LockInsuranceFundStake>,
+        market_index: u16,
+        lockup_tier: InsuranceFundLockupTier,
+    ) -> Result<()> {
+        handle_lock_insurance_fund_stake(ctx, market_index, lockup_tier)
+    }
+
+    pub fn unlock_insurance_fund_stake(
+        ctx: Context<UnlockInsuranceFundStake>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_unlock_insurance_fund_stake(ctx, market_index)
+    }
+
+    pub fn wrap_insurance_fund_stake(
+        ctx: Context<WrapInsuranceFundStake>,
+        market_index: u16,
+        shares: u128,
+    ) -> Result<()> {
+        handle_wrap_insurance_fund_stake(ctx, market_index, shares)
+    }
+
+    pub fn unwrap_insurance_fund_stake(
+        ctx: Context<UnwrapInsuranceFundStake>,
+        market_index: u16,
+        amount: u64,
+    ) -> Result<()> {
+        handle_unwrap_insurance_fund_stake(ctx, market_index, amount)
+    }
+
     pub fn transfer_protocol_if_shares(
         ctx: Context<TransferProtocolIfShares>,
         market_index: u16,
@@ -748,6 +942,12 @@ pub mod drift {
         )
     }
 
+    pub fn initialize_perp_market_rollover(
+        ctx: Context<InitializePerpMarketRollover>,
+    ) -> Result<()> {
+        handle_initialize_perp_market_rollover(ctx)
+    }
+
     pub fn delete_initialized_perp_market(
         ctx: Context<DeleteInitializedPerpMarket>,
         market_index: u16,
@@ -786,6 +986,26 @@ pub mod drift {
         handle_update_perp_market_expiry(ctx, expiry_ts)
     }
 
+    pub fn update_perp_market_future_params(
+        ctx: Context<AdminUpdatePerpMarket>,
+        expiry_ts: i64,
+        settlement_window: u32,
+        rollover_period: u32,
+    ) -> Result<()> {
+        handle_update_perp_market_future_params(ctx, expiry_ts, settlement_window, rollover_period)
+    }
+
+    pub fn initialize_prediction_market(ctx: Context<AdminUpdatePerpMarket>) -> Result<()> {
+        handle_initialize_prediction_market(ctx)
+    }
+
+    pub fn resolve_perp_prediction_market(
+        ctx: Context<AdminUpdatePerpMarket>,
+        resolution: PredictionMarketResolution,
+    ) -> Result<()> {
+        handle_resolve_perp_prediction_market(ctx, resolution)
+    }
+
     pub fn settle_expired_market_pools_to_revenue_pool(
         ctx: Context<SettleExpiredMarketPoolsToRevenuePool>,
     ) -> Result<()> {
@@ -837,6 +1057,26 @@ pub mod drift {
         handle_update_perp_market_funding_period(ctx, funding_period)
     }
 
+    pub fn update_perp_market_funding_params(
+        ctx: Context<AdminUpdatePerpMarket>,
+        enabled: bool,
+        premium_index: FundingPremiumIndex,
+        interest_rate: i16,
+        premium_clamp_threshold: u16,
+        premium_clamp_slope: u8,
+        continuous: bool,
+    ) -> Result<()> {
+        handle_update_perp_market_funding_params(
+            ctx,
+            enabled,
+            premium_index,
+            interest_rate,
+            premium_clamp_threshold,
+            premium_clamp_slope,
+            continuous,
+        )
+    }
+
     pub fn update_perp_market_max_imbalances(
         ctx: Context<AdminUpdatePerpMarket>,
         unrealized_max_imbalance: u64,
@@ -911,6 +1151,13 @@ pub mod drift {
         handle_update_spot_market_paused_operations(ctx, paused_operations)
     }
 
+    pub fn update_spot_market_oracle_guard_rails_override(
+        ctx: Context<AdminUpdateSpotMarket>,
+        guard_rails_override: ValidityGuardRailsOverride,
+    ) -> Result<()> {
+        handle_update_spot_market_oracle_guard_rails_override(ctx, guard_rails_override)
+    }
+
     pub fn update_spot_market_asset_tier(
         ctx: Context<AdminUpdateSpotMarket>,
         asset_tier: AssetTier,
@@ -1027,6 +1274,13 @@ pub mod drift {
         handle_update_perp_market_paused_operations(ctx, paused_operations)
     }
 
+    pub fn update_perp_market_oracle_guard_rails_override(
+        ctx: Context<AdminUpdatePerpMarket>,
+        guard_rails_override: ValidityGuardRailsOverride,
+    ) -> Result<()> {
+        handle_update_perp_market_oracle_guard_rails_override(ctx, guard_rails_override)
+    }
+
     pub fn update_perp_market_contract_tier(
         ctx: Context<AdminUpdatePerpMarket>,
         contract_tier: ContractTier,
@@ -1331,6 +1585,29 @@ pub mod drift {
         )
     }
 
+    pub fn initialize_insurance_fund_junior_tranche(
+        ctx: Context<InitializeInsuranceFundJuniorTranche>,
+        market_index: u16,
+        revenue_boost: u32,
+    ) -> Result<()> {
+        handle_initialize_insurance_fund_junior_tranche(ctx, market_index, revenue_boost)
+    }
+
+    pub fn update_insurance_fund_junior_tranche_revenue_boost(
+        ctx: Context<AdminUpdateInsuranceFundJuniorTranche>,
+        market_index: u16,
+        revenue_boost: u32,
+    ) -> Result<()> {
+        handle_update_insurance_fund_junior_tranche_revenue_boost(ctx, market_index, revenue_boost)
+    }
+
+    pub fn initialize_insurance_fund_share_mint(
+        ctx: Context<InitializeInsuranceFundShareMint>,
+        market_index: u16,
+    ) -> Result<()> {
+        handle_initialize_insurance_fund_share_mint(ctx, market_index)
+    }
+
     pub fn initialize_prelaunch_oracle(
         ctx: Context<InitializePrelaunchOracle>,
         params: PrelaunchOracleParams,
@@ -1352,6 +1629,92 @@ pub mod drift {
         handle_delete_prelaunch_oracle(ctx, perp_market_index)
     }
 
+    pub fn initialize_option_market(
+        ctx: Context<InitializeOptionMarket>,
+        params: OptionMarketParams,
+    ) -> Result<()> {
+        handle_initialize_option_market(ctx, params)
+    }
+
+    pub fn update_option_market_implied_volatility(
+        ctx: Context<AdminUpdateOptionMarket>,
+        implied_volatility: u64,
+    ) -> Result<()> {
+        handle_update_option_market_implied_volatility(ctx, implied_volatility)
+    }
+
+    pub fn initialize_perp_market_oracle_aggregate<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, InitializePerpMarketOracleAggregate<'info>>,
+        market_index: u16,
+        method: OracleAggregationMethod,
+    ) -> Result<()> {
+        handle_initialize_perp_market_oracle_aggregate(ctx, market_index, method)
+    }
+
+    pub fn update_perp_market_oracle_aggregate<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, UpdatePerpMarketOracleAggregate<'info>>,
+        market_index: u16,
+        method: OracleAggregationMethod,
+    ) -> Result<()> {
+        handle_update_perp_market_oracle_aggregate(ctx, market_index, method)
+    }
+
+    pub fn initialize_spot_market_oracle_aggregate<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, InitializeSpotMarketOracleAggregate<'info>>,
+        market_index: u16,
+        method: OracleAggregationMethod,
+    ) -> Result<()> {
+        handle_initialize_spot_market_oracle_aggregate(ctx, market_index, method)
+    }
+
+    pub fn update_spot_market_oracle_aggregate<'c: 'info, 'info>(
+        ctx: Context<'_, '_, 'c, 'info, UpdateSpotMarketOracleAggregate<'info>>,
+        market_index: u16,
+        method: OracleAggregationMethod,
+    ) -> Result<()> {
+        handle_update_spot_market_oracle_aggregate(ctx, market_index, method)
+    }
+
+    pub fn initialize_perp_market_circuit_breaker(
+        ctx: Context<InitializePerpMarketCircuitBreaker>,
+        params: OracleCircuitBreakerParams,
+    ) -> Result<()> {
+        handle_initialize_perp_market_circuit_breaker(ctx, params)
+    }
+
+    pub fn update_perp_market_circuit_breaker_params(
+        ctx: Context<AdminUpdatePerpMarketCircuitBreaker>,
+        params: OracleCircuitBreakerParams,
+    ) -> Result<()> {
+        handle_update_perp_market_circuit_breaker_params(ctx, params)
+    }
+
+    pub fn reset_perp_market_circuit_breaker(
+        ctx: Context<AdminUpdatePerpMarketCircuitBreaker>,
+    ) -> Result<()> {
+        handle_reset_perp_market_circuit_breaker(ctx)
+    }
+
+    pub fn initialize_spot_market_circuit_breaker(
+        ctx: Context<InitializeSpotMarketCircuitBreaker>,
+        params: OracleCircuitBreakerParams,
+    ) -> Result<()> {
+        handle_initialize_spot_market_circuit_breaker(ctx, params)
+    }
+
+    pub fn update_spot_market_circuit_breaker_params(
+        ctx: Context<AdminUpdateSpotMarketCircuitBreaker>,
+        params: OracleCircuitBreakerParams,
+    ) -> Result<()> {
+        handle_update_spot_market_circuit_breaker_params(ctx, params)
+    }
+
+    pub fn reset_spot_market_circuit_breaker(
+        ctx: Context<AdminUpdateSpotMarketCircuitBreaker>,
+    ) -> Result<()> {
+        handle_reset_spot_market_circuit_breaker(ctx)
+    }
+
     pub fn initialize_pyth_pull_oracle(
         ctx: Context<InitPythPullPriceFeed>,
         feed_id: [u8; 32],
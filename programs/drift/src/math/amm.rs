@@ -10,8 +10,9 @@ use crate::math::casting::Cast;
 use crate::math::constants::{
     BID_ASK_SPREAD_PRECISION_I128, CONCENTRATION_PRECISION,
     DEFAULT_MAX_TWAP_UPDATE_PRICE_BAND_DENOMINATOR, FIVE_MINUTE, ONE_HOUR, ONE_MINUTE,
-    PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO, PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO_I128,
-    PRICE_TO_PEG_PRECISION_RATIO, QUOTE_PRECISION_I64,
+    PEG_PRECISION, PRICE_PRECISION, PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO,
+    PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO_I128, PRICE_TO_PEG_PRECISION_RATIO,
+    QUOTE_PRECISION_I64,
 };
 use crate::math::orders::standardize_base_asset_amount;
 use crate::math::quote_asset::reserve_to_asset_amount;
@@ -40,6 +41,60 @@ pub fn calculate_price(
         .try_to_u64()
 }
 
+/// The base and quote asset reserves that price the amm at `price` with its current k and peg
+pub fn calculate_reserves_at_price(amm: &AMM, price: u64) -> DriftResult<(u128, u128)> {
+    validate!(price > 0, ErrorCode::InvalidAmmDetected, "price == 0")?;
+
+    let invariant_sqrt_u192 = U192::from(amm.sqrt_k);
+    let invariant = invariant_sqrt_u192.safe_mul(invariant_sqrt_u192)?;
+
+    let base_asset_reserve = invariant
+        .safe_mul(U192::from(PRICE_PRECISION))?
+        .safe_div(U192::from(price))?
+        .safe_mul(U192::from(amm.peg_multiplier))?
+        .safe_div(U192::from(PEG_PRECISION))?
+        .integer_sqrt()
+        .try_to_u128()?;
+
+    let quote_asset_reserve = invariant
+        .safe_div(U192::from(base_asset_reserve))?
+        .try_to_u128()?;
+
+    Ok((base_asset_reserve, quote_asset_reserve))
+}
+
+/// Moves the spread reserves of a bounded contract's amm so it never quotes outside
+/// [min_price, max_price]
+pub fn clamp_spread_reserves(amm: &mut AMM, min_price: u64, max_price: u64) -> DriftResult {
+    let ask_price = calculate_price(
+        amm.ask_quote_asset_reserve,
+        amm.ask_base_asset_reserve,
+        amm.peg_multiplier,
+    )?;
+
+    if ask_price > max_price {
+        let (ask_base_asset_reserve, ask_quote_asset_reserve) =
+            calculate_reserves_at_price(amm, max_price)?;
+        amm.ask_base_asset_reserve = ask_base_asset_reserve;
+        amm.ask_quote_asset_reserve = ask_quote_asset_reserve;
+    }
+
+    let bid_price = calculate_price(
+        amm.bid_quote_asset_reserve,
+        amm.bid_base_asset_reserve,
+        amm.peg_multiplier,
+    )?;
+
+    if bid_price < min_price {
+        let (bid_base_asset_reserve, bid_quote_asset_reserve) =
+            calculate_reserves_at_price(amm, min_price)?;
+        amm.bid_base_asset_reserve = bid_base_asset_reserve;
+        amm.bid_quote_asset_reserve = bid_quote_asset_reserve;
+    }
+
+    Ok(())
+}
+
 pub fn calculate_bid_ask_bounds(
     concentration_coef: u128,
     sqrt_k: u128,
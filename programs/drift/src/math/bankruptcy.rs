@@ -18,7 +18,12 @@ pub fn is_user_bankrupt(user: &User) -> bool {
         }
     }
 
-    for perp_position in user.perp_positions.iter() {
+    for (position_index, perp_position) in user.perp_positions.iter().enumerate() {
+        // isolated positions neither back nor draw on the cross margin account
+        if user.is_isolated_perp_position(position_index) {
+            continue;
+        }
+
         if perp_position.base_asset_amount != 0
             || perp_position.quote_asset_amount > 0
             || perp_position.has_open_order()
@@ -34,3 +39,15 @@ pub fn is_user_bankrupt(user: &User) -> bool {
 
     has_liability
 }
+
+pub fn is_isolated_perp_position_bankrupt(user: &User, position_index: usize) -> bool {
+    // isolated position is bankrupt iff it has negative pnl and no exposure left
+
+    let perp_position = &user.perp_positions[position_index];
+
+    user.is_isolated_perp_position(position_index)
+        && perp_position.base_asset_amount == 0
+        && perp_position.quote_asset_amount < 0
+        && !perp_position.has_open_order()
+        && !perp_position.is_lp()
+}
@@ -1,4 +1,4 @@
-use crate::math::bankruptcy::is_user_bankrupt;
+use crate::math::bankruptcy::{is_isolated_perp_position_bankrupt, is_user_bankrupt};
 use crate::state::spot_market::SpotBalanceType;
 use crate::state::user::{PerpPosition, SpotPosition, User};
 use crate::test_utils::{get_positions, get_spot_positions};
@@ -81,3 +81,60 @@ fn user_with_empty_position_and_balances() {
     let is_bankrupt = is_user_bankrupt(&user);
     assert!(!is_bankrupt);
 }
+
+#[test]
+fn user_with_isolated_position_with_negative_quote() {
+    let user = User {
+        perp_positions: get_positions(PerpPosition {
+            quote_asset_amount: -1,
+            ..PerpPosition::default()
+        }),
+        spot_positions: get_spot_positions(SpotPosition {
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: 1,
+            ..SpotPosition::default()
+        }),
+        isolated_perp_positions: 1,
+        ..User::default()
+    };
+
+    let is_bankrupt = is_user_bankrupt(&user);
+    assert!(!is_bankrupt);
+
+    let is_bankrupt = is_isolated_perp_position_bankrupt(&user, 0);
+    assert!(is_bankrupt);
+}
+
+#[test]
+fn user_with_isolated_position_with_base() {
+    let user = User {
+        perp_positions: get_positions(PerpPosition {
+            base_asset_amount: 1,
+            quote_asset_amount: -1,
+            ..PerpPosition::default()
+        }),
+        isolated_perp_positions: 1,
+        ..User::default()
+    };
+
+    let is_bankrupt = is_isolated_perp_position_bankrupt(&user, 0);
+    assert!(!is_bankrupt);
+
+    // isolated collateral does not back cross margin account
+    let is_bankrupt = is_user_bankrupt(&user);
+    assert!(!is_bankrupt);
+}
+
+#[test]
+fn user_with_cross_position_not_isolated_bankrupt() {
+    let user = User {
+        perp_positions: get_positions(PerpPosition {
+            quote_asset_amount: -1,
+            ..PerpPosition::default()
+        }),
+        ..User::default()
+    };
+
+    let is_bankrupt = is_isolated_perp_position_bankrupt(&user, 0);
+    assert!(!is_bankrupt);
+}
@@ -38,6 +38,7 @@ pub const FUNDING_RATE_BUFFER_I128: i128 = FUNDING_RATE_BUFFER as i128; // expo
 
 pub const MARGIN_PRECISION: u32 = 10_000; // expo = -4
 pub const MARGIN_PRECISION_U128: u128 = 10_000; // expo = -4
+pub const MARGIN_PRECISION_I128: i128 = MARGIN_PRECISION as i128; // expo = -4
 pub const SPOT_WEIGHT_PRECISION: u32 = MARGIN_PRECISION; // expo = -4
 pub const SPOT_WEIGHT_PRECISION_U128: u128 = SPOT_WEIGHT_PRECISION as u128; // expo = -4
 pub const SPOT_WEIGHT_PRECISION_I128: i128 = SPOT_WEIGHT_PRECISION as i128; // expo = -4
@@ -63,6 +64,13 @@ pub const BID_ASK_SPREAD_PRECISION_I128: i128 = BID_ASK_SPREAD_PRECISION as i128
 
 pub const CONCENTRATION_PRECISION: u128 = PERCENTAGE_PRECISION; // expo 6
 pub const IF_FACTOR_PRECISION: u128 = PERCENTAGE_PRECISION; // expo 6
+pub const IF_BOOST_PRECISION: u128 = PERCENTAGE_PRECISION; // expo 6
+pub const IF_BOOST_SHARES_PER_WEIGHT_PRECISION: u128 = 1_000_000_000_000; // expo 12
+pub const IF_TRANCHE_SHARE_FACTOR_PRECISION: u128 = 1_000_000_000_000; // expo 12
+pub const IF_TRANCHE_SHARE_FACTOR_PRECISION_U64: u64 = 1_000_000_000_000; // expo 12
+
+// junior tranches shrunk below this are wiped and start a new epoch
+pub const MIN_IF_TRANCHE_SHARE_FACTOR: u128 = IF_TRANCHE_SHARE_FACTOR_PRECISION / 1_000_000;
 
 pub const SPOT_UTILIZATION_PRECISION: u128 = PERCENTAGE_PRECISION; // expo = -6
 pub const SPOT_UTILIZATION_PRECISION_U32: u32 = PERCENTAGE_PRECISION as u32; // expo = -6
@@ -123,6 +131,8 @@ pub const TWENTY_FOUR_HOUR: i64 = 3600 * 24;
 pub const THIRTEEN_DAY: i64 = TWENTY_FOUR_HOUR * 13; // IF unstake default
 pub const EPOCH_DURATION: i64 = TWENTY_FOUR_HOUR * 28;
 pub const THIRTY_DAY: i64 = TWENTY_FOUR_HOUR * 30;
+pub const NINETY_DAY: i64 = TWENTY_FOUR_HOUR * 90;
+pub const ONE_HUNDRED_EIGHTY_DAY: i64 = TWENTY_FOUR_HOUR * 180;
 pub const THIRTY_DAY_I128: i128 = (TWENTY_FOUR_HOUR * 30) as i128;
 pub const ONE_YEAR: u128 = 31536000;
 
@@ -160,6 +170,8 @@ pub const FEE_ADJUSTMENT_MAX: u64 = 100;
 
 // PRICE AMOUNTS
 pub const HUNDRENTH_OF_CENT: u128 = PRICE_PRECISION / 10_000; //.0001
+pub const MAX_PREDICTION_MARKET_PRICE: u64 = PRICE_PRECISION_U64; // prediction markets settle at 0 or 1
+pub const MAX_PREDICTION_MARKET_PRICE_I64: i64 = MAX_PREDICTION_MARKET_PRICE as i64;
 
 // CONSTRAINTS
 pub const MAX_K_BPS_INCREASE: i128 = TEN_BPS;
@@ -196,6 +208,8 @@ pub const DEFAULT_QUOTE_ASSET_AMOUNT_TICK_SIZE: u64 =
 
 // FUNDING
 pub const FUNDING_RATE_OFFSET_DENOMINATOR: i64 = 5000; // 5000 => 7.3% annualized rate for hourly funding
+pub const FUNDING_PARAMS_PRECISION: i64 = 10_000; // expo = -4
+pub const MAX_FUNDING_PREMIUM_CLAMP_SLOPE: u8 = 100;
 
 // ORDERS
 pub const AUCTION_DERIVE_PRICE_FRACTION: i64 = 200;
@@ -206,3 +220,15 @@ pub const SPOT_MARKET_TOKEN_TWAP_WINDOW: i64 = TWENTY_FOUR_HOUR;
 // FUEL
 pub const FUEL_WINDOW_U128: u128 = EPOCH_DURATION as u128;
 pub const FUEL_START_TS: i64 = 1715745600_i64; // May 15 2024 UTC
+
+// PORTFOLIO MARGIN
+pub const PORTFOLIO_MARGIN_PRICE_SHOCKS: [i128; 7] = [
+    -PERCENTAGE_PRECISION_I128,
+    -PERCENTAGE_PRECISION_I128 * 2 / 3,
+    -PERCENTAGE_PRECISION_I128 / 3,
+    0,
+    PERCENTAGE_PRECISION_I128 / 3,
+    PERCENTAGE_PRECISION_I128 * 2 / 3,
+    PERCENTAGE_PRECISION_I128,
+]; // fraction of an underlying's shock size
+pub const PORTFOLIO_MARGIN_GROSS_CHARGE: u128 = PERCENTAGE_PRECISION / 10; // 10% of the full shock on gross notional, so hedged books still carry a requirement
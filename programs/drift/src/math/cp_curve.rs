@@ -295,6 +295,7 @@ pub fn update_k(market: &mut PerpMarket, update_k_result: &UpdateKResult) -> Dri
 
     let reserve_price_after = market.amm.reserve_price()?;
     crate::controller::amm::update_spreads(&mut market.amm, reserve_price_after)?;
+    market.update_bounded_spread_reserves()?;
 
     Ok(())
 }
@@ -6,13 +6,14 @@ use crate::error::{DriftResult, ErrorCode};
 use crate::math::bn;
 use crate::math::casting::Cast;
 use crate::math::constants::{
-    AMM_TO_QUOTE_PRECISION_RATIO, AMM_TO_QUOTE_PRECISION_RATIO_I128, FUNDING_RATE_BUFFER,
-    PRICE_PRECISION, QUOTE_TO_BASE_AMT_FUNDING_PRECISION,
+    AMM_TO_QUOTE_PRECISION_RATIO, AMM_TO_QUOTE_PRECISION_RATIO_I128, FUNDING_PARAMS_PRECISION,
+    FUNDING_RATE_BUFFER, FUNDING_RATE_BUFFER_I128, MAX_FUNDING_PREMIUM_CLAMP_SLOPE, ONE_HOUR,
+    ONE_YEAR, PRICE_PRECISION, QUOTE_TO_BASE_AMT_FUNDING_PRECISION, TWENTY_FOUR_HOUR,
 };
 use crate::math::repeg::{calculate_fee_pool, get_total_fee_lower_bound};
 use crate::math::safe_math::SafeMath;
 
-use crate::state::perp_market::PerpMarket;
+use crate::state::perp_market::{FundingPremiumIndex, PerpMarket, AMM};
 use crate::state::user::PerpPosition;
 
 #[cfg(test)]
@@ -24,6 +25,39 @@ mod tests;
 pub fn calculate_funding_rate_long_short(
     market: &mut PerpMarket,
     funding_rate: i128,
+) -> DriftResult<(i128, i128, i128)> {
+    let funding_rate_pnl_limit = calculate_funding_rate_pnl_limit(market)?;
+
+    _calculate_funding_rate_long_short(market, funding_rate, funding_rate_pnl_limit)
+}
+
+/// Continuous funding accrues many times per funding period, so each accrual can only use the
+/// interval's share of the funding period's fee pool limit
+pub fn calculate_funding_rate_long_short_for_interval(
+    market: &mut PerpMarket,
+    funding_rate: i128,
+    funding_interval: i64,
+    funding_period: i64,
+) -> DriftResult<(i128, i128, i128)> {
+    let funding_rate_pnl_limit = calculate_funding_rate_pnl_limit(market)?
+        .safe_mul(funding_interval.min(funding_period).cast()?)?
+        .safe_div(funding_period.cast()?)?;
+
+    _calculate_funding_rate_long_short(market, funding_rate, funding_rate_pnl_limit)
+}
+
+/// The amount of fees the protocol can use per funding period before it hits it's lower bound
+fn calculate_funding_rate_pnl_limit(market: &PerpMarket) -> DriftResult<i128> {
+    let fee_pool = calculate_fee_pool(market)?;
+
+    // limit to 1/3 of current fee pool per funding period
+    Ok(-fee_pool.cast::<i128>()?.safe_div(3)?)
+}
+
+fn _calculate_funding_rate_long_short(
+    market: &mut PerpMarket,
+    funding_rate: i128,
+    funding_rate_pnl_limit: i128,
 ) -> DriftResult<(i128, i128, i128)> {
     // Calculate the funding payment owed by the net_market_position if funding is not capped
     // If the net market position owes funding payment, the protocol receives payment
@@ -51,8 +85,12 @@ pub fn calculate_funding_rate_long_short(
         return Ok((funding_rate, funding_rate, uncapped_funding_pnl));
     }
 
-    let (capped_funding_rate, capped_funding_pnl) =
-        calculate_capped_funding_rate(market, uncapped_funding_pnl, funding_rate)?;
+    let (capped_funding_rate, capped_funding_pnl) = calculate_capped_funding_rate(
+        market,
+        uncapped_funding_pnl,
+        funding_rate,
+        funding_rate_pnl_limit,
+    )?;
 
     let new_total_fee_minus_distributions = market
         .amm
@@ -95,13 +133,8 @@ fn calculate_capped_funding_rate(
     market: &PerpMarket,
     uncapped_funding_pnl: i128, // if negative, users would net receive from protocol
     funding_rate: i128,
+    funding_rate_pnl_limit: i128,
 ) -> DriftResult<(i128, i128)> {
-    // The funding_rate_pnl_limit is the amount of fees the protocol can use before it hits it's lower bound
-    let fee_pool = calculate_fee_pool(market)?;
-
-    // limit to 1/3 of current fee pool per funding period
-    let funding_rate_pnl_limit = -fee_pool.cast::<i128>()?.safe_div(3)?;
-
     // if theres enough in fees, give user's uncapped funding
     // if theres a little/nothing in fees, give the user's capped outflow funding
     let capped_funding_pnl = max(uncapped_funding_pnl, funding_rate_pnl_limit);
@@ -141,6 +174,154 @@ fn calculate_capped_funding_rate(
     Ok((capped_funding_rate, capped_funding_pnl))
 }
 
+/// The funding rate of a market with funding params enabled, accrued over funding_interval seconds
+/// precision: FUNDING_RATE_PRECISION
+pub fn calculate_funding_rate_from_params(
+    amm: &AMM,
+    mark_price_twap: u64,
+    oracle_price_twap: i64,
+    max_price_spread: i64,
+    funding_interval: i64,
+) -> DriftResult<i64> {
+    let price_spread =
+        calculate_funding_price_spread(amm, mark_price_twap, oracle_price_twap, max_price_spread)?;
+
+    let (funding_rate, _) = calculate_accrued_funding_rate(price_spread, funding_interval, 0)?;
+
+    Ok(funding_rate)
+}
+
+/// The funding rate accrued over funding_interval seconds at a daily price spread. Short
+/// intervals accrue fractions of FUNDING_RATE_PRECISION, so the truncated part is returned to be
+/// carried into the next accrual
+/// precision: FUNDING_RATE_PRECISION, remainder: FUNDING_RATE_PRECISION / TWENTY_FOUR_HOUR
+pub fn calculate_accrued_funding_rate(
+    price_spread: i64,
+    funding_interval: i64,
+    funding_rate_remainder: i32,
+) -> DriftResult<(i64, i32)> {
+    let funding_rate_numerator = price_spread
+        .cast::<i128>()?
+        .safe_mul(FUNDING_RATE_BUFFER_I128)?
+        .safe_mul(funding_interval.cast()?)?
+        .safe_add(funding_rate_remainder.cast()?)?;
+
+    let funding_rate = funding_rate_numerator.safe_div(TWENTY_FOUR_HOUR.cast()?)?;
+    let funding_rate_remainder =
+        funding_rate_numerator.safe_sub(funding_rate.safe_mul(TWENTY_FOUR_HOUR.cast()?)?)?;
+
+    Ok((funding_rate.cast()?, funding_rate_remainder.cast()?))
+}
+
+/// The daily price spread funding is paid at for a market with funding params enabled
+/// precision: PRICE_PRECISION
+pub fn calculate_funding_price_spread(
+    amm: &AMM,
+    mark_price_twap: u64,
+    oracle_price_twap: i64,
+    max_price_spread: i64,
+) -> DriftResult<i64> {
+    let funding_params = &amm.funding_params;
+
+    let premium = calculate_funding_premium(
+        funding_params.premium_index,
+        mark_price_twap,
+        amm.last_bid_price_twap,
+        amm.last_ask_price_twap,
+        oracle_price_twap,
+    )?;
+
+    Ok(clamp_funding_premium(
+        premium,
+        oracle_price_twap,
+        funding_params.premium_clamp_threshold,
+        funding_params.premium_clamp_slope,
+    )?
+    .safe_add(calculate_funding_interest(
+        oracle_price_twap,
+        funding_params.interest_rate,
+    )?)?
+    .clamp(-max_price_spread, max_price_spread))
+}
+
+/// precision: PRICE_PRECISION
+pub fn calculate_funding_premium(
+    premium_index: FundingPremiumIndex,
+    mark_price_twap: u64,
+    bid_price_twap: u64,
+    ask_price_twap: u64,
+    oracle_price_twap: i64,
+) -> DriftResult<i64> {
+    match premium_index {
+        FundingPremiumIndex::MarkTwap => mark_price_twap.cast::<i64>()?.safe_sub(oracle_price_twap),
+        FundingPremiumIndex::BidAskTwap => {
+            let bid_premium = bid_price_twap
+                .cast::<i64>()?
+                .safe_sub(oracle_price_twap)?
+                .max(0);
+            let ask_discount = oracle_price_twap.safe_sub(ask_price_twap.cast()?)?.max(0);
+
+            bid_premium.safe_sub(ask_discount)
+        }
+    }
+}
+
+/// Piecewise clamp of the premium: the part within premium_clamp_threshold of the oracle twap
+/// counts in full, only premium_clamp_slope percent of the rest does
+/// precision: PRICE_PRECISION
+pub fn clamp_funding_premium(
+    premium: i64,
+    oracle_price_twap: i64,
+    premium_clamp_threshold: u16,
+    premium_clamp_slope: u8,
+) -> DriftResult<i64> {
+    let threshold = oracle_price_twap
+        .unsigned_abs()
+        .cast::<u128>()?
+        .safe_mul(premium_clamp_threshold.cast()?)?
+        .safe_div(FUNDING_PARAMS_PRECISION.cast()?)?
+        .cast::<i64>()?;
+
+    let premium_magnitude = premium.abs();
+    if premium_magnitude <= threshold {
+        return Ok(premium);
+    }
+
+    let clamped_magnitude = premium_magnitude
+        .safe_sub(threshold)?
+        .safe_mul(premium_clamp_slope.cast()?)?
+        .safe_div(MAX_FUNDING_PREMIUM_CLAMP_SLOPE.cast()?)?
+        .safe_add(threshold)?;
+
+    clamped_magnitude.safe_mul(premium.signum())
+}
+
+/// The daily interest rate component of the funding rate
+/// precision: PRICE_PRECISION
+pub fn calculate_funding_interest(oracle_price_twap: i64, interest_rate: i16) -> DriftResult<i64> {
+    oracle_price_twap
+        .abs()
+        .cast::<i128>()?
+        .safe_mul(interest_rate.cast()?)?
+        .safe_mul(TWENTY_FOUR_HOUR.cast()?)?
+        .safe_div(FUNDING_PARAMS_PRECISION.cast()?)?
+        .safe_div(ONE_YEAR.cast()?)?
+        .cast()
+}
+
+/// The seconds of funding owed at an update: the time since the last update, up to the longer of
+/// the current funding period and the one in place at the last update
+pub fn calculate_funding_interval(amm: &AMM, now: i64) -> DriftResult<i64> {
+    let max_funding_interval = amm
+        .funding_period
+        .max(amm.funding_params.last_funding_period.cast()?)
+        .max(ONE_HOUR);
+
+    Ok(now
+        .safe_sub(amm.last_funding_rate_ts)?
+        .clamp(0, max_funding_interval))
+}
+
 pub fn calculate_funding_payment(
     amm_cumulative_funding_rate: i128,
     market_position: &PerpPosition,
@@ -1,21 +1,25 @@
-use crate::controller::funding::update_funding_rate;
+use crate::controller::funding::{accrue_funding, settle_funding_payment, update_funding_rate};
 use crate::controller::repeg::_update_amm;
 use crate::math::helpers::on_the_hour_update;
 use crate::math::oracle::block_operation;
 
 use crate::math::constants::{
-    AMM_RESERVE_PRECISION, ONE_HOUR_I128, PRICE_PRECISION, PRICE_PRECISION_U64, QUOTE_PRECISION,
+    AMM_RESERVE_PRECISION, BASE_PRECISION_I64, ONE_HOUR_I128, PRICE_PRECISION, PRICE_PRECISION_I64,
+    PRICE_PRECISION_U64, QUOTE_PRECISION,
 };
 use crate::math::funding::*;
 use std::cmp::min;
 
-use crate::test_utils::get_pyth_price;
+use crate::test_utils::{get_positions, get_pyth_price};
 
 // use crate::create_anchor_account_info;
 use crate::state::oracle::HistoricalOracleData;
 use crate::state::oracle_map::OracleMap;
-use crate::state::perp_market::{ContractTier, PerpMarket, AMM};
+use crate::state::perp_market::{
+    ContractTier, FundingParams, FundingPremiumIndex, PerpMarket, AMM,
+};
 use crate::state::state::{OracleGuardRails, State, ValidityGuardRails};
+use crate::state::user::{PerpPosition, User};
 use solana_program::pubkey::Pubkey;
 use std::str::FromStr;
 
@@ -722,3 +726,502 @@ fn unsettled_funding_pnl() {
     assert_ne!(market.amm.net_unsettled_funding_pnl, 0); // important: imbalanced market adds funding rev
     assert_eq!(market.amm.net_unsettled_funding_pnl, -71722677); // users up
 }
+
+#[test]
+fn funding_premium() {
+    let oracle_price_twap = 50 * PRICE_PRECISION_I64;
+
+    let premium = calculate_funding_premium(
+        FundingPremiumIndex::MarkTwap,
+        50_500_000,
+        50_200_000,
+        50_800_000,
+        oracle_price_twap,
+    )
+    .unwrap();
+    assert_eq!(premium, 500_000);
+
+    // bid twap above the oracle twap
+    let premium = calculate_funding_premium(
+        FundingPremiumIndex::BidAskTwap,
+        50_500_000,
+        50_200_000,
+        50_800_000,
+        oracle_price_twap,
+    )
+    .unwrap();
+    assert_eq!(premium, 200_000);
+
+    // ask twap below the oracle twap
+    let premium = calculate_funding_premium(
+        FundingPremiumIndex::BidAskTwap,
+        50_500_000,
+        50_200_000,
+        50_800_000,
+        51 * PRICE_PRECISION_I64,
+    )
+    .unwrap();
+    assert_eq!(premium, -200_000);
+
+    // oracle twap inside the bid/ask twaps
+    let premium = calculate_funding_premium(
+        FundingPremiumIndex::BidAskTwap,
+        50_500_000,
+        50_200_000,
+        50_800_000,
+        50_500_000,
+    )
+    .unwrap();
+    assert_eq!(premium, 0);
+}
+
+#[test]
+fn funding_premium_clamp() {
+    let oracle_price_twap = 50 * PRICE_PRECISION_I64;
+
+    // 0.5% of the oracle twap counts in full
+    assert_eq!(
+        clamp_funding_premium(200_000, oracle_price_twap, 50, 50).unwrap(),
+        200_000
+    );
+    assert_eq!(
+        clamp_funding_premium(250_000, oracle_price_twap, 50, 50).unwrap(),
+        250_000
+    );
+
+    // half of the rest counts
+    assert_eq!(
+        clamp_funding_premium(1_000_000, oracle_price_twap, 50, 50).unwrap(),
+        625_000
+    );
+    assert_eq!(
+        clamp_funding_premium(-1_000_000, oracle_price_twap, 50, 50).unwrap(),
+        -625_000
+    );
+
+    assert_eq!(
+        clamp_funding_premium(1_000_000, oracle_price_twap, 50, 0).unwrap(),
+        250_000
+    );
+    assert_eq!(
+        clamp_funding_premium(1_000_000, oracle_price_twap, 50, 100).unwrap(),
+        1_000_000
+    );
+    assert_eq!(
+        clamp_funding_premium(-1_000_000, oracle_price_twap, 0, 100).unwrap(),
+        -1_000_000
+    );
+}
+
+#[test]
+fn funding_interest() {
+    let oracle_price_twap = 50 * PRICE_PRECISION_I64;
+
+    // 10% annualized, 50 * 10% / 365 per day
+    assert_eq!(
+        calculate_funding_interest(oracle_price_twap, 1000).unwrap(),
+        13698
+    );
+    assert_eq!(
+        calculate_funding_interest(oracle_price_twap, -1000).unwrap(),
+        -13698
+    );
+    assert_eq!(calculate_funding_interest(oracle_price_twap, 0).unwrap(), 0);
+}
+
+#[test]
+fn funding_interval() {
+    let mut amm = AMM {
+        funding_period: 3600,
+        last_funding_rate_ts: 3600,
+        ..AMM::default()
+    };
+
+    // an early update only accrues the time since the last one
+    assert_eq!(calculate_funding_interval(&amm, 5400).unwrap(), 1800);
+    // missed updates don't accrue past the funding period
+    assert_eq!(calculate_funding_interval(&amm, 14400).unwrap(), 3600);
+
+    // the period was shortened from 8 hours, the running interval still accrues in full
+    amm.funding_params.last_funding_period = 28800;
+    assert_eq!(calculate_funding_interval(&amm, 14400).unwrap(), 10800);
+    assert_eq!(calculate_funding_interval(&amm, 36000).unwrap(), 28800);
+
+    // the period was lengthened to 8 hours
+    amm.funding_period = 28800;
+    amm.funding_params.last_funding_period = 3600;
+    assert_eq!(calculate_funding_interval(&amm, 28800).unwrap(), 25200);
+
+    // updating every slot still accrues per second
+    amm.funding_period = 0;
+    amm.funding_params.last_funding_period = 0;
+    assert_eq!(calculate_funding_interval(&amm, 3610).unwrap(), 10);
+    assert_eq!(calculate_funding_interval(&amm, 0).unwrap(), 0);
+}
+
+#[test]
+fn funding_rate_from_params() {
+    let oracle_price_twap = 50 * PRICE_PRECISION_I64;
+    let max_price_spread = oracle_price_twap / 33;
+
+    let mut amm = AMM {
+        funding_period: 3600,
+        last_funding_rate_ts: 0,
+        last_bid_price_twap: 50_400_000,
+        last_ask_price_twap: 50_600_000,
+        funding_params: FundingParams {
+            enabled: true,
+            premium_clamp_slope: 100,
+            ..FundingParams::default()
+        },
+        ..AMM::default()
+    };
+
+    // same as the default formula without its interest offset, 0.5 / 24
+    let funding_rate = calculate_funding_rate_from_params(
+        &amm,
+        50_500_000,
+        oracle_price_twap,
+        max_price_spread,
+        3600,
+    )
+    .unwrap();
+    assert_eq!(funding_rate, 20_833_333);
+
+    // half an interval
+    let funding_rate = calculate_funding_rate_from_params(
+        &amm,
+        50_500_000,
+        oracle_price_twap,
+        max_price_spread,
+        1800,
+    )
+    .unwrap();
+    assert_eq!(funding_rate, 10_416_666);
+
+    // capped by the contract tier
+    let funding_rate = calculate_funding_rate_from_params(
+        &amm,
+        55_000_000,
+        oracle_price_twap,
+        max_price_spread,
+        3600,
+    )
+    .unwrap();
+    assert_eq!(funding_rate, 63_131_291);
+
+    // bid twap premium of 0.4, with half of it beyond 0.2 counting, plus 10% annualized interest
+    amm.funding_params.premium_index = FundingPremiumIndex::BidAskTwap;
+    amm.funding_params.premium_clamp_threshold = 40;
+    amm.funding_params.premium_clamp_slope = 50;
+    amm.funding_params.interest_rate = 1000;
+    let funding_rate = calculate_funding_rate_from_params(
+        &amm,
+        50_500_000,
+        oracle_price_twap,
+        max_price_spread,
+        3600,
+    )
+    .unwrap();
+    // (300_000 + 13698) / 24
+    assert_eq!(funding_rate, 13_070_750);
+}
+
+#[test]
+fn funding_params_validate() {
+    let funding_params = FundingParams {
+        enabled: true,
+        premium_clamp_threshold: 50,
+        premium_clamp_slope: 100,
+        interest_rate: -10_000,
+        ..FundingParams::default()
+    };
+    funding_params.validate().unwrap();
+
+    let invalid = FundingParams {
+        premium_clamp_slope: 101,
+        ..funding_params
+    };
+    assert!(invalid.validate().is_err());
+
+    let invalid = FundingParams {
+        interest_rate: 10_001,
+        ..funding_params
+    };
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn update_funding_rate_with_params() {
+    let mut now = 0_i64;
+    let mut slot = 0_u64;
+
+    let state = State {
+        oracle_guard_rails: OracleGuardRails {
+            validity: ValidityGuardRails {
+                slots_before_stale_for_amm: 10,     // 5s
+                slots_before_stale_for_margin: 120, // 60s
+                confidence_interval_max_size: 1000,
+                too_volatile_ratio: 5,
+            },
+            ..OracleGuardRails::default()
+        },
+        ..State::default()
+    };
+
+    let mut oracle_price = get_pyth_price(51, 6);
+    let oracle_price_key =
+        Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+    let pyth_program = crate::ids::pyth_program::id();
+    create_account_info!(
+        oracle_price,
+        &oracle_price_key,
+        &pyth_program,
+        oracle_account_info
+    );
+    let mut oracle_map = OracleMap::load_one(&oracle_account_info, slot, None).unwrap();
+    let mut market = PerpMarket {
+        market_index: 0,
+        amm: AMM {
+            oracle: oracle_price_key,
+
+            base_asset_reserve: 512295081967,
+            quote_asset_reserve: 488 * AMM_RESERVE_PRECISION,
+            sqrt_k: 500 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 50000000,
+            base_asset_amount_with_amm: -12295081967, //~12
+            base_asset_amount_long: 12295081967,
+            base_asset_amount_short: -12295081967 * 2,
+            base_asset_amount_with_unsettled_lp: -((AMM_RESERVE_PRECISION * 500) as i128),
+            total_exchange_fee: QUOTE_PRECISION / 2,
+            total_fee_minus_distributions: ((QUOTE_PRECISION * 99999) as i128),
+
+            last_mark_price_twap: 50 * PRICE_PRECISION_U64,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: (49 * PRICE_PRECISION) as i64,
+
+                ..HistoricalOracleData::default()
+            },
+            funding_period: 3600,
+            funding_params: FundingParams {
+                enabled: true,
+                premium_clamp_slope: 100,
+                ..FundingParams::default()
+            },
+
+            ..AMM::default()
+        },
+        ..PerpMarket::default()
+    };
+
+    now += 3600;
+    slot += 3600 * 2;
+
+    let oracle_price_data = oracle_map.get_price_data(&market.amm.oracle).unwrap();
+    _update_amm(&mut market, oracle_price_data, &state, now, slot).unwrap();
+
+    let did_succeed = update_funding_rate(
+        0,
+        &mut market,
+        &mut oracle_map,
+        now,
+        slot,
+        &state.oracle_guard_rails,
+        false,
+        None,
+    )
+    .unwrap();
+    assert!(did_succeed);
+    assert_eq!(market.amm.last_mark_price_twap, 47629736);
+    assert_eq!(
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+        51000000
+    );
+
+    // (47629736 - 51000000) / 24, without the default formula's interest offset
+    assert_eq!(market.amm.last_funding_rate, -140427666);
+    assert_eq!(market.amm.cumulative_funding_rate_long, -140427666);
+    assert_eq!(market.amm.cumulative_funding_rate_short, -140427666);
+    assert_eq!(market.amm.last_funding_rate_ts, now);
+    assert_eq!(market.amm.funding_params.last_funding_period, 3600);
+}
+
+fn continuous_funding_market() -> PerpMarket {
+    PerpMarket {
+        market_index: 0,
+        amm: AMM {
+            // 0.864 premium, 1e-5 per second
+            last_mark_price_twap: 50_864_000,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: 50 * PRICE_PRECISION_I64,
+                ..HistoricalOracleData::default()
+            },
+            funding_period: 3600,
+            last_funding_rate_ts: 0,
+            funding_params: FundingParams {
+                enabled: true,
+                continuous: true,
+                premium_clamp_slope: 100,
+                ..FundingParams::default()
+            },
+            ..AMM::default()
+        },
+        ..PerpMarket::default()
+    }
+}
+
+#[test]
+fn continuous_funding_accrual() {
+    let mut market = continuous_funding_market();
+
+    assert!(accrue_funding(&mut market, 1800, false).unwrap());
+    assert_eq!(market.amm.cumulative_funding_rate_long, 18_000_000);
+    assert_eq!(market.amm.cumulative_funding_rate_short, 18_000_000);
+    // reported per funding period
+    assert_eq!(market.amm.last_funding_rate, 36_000_000);
+    assert_eq!(market.amm.last_funding_rate_long, 36_000_000);
+    assert_eq!(market.amm.last_funding_rate_ts, 1800);
+    assert_eq!(market.next_funding_rate_record_id, 0);
+
+    // nothing left to accrue
+    assert!(!accrue_funding(&mut market, 1800, false).unwrap());
+
+    // crossing the funding period boundary records the funding rate
+    assert!(accrue_funding(&mut market, 3600, false).unwrap());
+    assert_eq!(market.amm.cumulative_funding_rate_long, 36_000_000);
+    assert_eq!(market.next_funding_rate_record_id, 1);
+
+    // the same as accruing all at once
+    let mut other_market = continuous_funding_market();
+    assert!(accrue_funding(&mut other_market, 3600, false).unwrap());
+    assert_eq!(
+        other_market.amm.cumulative_funding_rate_long,
+        market.amm.cumulative_funding_rate_long
+    );
+    assert_eq!(
+        other_market.amm.cumulative_funding_rate_short,
+        market.amm.cumulative_funding_rate_short
+    );
+
+    // paused time is skipped
+    assert!(!accrue_funding(&mut market, 5400, true).unwrap());
+    assert_eq!(market.amm.cumulative_funding_rate_long, 36_000_000);
+    assert_eq!(market.amm.last_funding_rate_ts, 5400);
+
+    assert!(accrue_funding(&mut market, 7200, false).unwrap());
+    assert_eq!(market.amm.cumulative_funding_rate_long, 54_000_000);
+
+    // periodic funding markets don't accrue
+    let mut periodic_market = continuous_funding_market();
+    periodic_market.amm.funding_params.continuous = false;
+    assert!(!accrue_funding(&mut periodic_market, 3600, false).unwrap());
+    assert_eq!(periodic_market.amm.cumulative_funding_rate_long, 0);
+    assert_eq!(periodic_market.amm.last_funding_rate_ts, 0);
+}
+
+#[test]
+fn continuous_funding_settlement_frequency() {
+    let user_key = Pubkey::default();
+    let position = PerpPosition {
+        market_index: 0,
+        base_asset_amount: BASE_PRECISION_I64,
+        quote_asset_amount: -50 * QUOTE_PRECISION as i64,
+        ..PerpPosition::default()
+    };
+
+    // settled every half hour
+    let mut market = continuous_funding_market();
+    let mut user = User {
+        perp_positions: get_positions(position),
+        ..User::default()
+    };
+    for now in [1800, 3600] {
+        accrue_funding(&mut market, now, false).unwrap();
+        settle_funding_payment(&mut user, &user_key, &mut market, now).unwrap();
+    }
+
+    // settled once
+    let mut other_market = continuous_funding_market();
+    let mut other_user = User {
+        perp_positions: get_positions(position),
+        ..User::default()
+    };
+    accrue_funding(&mut other_market, 3600, false).unwrap();
+    settle_funding_payment(&mut other_user, &user_key, &mut other_market, 3600).unwrap();
+
+    // an hour of 1e-5 per second on one contract
+    assert_eq!(
+        user.perp_positions[0].quote_asset_amount,
+        -50 * QUOTE_PRECISION as i64 - 36_000
+    );
+    assert_eq!(
+        user.perp_positions[0].quote_asset_amount,
+        other_user.perp_positions[0].quote_asset_amount
+    );
+    assert_eq!(
+        user.perp_positions[0].last_cumulative_funding_rate,
+        other_user.perp_positions[0].last_cumulative_funding_rate
+    );
+}
+
+#[test]
+fn continuous_funding_carries_truncated_rate() {
+    let mut market = continuous_funding_market();
+    // 1.157e-3 of FUNDING_RATE_PRECISION per second
+    market.amm.last_mark_price_twap = 50_000_100;
+
+    for now in 1..=3600 {
+        assert!(accrue_funding(&mut market, now, false).unwrap());
+    }
+
+    // the same as accruing all at once
+    let mut other_market = continuous_funding_market();
+    other_market.amm.last_mark_price_twap = 50_000_100;
+    assert!(accrue_funding(&mut other_market, 3600, false).unwrap());
+
+    assert_eq!(market.amm.cumulative_funding_rate_long, 4166);
+    assert_eq!(
+        market.amm.cumulative_funding_rate_long,
+        other_market.amm.cumulative_funding_rate_long
+    );
+    assert_eq!(
+        market.funding_rate_remainder,
+        other_market.funding_rate_remainder
+    );
+}
+
+#[test]
+fn continuous_funding_fee_pool_limit_per_period() {
+    // more shorts than longs, positive funding, 1/3 of fee pool too small
+    let mut market = PerpMarket {
+        amm: AMM {
+            base_asset_reserve: 512295081967,
+            quote_asset_reserve: 488 * AMM_RESERVE_PRECISION,
+            sqrt_k: 500 * AMM_RESERVE_PRECISION,
+            peg_multiplier: 50000000,
+            base_asset_amount_with_amm: -12295081967,
+            base_asset_amount_long: 12295081967,
+            base_asset_amount_short: -12295081967 * 2,
+            total_exchange_fee: QUOTE_PRECISION / 2,
+            total_fee_minus_distributions: (QUOTE_PRECISION as i128) / 2,
+            last_mark_price_twap: 50 * PRICE_PRECISION_U64,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: (49 * PRICE_PRECISION) as i64,
+                ..HistoricalOracleData::default()
+            },
+            funding_period: 3600,
+            ..AMM::default()
+        },
+        ..PerpMarket::default()
+    };
+
+    // two half hour accruals of the hourly rate
+    for _ in 0..2 {
+        calculate_funding_rate_long_short_for_interval(&mut market, 41666666 / 2, 1800, 3600)
+            .unwrap();
+    }
+
+    // no more than 1/3 of the fee pool is spent over the funding period, same as one hourly
+    // update spends
+    assert!(market.amm.total_fee_minus_distributions >= 416667);
+}
@@ -67,6 +67,49 @@ pub fn if_shares_to_vault_amount(
     Ok(amount)
 }
 
+/// Share tokens are minted one to one with the if shares they wrap. Rebases shrink the wrapped
+/// shares but not the token supply, so after a rebase each token is worth fewer shares
+pub fn if_shares_to_share_tokens(
+    n_shares: u128,
+    wrapped_if_shares: u128,
+    share_token_supply: u64,
+) -> DriftResult<u64> {
+    if share_token_supply == 0 {
+        return n_shares.cast();
+    }
+
+    get_proportion_u128(
+        n_shares,
+        share_token_supply.cast::<u128>()?,
+        wrapped_if_shares,
+    )?
+    .cast()
+}
+
+pub fn share_tokens_to_if_shares(
+    share_token_amount: u64,
+    wrapped_if_shares: u128,
+    share_token_supply: u64,
+) -> DriftResult<u128> {
+    validate!(
+        share_token_amount <= share_token_supply,
+        ErrorCode::InvalidIFSharesDetected,
+        "share_token_amount({}) > share_token_supply({})",
+        share_token_amount,
+        share_token_supply
+    )?;
+
+    if share_token_supply == 0 {
+        return Ok(0);
+    }
+
+    get_proportion_u128(
+        wrapped_if_shares,
+        share_token_amount.cast::<u128>()?,
+        share_token_supply.cast::<u128>()?,
+    )
+}
+
 pub fn calculate_rebase_info(
     total_if_shares: u128,
     insurance_fund_vault_balance: u64,
@@ -166,3 +166,55 @@ pub fn if_shares_lost_test() {
         true
     );
 }
+
+#[test]
+pub fn share_token_exchange_rate_test() {
+    // first tokens are one to one with shares
+    let share_tokens = if_shares_to_share_tokens(1000 * QUOTE_PRECISION, 0, 0).unwrap();
+    assert_eq!(share_tokens, (1000 * QUOTE_PRECISION) as u64);
+
+    let wrapped_if_shares = 1000 * QUOTE_PRECISION;
+    let share_token_supply = share_tokens;
+    assert_eq!(
+        share_tokens_to_if_shares(share_token_supply, wrapped_if_shares, share_token_supply)
+            .unwrap(),
+        wrapped_if_shares
+    );
+
+    // rebase by 10x, tokens are now worth a tenth of a share
+    let wrapped_if_shares = wrapped_if_shares / 10;
+    let share_tokens =
+        if_shares_to_share_tokens(QUOTE_PRECISION, wrapped_if_shares, share_token_supply).unwrap();
+    assert_eq!(share_tokens, (10 * QUOTE_PRECISION) as u64);
+
+    let wrapped_if_shares = wrapped_if_shares + QUOTE_PRECISION;
+    let share_token_supply = share_token_supply + share_tokens;
+    assert_eq!(
+        share_tokens_to_if_shares(
+            (10 * QUOTE_PRECISION) as u64,
+            wrapped_if_shares,
+            share_token_supply
+        )
+        .unwrap(),
+        QUOTE_PRECISION
+    );
+
+    // rounds down
+    assert_eq!(
+        share_tokens_to_if_shares(9, wrapped_if_shares, share_token_supply).unwrap(),
+        0
+    );
+    assert_eq!(
+        share_tokens_to_if_shares(share_token_supply, wrapped_if_shares, share_token_supply)
+            .unwrap(),
+        wrapped_if_shares
+    );
+
+    // can't unwrap more than the supply
+    assert!(share_tokens_to_if_shares(
+        share_token_supply + 1,
+        wrapped_if_shares,
+        share_token_supply
+    )
+    .is_err());
+}
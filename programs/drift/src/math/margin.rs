@@ -1,8 +1,8 @@
 use crate::error::DriftResult;
 use crate::error::ErrorCode;
 use crate::math::constants::{
-    MARGIN_PRECISION_U128, MAX_POSITIVE_UPNL_FOR_INITIAL_MARGIN, PRICE_PRECISION,
-    SPOT_IMF_PRECISION_U128, SPOT_WEIGHT_PRECISION, SPOT_WEIGHT_PRECISION_U128,
+    MARGIN_PRECISION_U128, MAX_POSITIVE_UPNL_FOR_INITIAL_MARGIN, MAX_PREDICTION_MARKET_PRICE_I64,
+    PRICE_PRECISION, SPOT_IMF_PRECISION_U128, SPOT_WEIGHT_PRECISION, SPOT_WEIGHT_PRECISION_U128,
 };
 use crate::math::position::{
     calculate_base_asset_value_and_pnl_with_oracle_price,
@@ -14,12 +14,18 @@ use crate::{validation, PRICE_PRECISION_I64};
 
 use crate::math::casting::Cast;
 use crate::math::funding::calculate_funding_payment;
+use crate::math::options::calculate_option_margin_requirement;
 use crate::math::oracle::{is_oracle_valid_for_action, DriftAction};
 
 use crate::math::spot_balance::{get_strict_token_value, get_token_value};
 
+use crate::math::portfolio_margin::calculate_portfolio_margin;
 use crate::math::safe_math::SafeMath;
-use crate::state::margin_calculation::{MarginCalculation, MarginContext, MarketIdentifier};
+use crate::state::load_ref::load_ref;
+use crate::state::margin_calculation::{
+    MarginCalculation, MarginCalculationMode, MarginContext, MarketIdentifier,
+};
+use crate::state::option_market::OptionMarket;
 use crate::state::oracle::{OraclePriceData, StrictOraclePrice};
 use crate::state::oracle_map::OracleMap;
 use crate::state::perp_market::{ContractTier, MarketStatus, PerpMarket};
@@ -29,6 +35,7 @@ use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::user::{MarketType, OrderFillSimulation, PerpPosition, User};
 use num_integer::Roots;
 use solana_program::msg;
+use std::cell::Ref;
 use std::cmp::{max, min, Ordering};
 
 #[cfg(test)]
@@ -105,11 +112,7 @@ pub fn calculate_perp_position_value_and_pnl(
     user_custom_margin_ratio: u32,
     track_open_order_fraction: bool,
 ) -> DriftResult<(u128, i128, u128, u128, u128)> {
-    let valuation_price = if market.status == MarketStatus::Settlement {
-        market.expiry_price
-    } else {
-        oracle_price_data.price
-    };
+    let valuation_price = market.get_margin_valuation_price(oracle_price_data.price)?;
 
     // the funding must be calculated before calculated the unrealized pnl w simulated lp position
     let unrealized_funding = calculate_funding_payment(
@@ -147,12 +150,27 @@ pub fn calculate_perp_position_value_and_pnl(
 
     let mut margin_requirement = if market.status == MarketStatus::Settlement {
         0
+    } else if market.is_prediction_market() {
+        calculate_prediction_market_margin_requirement(
+            worst_case_base_asset_amount,
+            valuation_price,
+        )?
+        .safe_mul(strict_quote_price.max().cast()?)?
+        .safe_div(PRICE_PRECISION)?
     } else {
         worse_case_base_asset_value
             .safe_mul(margin_ratio.cast()?)?
             .safe_div(MARGIN_PRECISION_U128)?
     };
 
+    // dated futures are valued at the oracle, the basis against the position is charged on top
+    margin_requirement = margin_requirement.safe_add(
+        market
+            .get_basis_margin_requirement(worst_case_base_asset_amount, oracle_price_data.price)?
+            .safe_mul(strict_quote_price.max().cast()?)?
+            .safe_div(PRICE_PRECISION)?,
+    )?;
+
     // add small margin requirement for every open order
     margin_requirement = margin_requirement
         .safe_add(market_position.margin_requirement_for_open_orders()?)?
@@ -210,6 +228,64 @@ pub fn calculate_perp_position_value_and_pnl(
     ))
 }
 
+/// Prediction markets are margined for their max loss: longs can lose their whole value and shorts
+/// the distance to MAX_PREDICTION_MARKET_PRICE
+/// precision: QUOTE_PRECISION
+pub fn calculate_prediction_market_margin_requirement(
+    base_asset_amount: i128,
+    valuation_price: i64,
+) -> DriftResult<u128> {
+    let max_loss_per_contract = if base_asset_amount > 0 {
+        valuation_price
+    } else {
+        MAX_PREDICTION_MARKET_PRICE_I64.safe_sub(valuation_price)?
+    }
+    .max(0);
+
+    calculate_base_asset_value_with_oracle_price(base_asset_amount, max_loss_per_contract)
+}
+
+/// Options are margined on their delta and vega instead of their premium notional. Returns the
+/// position's margin requirement and its open order portion
+pub fn calculate_option_position_margin_requirement(
+    market_position: &PerpPosition,
+    market: &PerpMarket,
+    option_market: &OptionMarket,
+    strict_quote_price: &StrictOraclePrice,
+    margin_requirement_type: MarginRequirementType,
+    user_custom_margin_ratio: u32,
+    track_open_order_fraction: bool,
+) -> DriftResult<(u128, u128)> {
+    let worst_case_base_asset_amount = market_position.worst_case_base_asset_amount()?;
+
+    let margin_ratio = user_custom_margin_ratio.max(market.get_margin_ratio(
+        worst_case_base_asset_amount.unsigned_abs(),
+        margin_requirement_type,
+    )?);
+
+    let margin_requirement = calculate_option_margin_requirement(
+        option_market,
+        worst_case_base_asset_amount,
+        margin_ratio,
+    )?
+    .safe_mul(strict_quote_price.max().cast()?)?
+    .safe_div(PRICE_PRECISION)?
+    .safe_add(market_position.margin_requirement_for_open_orders()?)?;
+
+    let open_order_margin_requirement =
+        if track_open_order_fraction && worst_case_base_asset_amount != 0 {
+            let worst_case_base_asset_amount = worst_case_base_asset_amount.unsigned_abs();
+            worst_case_base_asset_amount
+                .safe_sub(market_position.base_asset_amount.unsigned_abs().cast()?)?
+                .safe_mul(margin_requirement)?
+                .safe_div(worst_case_base_asset_amount)?
+        } else {
+            0_u128
+        };
+
+    Ok((margin_requirement, open_order_margin_requirement))
+}
+
 pub fn calculate_user_safest_position_tiers(
     user: &User,
     perp_market_map: &PerpMarketMap,
@@ -244,6 +320,17 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
     oracle_map: &mut OracleMap,
     context: MarginContext,
 ) -> DriftResult<MarginCalculation> {
+    let context = if user.is_portfolio_margin_enabled()
+        && matches!(context.mode, MarginCalculationMode::Standard { .. })
+    {
+        MarginContext {
+            mode: MarginCalculationMode::Portfolio,
+            ..context
+        }
+    } else {
+        context
+    };
+
     let mut calculation = MarginCalculation::new(context);
 
     let user_custom_margin_ratio = if context.margin_type == MarginRequirementType::Initial {
@@ -252,10 +339,13 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
         0_u32
     };
 
+    // isolated perp position margin only considers the collateral posted to that position
+    let isolated_perp_market_index = context.isolated_perp_market_index;
+
     for spot_position in user.spot_positions.iter() {
         validation::position::validate_spot_position(spot_position)?;
 
-        if spot_position.is_available() {
+        if spot_position.is_available() || isolated_perp_market_index.is_some() {
             continue;
         }
 
@@ -263,9 +353,10 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
         let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
             MarketType::Spot,
             spot_market.market_index,
-            &spot_market.oracle,
+            spot_market.oracle_id(),
             spot_market.historical_oracle_data.last_oracle_price_twap,
             spot_market.get_max_confidence_interval_multiplier()?,
+            spot_market.get_validity_guard_rails_override(),
         )?;
 
         calculation.update_all_oracles_valid(is_oracle_valid_for_action(
@@ -443,11 +534,27 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
         }
     }
 
-    for market_position in user.perp_positions.iter() {
+    for (position_index, market_position) in user.perp_positions.iter().enumerate() {
         if market_position.is_available() {
             continue;
         }
 
+        let is_isolated_position = user.is_isolated_perp_position(position_index);
+
+        match isolated_perp_market_index {
+            Some(market_index) => {
+                if !is_isolated_position || market_position.market_index != market_index {
+                    continue;
+                }
+            }
+            None => {
+                // isolated positions are liquidated on their own
+                if is_isolated_position && calculation.is_liquidation_mode() {
+                    continue;
+                }
+            }
+        }
+
         let market = &perp_market_map.get_ref(&market_position.market_index)?;
 
         let quote_spot_market = spot_market_map.get_ref(&market.quote_spot_market_index)?;
@@ -455,11 +562,12 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
             .get_price_data_and_validity(
                 MarketType::Spot,
                 quote_spot_market.market_index,
-                &quote_spot_market.oracle,
+                quote_spot_market.oracle_id(),
                 quote_spot_market
                     .historical_oracle_data
                     .last_oracle_price_twap,
                 quote_spot_market.get_max_confidence_interval_multiplier()?,
+                quote_spot_market.get_validity_guard_rails_override(),
             )?;
 
         calculation.update_all_oracles_valid(is_oracle_valid_for_action(
@@ -474,16 +582,40 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
                 .last_oracle_price_twap_5min,
             calculation.context.strict,
         );
+        let quote_decimals = quote_spot_market.decimals;
         drop(quote_spot_market);
 
         let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
             MarketType::Perp,
             market.market_index,
-            &market.amm.oracle,
+            market.oracle_id(),
             market.amm.historical_oracle_data.last_oracle_price_twap,
             market.get_max_confidence_interval_multiplier()?,
+            market.get_validity_guard_rails_override(),
         )?;
 
+        // the collateral posted to an isolated position sits in its quote_asset_amount
+        // so only the pnl on top of the entry amount is weighted like unrealized pnl
+        let (position_to_value, isolated_collateral) = if is_isolated_position {
+            let isolated_collateral = get_strict_token_value(
+                market_position
+                    .quote_asset_amount
+                    .safe_sub(market_position.quote_entry_amount)?
+                    .cast()?,
+                quote_decimals,
+                &strict_quote_price,
+            )?;
+
+            let position_to_value = PerpPosition {
+                quote_asset_amount: market_position.quote_entry_amount,
+                ..*market_position
+            };
+
+            (position_to_value, isolated_collateral)
+        } else {
+            (*market_position, 0_i128)
+        };
+
         let (
             perp_margin_requirement,
             weighted_pnl,
@@ -491,7 +623,7 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
             open_order_margin_requirement,
             base_asset_value,
         ) = calculate_perp_position_value_and_pnl(
-            market_position,
+            &position_to_value,
             market,
             oracle_price_data,
             &strict_quote_price,
@@ -507,6 +639,42 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
             oracle_price_data.price,
         )?;
 
+        let (perp_margin_requirement, open_order_margin_requirement) =
+            if market.is_option() && market.status != MarketStatus::Settlement {
+                let option_market_account_info = oracle_map.get_account_info(&market.amm.oracle)?;
+                let option_market: Ref<OptionMarket> =
+                    load_ref(&option_market_account_info).or(Err(ErrorCode::UnableToLoadOracle))?;
+
+                calculate_option_position_margin_requirement(
+                    &position_to_value,
+                    market,
+                    &option_market,
+                    &strict_quote_price,
+                    context.margin_type,
+                    user_custom_margin_ratio,
+                    calculation.track_open_orders_fraction(),
+                )?
+            } else {
+                (perp_margin_requirement, open_order_margin_requirement)
+            };
+
+        if is_isolated_position && isolated_perp_market_index.is_none() {
+            calculation.add_isolated_perp_position(
+                market.market_index,
+                isolated_collateral.safe_add(weighted_pnl)?,
+                perp_margin_requirement,
+            )?;
+
+            calculation.update_all_oracles_valid(is_oracle_valid_for_action(
+                oracle_validity,
+                Some(DriftAction::MarginCalc),
+            )?);
+
+            continue;
+        }
+
+        calculation.add_total_collateral(isolated_collateral)?;
+
         calculation.add_margin_requirement(
             perp_margin_requirement,
             worst_case_base_asset_value,
@@ -544,6 +712,23 @@ pub fn calculate_margin_requirement_and_total_collateral_and_liability_info(
         }
     }
 
+    // the cross margin account of a portfolio margin user is stress tested as a whole,
+    // liquidations of opted in users are measured against the same requirement
+    if isolated_perp_market_index.is_none()
+        && (calculation.is_portfolio_mode()
+            || (calculation.is_liquidation_mode() && user.is_portfolio_margin_enabled()))
+    {
+        let portfolio_margin = calculate_portfolio_margin(
+            user,
+            perp_market_map,
+            spot_market_map,
+            oracle_map,
+            context.margin_type,
+            context.strict,
+        )?;
+        calculation.apply_portfolio_margin(&portfolio_margin)?;
+    }
+
     calculation.validate_num_spot_liabilities()?;
 
     Ok(calculation)
@@ -733,7 +918,7 @@ pub fn calculate_max_withdrawable_amount(
         .get_spot_position(market_index)?
         .get_token_amount(spot_market)?;
 
-    let oracle_price = oracle_map.get_price_data(&spot_market.oracle)?.price;
+    let oracle_price = oracle_map.get_price_data(spot_market.oracle_id())?.price;
 
     let asset_weight = spot_market.get_asset_weight(
         token_amount,
@@ -807,7 +992,7 @@ pub fn validate_spot_margin_trading(
         let bids = spot_position.open_bids;
         if bids > 0 {
             let spot_market = spot_market_map.get_ref(&spot_position.market_index)?;
-            let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+            let oracle_price_data = oracle_map.get_price_data(spot_market.oracle_id())?;
             let open_bids_value =
                 get_token_value(-bids as i128, spot_market.decimals, oracle_price_data.price)?;
 
@@ -850,9 +1035,10 @@ pub fn calculate_user_equity(
         let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
             MarketType::Spot,
             spot_market.market_index,
-            &spot_market.oracle,
+            spot_market.oracle_id(),
             spot_market.historical_oracle_data.last_oracle_price_twap,
             spot_market.get_max_confidence_interval_multiplier()?,
+            spot_market.get_validity_guard_rails_override(),
         )?;
         all_oracles_valid &=
             is_oracle_valid_for_action(oracle_validity, Some(DriftAction::MarginCalc))?;
@@ -877,11 +1063,12 @@ pub fn calculate_user_equity(
                 .get_price_data_and_validity(
                     MarketType::Spot,
                     quote_spot_market.market_index,
-                    &quote_spot_market.oracle,
+                    quote_spot_market.oracle_id(),
                     quote_spot_market
                         .historical_oracle_data
                         .last_oracle_price_twap,
                     quote_spot_market.get_max_confidence_interval_multiplier()?,
+                    quote_spot_market.get_validity_guard_rails_override(),
                 )?;
 
             all_oracles_valid &=
@@ -893,19 +1080,16 @@ pub fn calculate_user_equity(
         let (oracle_price_data, oracle_validity) = oracle_map.get_price_data_and_validity(
             MarketType::Perp,
             market.market_index,
-            &market.amm.oracle,
+            market.oracle_id(),
             market.amm.historical_oracle_data.last_oracle_price_twap,
             market.get_max_confidence_interval_multiplier()?,
+            market.get_validity_guard_rails_override(),
         )?;
 
         all_oracles_valid &=
             is_oracle_valid_for_action(oracle_validity, Some(DriftAction::MarginCalc))?;
 
-        let valuation_price = if market.status == MarketStatus::Settlement {
-            market.expiry_price
-        } else {
-            oracle_price_data.price
-        };
+        let valuation_price = market.get_margin_valuation_price(oracle_price_data.price)?;
 
         let unrealized_funding = calculate_funding_payment(
             if market_position.base_asset_amount > 0 {
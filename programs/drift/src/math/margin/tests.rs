@@ -1434,8 +1434,8 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
     use crate::controller::position::PositionDirection;
     use crate::math::constants::{
         AMM_RESERVE_PRECISION, LIQUIDATION_FEE_PRECISION, PEG_PRECISION, QUOTE_PRECISION,
-        SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
-        SPOT_WEIGHT_PRECISION,
+        QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
     };
     use crate::math::margin::{
         calculate_margin_requirement_and_total_collateral_and_liability_info, MarginRequirementType,
@@ -2291,6 +2291,137 @@ mod calculate_margin_requirement_and_total_collateral_and_liability_info {
         assert_eq!(margin_requirement, 10100000);
         assert_eq!(total_collateral, 9500000);
     }
+
+    #[test]
+    pub fn isolated_perp_position() {
+        let slot = 0_u64;
+
+        let mut sol_oracle_price = get_pyth_price(100, 6);
+        let sol_oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            sol_oracle_price,
+            &sol_oracle_price_key,
+            &pyth_program,
+            sol_oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&sol_oracle_account_info, slot, None).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            deposit_balance: 10000 * SPOT_BALANCE_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(PRICE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let spot_market_map =
+            SpotMarketMap::load_one(&usdc_spot_market_account_info, true).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                order_step_size: 10000000,
+                oracle: sol_oracle_price_key,
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Initialized,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_positions = [SpotPosition::default(); 8];
+        spot_positions[0] = SpotPosition {
+            market_index: 0,
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: 10 * SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        };
+        // $20 posted to the isolated position on top of a $100 entry
+        let user = User {
+            orders: [Order::default(); 32],
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: BASE_PRECISION_I64,
+                quote_asset_amount: -80 * QUOTE_PRECISION_I64,
+                quote_entry_amount: -100 * QUOTE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions,
+            isolated_perp_positions: 1,
+            ..User::default()
+        };
+
+        let calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::standard(MarginRequirementType::Initial),
+        )
+        .unwrap();
+
+        assert_eq!(calculation.margin_requirement, 0);
+        assert_eq!(calculation.total_collateral, 10000000);
+
+        let isolated_calculation = calculation.get_isolated_perp_position(0).unwrap();
+        assert_eq!(isolated_calculation.margin_requirement, 10000000);
+        assert_eq!(isolated_calculation.total_collateral, 20000000);
+        assert!(calculation.meets_margin_requirement());
+
+        let MarginCalculation {
+            total_collateral,
+            margin_requirement,
+            ..
+        } = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::standard(MarginRequirementType::Initial).isolated_perp_position(0),
+        )
+        .unwrap();
+
+        assert_eq!(margin_requirement, 10000000);
+        assert_eq!(total_collateral, 20000000);
+
+        // cross account collateral isnt used to back the isolated position
+        let user = User {
+            perp_positions: get_positions(PerpPosition {
+                quote_asset_amount: -95 * QUOTE_PRECISION_I64,
+                ..user.perp_positions[0]
+            }),
+            ..user
+        };
+
+        let calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::standard(MarginRequirementType::Initial),
+        )
+        .unwrap();
+
+        assert!(calculation.meets_cross_margin_requirement());
+        assert!(!calculation.meets_isolated_margin_requirement(0));
+        assert!(!calculation.meets_margin_requirement());
+    }
 }
 
 #[cfg(test)]
@@ -3530,3 +3661,30 @@ mod calculate_user_equity {
         assert_eq!(net_usd_value, 1000000000);
     }
 }
+
+#[cfg(test)]
+mod calculate_prediction_market_margin_requirement {
+    use crate::math::constants::{BASE_PRECISION_I128, MAX_PREDICTION_MARKET_PRICE_I64};
+    use crate::math::margin::calculate_prediction_market_margin_requirement;
+
+    #[test]
+    fn max_loss() {
+        // longs lose what they paid, shorts lose the rest of the range
+        let requirement =
+            calculate_prediction_market_margin_requirement(2 * BASE_PRECISION_I128, 300_000)
+                .unwrap();
+        assert_eq!(requirement, 600_000);
+
+        let requirement =
+            calculate_prediction_market_margin_requirement(-2 * BASE_PRECISION_I128, 300_000)
+                .unwrap();
+        assert_eq!(requirement, 1_400_000);
+
+        let requirement = calculate_prediction_market_margin_requirement(
+            -2 * BASE_PRECISION_I128,
+            MAX_PREDICTION_MARKET_PRICE_I64,
+        )
+        .unwrap();
+        assert_eq!(requirement, 0);
+    }
+}
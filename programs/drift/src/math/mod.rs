@@ -19,9 +19,11 @@ pub mod liquidation;
 pub mod lp;
 pub mod margin;
 pub mod matching;
+pub mod options;
 pub mod oracle;
 pub mod orders;
 pub mod pnl;
+pub mod portfolio_margin;
 pub mod position;
 pub mod quote_asset;
 pub mod repeg;
@@ -0,0 +1,311 @@
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION, MARGIN_PRECISION_U128, ONE_YEAR, PERCENTAGE_PRECISION,
+    PERCENTAGE_PRECISION_I64,
+};
+use crate::math::safe_math::SafeMath;
+use crate::state::option_market::{OptionMarket, OptionType};
+use crate::validate;
+use num_integer::Roots;
+
+#[cfg(test)]
+mod tests;
+
+/// Fixed point precision the pricing model is evaluated in
+const OPTION_PRECISION: i128 = 1_000_000_000_000; // expo = -12
+/// OPTION_PRECISION / PRICE_PRECISION, also OPTION_PRECISION / PERCENTAGE_PRECISION
+const OPTION_TO_PRICE_PRECISION_RATIO: i128 = 1_000_000;
+const LN_2: i128 = 693_147_180_560; // expo = -12
+const SQRT_2_PI: i128 = 2_506_628_274_631; // expo = -12
+
+/// Abramowitz and Stegun 26.2.17 coefficients for the normal cdf, error < 7.5e-8
+const NORMAL_CDF_P: i128 = 231_641_900_000; // expo = -12
+const NORMAL_CDF_B: [i128; 5] = [
+    319_381_530_000,
+    -356_563_782_000,
+    1_781_477_937_000,
+    -1_821_255_978_000,
+    1_330_274_429_000,
+]; // expo = -12
+
+/// The implied volatility move short options are margined for, on top of their delta
+/// precision: PERCENTAGE_PRECISION
+pub const OPTION_VEGA_MARGIN_SHOCK: u128 = PERCENTAGE_PRECISION / 4; // 25 vol points
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptionGreeks {
+    /// precision: PRICE_PRECISION
+    pub premium: i64,
+    /// precision: PERCENTAGE_PRECISION, negative for puts
+    pub delta: i64,
+    /// The premium change for a 100% change in implied volatility
+    /// precision: PRICE_PRECISION
+    pub vega: i64,
+}
+
+pub fn calculate_intrinsic_value(
+    option_type: OptionType,
+    underlying_price: i64,
+    strike: u64,
+) -> DriftResult<i64> {
+    let strike = strike.cast::<i64>()?;
+
+    Ok(match option_type {
+        OptionType::Call => underlying_price.safe_sub(strike)?.max(0),
+        OptionType::Put => strike.safe_sub(underlying_price)?.max(0),
+    })
+}
+
+/// Black-Scholes price, delta and vega of a european option with no interest rate. Options at or
+/// past expiry, or without volatility, are worth their intrinsic value
+pub fn calculate_option_greeks(
+    option_type: OptionType,
+    underlying_price: i64,
+    strike: u64,
+    time_to_expiry: i64,
+    implied_volatility: u64,
+) -> DriftResult<OptionGreeks> {
+    validate!(
+        strike > 0,
+        ErrorCode::InvalidOptionMarket,
+        "option strike == 0"
+    )?;
+
+    if underlying_price <= 0 || time_to_expiry <= 0 || implied_volatility == 0 {
+        let in_the_money = match option_type {
+            OptionType::Call => underlying_price > strike.cast()?,
+            OptionType::Put => underlying_price < strike.cast()?,
+        };
+
+        let delta = match (option_type, in_the_money) {
+            (OptionType::Call, true) => PERCENTAGE_PRECISION_I64,
+            (OptionType::Put, true) => -PERCENTAGE_PRECISION_I64,
+            (_, false) => 0,
+        };
+
+        return Ok(OptionGreeks {
+            premium: calculate_intrinsic_value(option_type, underlying_price, strike)?,
+            delta,
+            vega: 0,
+        });
+    }
+
+    let underlying_price = underlying_price
+        .cast::<i128>()?
+        .safe_mul(OPTION_TO_PRICE_PRECISION_RATIO)?;
+    let strike = strike
+        .cast::<i128>()?
+        .safe_mul(OPTION_TO_PRICE_PRECISION_RATIO)?;
+    let sigma = implied_volatility
+        .cast::<i128>()?
+        .safe_mul(OPTION_TO_PRICE_PRECISION_RATIO)?;
+
+    // time to expiry in years
+    let time = time_to_expiry
+        .cast::<i128>()?
+        .safe_mul(OPTION_PRECISION)?
+        .safe_div(ONE_YEAR.cast()?)?;
+    let sqrt_time = time
+        .safe_mul(OPTION_PRECISION)?
+        .cast::<u128>()?
+        .nth_root(2)
+        .cast::<i128>()?;
+    let sigma_sqrt_time = sigma.safe_mul(sqrt_time)?.safe_div(OPTION_PRECISION)?;
+
+    if sigma_sqrt_time == 0 {
+        return calculate_option_greeks(
+            option_type,
+            underlying_price
+                .safe_div(OPTION_TO_PRICE_PRECISION_RATIO)?
+                .cast()?,
+            strike.safe_div(OPTION_TO_PRICE_PRECISION_RATIO)?.cast()?,
+            0,
+            0,
+        );
+    }
+
+    let log_moneyness = ln(underlying_price
+        .safe_mul(OPTION_PRECISION)?
+        .safe_div(strike)?)?;
+    let half_variance = sigma
+        .safe_mul(sigma)?
+        .safe_div(OPTION_PRECISION)?
+        .safe_mul(time)?
+        .safe_div(OPTION_PRECISION)?
+        .safe_div(2)?;
+
+    let d1 = log_moneyness
+        .safe_add(half_variance)?
+        .safe_mul(OPTION_PRECISION)?
+        .safe_div(sigma_sqrt_time)?;
+    let d2 = d1.safe_sub(sigma_sqrt_time)?;
+
+    let cdf_d1 = normal_cdf(d1)?;
+    let cdf_d2 = normal_cdf(d2)?;
+
+    let call_premium = underlying_price
+        .safe_mul(cdf_d1)?
+        .safe_div(OPTION_PRECISION)?
+        .safe_sub(strike.safe_mul(cdf_d2)?.safe_div(OPTION_PRECISION)?)?;
+
+    // put-call parity
+    let (premium, delta) = match option_type {
+        OptionType::Call => (call_premium, cdf_d1),
+        OptionType::Put => (
+            call_premium.safe_sub(underlying_price)?.safe_add(strike)?,
+            cdf_d1.safe_sub(OPTION_PRECISION)?,
+        ),
+    };
+
+    let vega = underlying_price
+        .safe_mul(normal_pdf(d1)?)?
+        .safe_div(OPTION_PRECISION)?
+        .safe_mul(sqrt_time)?
+        .safe_div(OPTION_PRECISION)?;
+
+    Ok(OptionGreeks {
+        premium: premium
+            .max(0)
+            .safe_div(OPTION_TO_PRICE_PRECISION_RATIO)?
+            .cast()?,
+        delta: delta.safe_div(OPTION_TO_PRICE_PRECISION_RATIO)?.cast()?,
+        vega: vega.safe_div(OPTION_TO_PRICE_PRECISION_RATIO)?.cast()?,
+    })
+}
+
+/// The margin requirement for an option position: the underlying move covered by the market's
+/// margin ratio times the option's delta, plus the premium move for a OPTION_VEGA_MARGIN_SHOCK
+/// implied volatility move. Longs can't lose more than the option's value
+/// precision: QUOTE_PRECISION
+pub fn calculate_option_margin_requirement(
+    option_market: &OptionMarket,
+    base_asset_amount: i128,
+    margin_ratio: u32,
+) -> DriftResult<u128> {
+    let delta_requirement = option_market
+        .delta
+        .unsigned_abs()
+        .cast::<u128>()?
+        .safe_mul(option_market.underlying_price.unsigned_abs().cast()?)?
+        .safe_div(PERCENTAGE_PRECISION)?
+        .safe_mul(margin_ratio.cast()?)?
+        .safe_div(MARGIN_PRECISION_U128)?;
+
+    let vega_requirement = option_market
+        .vega
+        .unsigned_abs()
+        .cast::<u128>()?
+        .safe_mul(OPTION_VEGA_MARGIN_SHOCK)?
+        .safe_div(PERCENTAGE_PRECISION)?;
+
+    let mut requirement_per_contract = delta_requirement.safe_add(vega_requirement)?;
+
+    if base_asset_amount > 0 {
+        requirement_per_contract =
+            requirement_per_contract.min(option_market.premium.max(0).cast()?);
+    }
+
+    // contracts are in AMM_RESERVE_PRECISION, requirement per contract in PRICE_PRECISION
+    base_asset_amount
+        .unsigned_abs()
+        .safe_mul(requirement_per_contract)?
+        .safe_div(AMM_RESERVE_PRECISION)
+}
+
+/// Natural log of x > 0
+/// precision: OPTION_PRECISION
+fn ln(x: i128) -> DriftResult<i128> {
+    validate!(x > 0, ErrorCode::MathError, "ln of {} <= 0", x)?;
+
+    // x = m * 2^k with m in [1, 2)
+    let mut m = x;
+    let mut k = 0_i128;
+    while m >= OPTION_PRECISION.safe_mul(2)? {
+        m = m.safe_div(2)?;
+        k = k.safe_add(1)?;
+    }
+    while m < OPTION_PRECISION {
+        m = m.safe_mul(2)?;
+        k = k.safe_sub(1)?;
+    }
+
+    // ln(m) = 2 * (z + z^3/3 + z^5/5 + ...) with z = (m - 1) / (m + 1) in [0, 1/3)
+    let z = m
+        .safe_sub(OPTION_PRECISION)?
+        .safe_mul(OPTION_PRECISION)?
+        .safe_div(m.safe_add(OPTION_PRECISION)?)?;
+    let z_squared = z.safe_mul(z)?.safe_div(OPTION_PRECISION)?;
+
+    let mut term = z;
+    let mut sum = 0_i128;
+    let mut n = 1_i128;
+    while term != 0 {
+        sum = sum.safe_add(term.safe_div(n)?)?;
+        term = term.safe_mul(z_squared)?.safe_div(OPTION_PRECISION)?;
+        n = n.safe_add(2)?;
+    }
+
+    k.safe_mul(LN_2)?.safe_add(sum.safe_mul(2)?)
+}
+
+/// e^-y for y >= 0
+/// precision: OPTION_PRECISION
+fn exp_neg(y: i128) -> DriftResult<i128> {
+    // e^-y = e^-r / 2^k with r in [0, ln 2)
+    let k = y.safe_div(LN_2)?;
+    if k >= 64 {
+        return Ok(0);
+    }
+    let r = y.safe_sub(k.safe_mul(LN_2)?)?;
+
+    let mut term = OPTION_PRECISION;
+    let mut sum = OPTION_PRECISION;
+    let mut n = 1_i128;
+    while term != 0 {
+        term = term.safe_mul(r)?.safe_div(OPTION_PRECISION)?.safe_div(n)?;
+        sum = if n % 2 == 1 {
+            sum.safe_sub(term)?
+        } else {
+            sum.safe_add(term)?
+        };
+        n = n.safe_add(1)?;
+    }
+
+    Ok(sum >> k)
+}
+
+/// precision: OPTION_PRECISION
+fn normal_pdf(x: i128) -> DriftResult<i128> {
+    exp_neg(x.safe_mul(x)?.safe_div(OPTION_PRECISION)?.safe_div(2)?)?
+        .safe_mul(OPTION_PRECISION)?
+        .safe_div(SQRT_2_PI)
+}
+
+/// precision: OPTION_PRECISION
+fn normal_cdf(x: i128) -> DriftResult<i128> {
+    let x_abs = x.abs();
+
+    let t = OPTION_PRECISION.safe_mul(OPTION_PRECISION)?.safe_div(
+        OPTION_PRECISION.safe_add(NORMAL_CDF_P.safe_mul(x_abs)?.safe_div(OPTION_PRECISION)?)?,
+    )?;
+
+    let mut polynomial = 0_i128;
+    let mut t_power = t;
+    for b in NORMAL_CDF_B {
+        polynomial = polynomial.safe_add(b.safe_mul(t_power)?.safe_div(OPTION_PRECISION)?)?;
+        t_power = t_power.safe_mul(t)?.safe_div(OPTION_PRECISION)?;
+    }
+
+    let cdf = OPTION_PRECISION.safe_sub(
+        normal_pdf(x_abs)?
+            .safe_mul(polynomial)?
+            .safe_div(OPTION_PRECISION)?,
+    )?;
+
+    if x >= 0 {
+        Ok(cdf)
+    } else {
+        OPTION_PRECISION.safe_sub(cdf)
+    }
+}
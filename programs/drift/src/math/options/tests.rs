@@ -0,0 +1,167 @@
+fn assert_approx_eq(actual: i64, expected: i64) {
+    assert!(
+        (actual - expected).abs() <= 100,
+        "actual {} != expected {}",
+        actual,
+        expected
+    );
+}
+
+mod calculate_option_greeks {
+    use crate::math::constants::{ONE_YEAR, PERCENTAGE_PRECISION_I64, PRICE_PRECISION_I64};
+    use crate::math::options::calculate_option_greeks;
+    use crate::math::options::tests::assert_approx_eq;
+    use crate::state::option_market::OptionType;
+
+    #[test]
+    fn at_the_money() {
+        let call = calculate_option_greeks(
+            OptionType::Call,
+            100 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64 as u64,
+            ONE_YEAR as i64,
+            200_000,
+        )
+        .unwrap();
+
+        // bs: 7.965567, delta 0.539828, vega 39.6953
+        assert_approx_eq(call.premium, 7965579);
+        assert_approx_eq(call.delta, 539827);
+        assert_approx_eq(call.vega, 39695254);
+
+        let put = calculate_option_greeks(
+            OptionType::Put,
+            100 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64 as u64,
+            ONE_YEAR as i64,
+            200_000,
+        )
+        .unwrap();
+
+        assert_eq!(put.premium, call.premium);
+        assert_approx_eq(put.delta, call.delta - PERCENTAGE_PRECISION_I64);
+        assert_eq!(put.vega, call.vega);
+    }
+
+    #[test]
+    fn in_the_money() {
+        let call = calculate_option_greeks(
+            OptionType::Call,
+            110 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64 as u64,
+            ONE_YEAR as i64 / 4,
+            500_000,
+        )
+        .unwrap();
+
+        assert_approx_eq(call.premium, 16190432);
+        assert_approx_eq(call.delta, 693656);
+        assert_approx_eq(call.vega, 19302889);
+
+        let put = calculate_option_greeks(
+            OptionType::Put,
+            110 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64 as u64,
+            ONE_YEAR as i64 / 4,
+            500_000,
+        )
+        .unwrap();
+
+        // put-call parity, c - p = s - k
+        assert_eq!(call.premium - put.premium, 10 * PRICE_PRECISION_I64);
+        assert_approx_eq(put.delta, -306343);
+    }
+
+    #[test]
+    fn expired() {
+        let put = calculate_option_greeks(
+            OptionType::Put,
+            90 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64 as u64,
+            0,
+            500_000,
+        )
+        .unwrap();
+
+        assert_eq!(put.premium, 10 * PRICE_PRECISION_I64);
+        assert_eq!(put.delta, -PERCENTAGE_PRECISION_I64);
+        assert_eq!(put.vega, 0);
+
+        let call = calculate_option_greeks(
+            OptionType::Call,
+            90 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64 as u64,
+            0,
+            500_000,
+        )
+        .unwrap();
+
+        assert_eq!(call.premium, 0);
+        assert_eq!(call.delta, 0);
+        assert_eq!(call.vega, 0);
+    }
+
+    #[test]
+    fn zero_strike() {
+        assert!(calculate_option_greeks(
+            OptionType::Call,
+            100 * PRICE_PRECISION_I64,
+            0,
+            ONE_YEAR as i64,
+            200_000,
+        )
+        .is_err());
+    }
+}
+
+mod calculate_option_margin_requirement {
+    use crate::math::constants::{BASE_PRECISION_I128, PRICE_PRECISION_I64};
+    use crate::math::options::calculate_option_margin_requirement;
+    use crate::state::option_market::{OptionMarket, OptionType};
+
+    #[test]
+    fn short_and_long() {
+        let option_market = OptionMarket {
+            option_type: OptionType::Call,
+            strike: 100 * PRICE_PRECISION_I64 as u64,
+            underlying_price: 100 * PRICE_PRECISION_I64,
+            premium: 7965579,
+            delta: 539827,
+            vega: 39695254,
+            ..OptionMarket::default()
+        };
+
+        // 10% of the delta adjusted underlying plus a 25 vol point move, per contract
+        // 5398270 + 9923813
+        let requirement =
+            calculate_option_margin_requirement(&option_market, -2 * BASE_PRECISION_I128, 1000)
+                .unwrap();
+        assert_eq!(requirement, 30644166);
+
+        // longs can only lose the premium
+        let requirement =
+            calculate_option_margin_requirement(&option_market, 2 * BASE_PRECISION_I128, 1000)
+                .unwrap();
+        assert_eq!(requirement, 15931158);
+
+        let requirement = calculate_option_margin_requirement(&option_market, 0, 1000).unwrap();
+        assert_eq!(requirement, 0);
+    }
+
+    #[test]
+    fn settled() {
+        let option_market = OptionMarket {
+            option_type: OptionType::Put,
+            strike: 100 * PRICE_PRECISION_I64 as u64,
+            underlying_price: 90 * PRICE_PRECISION_I64,
+            premium: 10 * PRICE_PRECISION_I64,
+            ..OptionMarket::default()
+        };
+
+        // expired options have no greeks left, their value is all in the position's pnl
+        let requirement =
+            calculate_option_margin_requirement(&option_market, -BASE_PRECISION_I128, 1000)
+                .unwrap();
+        assert_eq!(requirement, 0);
+    }
+}
@@ -8,8 +8,9 @@ use crate::math::amm;
 use crate::math::casting::Cast;
 use crate::math::constants::BID_ASK_SPREAD_PRECISION;
 use crate::math::safe_math::SafeMath;
+use crate::math::safe_unwrap::SafeUnwrap;
 
-use crate::state::oracle::OraclePriceData;
+use crate::state::oracle::{OracleAggregate, OracleAggregationMethod, OraclePriceData};
 use crate::state::paused_operations::PerpOperation;
 use crate::state::perp_market::PerpMarket;
 use crate::state::state::{OracleGuardRails, ValidityGuardRails};
@@ -187,7 +188,9 @@ pub fn get_oracle_status(
         market.market_index,
         market.amm.historical_oracle_data.last_oracle_price_twap,
         oracle_price_data,
-        &guard_rails.validity,
+        &guard_rails
+            .validity
+            .with_override(market.get_validity_guard_rails_override()),
         market.get_max_confidence_interval_multiplier()?,
         false,
     )?;
@@ -306,3 +309,99 @@ pub fn oracle_validity(
 
     Ok(oracle_validity)
 }
+
+/// Aggregates the price data of a market's primary oracle and its secondary oracles. Each source is
+/// validated on its own first with the market's guard rails, with the median price of all the
+/// sources standing in for the twap. Stale or invalid sources are skipped. Returns the aggregate
+/// price data and a bitmask of the sources used (bit 0 is the primary). Falls back to the
+/// primary's price data with no sources used if none are valid
+pub fn aggregate_oracle_price_data(
+    oracle_aggregate: &OracleAggregate,
+    primary_price_data: &OraclePriceData,
+    secondary_price_data: &[OraclePriceData],
+    valid_oracle_guard_rails: &ValidityGuardRails,
+    max_confidence_interval_multiplier: u64,
+) -> DriftResult<(OraclePriceData, u8)> {
+    let oracle_price_data = std::iter::once(primary_price_data)
+        .chain(secondary_price_data.iter())
+        .collect::<Vec<&OraclePriceData>>();
+
+    let reference_price = get_median_price(
+        &oracle_price_data
+            .iter()
+            .map(|price_data| price_data.price)
+            .collect::<Vec<i64>>(),
+    )?;
+
+    let mut valid_price_data = Vec::with_capacity(oracle_price_data.len());
+    let mut sources_used = 0_u8;
+    for (i, price_data) in oracle_price_data.into_iter().enumerate() {
+        let oracle_validity = oracle_validity(
+            oracle_aggregate.market_type,
+            oracle_aggregate.market_index,
+            reference_price,
+            price_data,
+            valid_oracle_guard_rails,
+            max_confidence_interval_multiplier,
+            true,
+        )?;
+
+        if oracle_validity != OracleValidity::Valid {
+            continue;
+        }
+
+        valid_price_data.push(*price_data);
+        sources_used |= 1 << i;
+
+        if oracle_aggregate.method == OracleAggregationMethod::FirstValid {
+            return Ok((*price_data, sources_used));
+        }
+    }
+
+    if valid_price_data.is_empty() {
+        return Ok((*primary_price_data, 0));
+    }
+
+    let price = get_median_price(
+        &valid_price_data
+            .iter()
+            .map(|price_data| price_data.price)
+            .collect::<Vec<i64>>(),
+    )?;
+
+    let aggregate_price_data = OraclePriceData {
+        price,
+        confidence: valid_price_data
+            .iter()
+            .map(|price_data| price_data.confidence)
+            .max()
+            .safe_unwrap()?,
+        delay: valid_price_data
+            .iter()
+            .map(|price_data| price_data.delay)
+            .max()
+            .safe_unwrap()?,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    Ok((aggregate_price_data, sources_used))
+}
+
+/// The middle price, or the mean of the two middle prices for an even number of prices
+fn get_median_price(prices: &[i64]) -> DriftResult<i64> {
+    let mut prices = prices.to_vec();
+    prices.sort_unstable();
+
+    let mid = prices.len() / 2;
+    if prices.len() % 2 == 1 {
+        return Ok(prices[mid]);
+    }
+
+    prices
+        .get(mid.checked_sub(1).safe_unwrap()?)
+        .safe_unwrap()?
+        .cast::<i128>()?
+        .safe_add(prices.get(mid).safe_unwrap()?.cast()?)?
+        .safe_div(2)?
+        .cast()
+}
@@ -131,3 +131,161 @@ fn calculate_oracle_valid() {
     assert!(oracle_status.mark_too_divergent);
     assert!(oracle_status.oracle_validity == OracleValidity::TooUncertain);
 }
+
+mod aggregate_oracle_price_data {
+    use crate::math::constants::PRICE_PRECISION_I64;
+    use crate::math::oracle::aggregate_oracle_price_data;
+    use crate::state::oracle::{OracleAggregate, OracleAggregationMethod, OraclePriceData};
+    use crate::state::state::{OracleGuardRails, ValidityGuardRailsOverride};
+
+    fn price_data(price: i64, confidence: u64, delay: i64) -> OraclePriceData {
+        OraclePriceData {
+            price: price * PRICE_PRECISION_I64,
+            confidence,
+            delay,
+            has_sufficient_number_of_data_points: true,
+        }
+    }
+
+    fn oracle_aggregate(method: OracleAggregationMethod) -> OracleAggregate {
+        OracleAggregate {
+            num_secondary_oracles: 2,
+            method,
+            ..OracleAggregate::default()
+        }
+    }
+
+    #[test]
+    fn median() {
+        let guard_rails = OracleGuardRails::default().validity;
+
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::Median),
+            &price_data(100, 1000, 1),
+            &[price_data(103, 3000, 2), price_data(101, 2000, 0)],
+            &guard_rails,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 101 * PRICE_PRECISION_I64);
+        assert_eq!(aggregate_price_data.confidence, 3000);
+        assert_eq!(aggregate_price_data.delay, 2);
+        assert_eq!(sources_used, 0b111);
+
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::Median),
+            &price_data(100, 1000, 1),
+            &[price_data(102, 1000, 1)],
+            &guard_rails,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 101 * PRICE_PRECISION_I64);
+        assert_eq!(sources_used, 0b11);
+    }
+
+    #[test]
+    fn median_skips_invalid_sources() {
+        let guard_rails = OracleGuardRails::default().validity;
+
+        // primary is stale
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::Median),
+            &price_data(90, 1000, 1000),
+            &[price_data(100, 1000, 1), price_data(102, 1000, 1)],
+            &guard_rails,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 101 * PRICE_PRECISION_I64);
+        assert_eq!(aggregate_price_data.delay, 1);
+        assert_eq!(sources_used, 0b110);
+    }
+
+    #[test]
+    fn first_valid() {
+        let guard_rails = OracleGuardRails::default().validity;
+
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::FirstValid),
+            &price_data(100, 1000, 1),
+            &[price_data(103, 1000, 1), price_data(101, 1000, 1)],
+            &guard_rails,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 100 * PRICE_PRECISION_I64);
+        assert_eq!(sources_used, 0b1);
+
+        // primary confidence too large
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::FirstValid),
+            &price_data(100, 10 * PRICE_PRECISION_I64 as u64, 1),
+            &[price_data(103, 1000, 1), price_data(101, 1000, 1)],
+            &guard_rails,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 103 * PRICE_PRECISION_I64);
+        assert_eq!(sources_used, 0b10);
+    }
+
+    #[test]
+    fn no_valid_sources() {
+        let guard_rails = OracleGuardRails::default().validity;
+
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::Median),
+            &price_data(100, 1000, 1000),
+            &[price_data(103, 1000, 1000), price_data(-1, 1000, 1)],
+            &guard_rails,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 100 * PRICE_PRECISION_I64);
+        assert_eq!(aggregate_price_data.delay, 1000);
+        assert_eq!(sources_used, 0);
+    }
+
+    #[test]
+    fn uses_market_guard_rails() {
+        let guard_rails = OracleGuardRails::default().validity;
+
+        // primary confidence is too large for the state's guard rails
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::FirstValid),
+            &price_data(100, 10 * PRICE_PRECISION_I64 as u64, 1),
+            &[price_data(103, 1000, 1), price_data(101, 1000, 1)],
+            &guard_rails,
+            10,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 100 * PRICE_PRECISION_I64);
+        assert_eq!(sources_used, 0b1);
+
+        // primary is stale for the state's guard rails
+        let guard_rails = guard_rails.with_override(ValidityGuardRailsOverride {
+            slots_before_stale_for_amm: 200,
+            slots_before_stale_for_margin: 200,
+            ..ValidityGuardRailsOverride::default()
+        });
+        let (aggregate_price_data, sources_used) = aggregate_oracle_price_data(
+            &oracle_aggregate(OracleAggregationMethod::FirstValid),
+            &price_data(100, 1000, 100),
+            &[price_data(103, 1000, 1), price_data(101, 1000, 1)],
+            &guard_rails,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(aggregate_price_data.price, 100 * PRICE_PRECISION_I64);
+        assert_eq!(sources_used, 0b1);
+    }
+}
@@ -33,7 +33,8 @@ use crate::state::perp_market_map::PerpMarketMap;
 use crate::state::spot_market::SpotMarket;
 use crate::state::spot_market_map::SpotMarketMap;
 use crate::state::user::{
-    MarketType, Order, OrderFillSimulation, OrderStatus, OrderTriggerCondition, PerpPosition, User,
+    MarketType, Order, OrderBitFlag, OrderFillSimulation, OrderStatus, OrderTriggerCondition,
+    PerpPosition, User,
 };
 use crate::state::user_map::UserMap;
 use crate::validate;
@@ -344,11 +345,14 @@ pub fn should_expire_order_before_fill(
     user: &User,
     order_index: usize,
     now: i64,
+    slot: u64,
 ) -> DriftResult<bool> {
-    let should_order_be_expired = should_expire_order(user, order_index, now)?;
+    let should_order_be_expired = should_expire_order(user, order_index, now, slot)?;
     if should_order_be_expired && user.orders[order_index].is_limit_order() {
         let now_sub_buffer = now.safe_sub(15)?;
-        if !should_expire_order(user, order_index, now_sub_buffer)? {
+        // ~15s of slots
+        let slot_sub_buffer = slot.saturating_sub(38);
+        if !should_expire_order(user, order_index, now_sub_buffer, slot_sub_buffer)? {
             msg!("invalid fill. cant force expire limit order until 15s after max_ts. max ts {}, now {}, now plus buffer {}", user.orders[order_index].max_ts, now, now_sub_buffer);
             return Err(ErrorCode::ImpossibleFill);
         }
@@ -358,13 +362,18 @@ pub fn should_expire_order_before_fill(
 }
 
 #[inline(always)]
-pub fn should_expire_order(user: &User, user_order_index: usize, now: i64) -> DriftResult<bool> {
+pub fn should_expire_order(
+    user: &User,
+    user_order_index: usize,
+    now: i64,
+    slot: u64,
+) -> DriftResult<bool> {
     let order = &user.orders[user_order_index];
     if order.status != OrderStatus::Open || order.max_ts == 0 || order.must_be_triggered() {
         return Ok(false);
     }
 
-    Ok(now > order.max_ts)
+    order.is_expired(now, slot)
 }
 
 pub fn should_cancel_reduce_only_order(
@@ -379,6 +388,31 @@ pub fn should_cancel_reduce_only_order(
     Ok(should_cancel)
 }
 
+/// Fill or kill and minimum fill orders can't be left filled below their minimum fill. Orders that
+/// didn't fill at all pass so they can be canceled without a fill. A minimum fill of the whole
+/// order size is fill or kill
+pub fn validate_fill_requirement(
+    min_fill_base_asset_amount: u64,
+    order_base_asset_amount: u64,
+    base_asset_amount_filled: u64,
+) -> DriftResult {
+    if base_asset_amount_filled == 0 || base_asset_amount_filled >= min_fill_base_asset_amount {
+        return Ok(());
+    }
+
+    msg!(
+        "order filled {} less than its minimum fill {}",
+        base_asset_amount_filled,
+        min_fill_base_asset_amount
+    );
+
+    if min_fill_base_asset_amount == order_base_asset_amount {
+        Err(ErrorCode::FillOrKillOrderNotFilled)
+    } else {
+        Err(ErrorCode::MinimumFillNotMet)
+    }
+}
+
 pub fn order_breaches_maker_oracle_price_bands(
     order: &Order,
     oracle_price: i64,
@@ -582,6 +616,49 @@ pub fn order_satisfies_trigger_condition(order: &Order, oracle_price: u64) -> Dr
     }
 }
 
+/// The trigger price a trailing stop moves to at oracle_price. The trigger price of a Below order only
+/// ratchets up and that of an Above order only ratchets down, so None is returned if it wouldn't improve
+pub fn calculate_trailing_stop_trigger_price(
+    order: &Order,
+    oracle_price: u64,
+    tick_size: u64,
+) -> DriftResult<Option<u64>> {
+    let offset = order.oracle_price_offset.unsigned_abs().cast::<u64>()?;
+    let trail = if order.is_bit_flag_set(OrderBitFlag::TrailingStopPercentage) {
+        oracle_price
+            .safe_mul(offset)?
+            .safe_div(PERCENTAGE_PRECISION_U64)?
+    } else {
+        offset
+    };
+
+    let (trigger_price, improves) = match order.trigger_condition {
+        OrderTriggerCondition::Below => {
+            let trigger_price = standardize_price(
+                oracle_price.saturating_sub(trail),
+                tick_size,
+                order.direction,
+            )?;
+            (trigger_price, trigger_price > order.trigger_price)
+        }
+        OrderTriggerCondition::Above => {
+            let trigger_price =
+                standardize_price(oracle_price.safe_add(trail)?, tick_size, order.direction)?;
+            (
+                trigger_price,
+                order.trigger_price == 0 || trigger_price < order.trigger_price,
+            )
+        }
+        _ => return Ok(None),
+    };
+
+    if improves && trigger_price != 0 {
+        Ok(Some(trigger_price))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn is_new_order_risk_increasing(
     order: &Order,
     position_base_asset_amount: i64,
@@ -794,11 +871,11 @@ pub fn calculate_max_perp_order_size(
 
     let perp_market = perp_market_map.get_ref(&market_index)?;
 
-    let oracle_price_data_price = oracle_map.get_price_data(&perp_market.amm.oracle)?.price;
+    let oracle_price_data_price = oracle_map.get_price_data(perp_market.oracle_id())?.price;
 
     let quote_spot_market = spot_market_map.get_ref(&perp_market.quote_spot_market_index)?;
     let quote_oracle_price = oracle_map
-        .get_price_data(&quote_spot_market.oracle)?
+        .get_price_data(quote_spot_market.oracle_id())?
         .price
         .max(
             quote_spot_market
@@ -919,7 +996,7 @@ pub fn calculate_max_spot_order_size(
 
     let spot_market = spot_market_map.get_ref(&market_index)?;
 
-    let oracle_price_data = oracle_map.get_price_data(&spot_market.oracle)?;
+    let oracle_price_data = oracle_map.get_price_data(spot_market.oracle_id())?;
     let twap = spot_market
         .historical_oracle_data
         .last_oracle_price_twap_5min;
@@ -1220,7 +1297,7 @@ pub fn find_bids_and_asks_from_users(
                 continue;
             }
 
-            if now > order.max_ts && order.max_ts != 0 {
+            if order.is_expired(now, slot)? {
                 continue;
             }
 
@@ -327,7 +327,7 @@ mod order_breaches_oracle_price_limits {
 
 mod should_expire_order {
     use crate::math::orders::should_expire_order;
-    use crate::state::user::{Order, OrderStatus, OrderType, User};
+    use crate::state::user::{Order, OrderStatus, OrderType, TimeInForce, User};
     use crate::test_utils::get_orders;
 
     #[test]
@@ -344,7 +344,7 @@ mod should_expire_order {
 
         let now = 100;
 
-        let is_expired = should_expire_order(&user, 0, now).unwrap();
+        let is_expired = should_expire_order(&user, 0, now, 0).unwrap();
 
         assert!(!is_expired);
     }
@@ -363,7 +363,7 @@ mod should_expire_order {
 
         let now = 100;
 
-        let is_expired = should_expire_order(&user, 0, now).unwrap();
+        let is_expired = should_expire_order(&user, 0, now, 0).unwrap();
 
         assert!(!is_expired);
     }
@@ -382,7 +382,7 @@ mod should_expire_order {
 
         let now = 100;
 
-        let is_expired = should_expire_order(&user, 0, now).unwrap();
+        let is_expired = should_expire_order(&user, 0, now, 0).unwrap();
 
         assert!(is_expired);
     }
@@ -401,7 +401,7 @@ mod should_expire_order {
 
         let now = 100;
 
-        let is_expired = should_expire_order(&user, 0, now).unwrap();
+        let is_expired = should_expire_order(&user, 0, now, 0).unwrap();
 
         assert!(!is_expired);
     }
@@ -420,7 +420,7 @@ mod should_expire_order {
 
         let now = 100;
 
-        let is_expired = should_expire_order(&user, 0, now).unwrap();
+        let is_expired = should_expire_order(&user, 0, now, 0).unwrap();
 
         assert!(!is_expired);
     }
@@ -439,10 +439,33 @@ mod should_expire_order {
 
         let now = 100;
 
-        let is_expired = should_expire_order(&user, 0, now).unwrap();
+        let is_expired = should_expire_order(&user, 0, now, 0).unwrap();
 
         assert!(!is_expired);
     }
+
+    #[test]
+    fn good_til_slot() {
+        let user = User {
+            orders: get_orders(Order {
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                max_ts: 200,
+                time_in_force: TimeInForce::GoodTilSlot,
+                ..Order::default()
+            }),
+            ..User::default()
+        };
+
+        // max_ts is compared against the slot, not the timestamp
+        let now = 1000;
+
+        let is_expired = should_expire_order(&user, 0, now, 200).unwrap();
+        assert!(!is_expired);
+
+        let is_expired = should_expire_order(&user, 0, now, 201).unwrap();
+        assert!(is_expired);
+    }
 }
 
 mod get_max_fill_amounts {
@@ -3861,3 +3884,122 @@ mod fallback_price_logic {
         assert_eq!(result, 99500000);
     }
 }
+
+mod calculate_trailing_stop_trigger_price {
+    use crate::math::constants::{PERCENTAGE_PRECISION_U64, PRICE_PRECISION_U64};
+    use crate::math::orders::calculate_trailing_stop_trigger_price;
+    use crate::state::user::{Order, OrderBitFlag, OrderTriggerCondition, OrderType};
+    use crate::PositionDirection;
+
+    #[test]
+    fn price_offset() {
+        let mut order = Order {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_price: 95 * PRICE_PRECISION_U64,
+            oracle_price_offset: 5 * PRICE_PRECISION_U64 as i32,
+            bit_flags: OrderBitFlag::TrailingStop as u8,
+            ..Order::default()
+        };
+
+        // oracle rallies, stop follows
+        let trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 110 * PRICE_PRECISION_U64, 1).unwrap();
+        assert_eq!(trigger_price, Some(105 * PRICE_PRECISION_U64));
+
+        // oracle falls, stop stays put
+        let trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 99 * PRICE_PRECISION_U64, 1).unwrap();
+        assert_eq!(trigger_price, None);
+
+        order.direction = PositionDirection::Long;
+        order.trigger_condition = OrderTriggerCondition::Above;
+        order.trigger_price = 105 * PRICE_PRECISION_U64;
+
+        let trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 90 * PRICE_PRECISION_U64, 1).unwrap();
+        assert_eq!(trigger_price, Some(95 * PRICE_PRECISION_U64));
+
+        let trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 101 * PRICE_PRECISION_U64, 1).unwrap();
+        assert_eq!(trigger_price, None);
+
+        // triggered orders no longer trail
+        order.trigger_condition = OrderTriggerCondition::TriggeredAbove;
+        let trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 90 * PRICE_PRECISION_U64, 1).unwrap();
+        assert_eq!(trigger_price, None);
+    }
+
+    #[test]
+    fn percentage_offset() {
+        let order = Order {
+            order_type: OrderType::TriggerMarket,
+            direction: PositionDirection::Short,
+            trigger_condition: OrderTriggerCondition::Below,
+            trigger_price: 0,
+            oracle_price_offset: (PERCENTAGE_PRECISION_U64 / 10) as i32,
+            bit_flags: OrderBitFlag::TrailingStop as u8
+                | OrderBitFlag::TrailingStopPercentage as u8,
+            ..Order::default()
+        };
+
+        // 10% below 200
+        let trigger_price =
+            calculate_trailing_stop_trigger_price(&order, 200 * PRICE_PRECISION_U64, 1).unwrap();
+        assert_eq!(trigger_price, Some(180 * PRICE_PRECISION_U64));
+    }
+}
+
+mod validate_fill_requirement {
+    use crate::error::ErrorCode;
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::math::orders::validate_fill_requirement;
+
+    #[test]
+    fn fill_or_kill() {
+        let order_size = 2 * BASE_PRECISION_U64;
+
+        assert_eq!(
+            validate_fill_requirement(order_size, order_size, order_size),
+            Ok(())
+        );
+        assert_eq!(
+            validate_fill_requirement(order_size, order_size, BASE_PRECISION_U64),
+            Err(ErrorCode::FillOrKillOrderNotFilled)
+        );
+        // no fill is left to be canceled
+        assert_eq!(validate_fill_requirement(order_size, order_size, 0), Ok(()));
+    }
+
+    #[test]
+    fn minimum_fill() {
+        let min_fill = BASE_PRECISION_U64;
+
+        assert_eq!(
+            validate_fill_requirement(min_fill, 2 * BASE_PRECISION_U64, min_fill),
+            Ok(())
+        );
+        assert_eq!(
+            validate_fill_requirement(min_fill, 2 * BASE_PRECISION_U64, 2 * min_fill),
+            Ok(())
+        );
+        assert_eq!(
+            validate_fill_requirement(min_fill, 2 * BASE_PRECISION_U64, min_fill / 2),
+            Err(ErrorCode::MinimumFillNotMet)
+        );
+        assert_eq!(
+            validate_fill_requirement(min_fill, 2 * BASE_PRECISION_U64, 0),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn no_fill_requirement() {
+        assert_eq!(
+            validate_fill_requirement(0, BASE_PRECISION_U64, BASE_PRECISION_U64 / 2),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use anchor_lang::prelude::Pubkey;
+use std::cell::Ref;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::{
+    AMM_RESERVE_PRECISION_I128, MARGIN_PRECISION_I128, MARGIN_PRECISION_U128, PERCENTAGE_PRECISION,
+    PERCENTAGE_PRECISION_I128, PORTFOLIO_MARGIN_GROSS_CHARGE, PORTFOLIO_MARGIN_PRICE_SHOCKS,
+    SPOT_WEIGHT_PRECISION,
+};
+use crate::math::funding::calculate_funding_payment;
+use crate::math::margin::{calculate_prediction_market_margin_requirement, MarginRequirementType};
+use crate::math::options::calculate_option_margin_requirement;
+use crate::math::safe_math::SafeMath;
+use crate::math::spot_balance::{get_strict_token_value, get_token_value};
+use crate::state::load_ref::load_ref;
+use crate::state::option_market::OptionMarket;
+use crate::state::oracle::{OracleSource, StrictOraclePrice};
+use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market::MarketStatus;
+use crate::state::perp_market_map::PerpMarketMap;
+use crate::state::spot_market::SpotMarket;
+use crate::state::spot_market_map::SpotMarketMap;
+use crate::state::user::User;
+
+#[cfg(test)]
+mod tests;
+
+/// The positions a user holds on a single underlying, keyed by the oracle that prices it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnderlyingExposure {
+    pub oracle: Pubkey,
+    /// signed value of the positions assuming the worst case side of open orders fill
+    /// precision: QUOTE_PRECISION
+    pub net_value: i128,
+    /// precision: QUOTE_PRECISION
+    pub gross_value: u128,
+    /// largest price move the underlying is stressed by
+    /// precision: MARGIN_PRECISION
+    pub shock_size: u32,
+}
+
+impl UnderlyingExposure {
+    pub fn new(oracle: Pubkey) -> Self {
+        Self {
+            oracle,
+            ..Self::default()
+        }
+    }
+
+    pub fn add_position(&mut self, value: i128, shock_size: u32) -> DriftResult {
+        self.net_value = self.net_value.safe_add(value)?;
+        self.gross_value = self.gross_value.safe_add(value.unsigned_abs())?;
+        self.shock_size = self.shock_size.max(shock_size);
+        Ok(())
+    }
+
+    /// pnl of the positions if the underlying price moves by shock (a fraction of shock_size)
+    pub fn calculate_shocked_pnl(&self, shock: i128) -> DriftResult<i128> {
+        self.net_value
+            .safe_mul(self.shock_size.cast()?)?
+            .safe_mul(shock)?
+            .safe_div_floor(MARGIN_PRECISION_I128.safe_mul(PERCENTAGE_PRECISION_I128)?)
+    }
+
+    pub fn calculate_worst_case_loss(&self) -> DriftResult<u128> {
+        let mut worst_case_loss = 0_u128;
+        for shock in PORTFOLIO_MARGIN_PRICE_SHOCKS {
+            let pnl = self.calculate_shocked_pnl(shock)?;
+            if pnl < 0 {
+                worst_case_loss = worst_case_loss.max(pnl.unsigned_abs());
+            }
+        }
+
+        Ok(worst_case_loss)
+    }
+
+    pub fn calculate_margin_requirement(&self) -> DriftResult<u128> {
+        let gross_charge = self
+            .gross_value
+            .safe_mul(self.shock_size.cast()?)?
+            .safe_mul(PORTFOLIO_MARGIN_GROSS_CHARGE)?
+            .safe_div(MARGIN_PRECISION_U128.safe_mul(PERCENTAGE_PRECISION)?)?;
+
+        Ok(self.calculate_worst_case_loss()?.max(gross_charge))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PortfolioMarginCalculation {
+    /// precision: QUOTE_PRECISION
+    pub total_collateral: i128,
+    /// precision: QUOTE_PRECISION
+    pub margin_requirement: u128,
+    /// gross value of the stressed positions, used to size the liquidation buffer
+    /// precision: QUOTE_PRECISION
+    pub liability_value: u128,
+}
+
+/// Stress tests the user's cross margin account across PORTFOLIO_MARGIN_PRICE_SHOCKS for every underlying.
+/// Spot and perp positions priced by the same oracle offset each other, so a delta neutral
+/// book only pays the gross charge. Collateral is the account's equity at oracle prices, with
+/// positive perp pnl weighted the same way as in standard mode. If strict, positions are also
+/// valued against the 5 min oracle twap, whichever price is worse for the user
+pub fn calculate_portfolio_margin(
+    user: &User,
+    perp_market_map: &PerpMarketMap,
+    spot_market_map: &SpotMarketMap,
+    oracle_map: &mut OracleMap,
+    margin_type: MarginRequirementType,
+    strict: bool,
+) -> DriftResult<PortfolioMarginCalculation> {
+    let user_custom_margin_ratio = if margin_type == MarginRequirementType::Initial {
+        user.max_margin_ratio
+    } else {
+        0_u32
+    };
+
+    let mut total_collateral = 0_i128;
+    let mut open_orders_margin_requirement = 0_u128;
+    let mut unhedged_margin_requirement = 0_u128;
+    let mut exposures: Vec<UnderlyingExposure> =
+        Vec::with_capacity(user.spot_positions.len() + user.perp_positions.len());
+
+    for spot_position in user.spot_positions.iter() {
+        if spot_position.is_available() {
+            continue;
+        }
+
+        let spot_market = spot_market_map.get_ref(&spot_position.market_index)?;
+        let strict_oracle_price = StrictOraclePrice::new(
+            oracle_map.get_price_data(spot_market.oracle_id())?.price,
+            spot_market
+                .historical_oracle_data
+                .last_oracle_price_twap_5min,
+            strict,
+        );
+        strict_oracle_price.validate()?;
+
+        let signed_token_amount = spot_position.get_signed_token_amount(&spot_market)?;
+        total_collateral = total_collateral.safe_add(get_strict_token_value(
+            signed_token_amount,
+            spot_market.decimals,
+            &strict_oracle_price,
+        )?)?;
+
+        if spot_market.oracle_source == OracleSource::QuoteAsset {
+            continue;
+        }
+
+        let token_amount_all_bids_fill =
+            signed_token_amount.safe_add(spot_position.open_bids.cast()?)?;
+        let token_amount_all_asks_fill =
+            signed_token_amount.safe_add(spot_position.open_asks.cast()?)?;
+        let worst_case_token_amount = if token_amount_all_bids_fill.unsigned_abs()
+            > token_amount_all_asks_fill.unsigned_abs()
+        {
+            token_amount_all_bids_fill
+        } else {
+            token_amount_all_asks_fill
+        };
+
+        // the exposure is stressed at the higher price so it is never understated
+        let worst_case_value = get_token_value(
+            worst_case_token_amount,
+            spot_market.decimals,
+            strict_oracle_price.max(),
+        )?;
+
+        get_underlying_exposure(&mut exposures, spot_market.oracle).add_position(
+            worst_case_value,
+            calculate_spot_shock_size(&spot_market, margin_type),
+        )?;
+
+        open_orders_margin_requirement = open_orders_margin_requirement
+            .safe_add(spot_position.margin_requirement_for_open_orders()?)?;
+    }
+
+    for (position_index, perp_position) in user.perp_positions.iter().enumerate() {
+        if perp_position.is_available() || user.is_isolated_perp_position(position_index) {
+            continue;
+        }
+
+        let perp_market = perp_market_map.get_ref(&perp_position.market_index)?;
+        let strict_oracle_price = StrictOraclePrice::new(
+            oracle_map.get_price_data(perp_market.oracle_id())?.price,
+            perp_market
+                .amm
+                .historical_oracle_data
+                .last_oracle_price_twap_5min,
+            strict,
+        );
+        // longs are valued at the lower price and shorts at the higher
+        let oracle_price = if perp_position.base_asset_amount > 0 {
+            strict_oracle_price.min()
+        } else {
+            strict_oracle_price.max()
+        };
+        let valuation_price = perp_market.get_margin_valuation_price(oracle_price)?;
+
+        let unrealized_funding = calculate_funding_payment(
+            if perp_position.base_asset_amount > 0 {
+                perp_market.amm.cumulative_funding_rate_long
+            } else {
+                perp_market.amm.cumulative_funding_rate_short
+            },
+            perp_position,
+        )?;
+
+        let unrealized_pnl = perp_position
+            .get_unrealized_pnl(valuation_price)?
+            .safe_add(unrealized_funding.cast()?)?;
+
+        let unrealized_asset_weight =
+            perp_market.get_unrealized_asset_weight(unrealized_pnl, margin_type)?;
+
+        total_collateral = total_collateral.safe_add(
+            unrealized_pnl
+                .safe_mul(unrealized_asset_weight.cast()?)?
+                .safe_div(SPOT_WEIGHT_PRECISION.cast()?)?,
+        )?;
+
+        open_orders_margin_requirement = open_orders_margin_requirement
+            .safe_add(perp_position.margin_requirement_for_open_orders()?)?;
+
+        if perp_market.status == MarketStatus::Settlement {
+            continue;
+        }
+
+        let worst_case_base_asset_amount = perp_position.worst_case_base_asset_amount()?;
+        let worst_case_value = worst_case_base_asset_amount
+            .safe_mul(valuation_price.cast()?)?
+            .safe_div(AMM_RESERVE_PRECISION_I128)?;
+
+        let shock_size = user_custom_margin_ratio.max(
+            perp_market
+                .get_margin_ratio(worst_case_base_asset_amount.unsigned_abs(), margin_type)?,
+        );
+
+        // prediction markets don't track an underlying, they carry their max loss
+        if perp_market.is_prediction_market() {
+            unhedged_margin_requirement = unhedged_margin_requirement.safe_add(
+                calculate_prediction_market_margin_requirement(
+                    worst_case_base_asset_amount,
+                    valuation_price,
+                )?,
+            )?;
+
+            continue;
+        }
+
+        // options net against their underlying through their delta, their vega isn't hedged
+        if perp_market.is_option() {
+            let option_market_account_info =
+                oracle_map.get_account_info(&perp_market.amm.oracle)?;
+            let option_market: Ref<OptionMarket> =
+                load_ref(&option_market_account_info).or(Err(ErrorCode::UnableToLoadOracle))?;
+
+            let delta_value = worst_case_base_asset_amount
+                .safe_mul(option_market.delta.cast()?)?
+                .safe_div(PERCENTAGE_PRECISION_I128)?
+                .safe_mul(option_market.underlying_price.cast()?)?
+                .safe_div(AMM_RESERVE_PRECISION_I128)?;
+
+            get_underlying_exposure(&mut exposures, option_market.underlying_oracle)
+                .add_position(delta_value, shock_size)?;
+
+            unhedged_margin_requirement =
+                unhedged_margin_requirement.safe_add(calculate_option_margin_requirement(
+                    &option_market,
+                    worst_case_base_asset_amount,
+                    0,
+                )?)?;
+
+            continue;
+        }
+
+        get_underlying_exposure(&mut exposures, perp_market.amm.oracle)
+            .add_position(worst_case_value, shock_size)?;
+
+        // the basis isn't hedged by other positions on the underlying
+        unhedged_margin_requirement = unhedged_margin_requirement.safe_add(
+            perp_market.get_basis_margin_requirement(worst_case_base_asset_amount, oracle_price)?,
+        )?;
+    }
+
+    let mut margin_requirement =
+        open_orders_margin_requirement.safe_add(unhedged_margin_requirement)?;
+    let mut liability_value = 0_u128;
+    for exposure in exposures.iter() {
+        margin_requirement =
+            margin_requirement.safe_add(exposure.calculate_margin_requirement()?)?;
+        liability_value = liability_value.safe_add(exposure.gross_value)?;
+    }
+
+    Ok(PortfolioMarginCalculation {
+        total_collateral,
+        margin_requirement,
+        liability_value,
+    })
+}
+
+fn get_underlying_exposure(
+    exposures: &mut Vec<UnderlyingExposure>,
+    oracle: Pubkey,
+) -> &mut UnderlyingExposure {
+    let index = match exposures
+        .iter()
+        .position(|exposure| exposure.oracle == oracle)
+    {
+        Some(index) => index,
+        None => {
+            exposures.push(UnderlyingExposure::new(oracle));
+            exposures.len() - 1
+        }
+    };
+
+    &mut exposures[index]
+}
+
+pub fn calculate_spot_shock_size(
+    spot_market: &SpotMarket,
+    margin_type: MarginRequirementType,
+) -> u32 {
+    let (asset_weight, liability_weight) = match margin_type {
+        MarginRequirementType::Initial | MarginRequirementType::Fill => (
+            spot_market.initial_asset_weight,
+            spot_market.initial_liability_weight,
+        ),
+        MarginRequirementType::Maintenance => (
+            spot_market.maintenance_asset_weight,
+            spot_market.maintenance_liability_weight,
+        ),
+    };
+
+    SPOT_WEIGHT_PRECISION
+        .saturating_sub(asset_weight)
+        .max(liability_weight.saturating_sub(SPOT_WEIGHT_PRECISION))
+}
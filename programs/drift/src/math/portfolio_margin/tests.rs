@@ -0,0 +1,259 @@
+mod underlying_exposure {
+    use anchor_lang::prelude::Pubkey;
+
+    use crate::math::constants::QUOTE_PRECISION_I128;
+    use crate::math::portfolio_margin::UnderlyingExposure;
+
+    #[test]
+    fn short_exposure() {
+        let mut exposure = UnderlyingExposure::new(Pubkey::default());
+        exposure
+            .add_position(-100 * QUOTE_PRECISION_I128, 1000)
+            .unwrap();
+
+        // 10% shock up loses $10
+        assert_eq!(exposure.calculate_worst_case_loss().unwrap(), 10000000);
+        assert_eq!(exposure.calculate_margin_requirement().unwrap(), 10000000);
+    }
+
+    #[test]
+    fn hedged_exposure() {
+        let mut exposure = UnderlyingExposure::new(Pubkey::default());
+        exposure
+            .add_position(100 * QUOTE_PRECISION_I128, 2000)
+            .unwrap();
+        exposure
+            .add_position(-100 * QUOTE_PRECISION_I128, 1000)
+            .unwrap();
+
+        assert_eq!(exposure.net_value, 0);
+        assert_eq!(exposure.gross_value, 200000000);
+        assert_eq!(exposure.shock_size, 2000);
+
+        // no loss on any shock, only the gross charge of 10% of a 20% shock on $200
+        assert_eq!(exposure.calculate_worst_case_loss().unwrap(), 0);
+        assert_eq!(exposure.calculate_margin_requirement().unwrap(), 4000000);
+    }
+}
+
+mod calculate_portfolio_margin {
+    use std::str::FromStr;
+
+    use anchor_lang::Owner;
+    use solana_program::pubkey::Pubkey;
+
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I64, PEG_PRECISION, PRICE_PRECISION_I64,
+        QUOTE_PRECISION_I64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
+        SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_WEIGHT_PRECISION,
+    };
+    use crate::math::margin::{
+        calculate_margin_requirement_and_total_collateral_and_liability_info, MarginRequirementType,
+    };
+    use crate::math::portfolio_margin::{calculate_portfolio_margin, PortfolioMarginCalculation};
+    use crate::state::margin_calculation::MarginContext;
+    use crate::state::oracle::{HistoricalOracleData, OracleSource};
+    use crate::state::oracle_map::OracleMap;
+    use crate::state::perp_market::{MarketStatus, PerpMarket, AMM};
+    use crate::state::perp_market_map::PerpMarketMap;
+    use crate::state::spot_market::{SpotBalanceType, SpotMarket};
+    use crate::state::spot_market_map::SpotMarketMap;
+    use crate::state::user::{PerpPosition, SpotPosition, User, UserStatus};
+    use crate::test_utils::*;
+    use crate::test_utils::{get_positions, get_pyth_price};
+    use crate::{create_account_info, create_anchor_account_info};
+
+    #[test]
+    fn spot_hedged_with_perp() {
+        let slot = 0_u64;
+
+        let mut sol_oracle_price = get_pyth_price(100, 6);
+        let sol_oracle_price_key =
+            Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let pyth_program = crate::ids::pyth_program::id();
+        create_account_info!(
+            sol_oracle_price,
+            &sol_oracle_price_key,
+            &pyth_program,
+            sol_oracle_account_info
+        );
+        let mut oracle_map = OracleMap::load_one(&sol_oracle_account_info, slot, None).unwrap();
+
+        let mut usdc_spot_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 6,
+            initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            deposit_balance: 10000 * SPOT_BALANCE_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(QUOTE_PRECISION_I64),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(usdc_spot_market, SpotMarket, usdc_spot_market_account_info);
+        let mut sol_spot_market = SpotMarket {
+            market_index: 1,
+            oracle_source: OracleSource::Pyth,
+            oracle: sol_oracle_price_key,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION,
+            decimals: 9,
+            initial_asset_weight: 8 * SPOT_WEIGHT_PRECISION / 10,
+            maintenance_asset_weight: 9 * SPOT_WEIGHT_PRECISION / 10,
+            initial_liability_weight: 12 * SPOT_WEIGHT_PRECISION / 10,
+            maintenance_liability_weight: 11 * SPOT_WEIGHT_PRECISION / 10,
+            deposit_balance: 10 * SPOT_BALANCE_PRECISION,
+            historical_oracle_data: HistoricalOracleData::default_price(sol_oracle_price.agg.price),
+            ..SpotMarket::default()
+        };
+        create_anchor_account_info!(sol_spot_market, SpotMarket, sol_spot_market_account_info);
+        let spot_market_account_infos = Vec::from([
+            &usdc_spot_market_account_info,
+            &sol_spot_market_account_info,
+        ]);
+        let spot_market_map =
+            SpotMarketMap::load_multiple(spot_market_account_infos, true).unwrap();
+
+        let mut market = PerpMarket {
+            amm: AMM {
+                base_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                quote_asset_reserve: 100 * AMM_RESERVE_PRECISION,
+                bid_base_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                bid_quote_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_base_asset_reserve: 99 * AMM_RESERVE_PRECISION,
+                ask_quote_asset_reserve: 101 * AMM_RESERVE_PRECISION,
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: 100 * PEG_PRECISION,
+                order_step_size: 10000000,
+                oracle: sol_oracle_price_key,
+                ..AMM::default()
+            },
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            status: MarketStatus::Active,
+            ..PerpMarket::default()
+        };
+        create_anchor_account_info!(market, PerpMarket, market_account_info);
+        let perp_market_map = PerpMarketMap::load_one(&market_account_info, true).unwrap();
+
+        let mut spot_positions = [SpotPosition::default(); 8];
+        spot_positions[0] = SpotPosition {
+            market_index: 0,
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: 10 * SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        };
+        spot_positions[1] = SpotPosition {
+            market_index: 1,
+            balance_type: SpotBalanceType::Deposit,
+            scaled_balance: SPOT_BALANCE_PRECISION_U64,
+            ..SpotPosition::default()
+        };
+        let mut user = User {
+            perp_positions: get_positions(PerpPosition {
+                market_index: 0,
+                base_asset_amount: -BASE_PRECISION_I64,
+                quote_asset_amount: 100 * QUOTE_PRECISION_I64,
+                quote_entry_amount: 100 * QUOTE_PRECISION_I64,
+                ..PerpPosition::default()
+            }),
+            spot_positions,
+            ..User::default()
+        };
+
+        let portfolio_margin = calculate_portfolio_margin(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginRequirementType::Initial,
+            false,
+        )
+        .unwrap();
+
+        // long sol spot offsets the short sol perp
+        assert_eq!(
+            portfolio_margin,
+            PortfolioMarginCalculation {
+                total_collateral: 110000000,
+                margin_requirement: 4000000,
+                liability_value: 200000000,
+            }
+        );
+
+        let calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::standard(MarginRequirementType::Initial),
+        )
+        .unwrap();
+
+        // position by position requirement gives no offset
+        assert_eq!(calculation.margin_requirement, 10000000);
+        assert_eq!(calculation.total_collateral, 90000000);
+
+        // strict values the long sol spot at its lower 5 min twap
+        spot_market_map
+            .get_ref_mut(&1)
+            .unwrap()
+            .historical_oracle_data
+            .last_oracle_price_twap_5min = 90 * PRICE_PRECISION_I64;
+
+        let portfolio_margin = calculate_portfolio_margin(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginRequirementType::Initial,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            portfolio_margin,
+            PortfolioMarginCalculation {
+                total_collateral: 100000000,
+                margin_requirement: 4000000,
+                liability_value: 200000000,
+            }
+        );
+
+        spot_market_map
+            .get_ref_mut(&1)
+            .unwrap()
+            .historical_oracle_data
+            .last_oracle_price_twap_5min = sol_oracle_price.agg.price;
+
+        user.status = UserStatus::PortfolioMargin as u8;
+
+        let calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::standard(MarginRequirementType::Initial),
+        )
+        .unwrap();
+
+        assert!(calculation.is_portfolio_mode());
+        assert_eq!(calculation.margin_requirement, 4000000);
+        assert_eq!(calculation.total_collateral, 110000000);
+
+        // unhedged short perp pays for the full shock
+        user.spot_positions[1] = SpotPosition::default();
+
+        let calculation = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user,
+            &perp_market_map,
+            &spot_market_map,
+            &mut oracle_map,
+            MarginContext::portfolio(MarginRequirementType::Initial),
+        )
+        .unwrap();
+
+        assert_eq!(calculation.margin_requirement, 10000000);
+        assert_eq!(calculation.total_collateral, 10000000);
+    }
+}
@@ -41,7 +41,9 @@ pub fn calculate_repeg_validity_from_oracle_account(
         market.market_index,
         market.amm.historical_oracle_data.last_oracle_price_twap,
         &oracle_price_data,
-        &oracle_guard_rails.validity,
+        &oracle_guard_rails
+            .validity
+            .with_override(market.get_validity_guard_rails_override()),
         market.get_max_confidence_interval_multiplier()?,
         true,
     )? == OracleValidity::Valid;
@@ -72,3 +72,32 @@ pub fn calculate_new_twap(
 
     calculate_weighted_average(current_price, last_twap, since_last, from_start)
 }
+
+/// Time weighted average of the prices sampled between window_start_ts and window_end_ts. Returns
+/// the new twap and its ts, None if now is before the window or the window was already sampled to
+/// its end
+pub fn calculate_window_twap(
+    price: i64,
+    now: i64,
+    last_twap: i64,
+    last_ts: i64,
+    window_start_ts: i64,
+    window_end_ts: i64,
+) -> DriftResult<Option<(i64, i64)>> {
+    if now < window_start_ts || last_ts >= window_end_ts {
+        return Ok(None);
+    }
+
+    let now = now.min(window_end_ts);
+
+    let twap = if last_ts < window_start_ts {
+        price
+    } else {
+        let since_last = now.safe_sub(last_ts)?;
+        let since_start = last_ts.safe_sub(window_start_ts)?;
+
+        calculate_weighted_average(last_twap, price, since_start, since_last)?
+    };
+
+    Ok(Some((twap, now)))
+}
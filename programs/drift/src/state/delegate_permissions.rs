@@ -0,0 +1,196 @@
+use anchor_lang::prelude::*;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::state::traits::Size;
+use crate::state::user::MarketType;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+pub const MAX_DELEGATE_MARKET_INDEXES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum DelegateAction {
+    PlaceOrder = 0b00000001,
+    CancelOrder = 0b00000010,
+}
+
+impl DelegateAction {
+    pub fn is_action_allowed(current: u8, action: DelegateAction) -> bool {
+        current & action as u8 != 0
+    }
+}
+
+/// What a delegate that isn't the user's `delegate` can do for the user. A user can have one
+/// per delegate, each passed after the market accounts of the instructions it signs
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct DelegatePermissions {
+    /// The user account the delegate can act for
+    pub user: Pubkey,
+    pub delegate: Pubkey,
+    /// unix_timestamp the permissions stop working at, 0 if they don't expire
+    pub expiry_ts: i64,
+    /// The max notional of an order the delegate can place, 0 if there's no max
+    /// precision: QUOTE_PRECISION
+    pub max_order_notional: u64,
+    /// The perp markets the delegate can place and cancel orders in, all of them if there are none
+    pub perp_market_indexes: [u16; MAX_DELEGATE_MARKET_INDEXES],
+    /// The spot markets the delegate can place and cancel orders in, all of them if there are none
+    pub spot_market_indexes: [u16; MAX_DELEGATE_MARKET_INDEXES],
+    pub num_perp_market_indexes: u8,
+    pub num_spot_market_indexes: u8,
+    /// Bit flags of the DelegateActions the delegate can take
+    pub allowed_actions: u8,
+    /// Whether the orders the delegate places must be reduce only
+    pub reduce_only: bool,
+    pub padding: [u8; 12],
+}
+
+impl Size for DelegatePermissions {
+    const SIZE: usize = 128 + 8;
+}
+
+impl DelegatePermissions {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry_ts != 0 && now >= self.expiry_ts
+    }
+
+    pub fn get_market_indexes(&self, market_type: MarketType) -> &[u16] {
+        match market_type {
+            MarketType::Perp => &self.perp_market_indexes[..self.num_perp_market_indexes as usize],
+            MarketType::Spot => &self.spot_market_indexes[..self.num_spot_market_indexes as usize],
+        }
+    }
+
+    pub fn is_market_allowed(&self, market_type: MarketType, market_index: u16) -> bool {
+        let market_indexes = self.get_market_indexes(market_type);
+        market_indexes.is_empty() || market_indexes.contains(&market_index)
+    }
+
+    pub fn validate_action(&self, action: DelegateAction, now: i64) -> DriftResult {
+        validate!(
+            !self.is_expired(now),
+            ErrorCode::DelegateActionNotAllowed,
+            "delegate permissions expired at {}",
+            self.expiry_ts
+        )?;
+
+        validate!(
+            DelegateAction::is_action_allowed(self.allowed_actions, action),
+            ErrorCode::DelegateActionNotAllowed,
+            "delegate can't {:?}",
+            action
+        )?;
+
+        Ok(())
+    }
+
+    pub fn validate_order(
+        &self,
+        market_type: MarketType,
+        market_index: u16,
+        reduce_only: bool,
+        order_notional: u64,
+        now: i64,
+    ) -> DriftResult {
+        self.validate_action(DelegateAction::PlaceOrder, now)?;
+
+        validate!(
+            self.is_market_allowed(market_type, market_index),
+            ErrorCode::DelegateActionNotAllowed,
+            "delegate can't place orders in {:?} market {}",
+            market_type,
+            market_index
+        )?;
+
+        validate!(
+            !self.reduce_only || reduce_only,
+            ErrorCode::DelegateActionNotAllowed,
+            "delegate can only place reduce only orders"
+        )?;
+
+        validate!(
+            self.max_order_notional == 0 || order_notional <= self.max_order_notional,
+            ErrorCode::DelegateActionNotAllowed,
+            "order notional {} > delegate max order notional {}",
+            order_notional,
+            self.max_order_notional
+        )?;
+
+        Ok(())
+    }
+
+    pub fn validate_cancel(
+        &self,
+        market_type: MarketType,
+        market_index: u16,
+        now: i64,
+    ) -> DriftResult {
+        self.validate_action(DelegateAction::CancelOrder, now)?;
+
+        validate!(
+            self.is_market_allowed(market_type, market_index),
+            ErrorCode::DelegateActionNotAllowed,
+            "delegate can't cancel orders in {:?} market {}",
+            market_type,
+            market_index
+        )?;
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, params: DelegatePermissionsParams) -> DriftResult {
+        validate!(
+            params.allowed_actions != 0
+                && params.allowed_actions
+                    & !(DelegateAction::PlaceOrder as u8 | DelegateAction::CancelOrder as u8)
+                    == 0,
+            ErrorCode::InvalidDelegatePermissions,
+            "invalid allowed actions {}",
+            params.allowed_actions
+        )?;
+
+        validate!(
+            params.perp_market_indexes.len() <= MAX_DELEGATE_MARKET_INDEXES
+                && params.spot_market_indexes.len() <= MAX_DELEGATE_MARKET_INDEXES,
+            ErrorCode::InvalidDelegatePermissions,
+            "delegates can be limited to at most {} markets of each type",
+            MAX_DELEGATE_MARKET_INDEXES
+        )?;
+
+        self.expiry_ts = params.expiry_ts;
+        self.max_order_notional = params.max_order_notional;
+        self.allowed_actions = params.allowed_actions;
+        self.reduce_only = params.reduce_only;
+
+        self.perp_market_indexes = [0; MAX_DELEGATE_MARKET_INDEXES];
+        self.perp_market_indexes[..params.perp_market_indexes.len()]
+            .copy_from_slice(&params.perp_market_indexes);
+        self.num_perp_market_indexes = params.perp_market_indexes.len() as u8;
+
+        self.spot_market_indexes = [0; MAX_DELEGATE_MARKET_INDEXES];
+        self.spot_market_indexes[..params.spot_market_indexes.len()]
+            .copy_from_slice(&params.spot_market_indexes);
+        self.num_spot_market_indexes = params.spot_market_indexes.len() as u8;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, AnchorSerialize, AnchorDeserialize, PartialEq, Eq)]
+pub struct DelegatePermissionsParams {
+    /// 0 if the permissions don't expire
+    pub expiry_ts: i64,
+    /// precision: QUOTE_PRECISION, 0 if there's no max
+    pub max_order_notional: u64,
+    /// Bit flags of DelegateActions
+    pub allowed_actions: u8,
+    pub reduce_only: bool,
+    /// Empty to allow every perp market
+    pub perp_market_indexes: Vec<u16>,
+    /// Empty to allow every spot market
+    pub spot_market_indexes: Vec<u16>,
+}
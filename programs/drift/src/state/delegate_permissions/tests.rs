@@ -0,0 +1,184 @@
+use crate::math::constants::QUOTE_PRECISION_U64;
+use crate::state::delegate_permissions::{
+    DelegateAction, DelegatePermissions, DelegatePermissionsParams,
+};
+use crate::state::user::MarketType;
+
+fn delegate_permissions(params: DelegatePermissionsParams) -> DelegatePermissions {
+    let mut delegate_permissions = DelegatePermissions::default();
+    delegate_permissions.update(params).unwrap();
+    delegate_permissions
+}
+
+#[test]
+fn validate_action() {
+    let delegate_permissions = delegate_permissions(DelegatePermissionsParams {
+        expiry_ts: 100,
+        max_order_notional: 0,
+        allowed_actions: DelegateAction::CancelOrder as u8,
+        reduce_only: false,
+        perp_market_indexes: vec![],
+        spot_market_indexes: vec![],
+    });
+
+    assert!(delegate_permissions
+        .validate_action(DelegateAction::CancelOrder, 99)
+        .is_ok());
+    assert!(delegate_permissions
+        .validate_action(DelegateAction::PlaceOrder, 99)
+        .is_err());
+
+    // expired
+    assert!(delegate_permissions
+        .validate_action(DelegateAction::CancelOrder, 100)
+        .is_err());
+
+    let delegate_permissions = DelegatePermissions {
+        expiry_ts: 0,
+        ..delegate_permissions
+    };
+    assert!(delegate_permissions
+        .validate_action(DelegateAction::CancelOrder, i64::MAX)
+        .is_ok());
+}
+
+#[test]
+fn validate_order() {
+    let delegate_permissions = delegate_permissions(DelegatePermissionsParams {
+        expiry_ts: 0,
+        max_order_notional: 1000 * QUOTE_PRECISION_U64,
+        allowed_actions: DelegateAction::PlaceOrder as u8 | DelegateAction::CancelOrder as u8,
+        reduce_only: false,
+        perp_market_indexes: vec![0, 2],
+        spot_market_indexes: vec![],
+    });
+
+    assert!(delegate_permissions
+        .validate_order(MarketType::Perp, 2, false, 1000 * QUOTE_PRECISION_U64, 0)
+        .is_ok());
+
+    // market not in the allowlist
+    assert!(delegate_permissions
+        .validate_order(MarketType::Perp, 1, false, QUOTE_PRECISION_U64, 0)
+        .is_err());
+
+    // every spot market is allowed
+    assert!(delegate_permissions
+        .validate_order(MarketType::Spot, 1, false, QUOTE_PRECISION_U64, 0)
+        .is_ok());
+
+    // above the max notional
+    assert!(delegate_permissions
+        .validate_order(
+            MarketType::Perp,
+            0,
+            false,
+            1000 * QUOTE_PRECISION_U64 + 1,
+            0
+        )
+        .is_err());
+
+    let delegate_permissions = DelegatePermissions {
+        reduce_only: true,
+        max_order_notional: 0,
+        ..delegate_permissions
+    };
+
+    assert!(delegate_permissions
+        .validate_order(MarketType::Perp, 0, false, QUOTE_PRECISION_U64, 0)
+        .is_err());
+    assert!(delegate_permissions
+        .validate_order(MarketType::Perp, 0, true, u64::MAX, 0)
+        .is_ok());
+}
+
+#[test]
+fn validate_cancel() {
+    let delegate_permissions = delegate_permissions(DelegatePermissionsParams {
+        expiry_ts: 0,
+        max_order_notional: 0,
+        allowed_actions: DelegateAction::CancelOrder as u8,
+        reduce_only: false,
+        perp_market_indexes: vec![0, 2],
+        spot_market_indexes: vec![],
+    });
+
+    assert!(delegate_permissions
+        .validate_cancel(MarketType::Perp, 2, 0)
+        .is_ok());
+
+    // market not in the allowlist
+    assert!(delegate_permissions
+        .validate_cancel(MarketType::Perp, 1, 0)
+        .is_err());
+
+    // every spot market is allowed
+    assert!(delegate_permissions
+        .validate_cancel(MarketType::Spot, 1, 0)
+        .is_ok());
+
+    let delegate_permissions = DelegatePermissions {
+        allowed_actions: DelegateAction::PlaceOrder as u8,
+        ..delegate_permissions
+    };
+
+    assert!(delegate_permissions
+        .validate_cancel(MarketType::Perp, 0, 0)
+        .is_err());
+}
+
+#[test]
+fn update() {
+    let params = DelegatePermissionsParams {
+        expiry_ts: 0,
+        max_order_notional: 0,
+        allowed_actions: DelegateAction::PlaceOrder as u8,
+        reduce_only: false,
+        perp_market_indexes: vec![1, 2, 3],
+        spot_market_indexes: vec![4],
+    };
+
+    let mut delegate_permissions = delegate_permissions(params.clone());
+    assert_eq!(
+        delegate_permissions.get_market_indexes(MarketType::Perp),
+        &[1, 2, 3]
+    );
+    assert_eq!(
+        delegate_permissions.get_market_indexes(MarketType::Spot),
+        &[4]
+    );
+
+    // shrinking the allowlist clears the old indexes
+    delegate_permissions
+        .update(DelegatePermissionsParams {
+            perp_market_indexes: vec![5],
+            ..params.clone()
+        })
+        .unwrap();
+    assert_eq!(
+        delegate_permissions.get_market_indexes(MarketType::Perp),
+        &[5]
+    );
+    assert_eq!(delegate_permissions.perp_market_indexes[1..], [0; 7]);
+
+    assert!(delegate_permissions
+        .update(DelegatePermissionsParams {
+            perp_market_indexes: vec![0; 9],
+            ..params.clone()
+        })
+        .is_err());
+
+    assert!(delegate_permissions
+        .update(DelegatePermissionsParams {
+            allowed_actions: 0,
+            ..params.clone()
+        })
+        .is_err());
+
+    assert!(delegate_permissions
+        .update(DelegatePermissionsParams {
+            allowed_actions: 0b100,
+            ..params
+        })
+        .is_err());
+}
@@ -6,6 +6,7 @@ use crate::error::{DriftResult, ErrorCode::InvalidOrder};
 use crate::math::casting::Cast;
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::traits::Size;
+use crate::state::twap_order::TwapOrder;
 use crate::state::user::{MarketType, Order};
 use anchor_lang::Discriminator;
 use std::io::Write;
@@ -212,6 +213,9 @@ pub struct OrderActionRecord {
     pub taker_order_cumulative_base_asset_amount_filled: Option<u64>,
     /// precision: QUOTE_PRECISION
     pub taker_order_cumulative_quote_asset_amount_filled: Option<u64>,
+    /// set for orders that must be triggered
+    /// precision: PRICE_PRECISION
+    pub taker_order_trigger_price: Option<u64>,
 
     pub maker: Option<Pubkey>,
     pub maker_order_id: Option<u32>,
@@ -228,7 +232,7 @@ pub struct OrderActionRecord {
 }
 
 impl Size for OrderActionRecord {
-    const SIZE: usize = 384;
+    const SIZE: usize = 400;
 }
 
 pub fn get_order_action_record(
@@ -289,6 +293,9 @@ pub fn get_order_action_record(
         taker_order_cumulative_quote_asset_amount_filled: taker_order
             .as_ref()
             .map(|order| order.quote_asset_amount_filled),
+        taker_order_trigger_price: taker_order
+            .filter(|order| order.must_be_triggered())
+            .map(|order| order.trigger_price),
         maker,
         maker_order_id: maker_order.map(|order| order.order_id),
         maker_order_direction: maker_order.map(|order| order.direction),
@@ -332,6 +339,12 @@ pub enum OrderActionExplanation {
     OrderFilledWithAMMJitLPSplit,
     OrderFilledWithLPJit,
     DeriskLp,
+    BracketParentCanceled,
+    BracketSiblingFilled,
+    BracketSiblingCanceled,
+    TrailingStopTriggered,
+    HeartbeatTimeout,
+    TrailingStopUpdated,
 }
 
 #[event]
@@ -535,6 +548,10 @@ pub enum StakeAction {
     Unstake,
     UnstakeTransfer,
     StakeTransfer,
+    Lock,
+    Unlock,
+    Wrap,
+    Unwrap,
 }
 
 #[event]
@@ -555,6 +572,100 @@ pub struct SwapRecord {
     pub fee: u64,
 }
 
+#[event]
+#[derive(Default)]
+pub struct IsolatedPerpPositionTransferRecord {
+    /// unix_timestamp of action
+    pub ts: i64,
+    /// user account public key
+    pub user: Pubkey,
+    pub perp_market_index: u16,
+    /// positive when moving collateral into the isolated position, negative when moving it out
+    /// precision: QUOTE_PRECISION
+    pub amount: i64,
+    /// precision: QUOTE_PRECISION
+    pub quote_asset_amount_after: i64,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Default)]
+pub enum TwapOrderAction {
+    #[default]
+    Place,
+    ReleaseSlice,
+    Cancel,
+}
+
+#[event]
+#[derive(Default)]
+pub struct TwapOrderRecord {
+    /// unix_timestamp of action
+    pub ts: i64,
+    /// user account public key
+    pub user: Pubkey,
+    pub action: TwapOrderAction,
+    /// twap order after the action. the released slice's order id is last_slice_order_id
+    pub twap_order: TwapOrder,
+    /// keeper that released the slice
+    pub filler: Option<Pubkey>,
+    /// precision: QUOTE_PRECISION
+    pub filler_reward: Option<u64>,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Default)]
+pub enum HeartbeatAction {
+    #[default]
+    UpdateTimeout,
+    CancelOrders,
+    Heartbeat,
+}
+
+#[event]
+#[derive(Default)]
+pub struct HeartbeatRecord {
+    /// unix_timestamp of action
+    pub ts: i64,
+    /// user account public key
+    pub user: Pubkey,
+    pub action: HeartbeatAction,
+    pub last_heartbeat_ts: i64,
+    /// 0 if the dead man's switch is disarmed
+    pub heartbeat_timeout: u16,
+    pub canceled_order_ids: Vec<u32>,
+    /// keeper that canceled the orders
+    pub filler: Option<Pubkey>,
+    /// precision: QUOTE_PRECISION
+    pub filler_reward: Option<u64>,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Default)]
+pub enum OracleCircuitBreakerAction {
+    #[default]
+    Trip,
+    Reset,
+}
+
+#[event]
+#[derive(Default)]
+pub struct OracleCircuitBreakerRecord {
+    /// unix_timestamp of action
+    pub ts: i64,
+    pub market_index: u16,
+    pub market_type: MarketType,
+    pub action: OracleCircuitBreakerAction,
+    /// precision: PRICE_PRECISION
+    pub oracle_price: i64,
+    /// the market's 5 min oracle twap the move is measured from. 0 on reset
+    /// precision: PRICE_PRECISION
+    pub oracle_price_twap_5min: i64,
+    /// oracle move from the 5 min oracle twap. 0 on reset
+    /// precision: PERCENTAGE_PRECISION
+    pub price_move: u64,
+    /// the operations the breaker paused on trip or unpaused on reset
+    pub paused_operations: u8,
+    /// 0 on reset
+    pub tripped_until_ts: i64,
+}
+
 pub fn emit_stack<T: AnchorSerialize + Discriminator, const N: usize>(event: T) -> DriftResult {
     let mut data_buf = [0u8; N];
     let mut out_buf = [0u8; N];
@@ -1,5 +1,10 @@
 use crate::error::DriftResult;
 use crate::error::ErrorCode;
+use crate::math::casting::Cast;
+use crate::math::constants::{
+    IF_BOOST_PRECISION, IF_TRANCHE_SHARE_FACTOR_PRECISION_U64, NINETY_DAY, ONE_HUNDRED_EIGHTY_DAY,
+    THIRTY_DAY,
+};
 use crate::math::safe_math::SafeMath;
 use crate::safe_decrement;
 use crate::safe_increment;
@@ -8,6 +13,7 @@ use crate::state::traits::Size;
 use crate::validate;
 use crate::{math_error, EPOCH_DURATION};
 use anchor_lang::prelude::*;
+use borsh::{BorshDeserialize, BorshSerialize};
 
 #[cfg(test)]
 mod tests;
@@ -25,7 +31,16 @@ pub struct InsuranceFundStake {
     pub last_withdraw_request_ts: i64,
     pub cost_basis: i64,
     pub market_index: u16,
-    pub padding: [u8; 14],
+    /// Locked stakes can't request removal until their InsuranceFundStakeLockup ends and is
+    /// unlocked
+    pub lockup_tier: InsuranceFundLockupTier,
+    pub tranche: InsuranceFundTranche,
+    /// The junior tranche's epoch when the stake was last updated. The stake's shares were wiped
+    /// if the tranche has moved on to a new epoch
+    pub junior_epoch: u32,
+    /// The junior tranche's share factor when the stake was last updated
+    /// precision: IF_TRANCHE_SHARE_FACTOR_PRECISION
+    pub junior_share_factor: u64,
 }
 
 // implement SIZE const for InsuranceFundStake
@@ -45,7 +60,10 @@ impl InsuranceFundStake {
             if_base: 0,
             last_valid_ts: now,
             if_shares: 0,
-            padding: [0; 14],
+            lockup_tier: InsuranceFundLockupTier::None,
+            tranche: InsuranceFundTranche::Senior,
+            junior_epoch: 0,
+            junior_share_factor: 0,
         }
     }
 
@@ -90,6 +108,156 @@ impl InsuranceFundStake {
     }
 }
 
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum InsuranceFundTranche {
+    /// Only absorbs losses once the junior tranche is exhausted
+    #[default]
+    Senior,
+    /// Absorbs losses first in exchange for a boosted share of revenue
+    Junior,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum InsuranceFundLockupTier {
+    #[default]
+    None,
+    ThirtyDays,
+    NinetyDays,
+    OneHundredEightyDays,
+}
+
+impl InsuranceFundLockupTier {
+    pub fn duration(&self) -> i64 {
+        match self {
+            InsuranceFundLockupTier::None => 0,
+            InsuranceFundLockupTier::ThirtyDays => THIRTY_DAY,
+            InsuranceFundLockupTier::NinetyDays => NINETY_DAY,
+            InsuranceFundLockupTier::OneHundredEightyDays => ONE_HUNDRED_EIGHTY_DAY,
+        }
+    }
+
+    /// The extra weight a locked stake's shares get in revenue settles
+    /// precision: IF_BOOST_PRECISION
+    pub fn boost(&self) -> u128 {
+        match self {
+            InsuranceFundLockupTier::None => 0,
+            InsuranceFundLockupTier::ThirtyDays => IF_BOOST_PRECISION / 10,
+            InsuranceFundLockupTier::NinetyDays => IF_BOOST_PRECISION / 4,
+            InsuranceFundLockupTier::OneHundredEightyDays => IF_BOOST_PRECISION / 2,
+        }
+    }
+}
+
+/// The lockup of an insurance fund stake. Locked stakes earn boosted shares on top of their pro
+/// rata revenue until their lockup ends
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct InsuranceFundStakeLockup {
+    pub insurance_fund_stake: Pubkey,
+    /// The account that paid the lockup's rent, refunded when it's unlocked
+    pub payer: Pubkey,
+    /// unix_timestamp the stake can be unlocked at, the boost stops after it
+    pub lockup_end_ts: i64,
+    /// The stake's shares times its tier's boost
+    /// precision: if shares
+    pub boost_weight: u64,
+    /// The spot market's insurance_fund_cumulative_boost_shares_per_weight at the last accrual
+    /// precision: IF_BOOST_SHARES_PER_WEIGHT_PRECISION
+    pub last_cumulative_boost_shares_per_weight: u128,
+    pub if_base: u128, // exponent for boost_weight decimal places (for rebase)
+    /// unix_timestamp of the last accrual
+    pub last_accrual_ts: i64,
+    pub market_index: u16,
+    pub lockup_tier: InsuranceFundLockupTier,
+    pub padding: [u8; 5],
+}
+
+impl Size for InsuranceFundStakeLockup {
+    const SIZE: usize = 128 + 8;
+}
+
+impl InsuranceFundStakeLockup {
+    pub fn calculate_boost_weight(&self, if_shares: u128) -> DriftResult<u64> {
+        if_shares
+            .safe_mul(self.lockup_tier.boost())?
+            .safe_div(IF_BOOST_PRECISION)?
+            .cast()
+    }
+
+    pub fn is_unlockable(&self, now: i64) -> bool {
+        now >= self.lockup_end_ts
+    }
+}
+
+/// The junior tranche of a spot market's insurance fund. Junior stakes hold regular if shares,
+/// scaled by the share factor since they were last updated: losses burn junior shares before
+/// the senior stakes lose any value, and the tranche's boosted revenue mints it more shares
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct InsuranceFundJuniorTranche {
+    /// The if shares held by junior stakes
+    pub total_shares: u128,
+    pub if_base: u128, // exponent for total_shares decimal places (for rebase)
+    /// The spot market's insurance_fund_cumulative_boost_shares_per_weight at the last accrual
+    /// precision: IF_BOOST_SHARES_PER_WEIGHT_PRECISION
+    pub last_cumulative_boost_shares_per_weight: u128,
+    /// How much junior stakes' shares have grown or shrunk within the epoch
+    /// precision: IF_TRANCHE_SHARE_FACTOR_PRECISION
+    pub share_factor: u64,
+    /// The tranche's shares times its revenue boost
+    /// precision: if shares
+    pub boost_weight: u64,
+    /// Incremented when losses wipe out the tranche
+    pub epoch: u32,
+    /// The extra weight junior shares get in revenue settles
+    /// precision: IF_BOOST_PRECISION
+    pub revenue_boost: u32,
+    pub market_index: u16,
+    pub padding: [u8; 22],
+}
+
+impl Size for InsuranceFundJuniorTranche {
+    const SIZE: usize = 96 + 8;
+}
+
+impl InsuranceFundJuniorTranche {
+    pub fn new(market_index: u16, revenue_boost: u32, if_base: u128) -> Self {
+        InsuranceFundJuniorTranche {
+            market_index,
+            revenue_boost,
+            if_base,
+            share_factor: IF_TRANCHE_SHARE_FACTOR_PRECISION_U64,
+            ..InsuranceFundJuniorTranche::default()
+        }
+    }
+
+    pub fn calculate_boost_weight(&self) -> DriftResult<u64> {
+        self.total_shares
+            .safe_mul(self.revenue_boost.cast()?)?
+            .safe_div(IF_BOOST_PRECISION)?
+            .cast()
+    }
+
+    /// The junior stake's shares scaled by the tranche's share factor since it was last updated
+    pub fn calculate_stake_shares(
+        &self,
+        if_shares: u128,
+        insurance_fund_stake: &InsuranceFundStake,
+    ) -> DriftResult<u128> {
+        if insurance_fund_stake.junior_epoch != self.epoch
+            || insurance_fund_stake.junior_share_factor == 0
+        {
+            return Ok(0);
+        }
+
+        if_shares
+            .safe_mul(self.share_factor.cast()?)?
+            .safe_div(insurance_fund_stake.junior_share_factor.cast()?)
+    }
+}
+
 #[account(zero_copy(unsafe))]
 #[derive(Default, Eq, PartialEq, Debug)]
 #[repr(C)]
@@ -2,6 +2,7 @@ use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
 use crate::math::fuel::{calculate_perp_fuel_bonus, calculate_spot_fuel_bonus};
 use crate::math::margin::MarginRequirementType;
+use crate::math::portfolio_margin::PortfolioMarginCalculation;
 use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::get_strict_token_value;
 use crate::state::oracle::StrictOraclePrice;
@@ -19,6 +20,7 @@ pub enum MarginCalculationMode {
     Liquidation {
         market_to_track_margin_requirement: Option<MarketIdentifier>,
     },
+    Portfolio,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -31,6 +33,7 @@ pub struct MarginContext {
     pub fuel_bonus: u64,
     pub fuel_perp_delta: Option<(u16, i64)>,
     pub fuel_spot_deltas: [(u16, i128); 2],
+    pub isolated_perp_market_index: Option<u16>,
 }
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, AnchorSerialize, AnchorDeserialize)]
@@ -68,6 +71,15 @@ impl MarginContext {
             fuel_bonus: 0,
             fuel_perp_delta: None,
             fuel_spot_deltas: [(0, 0); 2],
+            isolated_perp_market_index: None,
+        }
+    }
+
+    // requirement is the worst case loss of the cross margin account across a grid of price shocks
+    pub fn portfolio(margin_type: MarginRequirementType) -> Self {
+        Self {
+            mode: MarginCalculationMode::Portfolio,
+            ..Self::standard(margin_type)
         }
     }
 
@@ -130,9 +142,17 @@ impl MarginContext {
             fuel_bonus: 0,
             fuel_perp_delta: None,
             fuel_spot_deltas: [(0, 0); 2],
+            isolated_perp_market_index: None,
         }
     }
 
+    // only calculate the margin for the user's isolated perp position in market_index
+    // collateral and positions in the cross margin account are ignored
+    pub fn isolated_perp_position(mut self, market_index: u16) -> Self {
+        self.isolated_perp_market_index = Some(market_index);
+        self
+    }
+
     pub fn track_market_margin_requirement(
         mut self,
         market_identifier: MarketIdentifier,
@@ -153,6 +173,26 @@ impl MarginContext {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IsolatedMarginCalculation {
+    pub market_index: u16,
+    pub total_collateral: i128,
+    pub margin_requirement: u128,
+}
+
+impl IsolatedMarginCalculation {
+    pub fn meets_margin_requirement(&self) -> bool {
+        self.total_collateral >= self.margin_requirement as i128
+    }
+
+    pub fn get_free_collateral(&self) -> DriftResult<u128> {
+        self.total_collateral
+            .safe_sub(self.margin_requirement.cast::<i128>()?)?
+            .max(0)
+            .cast()
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct MarginCalculation {
     pub context: MarginContext,
@@ -176,6 +216,7 @@ pub struct MarginCalculation {
     pub fuel_deposits: u32,
     pub fuel_borrows: u32,
     pub fuel_positions: u32,
+    pub isolated_perp_positions: [Option<IsolatedMarginCalculation>; 8],
 }
 
 impl MarginCalculation {
@@ -199,6 +240,7 @@ impl MarginCalculation {
             fuel_deposits: 0,
             fuel_borrows: 0,
             fuel_positions: 0,
+            isolated_perp_positions: [None; 8],
         }
     }
 
@@ -309,10 +351,57 @@ impl MarginCalculation {
             .safe_add(self.num_perp_liabilities)
     }
 
+    pub fn add_isolated_perp_position(
+        &mut self,
+        market_index: u16,
+        total_collateral: i128,
+        margin_requirement: u128,
+    ) -> DriftResult {
+        let slot = self
+            .isolated_perp_positions
+            .iter_mut()
+            .find(|isolated_position| isolated_position.is_none())
+            .ok_or(ErrorCode::InvalidMarginCalculation)?;
+
+        *slot = Some(IsolatedMarginCalculation {
+            market_index,
+            total_collateral,
+            margin_requirement,
+        });
+
+        Ok(())
+    }
+
+    pub fn get_isolated_perp_position(
+        &self,
+        market_index: u16,
+    ) -> Option<&IsolatedMarginCalculation> {
+        self.isolated_perp_positions
+            .iter()
+            .flatten()
+            .find(|isolated_position| isolated_position.market_index == market_index)
+    }
+
     pub fn meets_margin_requirement(&self) -> bool {
+        self.meets_cross_margin_requirement()
+            && self
+                .isolated_perp_positions
+                .iter()
+                .flatten()
+                .all(|isolated_position| isolated_position.meets_margin_requirement())
+    }
+
+    pub fn meets_cross_margin_requirement(&self) -> bool {
         self.total_collateral >= self.margin_requirement as i128
     }
 
+    pub fn meets_isolated_margin_requirement(&self, market_index: u16) -> bool {
+        self.get_isolated_perp_position(market_index)
+            .map_or(true, |isolated_position| {
+                isolated_position.meets_margin_requirement()
+            })
+    }
+
     pub fn positions_meets_margin_requirement(&self) -> DriftResult<bool> {
         Ok(self.total_collateral
             >= self
@@ -377,10 +466,40 @@ impl MarginCalculation {
         }
     }
 
-    fn is_liquidation_mode(&self) -> bool {
+    pub fn is_liquidation_mode(&self) -> bool {
         matches!(self.context.mode, MarginCalculationMode::Liquidation { .. })
     }
 
+    pub fn is_portfolio_mode(&self) -> bool {
+        matches!(self.context.mode, MarginCalculationMode::Portfolio)
+    }
+
+    pub fn apply_portfolio_margin(
+        &mut self,
+        portfolio_margin: &PortfolioMarginCalculation,
+    ) -> DriftResult {
+        self.total_collateral = portfolio_margin.total_collateral;
+        self.margin_requirement = portfolio_margin.margin_requirement;
+
+        if self.context.margin_buffer > 0 {
+            self.margin_requirement_plus_buffer = portfolio_margin.margin_requirement.safe_add(
+                portfolio_margin
+                    .liability_value
+                    .safe_mul(self.context.margin_buffer)?
+                    / MARGIN_PRECISION_U128,
+            )?;
+        }
+
+        self.open_orders_margin_requirement = self
+            .open_orders_margin_requirement
+            .min(self.margin_requirement);
+        self.tracked_market_margin_requirement = self
+            .tracked_market_margin_requirement
+            .min(self.margin_requirement);
+
+        Ok(())
+    }
+
     pub fn track_open_orders_fraction(&self) -> bool {
         matches!(
             self.context.mode,
@@ -1,3 +1,4 @@
+pub mod delegate_permissions;
 pub mod events;
 pub mod fill_mode;
 pub mod fulfillment;
@@ -5,7 +6,9 @@ pub mod fulfillment_params;
 pub mod insurance_fund_stake;
 pub mod load_ref;
 pub mod margin_calculation;
+pub mod option_market;
 pub mod oracle;
+pub mod oracle_circuit_breaker;
 pub mod oracle_map;
 pub mod order_params;
 pub mod paused_operations;
@@ -18,5 +21,7 @@ pub mod spot_market_map;
 #[allow(clippy::module_inception)]
 pub mod state;
 pub mod traits;
+pub mod twap_order;
 pub mod user;
 pub mod user_map;
+pub mod withdraw_guard;
@@ -0,0 +1,185 @@
+use anchor_lang::prelude::*;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::PERCENTAGE_PRECISION_U64;
+use crate::math::options::{calculate_intrinsic_value, calculate_option_greeks};
+use crate::math::safe_math::SafeMath;
+use crate::math::stats;
+use crate::state::oracle::{OraclePriceData, OracleSource};
+use crate::state::traits::Size;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum OptionType {
+    #[default]
+    Call,
+    Put,
+}
+
+/// A european option on an underlying oracle. It's the oracle of the perp market its contracts
+/// trade in, priced with Black-Scholes until expiry and at its intrinsic value against the
+/// underlying's settlement window twap after
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct OptionMarket {
+    pub underlying_oracle: Pubkey,
+    /// precision: PRICE_PRECISION
+    pub strike: u64,
+    pub expiry_ts: i64,
+    /// Annualized implied volatility the option is priced with
+    /// precision: PERCENTAGE_PRECISION
+    pub implied_volatility: u64,
+    /// precision: PRICE_PRECISION
+    pub premium: i64,
+    /// The underlying's confidence scaled by delta
+    /// precision: PRICE_PRECISION
+    pub confidence: u64,
+    /// precision: PERCENTAGE_PRECISION, negative for puts
+    pub delta: i64,
+    /// The premium change for a 100% change in implied volatility
+    /// precision: PRICE_PRECISION
+    pub vega: i64,
+    /// precision: PRICE_PRECISION
+    pub underlying_price: i64,
+    /// The underlying oracle twap over the settlement window
+    /// precision: PRICE_PRECISION
+    pub settlement_price_twap: i64,
+    /// unix_timestamp of the last settlement_price_twap update
+    pub settlement_price_twap_ts: i64,
+    pub last_update_slot: u64,
+    /// The seconds before expiry_ts the settlement price twap is taken over
+    pub settlement_window: u32,
+    pub perp_market_index: u16,
+    pub option_type: OptionType,
+    pub underlying_oracle_source: OracleSource,
+    pub padding: [u8; 16],
+}
+
+impl Size for OptionMarket {
+    const SIZE: usize = 144 + 8;
+}
+
+impl OptionMarket {
+    pub fn is_settled(&self) -> bool {
+        self.settlement_price_twap_ts >= self.expiry_ts
+    }
+
+    pub fn update(
+        &mut self,
+        underlying_price_data: &OraclePriceData,
+        now: i64,
+        slot: u64,
+    ) -> DriftResult {
+        let underlying_price = underlying_price_data.price;
+
+        if let Some((settlement_price_twap, settlement_price_twap_ts)) =
+            stats::calculate_window_twap(
+                underlying_price,
+                now,
+                self.settlement_price_twap,
+                self.settlement_price_twap_ts,
+                self.expiry_ts.safe_sub(self.settlement_window.cast()?)?,
+                self.expiry_ts,
+            )?
+        {
+            self.settlement_price_twap = settlement_price_twap;
+            self.settlement_price_twap_ts = settlement_price_twap_ts;
+        }
+
+        if self.is_settled() {
+            self.premium = self.get_settlement_price()?;
+            self.delta = 0;
+            self.vega = 0;
+            self.confidence = 0;
+        } else {
+            let greeks = calculate_option_greeks(
+                self.option_type,
+                underlying_price,
+                self.strike,
+                self.expiry_ts.safe_sub(now)?,
+                self.implied_volatility,
+            )?;
+
+            self.premium = greeks.premium;
+            self.delta = greeks.delta;
+            self.vega = greeks.vega;
+            self.confidence = underlying_price_data
+                .confidence
+                .cast::<u128>()?
+                .safe_mul(self.delta.unsigned_abs().cast()?)?
+                .safe_div(PERCENTAGE_PRECISION_U64.cast()?)?
+                .cast()?;
+        }
+
+        self.underlying_price = underlying_price;
+        // the option is as stale as the underlying read it's priced with
+        self.last_update_slot = slot.saturating_sub(underlying_price_data.delay.max(0).cast()?);
+
+        Ok(())
+    }
+
+    /// The option's intrinsic value against the settlement window twap
+    /// precision: PRICE_PRECISION
+    pub fn get_settlement_price(&self) -> DriftResult<i64> {
+        validate!(
+            self.is_settled(),
+            ErrorCode::MarketSettlementTargetPriceInvalid,
+            "option settlement window twap ts {} < expiry ts {}",
+            self.settlement_price_twap_ts,
+            self.expiry_ts
+        )?;
+
+        calculate_intrinsic_value(self.option_type, self.settlement_price_twap, self.strike)
+    }
+
+    pub fn validate(&self) -> DriftResult {
+        validate!(
+            self.strike > 0,
+            ErrorCode::InvalidOptionMarket,
+            "option strike == 0"
+        )?;
+
+        validate!(
+            self.settlement_window > 0,
+            ErrorCode::InvalidOptionMarket,
+            "option settlement window == 0"
+        )?;
+
+        validate!(
+            self.implied_volatility > 0,
+            ErrorCode::InvalidOptionMarket,
+            "option implied volatility == 0"
+        )?;
+
+        validate!(
+            !matches!(
+                self.underlying_oracle_source,
+                OracleSource::Prelaunch | OracleSource::QuoteAsset | OracleSource::EuropeanOption
+            ),
+            ErrorCode::InvalidOptionMarket,
+            "invalid underlying oracle source {:?}",
+            self.underlying_oracle_source
+        )?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, AnchorSerialize, AnchorDeserialize, PartialEq, Eq)]
+pub struct OptionMarketParams {
+    pub perp_market_index: u16,
+    pub option_type: OptionType,
+    /// precision: PRICE_PRECISION
+    pub strike: u64,
+    pub expiry_ts: i64,
+    pub settlement_window: u32,
+    /// precision: PERCENTAGE_PRECISION
+    pub implied_volatility: u64,
+    pub underlying_oracle_source: OracleSource,
+}
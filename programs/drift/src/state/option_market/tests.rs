@@ -0,0 +1,128 @@
+use crate::math::constants::{ONE_YEAR, PRICE_PRECISION_I64};
+use crate::state::option_market::{OptionMarket, OptionType};
+use crate::state::oracle::{OraclePriceData, OracleSource};
+
+fn underlying_price_data(price: i64) -> OraclePriceData {
+    OraclePriceData {
+        price,
+        confidence: PRICE_PRECISION_I64 as u64,
+        delay: 0,
+        has_sufficient_number_of_data_points: true,
+    }
+}
+
+#[test]
+fn update_and_settle() {
+    let expiry_ts = ONE_YEAR as i64;
+    let mut option_market = OptionMarket {
+        option_type: OptionType::Call,
+        strike: 100 * PRICE_PRECISION_I64 as u64,
+        expiry_ts,
+        implied_volatility: 200_000,
+        settlement_window: 100,
+        underlying_oracle_source: OracleSource::Pyth,
+        ..OptionMarket::default()
+    };
+    option_market.validate().unwrap();
+
+    option_market
+        .update(&underlying_price_data(100 * PRICE_PRECISION_I64), 0, 1)
+        .unwrap();
+
+    assert!((option_market.premium - 7965579).abs() <= 100);
+    assert!((option_market.delta - 539827).abs() <= 100);
+    // the underlying's $1 confidence scaled by delta
+    assert_eq!(option_market.confidence, option_market.delta.unsigned_abs());
+    assert_eq!(option_market.underlying_price, 100 * PRICE_PRECISION_I64);
+    assert_eq!(option_market.last_update_slot, 1);
+    assert_eq!(option_market.settlement_price_twap_ts, 0);
+    assert!(option_market.get_settlement_price().is_err());
+
+    // inside the settlement window
+    option_market
+        .update(
+            &underlying_price_data(110 * PRICE_PRECISION_I64),
+            expiry_ts - 50,
+            2,
+        )
+        .unwrap();
+    assert_eq!(
+        option_market.settlement_price_twap,
+        110 * PRICE_PRECISION_I64
+    );
+    assert!(!option_market.is_settled());
+    assert!(option_market.premium >= 10 * PRICE_PRECISION_I64);
+
+    // past expiry, the window is closed and the option is worth its intrinsic value
+    option_market
+        .update(
+            &underlying_price_data(130 * PRICE_PRECISION_I64),
+            expiry_ts + 50,
+            3,
+        )
+        .unwrap();
+    assert!(option_market.is_settled());
+    assert_eq!(option_market.settlement_price_twap, 120_000_001);
+    assert_eq!(option_market.settlement_price_twap_ts, expiry_ts);
+    assert_eq!(option_market.get_settlement_price().unwrap(), 20_000_001);
+    assert_eq!(option_market.premium, 20_000_001);
+    assert_eq!(option_market.delta, 0);
+    assert_eq!(option_market.vega, 0);
+
+    // later updates don't move the settlement price
+    option_market
+        .update(
+            &underlying_price_data(50 * PRICE_PRECISION_I64),
+            expiry_ts + 100,
+            4,
+        )
+        .unwrap();
+    assert_eq!(option_market.get_settlement_price().unwrap(), 20_000_001);
+
+    // the underlying's delay carries through to the option
+    option_market
+        .update(
+            &OraclePriceData {
+                delay: 3,
+                ..underlying_price_data(50 * PRICE_PRECISION_I64)
+            },
+            expiry_ts + 100,
+            10,
+        )
+        .unwrap();
+    assert_eq!(option_market.last_update_slot, 7);
+
+    // out of the money puts expire worthless
+    option_market.option_type = OptionType::Put;
+    assert_eq!(option_market.get_settlement_price().unwrap(), 0);
+}
+
+#[test]
+fn validate() {
+    let option_market = OptionMarket {
+        strike: 100 * PRICE_PRECISION_I64 as u64,
+        expiry_ts: ONE_YEAR as i64,
+        implied_volatility: 200_000,
+        settlement_window: 100,
+        ..OptionMarket::default()
+    };
+    option_market.validate().unwrap();
+
+    let invalid = OptionMarket {
+        strike: 0,
+        ..option_market
+    };
+    assert!(invalid.validate().is_err());
+
+    let invalid = OptionMarket {
+        settlement_window: 0,
+        ..option_market
+    };
+    assert!(invalid.validate().is_err());
+
+    let invalid = OptionMarket {
+        underlying_oracle_source: OracleSource::EuropeanOption,
+        ..option_market
+    };
+    assert!(invalid.validate().is_err());
+}
@@ -3,15 +3,24 @@ use std::cell::Ref;
 
 use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
-use crate::math::constants::{PRICE_PRECISION, PRICE_PRECISION_I64, PRICE_PRECISION_U64};
+use crate::math::constants::{
+    PERCENTAGE_PRECISION_I128, PERCENTAGE_PRECISION_U64, PRICE_PRECISION, PRICE_PRECISION_I64,
+    PRICE_PRECISION_U64,
+};
+use crate::math::oracle::OracleValidity;
 use crate::math::safe_math::SafeMath;
-use switchboard::{AggregatorAccountData, SwitchboardDecimal};
+use switchboard::{
+    AggregatorAccountData, PullFeedAccountData, SwitchboardDecimal, PULL_FEED_PRECISION,
+};
 
 use crate::error::ErrorCode::{InvalidOracle, UnableToLoadOracle};
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::load_ref::load_ref;
+use crate::state::option_market::OptionMarket;
 use crate::state::perp_market::PerpMarket;
+use crate::state::state::ValidityGuardRailsOverride;
 use crate::state::traits::Size;
+use crate::state::user::MarketType;
 use crate::validate;
 
 #[cfg(test)]
@@ -120,6 +129,8 @@ pub enum OracleSource {
     Pyth1KPull,
     Pyth1MPull,
     PythStableCoinPull,
+    SwitchboardOnDemand,
+    EuropeanOption,
 }
 
 #[derive(Default, Clone, Copy, Debug)]
@@ -165,6 +176,10 @@ pub fn get_oracle_price(
         OracleSource::PythStableCoinPull => {
             get_pyth_stable_coin_price(price_oracle, clock_slot, true)
         }
+        OracleSource::SwitchboardOnDemand => {
+            get_switchboard_on_demand_price(price_oracle, clock_slot)
+        }
+        OracleSource::EuropeanOption => get_option_market_price(price_oracle, clock_slot),
     }
 }
 
@@ -307,6 +322,52 @@ pub fn get_switchboard_price(
     })
 }
 
+pub fn get_switchboard_on_demand_price(
+    price_oracle: &AccountInfo,
+    clock_slot: u64,
+) -> DriftResult<OraclePriceData> {
+    let pull_feed: Ref<PullFeedAccountData> =
+        load_ref(price_oracle).or(Err(ErrorCode::UnableToLoadOracle))?;
+
+    let result = pull_feed.result;
+
+    let price = convert_switchboard_on_demand_value(result.value)?.cast::<i64>()?;
+    let std_dev = convert_switchboard_on_demand_value(result.std_dev)?.cast::<i64>()?;
+    let range = convert_switchboard_on_demand_value(result.range)?.cast::<i64>()?;
+
+    // std deviation and range should always be positive, if we get a negative make it u64::MAX so it's flagged as bad value
+    let confidence = if std_dev < 0 || range < 0 {
+        u64::MAX
+    } else {
+        let price_10bps = price.unsigned_abs().safe_div(1000)?;
+        std_dev
+            .unsigned_abs()
+            .max(range.unsigned_abs().safe_div(2)?)
+            .max(price_10bps)
+    };
+
+    // the result is only as fresh as the oldest submission it considered
+    let delay = clock_slot
+        .cast::<i64>()?
+        .safe_sub(result.min_slot.cast()?)?;
+
+    let has_sufficient_number_of_data_points = result.num_samples > 0
+        && result.num_samples >= pull_feed.min_sample_size
+        && (pull_feed.max_staleness == 0 || delay <= pull_feed.max_staleness.cast()?);
+
+    Ok(OraclePriceData {
+        price,
+        confidence,
+        delay,
+        has_sufficient_number_of_data_points,
+    })
+}
+
+/// Scale an on-demand fixed point value (18 decimals) to PRICE_PRECISION
+fn convert_switchboard_on_demand_value(value: i128) -> DriftResult<i128> {
+    value.safe_div(PULL_FEED_PRECISION.safe_div(PRICE_PRECISION.cast()?)?)
+}
+
 /// Given a decimal number represented as a mantissa (the digits) plus an
 /// original_precision (10.pow(some number of decimals)), scale the
 /// mantissa/digits to make sense with a new_precision.
@@ -334,6 +395,22 @@ pub fn get_prelaunch_price(price_oracle: &AccountInfo, slot: u64) -> DriftResult
     })
 }
 
+/// An option market's premium. Worthless options are quoted at the minimum price so the market
+/// keeps a valid oracle until it's settled
+pub fn get_option_market_price(
+    price_oracle: &AccountInfo,
+    slot: u64,
+) -> DriftResult<OraclePriceData> {
+    let option_market: Ref<OptionMarket> = load_ref(price_oracle).or(Err(UnableToLoadOracle))?;
+
+    Ok(OraclePriceData {
+        price: option_market.premium.max(1),
+        confidence: option_market.confidence,
+        delay: slot.saturating_sub(option_market.last_update_slot).cast()?,
+        has_sufficient_number_of_data_points: true,
+    })
+}
+
 #[derive(Clone, Copy)]
 pub struct StrictOraclePrice {
     pub current: i64,
@@ -405,7 +482,21 @@ pub struct PrelaunchOracle {
     // amm.last_update_slot at time oracle was updated
     pub amm_last_update_slot: u64,
     pub perp_market_index: u16,
-    pub padding: [u8; 70],
+    /// Source of the reference oracle, only used if reference_oracle is set
+    pub reference_oracle_source: OracleSource,
+    pub padding1: u8,
+    /// Weight given to the reference oracle price when blending it with the mark twap
+    /// precision: PERCENTAGE_PRECISION
+    pub reference_weight: u32,
+    /// First slot of the reference oracle's current run of valid prices, 0 if it isn't valid
+    pub reference_valid_since_slot: u64,
+    /// External feed blended into the price, the market transitions to it once it has been
+    /// valid for slots_before_transition. Pubkey::default() if there is no reference
+    pub reference_oracle: Pubkey,
+    /// Slots the reference oracle must be valid for before the market transitions to it,
+    /// 0 disables the transition
+    pub slots_before_transition: u32,
+    pub padding: [u8; 20],
 }
 
 impl Default for PrelaunchOracle {
@@ -417,7 +508,13 @@ impl Default for PrelaunchOracle {
             last_update_slot: 0,
             amm_last_update_slot: 0,
             perp_market_index: 0,
-            padding: [0; 70],
+            reference_oracle_source: OracleSource::default(),
+            padding1: 0,
+            reference_weight: 0,
+            reference_valid_since_slot: 0,
+            reference_oracle: Pubkey::default(),
+            slots_before_transition: 0,
+            padding: [0; 20],
         }
     }
 }
@@ -427,17 +524,42 @@ impl Size for PrelaunchOracle {
 }
 
 impl PrelaunchOracle {
-    pub fn update(&mut self, perp_market: &PerpMarket, slot: u64) -> DriftResult {
+    pub fn update(
+        &mut self,
+        perp_market: &PerpMarket,
+        reference_price_data: Option<(&OraclePriceData, OracleValidity)>,
+        slot: u64,
+    ) -> DriftResult {
         let last_twap = perp_market.amm.last_mark_price_twap.cast::<i64>()?;
-        let new_price = if self.max_price <= last_twap {
-            msg!(
-                "mark twap {} >= max price {}, using max",
-                last_twap,
-                self.max_price
-            );
+
+        let mut reference_confidence = 0_u64;
+        let price = match reference_price_data {
+            Some((reference_price_data, OracleValidity::Valid)) => {
+                if self.reference_valid_since_slot == 0 {
+                    self.reference_valid_since_slot = slot;
+                }
+
+                reference_confidence = reference_price_data.confidence;
+
+                self.get_blended_price(last_twap, reference_price_data.price)?
+            }
+            Some((_, oracle_validity)) => {
+                msg!(
+                    "reference oracle invalid ({}), using mark twap",
+                    oracle_validity
+                );
+                self.reference_valid_since_slot = 0;
+
+                last_twap
+            }
+            None => last_twap,
+        };
+
+        let new_price = if self.max_price <= price {
+            msg!("price {} >= max price {}, using max", price, self.max_price);
             self.max_price
         } else {
-            last_twap
+            price
         };
 
         self.price = new_price;
@@ -451,7 +573,7 @@ impl PrelaunchOracle {
 
         let mark_std = perp_market.amm.mark_std;
 
-        self.confidence = spread_twap.max(mark_std);
+        self.confidence = spread_twap.max(mark_std).max(reference_confidence);
 
         self.amm_last_update_slot = perp_market.amm.last_update_slot;
         self.last_update_slot = slot;
@@ -465,6 +587,34 @@ impl PrelaunchOracle {
         Ok(())
     }
 
+    fn get_blended_price(&self, mark_twap: i64, reference_price: i64) -> DriftResult<i64> {
+        let reference_weight = self.reference_weight.cast::<i128>()?;
+        let mark_weight = PERCENTAGE_PRECISION_I128.safe_sub(reference_weight)?;
+
+        mark_twap
+            .cast::<i128>()?
+            .safe_mul(mark_weight)?
+            .safe_add(reference_price.cast::<i128>()?.safe_mul(reference_weight)?)?
+            .safe_div(PERCENTAGE_PRECISION_I128)?
+            .cast()
+    }
+
+    pub fn has_reference_oracle(&self) -> bool {
+        self.reference_oracle != Pubkey::default()
+    }
+
+    pub fn should_transition(&self, slot: u64) -> DriftResult<bool> {
+        if !self.has_reference_oracle()
+            || self.slots_before_transition == 0
+            || self.reference_valid_since_slot == 0
+        {
+            return Ok(false);
+        }
+
+        Ok(slot.safe_sub(self.reference_valid_since_slot)?
+            >= self.slots_before_transition.cast::<u64>()?)
+    }
+
     pub fn validate(&self) -> DriftResult {
         validate!(self.price != 0, InvalidOracle, "price == 0",)?;
 
@@ -478,6 +628,30 @@ impl PrelaunchOracle {
             self.max_price
         )?;
 
+        validate!(
+            self.reference_weight.cast::<u64>()? <= PERCENTAGE_PRECISION_U64,
+            InvalidOracle,
+            "reference weight {} > {}",
+            self.reference_weight,
+            PERCENTAGE_PRECISION_U64
+        )?;
+
+        if self.has_reference_oracle() {
+            validate!(
+                self.reference_oracle_source != OracleSource::Prelaunch,
+                InvalidOracle,
+                "reference oracle can't be a prelaunch oracle",
+            )?;
+        } else {
+            validate!(
+                self.reference_weight == 0 && self.slots_before_transition == 0,
+                InvalidOracle,
+                "reference weight {} and slots before transition {} require a reference oracle",
+                self.reference_weight,
+                self.slots_before_transition
+            )?;
+        }
+
         Ok(())
     }
 }
@@ -487,4 +661,100 @@ pub struct PrelaunchOracleParams {
     pub perp_market_index: u16,
     pub price: Option<i64>,
     pub max_price: Option<i64>,
+    pub reference_oracle: Option<Pubkey>,
+    pub reference_oracle_source: Option<OracleSource>,
+    pub reference_weight: Option<u32>,
+    pub slots_before_transition: Option<u32>,
+}
+
+/// Secondary oracles a market's primary oracle is aggregated with. Once a market has an aggregate,
+/// its prices are read from the aggregate of all the market's oracles and the account must be
+/// passed in with the oracles
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct OracleAggregate {
+    /// The market's oracle, amm.oracle for perp markets
+    pub primary_oracle: Pubkey,
+    /// Only the first num_secondary_oracles are used
+    pub secondary_oracles: [Pubkey; 4],
+    pub market_index: u16,
+    pub market_type: MarketType,
+    pub method: OracleAggregationMethod,
+    pub num_secondary_oracles: u8,
+    pub padding: [u8; 3],
+}
+
+impl Size for OracleAggregate {
+    const SIZE: usize = 168 + 8;
+}
+
+impl OracleAggregate {
+    pub const MAX_SECONDARY_ORACLES: usize = 4;
+
+    /// The primary oracle followed by the secondary oracles
+    pub fn get_oracles(&self) -> Vec<Pubkey> {
+        let mut oracles = Vec::with_capacity(self.num_secondary_oracles as usize + 1);
+        oracles.push(self.primary_oracle);
+        oracles.extend_from_slice(&self.secondary_oracles[..self.num_secondary_oracles as usize]);
+        oracles
+    }
+}
+
+/// Identifies a price read from the oracle map. A market with an oracle aggregate reads the
+/// aggregate price, keyed by the market, other reads are of the oracle alone
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct OracleId {
+    pub oracle: Pubkey,
+    /// The market whose oracle aggregate the price is read from
+    pub aggregate_market: Option<OracleAggregateMarket>,
+}
+
+/// A market reading its oracle aggregate, with the market's current guard rails each source is
+/// validated with
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct OracleAggregateMarket {
+    pub market_type: MarketType,
+    pub market_index: u16,
+    pub validity_guard_rails_override: ValidityGuardRailsOverride,
+    pub max_confidence_interval_multiplier: u64,
+}
+
+impl OracleId {
+    pub fn new(
+        oracle: Pubkey,
+        market_type: MarketType,
+        market_index: u16,
+        has_aggregate: bool,
+        validity_guard_rails_override: ValidityGuardRailsOverride,
+        max_confidence_interval_multiplier: u64,
+    ) -> Self {
+        OracleId {
+            oracle,
+            aggregate_market: has_aggregate.then_some(OracleAggregateMarket {
+                market_type,
+                market_index,
+                validity_guard_rails_override,
+                max_confidence_interval_multiplier,
+            }),
+        }
+    }
+}
+
+impl From<&Pubkey> for OracleId {
+    fn from(oracle: &Pubkey) -> Self {
+        OracleId {
+            oracle: *oracle,
+            aggregate_market: None,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum OracleAggregationMethod {
+    /// The median price of the valid oracles
+    #[default]
+    Median,
+    /// The price of the first valid oracle, primary first
+    FirstValid,
 }
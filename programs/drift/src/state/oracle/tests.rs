@@ -1,10 +1,15 @@
+use anchor_lang::Owner;
 use solana_program::pubkey::Pubkey;
 use std::str::FromStr;
+use switchboard::{PullFeedAccountData, PULL_FEED_PRECISION};
 
-use crate::create_account_info;
-use crate::state::oracle::{get_oracle_price, OracleSource};
-use crate::state::perp_market::AMM;
+use crate::math::constants::{PERCENTAGE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64};
+use crate::math::oracle::OracleValidity;
+use crate::state::oracle::{get_oracle_price, OraclePriceData, OracleSource, PrelaunchOracle};
+use crate::state::oracle_map::OracleMap;
+use crate::state::perp_market::{PerpMarket, AMM};
 use crate::test_utils::*;
+use crate::{create_account_info, create_anchor_account_info};
 
 #[test]
 fn pyth_1k() {
@@ -91,3 +96,206 @@ fn pyth_pull_1m() {
     let twap = amm.get_oracle_twap(&bonk_market_account_info, 0).unwrap();
     assert_eq!(twap, Some(33576300));
 }
+
+#[test]
+fn switchboard_on_demand() {
+    let mut pull_feed: PullFeedAccountData = bytemuck::Zeroable::zeroed();
+    pull_feed.min_sample_size = 2;
+    pull_feed.max_staleness = 20;
+    pull_feed.result.value = 1005 * PULL_FEED_PRECISION / 10; // 100.5
+    pull_feed.result.std_dev = PULL_FEED_PRECISION / 20; // .05
+    pull_feed.result.range = PULL_FEED_PRECISION / 5; // .2
+    pull_feed.result.num_samples = 3;
+    pull_feed.result.slot = 95;
+    pull_feed.result.min_slot = 90;
+    let oracle_key = Pubkey::new_unique();
+    create_anchor_account_info!(
+        pull_feed,
+        &oracle_key,
+        PullFeedAccountData,
+        oracle_account_info
+    );
+
+    let oracle_price_data = get_oracle_price(
+        &OracleSource::SwitchboardOnDemand,
+        &oracle_account_info,
+        100,
+    )
+    .unwrap();
+    assert_eq!(oracle_price_data.price, 100_500_000);
+    // 10bps of price is larger than std dev and half the range
+    assert_eq!(oracle_price_data.confidence, 100_500);
+    assert_eq!(oracle_price_data.delay, 10);
+    assert!(oracle_price_data.has_sufficient_number_of_data_points);
+
+    // older than the feed's max staleness
+    let oracle_price_data = get_oracle_price(
+        &OracleSource::SwitchboardOnDemand,
+        &oracle_account_info,
+        111,
+    )
+    .unwrap();
+    assert_eq!(oracle_price_data.delay, 21);
+    assert!(!oracle_price_data.has_sufficient_number_of_data_points);
+
+    let amm = AMM {
+        oracle_source: OracleSource::SwitchboardOnDemand,
+        ..AMM::default()
+    };
+
+    let twap = amm.get_oracle_twap(&oracle_account_info, 100).unwrap();
+    assert_eq!(twap, Some(100_500_000));
+
+    // oracle map tells on demand feeds apart from legacy aggregators
+    let mut oracle_map = OracleMap::load_one(&oracle_account_info, 100, None).unwrap();
+    let oracle_price_data = oracle_map.get_price_data(&oracle_key).unwrap();
+    assert_eq!(oracle_price_data.price, 100_500_000);
+
+    // feeds owned by the on demand program
+    let switchboard_on_demand = crate::ids::switchboard_on_demand::id();
+    let mut lamports = 0;
+    let mut data = get_anchor_account_bytes(&mut pull_feed);
+    let on_demand_account_info = create_account_info(
+        &oracle_key,
+        true,
+        &mut lamports,
+        &mut data[..],
+        &switchboard_on_demand,
+    );
+
+    let mut oracle_map = OracleMap::load_one(&on_demand_account_info, 100, None).unwrap();
+    let oracle_price_data = oracle_map.get_price_data(&oracle_key).unwrap();
+    assert_eq!(oracle_price_data.price, 100_500_000);
+
+    let accounts = vec![on_demand_account_info];
+    let mut oracle_map = OracleMap::load(&mut accounts.iter().peekable(), 100, None).unwrap();
+    let oracle_price_data = oracle_map.get_price_data(&oracle_key).unwrap();
+    assert_eq!(oracle_price_data.price, 100_500_000);
+}
+
+#[test]
+fn switchboard_on_demand_insufficient_samples() {
+    let mut pull_feed: PullFeedAccountData = bytemuck::Zeroable::zeroed();
+    pull_feed.min_sample_size = 2;
+    pull_feed.result.value = 100 * PULL_FEED_PRECISION;
+    pull_feed.result.std_dev = 2 * PULL_FEED_PRECISION;
+    pull_feed.result.range = -PULL_FEED_PRECISION;
+    pull_feed.result.num_samples = 1;
+    pull_feed.result.min_slot = 100;
+    create_anchor_account_info!(pull_feed, PullFeedAccountData, oracle_account_info);
+
+    let oracle_price_data = get_oracle_price(
+        &OracleSource::SwitchboardOnDemand,
+        &oracle_account_info,
+        100,
+    )
+    .unwrap();
+    assert_eq!(oracle_price_data.price, 100_000_000);
+    // negative range is flagged as bad value
+    assert_eq!(oracle_price_data.confidence, u64::MAX);
+    assert!(!oracle_price_data.has_sufficient_number_of_data_points);
+}
+
+#[test]
+fn prelaunch_oracle_blends_reference() {
+    let perp_market = PerpMarket {
+        amm: AMM {
+            last_mark_price_twap: 100 * PRICE_PRECISION_U64,
+            last_bid_price_twap: 99 * PRICE_PRECISION_U64,
+            last_ask_price_twap: 101 * PRICE_PRECISION_U64,
+            ..AMM::default()
+        },
+        ..PerpMarket::default()
+    };
+
+    let mut oracle = PrelaunchOracle {
+        max_price: 1000 * PRICE_PRECISION_I64,
+        reference_oracle: Pubkey::new_unique(),
+        reference_oracle_source: OracleSource::PythPull,
+        reference_weight: PERCENTAGE_PRECISION_U64 as u32 / 4,
+        slots_before_transition: 10,
+        ..PrelaunchOracle::default()
+    };
+
+    let reference_price_data = OraclePriceData {
+        price: 120 * PRICE_PRECISION_I64,
+        confidence: 3 * PRICE_PRECISION_U64,
+        delay: 0,
+        has_sufficient_number_of_data_points: true,
+    };
+
+    oracle
+        .update(
+            &perp_market,
+            Some((&reference_price_data, OracleValidity::Valid)),
+            100,
+        )
+        .unwrap();
+    assert_eq!(oracle.price, 105 * PRICE_PRECISION_I64);
+    // reference confidence is wider than the spread
+    assert_eq!(oracle.confidence, 3 * PRICE_PRECISION_U64);
+    assert_eq!(oracle.reference_valid_since_slot, 100);
+    assert!(!oracle.should_transition(109).unwrap());
+    assert!(oracle.should_transition(110).unwrap());
+
+    // still valid, the run keeps its start
+    oracle
+        .update(
+            &perp_market,
+            Some((&reference_price_data, OracleValidity::Valid)),
+            105,
+        )
+        .unwrap();
+    assert_eq!(oracle.reference_valid_since_slot, 100);
+
+    // invalid reference falls back to mark twap and restarts the run
+    oracle
+        .update(
+            &perp_market,
+            Some((&reference_price_data, OracleValidity::StaleForAMM)),
+            106,
+        )
+        .unwrap();
+    assert_eq!(oracle.price, 100 * PRICE_PRECISION_I64);
+    assert_eq!(oracle.confidence, 2 * PRICE_PRECISION_U64);
+    assert_eq!(oracle.reference_valid_since_slot, 0);
+    assert!(!oracle.should_transition(110).unwrap());
+
+    // blended price is still capped by max price
+    oracle.max_price = 102 * PRICE_PRECISION_I64;
+    oracle
+        .update(
+            &perp_market,
+            Some((&reference_price_data, OracleValidity::Valid)),
+            107,
+        )
+        .unwrap();
+    assert_eq!(oracle.price, 102 * PRICE_PRECISION_I64);
+    assert_eq!(oracle.reference_valid_since_slot, 107);
+}
+
+#[test]
+fn prelaunch_oracle_validate_reference() {
+    let mut oracle = PrelaunchOracle {
+        price: PRICE_PRECISION_I64,
+        max_price: 10 * PRICE_PRECISION_I64,
+        ..PrelaunchOracle::default()
+    };
+    assert!(oracle.validate().is_ok());
+    assert!(!oracle.should_transition(100).unwrap());
+
+    // weight without a reference
+    oracle.reference_weight = 1;
+    assert!(oracle.validate().is_err());
+
+    oracle.reference_oracle = Pubkey::new_unique();
+    oracle.reference_oracle_source = OracleSource::PythPull;
+    assert!(oracle.validate().is_ok());
+
+    oracle.reference_weight = PERCENTAGE_PRECISION_U64 as u32 + 1;
+    assert!(oracle.validate().is_err());
+
+    oracle.reference_weight = PERCENTAGE_PRECISION_U64 as u32;
+    oracle.reference_oracle_source = OracleSource::Prelaunch;
+    assert!(oracle.validate().is_err());
+}
@@ -0,0 +1,124 @@
+use anchor_lang::prelude::*;
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::constants::PERCENTAGE_PRECISION;
+use crate::math::safe_math::SafeMath;
+use crate::state::traits::Size;
+use crate::state::user::MarketType;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+/// Tracks a market's oracle move from its 5 min oracle twap. When a valid oracle moves more than
+/// max_price_move, the breaker trips and pauses the market's paused_operations until the cooldown
+/// ends or an admin resets it
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct OracleCircuitBreaker {
+    /// The unix timestamp the breaker can be reset at. 0 if the breaker isn't tripped
+    pub tripped_until_ts: i64,
+    /// The max oracle move from the 5 min oracle twap before the breaker trips. 0 disables it
+    /// precision: PERCENTAGE_PRECISION
+    pub max_price_move: u32,
+    /// The number of seconds operations stay paused once the breaker trips
+    pub cooldown: u32,
+    pub market_index: u16,
+    pub market_type: MarketType,
+    /// The PerpOperation/SpotOperation bits to pause when the breaker trips
+    pub paused_operations: u8,
+    /// The bits the breaker paused when it tripped, excluding those that were already paused.
+    /// Only these are unpaused on reset
+    pub tripped_paused_operations: u8,
+    pub padding: [u8; 27],
+}
+
+impl Size for OracleCircuitBreaker {
+    const SIZE: usize = 48 + 8;
+}
+
+impl OracleCircuitBreaker {
+    pub fn is_tripped(&self) -> bool {
+        self.tripped_until_ts != 0
+    }
+
+    pub fn can_reset(&self, now: i64) -> bool {
+        self.is_tripped() && now >= self.tripped_until_ts
+    }
+
+    /// The oracle's move from the 5 min oracle twap
+    /// precision: PERCENTAGE_PRECISION
+    pub fn get_price_move(oracle_price: i64, oracle_price_twap_5min: i64) -> DriftResult<u128> {
+        oracle_price
+            .safe_sub(oracle_price_twap_5min)?
+            .unsigned_abs()
+            .cast::<u128>()?
+            .safe_mul(PERCENTAGE_PRECISION)?
+            .safe_div(oracle_price_twap_5min.unsigned_abs().cast()?)
+    }
+
+    /// Checks the oracle price against the market's 5 min oracle twap. Returns the price move if
+    /// it should trip the breaker
+    pub fn update(
+        &self,
+        oracle_price: i64,
+        oracle_price_twap_5min: i64,
+    ) -> DriftResult<Option<u128>> {
+        if self.is_tripped() || self.max_price_move == 0 || oracle_price_twap_5min <= 0 {
+            return Ok(None);
+        }
+
+        let price_move = Self::get_price_move(oracle_price, oracle_price_twap_5min)?;
+        if price_move > self.max_price_move.cast()? {
+            return Ok(Some(price_move));
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the market's paused operations with the breaker's operations paused
+    pub fn trip(&mut self, market_paused_operations: u8, now: i64) -> DriftResult<u8> {
+        self.tripped_paused_operations = self.paused_operations & !market_paused_operations;
+        self.tripped_until_ts = now.safe_add(self.cooldown.cast()?)?;
+
+        Ok(market_paused_operations | self.paused_operations)
+    }
+
+    /// Returns the market's paused operations with the operations the breaker paused unpaused
+    pub fn reset(&mut self, market_paused_operations: u8) -> u8 {
+        let market_paused_operations = market_paused_operations & !self.tripped_paused_operations;
+
+        self.tripped_paused_operations = 0;
+        self.tripped_until_ts = 0;
+
+        market_paused_operations
+    }
+}
+
+#[derive(Debug, Clone, Copy, AnchorSerialize, AnchorDeserialize, PartialEq, Eq)]
+pub struct OracleCircuitBreakerParams {
+    /// precision: PERCENTAGE_PRECISION
+    pub max_price_move: u32,
+    pub cooldown: u32,
+    pub paused_operations: u8,
+}
+
+impl OracleCircuitBreakerParams {
+    pub fn validate(&self) -> DriftResult {
+        validate!(
+            self.cooldown > 0,
+            ErrorCode::InvalidOracleCircuitBreakerParams,
+            "cooldown must be positive"
+        )?;
+
+        validate!(
+            self.paused_operations != 0,
+            ErrorCode::InvalidOracleCircuitBreakerParams,
+            "paused_operations must be set"
+        )?;
+
+        Ok(())
+    }
+}
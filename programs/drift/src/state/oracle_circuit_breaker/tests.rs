@@ -0,0 +1,110 @@
+mod update {
+    use crate::math::constants::{PERCENTAGE_PRECISION, PRICE_PRECISION_I64};
+    use crate::state::oracle_circuit_breaker::OracleCircuitBreaker;
+    use crate::state::paused_operations::PerpOperation;
+
+    fn circuit_breaker() -> OracleCircuitBreaker {
+        OracleCircuitBreaker {
+            max_price_move: PERCENTAGE_PRECISION as u32 / 10, // 10%
+            cooldown: 600,
+            paused_operations: PerpOperation::AmmFill as u8 | PerpOperation::Fill as u8,
+            ..OracleCircuitBreaker::default()
+        }
+    }
+
+    #[test]
+    fn measures_move_from_twap() {
+        let circuit_breaker = circuit_breaker();
+
+        // no twap yet
+        assert_eq!(
+            circuit_breaker
+                .update(100 * PRICE_PRECISION_I64, 0)
+                .unwrap(),
+            None
+        );
+
+        // within the max move
+        assert_eq!(
+            circuit_breaker
+                .update(109 * PRICE_PRECISION_I64, 100 * PRICE_PRECISION_I64)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            circuit_breaker
+                .update(91 * PRICE_PRECISION_I64, 100 * PRICE_PRECISION_I64)
+                .unwrap(),
+            None
+        );
+
+        // beyond the max move in either direction
+        assert_eq!(
+            circuit_breaker
+                .update(88 * PRICE_PRECISION_I64, 100 * PRICE_PRECISION_I64)
+                .unwrap(),
+            Some(120000) // 12%
+        );
+        assert_eq!(
+            circuit_breaker
+                .update(115 * PRICE_PRECISION_I64, 100 * PRICE_PRECISION_I64)
+                .unwrap(),
+            Some(150000) // 15%
+        );
+    }
+
+    #[test]
+    fn trips_and_resets() {
+        let mut circuit_breaker = circuit_breaker();
+
+        // settle pnl was already paused, so reset leaves it paused
+        let market_paused_operations = PerpOperation::SettlePnl as u8 | PerpOperation::Fill as u8;
+        let market_paused_operations = circuit_breaker.trip(market_paused_operations, 100).unwrap();
+        assert_eq!(
+            market_paused_operations,
+            PerpOperation::SettlePnl as u8
+                | PerpOperation::Fill as u8
+                | PerpOperation::AmmFill as u8
+        );
+        assert_eq!(
+            circuit_breaker.tripped_paused_operations,
+            PerpOperation::AmmFill as u8
+        );
+        assert_eq!(circuit_breaker.tripped_until_ts, 700);
+        assert!(circuit_breaker.is_tripped());
+
+        // no updates while tripped
+        assert_eq!(
+            circuit_breaker
+                .update(50 * PRICE_PRECISION_I64, 100 * PRICE_PRECISION_I64)
+                .unwrap(),
+            None
+        );
+        assert!(!circuit_breaker.can_reset(699));
+        assert!(circuit_breaker.can_reset(700));
+
+        let market_paused_operations = circuit_breaker.reset(market_paused_operations);
+        assert_eq!(
+            market_paused_operations,
+            PerpOperation::SettlePnl as u8 | PerpOperation::Fill as u8
+        );
+        assert!(!circuit_breaker.is_tripped());
+        assert_eq!(circuit_breaker.tripped_paused_operations, 0);
+    }
+
+    #[test]
+    fn disabled() {
+        let circuit_breaker = OracleCircuitBreaker {
+            max_price_move: 0,
+            ..circuit_breaker()
+        };
+
+        assert_eq!(
+            circuit_breaker
+                .update(10 * PRICE_PRECISION_I64, 100 * PRICE_PRECISION_I64)
+                .unwrap(),
+            None
+        );
+        assert!(!circuit_breaker.is_tripped());
+    }
+}
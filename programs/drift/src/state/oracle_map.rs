@@ -2,15 +2,21 @@ use crate::error::ErrorCode::UnableToLoadOracle;
 use crate::error::{DriftResult, ErrorCode};
 use crate::ids::{
     bonk_oracle, bonk_pull_oracle, drift_oracle_receiver_program, pepe_oracle, pyth_program,
-    switchboard_program, usdc_oracle, usdc_pull_oracle, usdt_oracle_mainnet,
+    switchboard_on_demand, switchboard_program, usdc_oracle, usdc_pull_oracle, usdt_oracle_mainnet,
     usdt_pull_oracle_mainnet,
 };
 use crate::math::constants::PRICE_PRECISION_I64;
-use crate::math::oracle::{oracle_validity, OracleValidity};
-use crate::state::oracle::{get_oracle_price, OraclePriceData, OracleSource, PrelaunchOracle};
-use crate::state::state::OracleGuardRails;
+use crate::math::oracle::{aggregate_oracle_price_data, oracle_validity, OracleValidity};
+use crate::state::load_ref::load_ref;
+use crate::state::option_market::OptionMarket;
+use crate::state::oracle::{
+    get_oracle_price, OracleAggregate, OracleAggregateMarket, OracleId, OraclePriceData,
+    OracleSource, PrelaunchOracle,
+};
+use crate::state::oracle_circuit_breaker::OracleCircuitBreaker;
+use crate::state::state::{OracleGuardRails, ValidityGuardRailsOverride};
 use crate::state::user::MarketType;
-use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::prelude::{AccountInfo, AccountLoader, Pubkey};
 use anchor_lang::Discriminator;
 use anchor_lang::Key;
 use arrayref::array_ref;
@@ -18,10 +24,12 @@ use solana_program::msg;
 use std::collections::BTreeMap;
 use std::iter::Peekable;
 use std::slice::Iter;
+use switchboard::PullFeedAccountData;
 
 use super::state::ValidityGuardRails;
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::traits::Size;
+use crate::validate;
 
 pub struct AccountInfoAndOracleSource<'a> {
     /// CHECK: ownders are validated in OracleMap::load
@@ -31,8 +39,12 @@ pub struct AccountInfoAndOracleSource<'a> {
 
 pub struct OracleMap<'a> {
     oracles: BTreeMap<Pubkey, AccountInfoAndOracleSource<'a>>,
-    price_data: BTreeMap<Pubkey, OraclePriceData>,
-    validity: BTreeMap<Pubkey, OracleValidity>,
+    /// Oracle aggregates keyed by their market
+    oracle_aggregates: BTreeMap<(MarketType, u16), OracleAggregate>,
+    /// Oracle circuit breakers keyed by their market
+    oracle_circuit_breakers: BTreeMap<(MarketType, u16), AccountLoader<'a, OracleCircuitBreaker>>,
+    price_data: BTreeMap<OracleId, OraclePriceData>,
+    validity: BTreeMap<OracleId, OracleValidity>,
     pub slot: u64,
     pub oracle_guard_rails: OracleGuardRails,
     pub quote_asset_price_data: OraclePriceData,
@@ -52,19 +64,77 @@ impl<'a> OracleMap<'a> {
             .clone())
     }
 
+    /// Markets with an oracle circuit breaker must pass it with their oracle to be updated or filled
+    pub fn get_oracle_circuit_breaker(
+        &self,
+        market_type: MarketType,
+        market_index: u16,
+    ) -> DriftResult<AccountLoader<'a, OracleCircuitBreaker>> {
+        self.oracle_circuit_breakers
+            .get(&(market_type, market_index))
+            .cloned()
+            .ok_or_else(|| {
+                msg!(
+                    "oracle circuit breaker for {} {} not found in oracle_map",
+                    market_type,
+                    market_index
+                );
+                ErrorCode::OracleCircuitBreakerNotFound
+            })
+    }
+
     fn should_get_quote_asset_price_data(&self, pubkey: &Pubkey) -> bool {
         pubkey == &Pubkey::default()
     }
 
-    pub fn get_price_data(&mut self, pubkey: &Pubkey) -> DriftResult<&OraclePriceData> {
-        if self.should_get_quote_asset_price_data(pubkey) {
+    /// Markets should read their price with their oracle_id so markets with an oracle aggregate
+    /// read the aggregate price
+    pub fn get_price_data(
+        &mut self,
+        oracle_id: impl Into<OracleId>,
+    ) -> DriftResult<&OraclePriceData> {
+        let oracle_id = oracle_id.into();
+        if self.should_get_quote_asset_price_data(&oracle_id.oracle) {
             return Ok(&self.quote_asset_price_data);
         }
 
-        if self.price_data.contains_key(pubkey) {
-            return self.price_data.get(pubkey).safe_unwrap();
+        if self.price_data.contains_key(&oracle_id) {
+            return self.price_data.get(&oracle_id).safe_unwrap();
         }
 
+        let price_data = match oracle_id.aggregate_market {
+            Some(aggregate_market) => {
+                let oracle_aggregate = *self
+                    .oracle_aggregates
+                    .get(&(aggregate_market.market_type, aggregate_market.market_index))
+                    .ok_or_else(|| {
+                        msg!(
+                            "oracle aggregate for {} {} not found in oracle_map",
+                            aggregate_market.market_type,
+                            aggregate_market.market_index
+                        );
+                        ErrorCode::OracleAggregateNotFound
+                    })?;
+
+                validate!(
+                    oracle_aggregate.primary_oracle == oracle_id.oracle,
+                    ErrorCode::InvalidOracle,
+                    "oracle aggregate primary oracle {} != market oracle {}",
+                    oracle_aggregate.primary_oracle,
+                    oracle_id.oracle
+                )?;
+
+                self.get_aggregate_price_data(&oracle_aggregate, &aggregate_market)?
+            }
+            None => self.get_oracle_price(&oracle_id.oracle)?,
+        };
+
+        self.price_data.insert(oracle_id, price_data);
+
+        self.price_data.get(&oracle_id).safe_unwrap()
+    }
+
+    fn get_oracle_price(&self, pubkey: &Pubkey) -> DriftResult<OraclePriceData> {
         let (account_info, oracle_source) = match self.oracles.get(pubkey) {
             Some(AccountInfoAndOracleSource {
                 account_info,
@@ -76,108 +146,103 @@ impl<'a> OracleMap<'a> {
             }
         };
 
-        let price_data = get_oracle_price(oracle_source, account_info, self.slot)?;
+        get_oracle_price(oracle_source, account_info, self.slot)
+    }
 
-        self.price_data.insert(*pubkey, price_data);
+    /// Every oracle of the aggregate must be passed in the oracle map. Stale or invalid ones are
+    /// skipped when aggregating
+    fn get_aggregate_price_data(
+        &self,
+        oracle_aggregate: &OracleAggregate,
+        aggregate_market: &OracleAggregateMarket,
+    ) -> DriftResult<OraclePriceData> {
+        let primary_price_data = self.get_oracle_price(&oracle_aggregate.primary_oracle)?;
+
+        let secondary_price_data = oracle_aggregate.get_oracles()[1..]
+            .iter()
+            .map(|oracle| self.get_oracle_price(oracle))
+            .collect::<DriftResult<Vec<OraclePriceData>>>()?;
+
+        let (price_data, sources_used) = aggregate_oracle_price_data(
+            oracle_aggregate,
+            &primary_price_data,
+            &secondary_price_data,
+            &self
+                .oracle_guard_rails
+                .validity
+                .with_override(aggregate_market.validity_guard_rails_override),
+            aggregate_market.max_confidence_interval_multiplier,
+        )?;
 
-        self.price_data.get(pubkey).safe_unwrap()
+        if sources_used & 1 == 0 {
+            msg!(
+                "{} {} primary oracle {} invalid, aggregate used sources {:#b}",
+                oracle_aggregate.market_type,
+                oracle_aggregate.market_index,
+                oracle_aggregate.primary_oracle,
+                sources_used
+            );
+        }
+
+        Ok(price_data)
     }
 
     pub fn get_price_data_and_validity(
         &mut self,
         market_type: MarketType,
         market_index: u16,
-        pubkey: &Pubkey,
+        oracle_id: impl Into<OracleId>,
         last_oracle_price_twap: i64,
         max_confidence_interval_multiplier: u64,
+        validity_guard_rails_override: ValidityGuardRailsOverride,
     ) -> DriftResult<(&OraclePriceData, OracleValidity)> {
-        if self.should_get_quote_asset_price_data(pubkey) {
+        let oracle_id = oracle_id.into();
+        if self.should_get_quote_asset_price_data(&oracle_id.oracle) {
             return Ok((&self.quote_asset_price_data, OracleValidity::Valid));
         }
 
-        if self.price_data.contains_key(pubkey) {
-            let oracle_price_data = self.price_data.get(pubkey).safe_unwrap()?;
-
-            let oracle_validity = if let Some(oracle_validity) = self.validity.get(pubkey) {
-                *oracle_validity
-            } else {
-                let oracle_validity = oracle_validity(
-                    market_type,
-                    market_index,
-                    last_oracle_price_twap,
-                    oracle_price_data,
-                    &self.oracle_guard_rails.validity,
-                    max_confidence_interval_multiplier,
-                    true,
-                )?;
-                self.validity.insert(*pubkey, oracle_validity);
-                oracle_validity
-            };
-            return Ok((oracle_price_data, oracle_validity));
+        if !self.price_data.contains_key(&oracle_id) {
+            self.get_price_data(oracle_id)?;
         }
 
-        let (account_info, oracle_source) = match self.oracles.get(pubkey) {
-            Some(AccountInfoAndOracleSource {
-                account_info,
-                oracle_source,
-            }) => (account_info, oracle_source),
-            None => {
-                msg!("oracle pubkey not found in oracle_map: {}", pubkey);
-                return Err(ErrorCode::OracleNotFound);
-            }
-        };
-
-        let price_data = get_oracle_price(oracle_source, account_info, self.slot)?;
-
-        self.price_data.insert(*pubkey, price_data);
-
-        let oracle_price_data = self.price_data.get(pubkey).safe_unwrap()?;
-        let oracle_validity = oracle_validity(
-            market_type,
-            market_index,
-            last_oracle_price_twap,
-            oracle_price_data,
-            &self.oracle_guard_rails.validity,
-            max_confidence_interval_multiplier,
-            true,
-        )?;
-        self.validity.insert(*pubkey, oracle_validity);
+        let oracle_price_data = self.price_data.get(&oracle_id).safe_unwrap()?;
 
+        let oracle_validity = if let Some(oracle_validity) = self.validity.get(&oracle_id) {
+            *oracle_validity
+        } else {
+            let oracle_validity = oracle_validity(
+                market_type,
+                market_index,
+                last_oracle_price_twap,
+                oracle_price_data,
+                &self
+                    .oracle_guard_rails
+                    .validity
+                    .with_override(validity_guard_rails_override),
+                max_confidence_interval_multiplier,
+                true,
+            )?;
+            self.validity.insert(oracle_id, oracle_validity);
+            oracle_validity
+        };
         Ok((oracle_price_data, oracle_validity))
     }
 
     pub fn get_price_data_and_guard_rails(
         &mut self,
-        pubkey: &Pubkey,
+        oracle_id: impl Into<OracleId>,
     ) -> DriftResult<(&OraclePriceData, &ValidityGuardRails)> {
-        if self.should_get_quote_asset_price_data(pubkey) {
+        let oracle_id = oracle_id.into();
+        if self.should_get_quote_asset_price_data(&oracle_id.oracle) {
             let validity_guard_rails = &self.oracle_guard_rails.validity;
             return Ok((&self.quote_asset_price_data, validity_guard_rails));
         }
 
-        if self.price_data.contains_key(pubkey) {
-            let oracle_price_data = self.price_data.get(pubkey).safe_unwrap()?;
-            let validity_guard_rails = &self.oracle_guard_rails.validity;
-
-            return Ok((oracle_price_data, validity_guard_rails));
+        if !self.price_data.contains_key(&oracle_id) {
+            self.get_price_data(oracle_id)?;
         }
 
-        let (account_info, oracle_source) = match self.oracles.get(pubkey) {
-            Some(AccountInfoAndOracleSource {
-                account_info,
-                oracle_source,
-            }) => (account_info, oracle_source),
-            None => {
-                msg!("oracle pubkey not found in oracle_map: {}", pubkey);
-                return Err(ErrorCode::OracleNotFound);
-            }
-        };
-
-        let price_data = get_oracle_price(oracle_source, account_info, self.slot)?;
-
-        self.price_data.insert(*pubkey, price_data);
-
-        let oracle_price_data = self.price_data.get(pubkey).safe_unwrap()?;
+        let oracle_price_data = self.price_data.get(&oracle_id).safe_unwrap()?;
         let validity_guard_rails = &self.oracle_guard_rails.validity;
 
         Ok((oracle_price_data, validity_guard_rails))
@@ -189,6 +254,11 @@ impl<'a> OracleMap<'a> {
         oracle_guard_rails: Option<OracleGuardRails>,
     ) -> DriftResult<OracleMap<'a>> {
         let mut oracles: BTreeMap<Pubkey, AccountInfoAndOracleSource<'a>> = BTreeMap::new();
+        let mut oracle_aggregates: BTreeMap<(MarketType, u16), OracleAggregate> = BTreeMap::new();
+        let mut oracle_circuit_breakers: BTreeMap<
+            (MarketType, u16),
+            AccountLoader<'a, OracleCircuitBreaker>,
+        > = BTreeMap::new();
 
         while let Some(account_info) = account_info_iter.peek() {
             if account_info.owner == &pyth_program::id() {
@@ -241,16 +311,60 @@ impl<'a> OracleMap<'a> {
                     UnableToLoadOracle
                 })?;
 
-                let expected_data_len = PrelaunchOracle::SIZE;
-                if data.len() < expected_data_len {
+                if data.len() < 8 {
                     break;
                 }
 
                 let account_discriminator = array_ref![data, 0, 8];
-                if account_discriminator != &PrelaunchOracle::discriminator() {
-                    break;
+                if data.len() >= OracleAggregate::SIZE
+                    && account_discriminator == &OracleAggregate::discriminator()
+                {
+                    let account_info = account_info_iter.next().safe_unwrap()?;
+                    let oracle_aggregate =
+                        *load_ref::<OracleAggregate>(account_info).or(Err(UnableToLoadOracle))?;
+
+                    oracle_aggregates.insert(
+                        (oracle_aggregate.market_type, oracle_aggregate.market_index),
+                        oracle_aggregate,
+                    );
+
+                    continue;
+                }
+
+                if data.len() >= OracleCircuitBreaker::SIZE
+                    && account_discriminator == &OracleCircuitBreaker::discriminator()
+                {
+                    let account_info = account_info_iter.next().safe_unwrap()?;
+
+                    validate!(
+                        account_info.is_writable,
+                        ErrorCode::InvalidOracle,
+                        "oracle circuit breaker {} must be writable",
+                        account_info.key()
+                    )?;
+
+                    let oracle_circuit_breaker: AccountLoader<'a, OracleCircuitBreaker> =
+                        AccountLoader::try_from(account_info).or(Err(UnableToLoadOracle))?;
+                    let (market_type, market_index) = {
+                        let oracle_circuit_breaker = load_ref::<OracleCircuitBreaker>(account_info)
+                            .or(Err(UnableToLoadOracle))?;
+                        (
+                            oracle_circuit_breaker.market_type,
+                            oracle_circuit_breaker.market_index,
+                        )
+                    };
+
+                    oracle_circuit_breakers
+                        .insert((market_type, market_index), oracle_circuit_breaker);
+
+                    continue;
                 }
 
+                let oracle_source = match get_drift_oracle_source(&data) {
+                    Some(oracle_source) => oracle_source,
+                    None => break,
+                };
+
                 let account_info = account_info_iter.next().safe_unwrap()?;
                 let pubkey = account_info.key();
 
@@ -258,7 +372,7 @@ impl<'a> OracleMap<'a> {
                     pubkey,
                     AccountInfoAndOracleSource {
                         account_info: account_info.clone(),
-                        oracle_source: OracleSource::Prelaunch,
+                        oracle_source,
                     },
                 );
 
@@ -266,12 +380,26 @@ impl<'a> OracleMap<'a> {
             } else if account_info.owner == &switchboard_program::id() {
                 let account_info = account_info_iter.next().safe_unwrap()?;
                 let pubkey = account_info.key();
+                let oracle_source = get_switchboard_oracle_source(account_info)?;
+
+                oracles.insert(
+                    pubkey,
+                    AccountInfoAndOracleSource {
+                        account_info: account_info.clone(),
+                        oracle_source,
+                    },
+                );
+
+                continue;
+            } else if account_info.owner == &switchboard_on_demand::id() {
+                let account_info = account_info_iter.next().safe_unwrap()?;
+                let pubkey = account_info.key();
 
                 oracles.insert(
                     pubkey,
                     AccountInfoAndOracleSource {
                         account_info: account_info.clone(),
-                        oracle_source: OracleSource::Switchboard,
+                        oracle_source: OracleSource::SwitchboardOnDemand,
                     },
                 );
 
@@ -289,6 +417,8 @@ impl<'a> OracleMap<'a> {
 
         Ok(OracleMap {
             oracles,
+            oracle_aggregates,
+            oracle_circuit_breakers,
             price_data: BTreeMap::new(),
             validity: BTreeMap::new(),
             slot,
@@ -347,33 +477,39 @@ impl<'a> OracleMap<'a> {
                 UnableToLoadOracle
             })?;
 
-            let expected_data_len = PrelaunchOracle::SIZE;
-            if data.len() < expected_data_len {
-                msg!("Unexpected account data len loading oracle");
-                return Err(UnableToLoadOracle);
-            }
-
-            let account_discriminator = array_ref![data, 0, 8];
-            if account_discriminator != &PrelaunchOracle::discriminator() {
-                msg!("Unexpected account discriminator");
-                return Err(UnableToLoadOracle);
-            }
+            let oracle_source = match get_drift_oracle_source(&data) {
+                Some(oracle_source) => oracle_source,
+                None => {
+                    msg!("Unexpected account data loading oracle");
+                    return Err(UnableToLoadOracle);
+                }
+            };
 
             let pubkey = account_info.key();
             oracles.insert(
                 pubkey,
                 AccountInfoAndOracleSource {
                     account_info: account_info.clone(),
-                    oracle_source: OracleSource::Prelaunch,
+                    oracle_source,
                 },
             );
         } else if account_info.owner == &switchboard_program::id() {
             let pubkey = account_info.key();
+            let oracle_source = get_switchboard_oracle_source(account_info)?;
             oracles.insert(
                 pubkey,
                 AccountInfoAndOracleSource {
                     account_info: account_info.clone(),
-                    oracle_source: OracleSource::Switchboard,
+                    oracle_source,
+                },
+            );
+        } else if account_info.owner == &switchboard_on_demand::id() {
+            let pubkey = account_info.key();
+            oracles.insert(
+                pubkey,
+                AccountInfoAndOracleSource {
+                    account_info: account_info.clone(),
+                    oracle_source: OracleSource::SwitchboardOnDemand,
                 },
             );
         } else if account_info.key() != Pubkey::default() {
@@ -388,6 +524,8 @@ impl<'a> OracleMap<'a> {
 
         Ok(OracleMap {
             oracles,
+            oracle_aggregates: BTreeMap::new(),
+            oracle_circuit_breakers: BTreeMap::new(),
             price_data: BTreeMap::new(),
             validity: BTreeMap::new(),
             slot,
@@ -402,11 +540,33 @@ impl<'a> OracleMap<'a> {
     }
 }
 
+/// Drift owned accounts that can be used as oracles: prelaunch oracles and option markets
+fn get_drift_oracle_source(data: &[u8]) -> Option<OracleSource> {
+    if data.len() < 8 {
+        return None;
+    }
+
+    let account_discriminator = array_ref![data, 0, 8];
+    if data.len() >= PrelaunchOracle::SIZE
+        && account_discriminator == &PrelaunchOracle::discriminator()
+    {
+        Some(OracleSource::Prelaunch)
+    } else if data.len() >= OptionMarket::SIZE
+        && account_discriminator == &OptionMarket::discriminator()
+    {
+        Some(OracleSource::EuropeanOption)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 impl<'a> OracleMap<'a> {
     pub fn empty() -> OracleMap<'a> {
         OracleMap {
             oracles: BTreeMap::new(),
+            oracle_aggregates: BTreeMap::new(),
+            oracle_circuit_breakers: BTreeMap::new(),
             validity: BTreeMap::new(),
             price_data: BTreeMap::new(),
             slot: 0,
@@ -420,3 +580,19 @@ impl<'a> OracleMap<'a> {
         }
     }
 }
+
+/// On-demand feeds are owned by the switchboard_on_demand program, but the local switchboard
+/// program writes both on-demand feeds and legacy aggregators, tell them apart by their
+/// discriminator
+fn get_switchboard_oracle_source(account_info: &AccountInfo) -> DriftResult<OracleSource> {
+    let data = account_info.try_borrow_data().map_err(|e| {
+        msg!("Failed to borrow data while loading oracle map {:?}", e);
+        UnableToLoadOracle
+    })?;
+
+    if data.len() >= 8 && array_ref![data, 0, 8] == &PullFeedAccountData::discriminator() {
+        Ok(OracleSource::SwitchboardOnDemand)
+    } else {
+        Ok(OracleSource::Switchboard)
+    }
+}
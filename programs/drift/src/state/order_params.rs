@@ -1,13 +1,14 @@
 use crate::controller::position::PositionDirection;
-use crate::error::DriftResult;
+use crate::error::{DriftResult, ErrorCode};
 use crate::math::casting::Cast;
+use crate::math::orders::{standardize_base_asset_amount, standardize_price};
 use crate::math::safe_math::SafeMath;
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::events::OrderActionExplanation;
 use crate::state::perp_market::{ContractTier, PerpMarket};
-use crate::state::user::{MarketType, OrderTriggerCondition, OrderType};
+use crate::state::user::{MarketType, OrderBitFlag, OrderTriggerCondition, OrderType};
 use crate::{
-    OracleSource, PERCENTAGE_PRECISION_I64, PERCENTAGE_PRECISION_U64, PRICE_PRECISION_I64,
+    validate, OracleSource, PERCENTAGE_PRECISION_I64, PERCENTAGE_PRECISION_U64, PRICE_PRECISION_I64,
 };
 use anchor_lang::prelude::*;
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -35,6 +36,10 @@ pub struct OrderParams {
     pub auction_duration: Option<u8>,     // specified in slots
     pub auction_start_price: Option<i64>, // specified in price or oracle_price_offset
     pub auction_end_price: Option<i64>,   // specified in price or oracle_price_offset
+    pub trailing_stop_offset: Option<TrailingStopOffset>, // only for trigger market orders
+    pub display_base_asset_amount: Option<u64>, // only for limit orders, shows this much of base_asset_amount at a time
+    pub max_slot: Option<u64>,                  // good til slot, can't be used with max_ts
+    pub fill_requirement: Option<FillRequirement>, // only for immediate or cancel limit orders
 }
 
 impl OrderParams {
@@ -638,6 +643,33 @@ fn get_auction_duration(
         .clamp(10, 180) as u8) // 180 slots max
 }
 
+/// How far the trigger price of a trailing stop trails the best oracle price seen since placement
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum TrailingStopOffset {
+    /// precision: PRICE_PRECISION
+    Price(u32),
+    /// precision: PERCENTAGE_PRECISION
+    Percentage(u32),
+}
+
+/// How much of an immediate or cancel order must fill when it is taken. A partial fill below the
+/// requirement reverts the instruction, an order that can't fill at all is canceled
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum FillRequirement {
+    FillOrKill,
+    /// precision for perps: BASE_PRECISION, for spot: token mint precision
+    MinimumFill(u64),
+}
+
+impl FillRequirement {
+    pub fn get_min_fill_base_asset_amount(&self, order_base_asset_amount: u64) -> u64 {
+        match self {
+            FillRequirement::FillOrKill => order_base_asset_amount,
+            FillRequirement::MinimumFill(min_fill_base_asset_amount) => *min_fill_base_asset_amount,
+        }
+    }
+}
+
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
 pub enum PostOnlyParam {
     #[default]
@@ -663,6 +695,7 @@ pub struct ModifyOrderParams {
     pub auction_start_price: Option<i64>,
     pub auction_end_price: Option<i64>,
     pub policy: Option<ModifyOrderPolicy>,
+    pub max_slot: Option<u64>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Eq, PartialEq)]
@@ -677,11 +710,164 @@ impl Default for ModifyOrderPolicy {
     }
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub struct TwapOrderParams {
+    pub direction: PositionDirection,
+    pub market_index: u16,
+    /// precision: BASE_PRECISION
+    pub base_asset_amount: u64,
+    pub num_slices: u16,
+    /// seconds between slices
+    pub interval: i64,
+    /// worst price a slice can fill at, 0 for no limit
+    /// precision: PRICE_PRECISION
+    pub price: u64,
+    pub reduce_only: bool,
+}
+
+/// A ladder of limit orders evenly spaced from start_price to end_price, expanded on-chain
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub struct ScaleOrderParams {
+    pub market_type: MarketType,
+    pub direction: PositionDirection,
+    pub market_index: u16,
+    /// size split across all the orders
+    /// precision for perps: BASE_PRECISION, for spot: token mint precision
+    pub total_base_asset_amount: u64,
+    /// price of the first order
+    /// precision: PRICE_PRECISION
+    pub start_price: u64,
+    /// price of the last order
+    /// precision: PRICE_PRECISION
+    pub end_price: u64,
+    pub num_orders: u8,
+    pub size_distribution: SizeDistribution,
+    pub reduce_only: bool,
+    pub post_only: PostOnlyParam,
+    pub max_ts: Option<i64>,
+}
+
+impl ScaleOrderParams {
+    /// Expands into num_orders limit orders. Prices and sizes are standardized to the market's tick
+    /// and step size, the last order takes whatever size is rounded off the others
+    pub fn get_order_params(
+        &self,
+        tick_size: u64,
+        step_size: u64,
+    ) -> DriftResult<Vec<OrderParams>> {
+        validate!(
+            self.num_orders >= 2 && self.num_orders <= 32,
+            ErrorCode::InvalidScaleOrder,
+            "num_orders ({}) must be between 2 and 32",
+            self.num_orders
+        )?;
+
+        validate!(
+            self.start_price > 0 && self.end_price > 0,
+            ErrorCode::InvalidScaleOrder,
+            "start_price ({}) and end_price ({}) must be greater than 0",
+            self.start_price,
+            self.end_price
+        )?;
+
+        validate!(
+            self.total_base_asset_amount > 0 && self.total_base_asset_amount % step_size == 0,
+            ErrorCode::InvalidScaleOrder,
+            "total_base_asset_amount ({}) must be a multiple of the step size ({})",
+            self.total_base_asset_amount,
+            step_size
+        )?;
+
+        let num_orders: u64 = self.num_orders.cast()?;
+        let total_weight = (0..num_orders)
+            .map(|i| self.size_distribution.get_weight(i, num_orders))
+            .sum::<u64>();
+
+        let mut order_params = Vec::with_capacity(num_orders.cast()?);
+        let mut base_asset_amount_remaining = self.total_base_asset_amount;
+        for i in 0..num_orders {
+            let price_step = self
+                .end_price
+                .abs_diff(self.start_price)
+                .safe_mul(i)?
+                .safe_div(num_orders - 1)?;
+            let price = if self.end_price >= self.start_price {
+                self.start_price.safe_add(price_step)?
+            } else {
+                self.start_price.safe_sub(price_step)?
+            };
+
+            let base_asset_amount = if i == num_orders - 1 {
+                base_asset_amount_remaining
+            } else {
+                standardize_base_asset_amount(
+                    self.total_base_asset_amount
+                        .cast::<u128>()?
+                        .safe_mul(self.size_distribution.get_weight(i, num_orders).cast()?)?
+                        .safe_div(total_weight.cast()?)?
+                        .cast()?,
+                    step_size,
+                )?
+            };
+
+            validate!(
+                base_asset_amount > 0,
+                ErrorCode::InvalidScaleOrder,
+                "total_base_asset_amount ({}) too small to split across {} orders",
+                self.total_base_asset_amount,
+                num_orders
+            )?;
+
+            base_asset_amount_remaining =
+                base_asset_amount_remaining.safe_sub(base_asset_amount)?;
+
+            order_params.push(OrderParams {
+                order_type: OrderType::Limit,
+                market_type: self.market_type,
+                direction: self.direction,
+                base_asset_amount,
+                price: standardize_price(price, tick_size, self.direction)?,
+                market_index: self.market_index,
+                reduce_only: self.reduce_only,
+                post_only: self.post_only,
+                max_ts: self.max_ts,
+                ..OrderParams::default()
+            });
+        }
+
+        Ok(order_params)
+    }
+}
+
+/// How the size of a scale order is split from the start price to the end price
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum SizeDistribution {
+    /// Every order is the same size
+    #[default]
+    Flat,
+    /// Orders get larger towards the end price
+    Ascending,
+    /// Orders get smaller towards the end price
+    Descending,
+}
+
+impl SizeDistribution {
+    pub fn get_weight(&self, index: u64, num_orders: u64) -> u64 {
+        match self {
+            SizeDistribution::Flat => 1,
+            SizeDistribution::Ascending => index + 1,
+            SizeDistribution::Descending => num_orders - index,
+        }
+    }
+}
+
 pub struct PlaceOrderOptions {
     pub try_expire_orders: bool,
     pub enforce_margin_check: bool,
     pub risk_increasing: bool,
     pub explanation: OrderActionExplanation,
+    pub bit_flags: u8,
+    pub bracket_id: u8,
 }
 
 impl Default for PlaceOrderOptions {
@@ -691,6 +877,8 @@ impl Default for PlaceOrderOptions {
             enforce_margin_check: true,
             risk_increasing: false,
             explanation: OrderActionExplanation::None,
+            bit_flags: 0,
+            bracket_id: 0,
         }
     }
 }
@@ -704,4 +892,15 @@ impl PlaceOrderOptions {
         self.explanation = explanation;
         self
     }
+
+    pub fn bracket(mut self, bracket_id: u8, bit_flags: u8) -> Self {
+        self.bracket_id = bracket_id;
+        self.bit_flags = bit_flags;
+        self
+    }
+
+    pub fn set_bit_flag(mut self, flag: OrderBitFlag) -> Self {
+        self.bit_flags |= flag as u8;
+        self
+    }
 }
@@ -855,7 +855,7 @@ mod get_close_perp_params {
     use crate::state::perp_market::{PerpMarket, AMM};
     use crate::{ContractTier, PRICE_PRECISION_U64};
 
-    use crate::state::user::{Order, OrderStatus};
+    use crate::state::user::{Order, OrderStatus, TimeInForce};
     use crate::test_utils::create_account_info;
     use crate::validation::order::validate_order;
     use crate::{OrderParams, PositionDirection, BASE_PRECISION_U64, PRICE_PRECISION_I64};
@@ -1185,7 +1185,9 @@ mod get_close_perp_params {
             auction_end_price: params.auction_end_price.unwrap_or(0),
             auction_duration: params.auction_duration.unwrap_or(0),
             max_ts: 100,
-            padding: [0; 3],
+            bit_flags: 0,
+            bracket_id: 0,
+            time_in_force: TimeInForce::GoodTilTime,
         }
     }
 
@@ -1250,3 +1252,138 @@ mod get_close_perp_params {
         validate_order(&order, &perp_market, Some(oracle_price), slot).unwrap();
     }
 }
+
+mod scale_order_params {
+    use crate::error::ErrorCode;
+    use crate::state::order_params::{PostOnlyParam, ScaleOrderParams, SizeDistribution};
+    use crate::state::user::{MarketType, OrderType};
+    use crate::{PositionDirection, BASE_PRECISION_U64, PRICE_PRECISION_U64};
+
+    #[test]
+    fn flat() {
+        let params = ScaleOrderParams {
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            total_base_asset_amount: BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 99 * PRICE_PRECISION_U64,
+            num_orders: 3,
+            size_distribution: SizeDistribution::Flat,
+            post_only: PostOnlyParam::MustPostOnly,
+            ..ScaleOrderParams::default()
+        };
+
+        let order_params = params
+            .get_order_params(PRICE_PRECISION_U64, BASE_PRECISION_U64 / 10)
+            .unwrap();
+
+        assert_eq!(order_params.len(), 3);
+        // 99.5 rounds down to the tick for bids
+        assert_eq!(
+            order_params.iter().map(|p| p.price).collect::<Vec<u64>>(),
+            vec![
+                100 * PRICE_PRECISION_U64,
+                99 * PRICE_PRECISION_U64,
+                99 * PRICE_PRECISION_U64
+            ]
+        );
+        // last order takes the remainder
+        assert_eq!(
+            order_params
+                .iter()
+                .map(|p| p.base_asset_amount)
+                .collect::<Vec<u64>>(),
+            vec![300000000, 300000000, 400000000]
+        );
+        for p in order_params.iter() {
+            assert_eq!(p.order_type, OrderType::Limit);
+            assert_eq!(p.market_type, MarketType::Perp);
+            assert_eq!(p.direction, PositionDirection::Long);
+            assert_eq!(p.post_only, PostOnlyParam::MustPostOnly);
+        }
+    }
+
+    #[test]
+    fn ascending_and_descending() {
+        let mut params = ScaleOrderParams {
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Short,
+            total_base_asset_amount: 10 * BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 103 * PRICE_PRECISION_U64,
+            num_orders: 4,
+            size_distribution: SizeDistribution::Ascending,
+            ..ScaleOrderParams::default()
+        };
+
+        let order_params = params
+            .get_order_params(PRICE_PRECISION_U64 / 100, BASE_PRECISION_U64 / 10)
+            .unwrap();
+
+        assert_eq!(
+            order_params.iter().map(|p| p.price).collect::<Vec<u64>>(),
+            vec![
+                100 * PRICE_PRECISION_U64,
+                101 * PRICE_PRECISION_U64,
+                102 * PRICE_PRECISION_U64,
+                103 * PRICE_PRECISION_U64
+            ]
+        );
+        assert_eq!(
+            order_params
+                .iter()
+                .map(|p| p.base_asset_amount)
+                .collect::<Vec<u64>>(),
+            vec![
+                BASE_PRECISION_U64,
+                2 * BASE_PRECISION_U64,
+                3 * BASE_PRECISION_U64,
+                4 * BASE_PRECISION_U64
+            ]
+        );
+
+        params.size_distribution = SizeDistribution::Descending;
+        let order_params = params
+            .get_order_params(PRICE_PRECISION_U64 / 100, BASE_PRECISION_U64 / 10)
+            .unwrap();
+
+        assert_eq!(
+            order_params
+                .iter()
+                .map(|p| p.base_asset_amount)
+                .collect::<Vec<u64>>(),
+            vec![
+                4 * BASE_PRECISION_U64,
+                3 * BASE_PRECISION_U64,
+                2 * BASE_PRECISION_U64,
+                BASE_PRECISION_U64
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let params = ScaleOrderParams {
+            total_base_asset_amount: BASE_PRECISION_U64,
+            start_price: 100 * PRICE_PRECISION_U64,
+            end_price: 99 * PRICE_PRECISION_U64,
+            num_orders: 33,
+            ..ScaleOrderParams::default()
+        };
+        assert_eq!(
+            params.get_order_params(PRICE_PRECISION_U64, BASE_PRECISION_U64 / 10),
+            Err(ErrorCode::InvalidScaleOrder)
+        );
+
+        // 0.1 can't be split across 2 orders of step size 0.1
+        let params = ScaleOrderParams {
+            total_base_asset_amount: BASE_PRECISION_U64 / 10,
+            num_orders: 2,
+            ..params
+        };
+        assert_eq!(
+            params.get_order_params(PRICE_PRECISION_U64, BASE_PRECISION_U64 / 10),
+            Err(ErrorCode::InvalidScaleOrder)
+        );
+    }
+}
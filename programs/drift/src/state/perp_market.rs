@@ -13,9 +13,11 @@ use crate::math::constants::{
 use crate::math::constants::{
     AMM_RESERVE_PRECISION_I128, AMM_TO_QUOTE_PRECISION_RATIO, BID_ASK_SPREAD_PRECISION,
     BID_ASK_SPREAD_PRECISION_U128, DEFAULT_REVENUE_SINCE_LAST_FUNDING_SPREAD_RETREAT,
-    LP_FEE_SLICE_DENOMINATOR, LP_FEE_SLICE_NUMERATOR, MARGIN_PRECISION_U128, PERCENTAGE_PRECISION,
-    PERCENTAGE_PRECISION_I128, PERCENTAGE_PRECISION_I64, PERCENTAGE_PRECISION_U64, PRICE_PRECISION,
-    SPOT_WEIGHT_PRECISION, TWENTY_FOUR_HOUR,
+    FUNDING_PARAMS_PRECISION, LP_FEE_SLICE_DENOMINATOR, LP_FEE_SLICE_NUMERATOR,
+    MARGIN_PRECISION_U128, MAX_FUNDING_PREMIUM_CLAMP_SLOPE, MAX_PREDICTION_MARKET_PRICE,
+    MAX_PREDICTION_MARKET_PRICE_I64, PERCENTAGE_PRECISION, PERCENTAGE_PRECISION_I128,
+    PERCENTAGE_PRECISION_I64, PERCENTAGE_PRECISION_U64, PRICE_PRECISION, SPOT_WEIGHT_PRECISION,
+    TWENTY_FOUR_HOUR,
 };
 use crate::math::helpers::get_proportion_i128;
 
@@ -23,15 +25,20 @@ use crate::math::margin::{
     calculate_size_discount_asset_weight, calculate_size_premium_liability_weight,
     MarginRequirementType,
 };
+use crate::math::position::calculate_base_asset_value_with_oracle_price;
 use crate::math::safe_math::SafeMath;
 use crate::math::stats;
 use crate::state::events::OrderActionExplanation;
 
 use crate::state::oracle::{
-    get_prelaunch_price, get_switchboard_price, HistoricalOracleData, OracleSource,
+    get_option_market_price, get_prelaunch_price, get_switchboard_on_demand_price,
+    get_switchboard_price, HistoricalOracleData, OracleId, OraclePriceData, OracleSource,
 };
 use crate::state::spot_market::{AssetTier, SpotBalance, SpotBalanceType};
+use crate::state::state::ValidityGuardRailsOverride;
 use crate::state::traits::{MarketIndexOffset, Size};
+use crate::state::user::MarketType;
+use crate::validate;
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use crate::state::paused_operations::PerpOperation;
@@ -86,6 +93,17 @@ pub enum ContractType {
     #[default]
     Perpetual,
     Future,
+    Option,
+    Prediction,
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum PredictionMarketResolution {
+    /// Settles at the oracle price once it reports 0 or 1
+    #[default]
+    Unresolved,
+    Yes,
+    No,
 }
 
 #[derive(
@@ -209,7 +227,8 @@ pub struct PerpMarket {
     /// Whether a market is active, reduce only, expired, etc
     /// Affects whether users can open/close positions
     pub status: MarketStatus,
-    /// Currently only Perpetual markets are supported
+    /// Perpetuals pay funding, dated futures, options and prediction markets don't and settle at
+    /// expiry_ts
     pub contract_type: ContractType,
     /// The contract tier determines how much insurance a market can receive, with more speculative markets receiving less insurance
     /// It also influences the order perp markets can be liquidated, with less speculative markets being liquidated first
@@ -230,7 +249,38 @@ pub struct PerpMarket {
     /// fuel multiplier for perp maker
     /// precision: 10
     pub fuel_boost_maker: u8,
-    pub padding: [u8; 43],
+    pub padding1: u8,
+    /// Overrides the state's oracle slots_before_stale_for_amm. 0 uses the state's
+    pub oracle_slots_before_stale_for_amm: u16,
+    /// Overrides the state's oracle confidence_interval_max_size. 0 uses the state's
+    /// precision: BID_ASK_SPREAD_PRECISION
+    pub oracle_confidence_interval_max_size: u32,
+    /// Overrides the state's oracle slots_before_stale_for_margin. 0 uses the state's
+    pub oracle_slots_before_stale_for_margin: u16,
+    /// The dated future listed when this one rolled over. 0 if it hasn't rolled over
+    pub rollover_market_index: u16,
+    /// The oracle twap over the settlement window, the target expiry price of dated futures
+    /// precision: PRICE_PRECISION
+    pub settlement_price_twap: i64,
+    /// unix_timestamp of the last settlement_price_twap update
+    pub settlement_price_twap_ts: i64,
+    /// The seconds before expiry_ts the settlement price twap is taken over. 0 settles dated
+    /// futures at the oracle twap
+    pub settlement_window: u32,
+    /// The seconds between a dated future's expiry and the next one's, which keepers can list once
+    /// the settlement window starts. 0 disables rollovers
+    pub rollover_period: u32,
+    /// The outcome an admin resolved a prediction market to, unresolved markets settle at their
+    /// oracle price
+    pub prediction_market_resolution: PredictionMarketResolution,
+    /// Whether prices are read from the market's OracleAggregate
+    pub has_oracle_aggregate: bool,
+    /// Whether amm updates and fills evaluate the market's OracleCircuitBreaker
+    pub has_oracle_circuit_breaker: bool,
+    pub padding2: u8,
+    /// The truncated part of the last continuous funding accrual, carried into the next
+    /// precision: FUNDING_RATE_PRECISION / TWENTY_FOUR_HOUR
+    pub funding_rate_remainder: i32,
 }
 
 impl Default for PerpMarket {
@@ -267,7 +317,20 @@ impl Default for PerpMarket {
             fuel_boost_position: 0,
             fuel_boost_taker: 0,
             fuel_boost_maker: 0,
-            padding: [0; 43],
+            padding1: 0,
+            oracle_slots_before_stale_for_amm: 0,
+            oracle_confidence_interval_max_size: 0,
+            oracle_slots_before_stale_for_margin: 0,
+            rollover_market_index: 0,
+            settlement_price_twap: 0,
+            settlement_price_twap_ts: 0,
+            settlement_window: 0,
+            rollover_period: 0,
+            prediction_market_resolution: PredictionMarketResolution::default(),
+            has_oracle_aggregate: false,
+            has_oracle_circuit_breaker: false,
+            padding2: 0,
+            funding_rate_remainder: 0,
         }
     }
 }
@@ -294,6 +357,307 @@ impl PerpMarket {
         Ok(self.status == MarketStatus::ReduceOnly)
     }
 
+    pub fn is_future(&self) -> bool {
+        self.contract_type == ContractType::Future
+    }
+
+    pub fn is_option(&self) -> bool {
+        self.contract_type == ContractType::Option
+    }
+
+    pub fn is_prediction_market(&self) -> bool {
+        self.contract_type == ContractType::Prediction
+    }
+
+    /// Prediction markets price inside [0, MAX_PREDICTION_MARKET_PRICE] whatever their oracle reports
+    pub fn get_bounded_oracle_price(&self, oracle_price: i64) -> i64 {
+        if self.is_prediction_market() {
+            oracle_price.clamp(0, MAX_PREDICTION_MARKET_PRICE_I64)
+        } else {
+            oracle_price
+        }
+    }
+
+    /// Prediction markets only fill inside [order_tick_size, MAX_PREDICTION_MARKET_PRICE]
+    pub fn get_bounded_fill_price(&self, price: u64) -> u64 {
+        if self.is_prediction_market() {
+            price.clamp(self.amm.order_tick_size, MAX_PREDICTION_MARKET_PRICE)
+        } else {
+            price
+        }
+    }
+
+    /// Orders without a limit price in prediction markets are bounded by the end of the range
+    /// they trade towards
+    pub fn get_bounded_limit_price(
+        &self,
+        limit_price: Option<u64>,
+        direction: PositionDirection,
+    ) -> Option<u64> {
+        if !self.is_prediction_market() {
+            return limit_price;
+        }
+
+        let limit_price = limit_price.unwrap_or(match direction {
+            PositionDirection::Long => MAX_PREDICTION_MARKET_PRICE,
+            PositionDirection::Short => self.amm.order_tick_size,
+        });
+
+        Some(self.get_bounded_fill_price(limit_price))
+    }
+
+    pub fn update_bounded_spread_reserves(&mut self) -> DriftResult {
+        if self.is_prediction_market() {
+            amm::clamp_spread_reserves(
+                &mut self.amm,
+                self.amm.order_tick_size,
+                MAX_PREDICTION_MARKET_PRICE,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The price a prediction market settles at: its admin resolution, or its oracle price once
+    /// that is exactly 0 or MAX_PREDICTION_MARKET_PRICE
+    pub fn get_prediction_market_settlement_price(&self, oracle_price: i64) -> DriftResult<i64> {
+        match self.prediction_market_resolution {
+            PredictionMarketResolution::Yes => Ok(MAX_PREDICTION_MARKET_PRICE_I64),
+            PredictionMarketResolution::No => Ok(0),
+            PredictionMarketResolution::Unresolved => {
+                validate!(
+                    oracle_price == 0 || oracle_price == MAX_PREDICTION_MARKET_PRICE_I64,
+                    ErrorCode::MarketSettlementTargetPriceInvalid,
+                    "unresolved prediction market oracle price {} isn't 0 or {}",
+                    oracle_price,
+                    MAX_PREDICTION_MARKET_PRICE_I64
+                )?;
+
+                Ok(oracle_price)
+            }
+        }
+    }
+
+    pub fn get_settlement_window_start_ts(&self) -> DriftResult<i64> {
+        self.expiry_ts.safe_sub(self.settlement_window.cast()?)
+    }
+
+    pub fn update_settlement_price_twap(&mut self, oracle_price: i64, now: i64) -> DriftResult {
+        if !self.is_future() || self.expiry_ts == 0 || self.settlement_window == 0 {
+            return Ok(());
+        }
+
+        if let Some((settlement_price_twap, settlement_price_twap_ts)) =
+            stats::calculate_window_twap(
+                oracle_price,
+                now,
+                self.settlement_price_twap,
+                self.settlement_price_twap_ts,
+                self.get_settlement_window_start_ts()?,
+                self.expiry_ts,
+            )?
+        {
+            self.settlement_price_twap = settlement_price_twap;
+            self.settlement_price_twap_ts = settlement_price_twap_ts;
+        }
+
+        Ok(())
+    }
+
+    /// The price the market is settled towards at expiry. Dated futures with a settlement window
+    /// use the oracle twap over the window, if it was sampled
+    pub fn get_target_expiry_price(&self) -> DriftResult<i64> {
+        if self.is_future()
+            && self.settlement_window != 0
+            && self.settlement_price_twap_ts >= self.get_settlement_window_start_ts()?
+        {
+            Ok(self.settlement_price_twap)
+        } else {
+            Ok(self.amm.historical_oracle_data.last_oracle_price_twap)
+        }
+    }
+
+    /// The price positions are valued at for margin
+    pub fn get_margin_valuation_price(&self, oracle_price: i64) -> DriftResult<i64> {
+        if self.status == MarketStatus::Settlement {
+            return Ok(self.expiry_price);
+        }
+
+        if self.is_prediction_market() {
+            return Ok(self.get_bounded_oracle_price(oracle_price));
+        }
+
+        Ok(oracle_price)
+    }
+
+    /// Dated futures trade at a basis to the oracle they're valued at. The basis against a
+    /// position is charged as extra margin, capped at the maintenance margin ratio of the oracle
+    /// price
+    /// precision: QUOTE_PRECISION
+    pub fn get_basis_margin_requirement(
+        &self,
+        base_asset_amount: i128,
+        oracle_price: i64,
+    ) -> DriftResult<u128> {
+        if !self.is_future() || self.status == MarketStatus::Settlement || base_asset_amount == 0 {
+            return Ok(0);
+        }
+
+        let basis = self
+            .amm
+            .last_mark_price_twap
+            .cast::<i64>()?
+            .safe_sub(self.amm.historical_oracle_data.last_oracle_price_twap)?;
+
+        let max_basis = oracle_price
+            .unsigned_abs()
+            .cast::<u128>()?
+            .safe_mul(self.margin_ratio_maintenance.cast()?)?
+            .safe_div(MARGIN_PRECISION_U128)?
+            .cast::<i64>()?;
+
+        let basis = basis.clamp(-max_basis, max_basis);
+
+        // longs close below the oracle when the future trades under it, shorts above it when over
+        let adverse_basis = if base_asset_amount > 0 {
+            basis.min(0).abs()
+        } else {
+            basis.max(0)
+        };
+
+        calculate_base_asset_value_with_oracle_price(base_asset_amount, adverse_basis)
+    }
+
+    /// The next dated future, configured like this one with fresh state and expiring
+    /// rollover_period after it
+    pub fn get_rollover_market(
+        &self,
+        pubkey: Pubkey,
+        market_index: u16,
+        oracle_price_data: &OraclePriceData,
+        now: i64,
+        slot: u64,
+    ) -> DriftResult<PerpMarket> {
+        let oracle_price = oracle_price_data.price;
+        validate!(
+            oracle_price > 0,
+            ErrorCode::InvalidOracle,
+            "oracle price {} <= 0",
+            oracle_price
+        )?;
+
+        let expiry_ts = self.expiry_ts.safe_add(self.rollover_period.cast()?)?;
+        validate!(
+            expiry_ts > now,
+            ErrorCode::InvalidPerpMarketRollover,
+            "rollover expiry ts {} <= now {}",
+            expiry_ts,
+            now
+        )?;
+
+        let sqrt_k = self.amm.sqrt_k;
+        let peg_multiplier = oracle_price.cast::<u128>()?;
+        let reserve_price = oracle_price.cast::<u64>()?;
+        let (min_base_asset_reserve, max_base_asset_reserve) =
+            amm::calculate_bid_ask_bounds(self.amm.concentration_coef, sqrt_k)?;
+
+        // the oracle aggregate and circuit breaker accounts are per market, so a market relying on
+        // them waits for the admin to set them up for the rolled market before it is activated
+        let status = if self.has_oracle_aggregate || self.has_oracle_circuit_breaker {
+            MarketStatus::Initialized
+        } else {
+            MarketStatus::Active
+        };
+
+        Ok(PerpMarket {
+            pubkey,
+            market_index,
+            name: self.name,
+            status,
+            contract_type: ContractType::Future,
+            contract_tier: self.contract_tier,
+            expiry_ts,
+            next_fill_record_id: 1,
+            next_funding_rate_record_id: 1,
+            next_curve_record_id: 1,
+            insurance_claim: InsuranceClaim {
+                max_revenue_withdraw_per_period: self
+                    .insurance_claim
+                    .max_revenue_withdraw_per_period,
+                quote_max_insurance: self.insurance_claim.quote_max_insurance,
+                ..InsuranceClaim::default()
+            },
+            unrealized_pnl_max_imbalance: self.unrealized_pnl_max_imbalance,
+            imf_factor: self.imf_factor,
+            unrealized_pnl_imf_factor: self.unrealized_pnl_imf_factor,
+            liquidator_fee: self.liquidator_fee,
+            if_liquidation_fee: self.if_liquidation_fee,
+            margin_ratio_initial: self.margin_ratio_initial,
+            margin_ratio_maintenance: self.margin_ratio_maintenance,
+            unrealized_pnl_initial_asset_weight: self.unrealized_pnl_initial_asset_weight,
+            unrealized_pnl_maintenance_asset_weight: self.unrealized_pnl_maintenance_asset_weight,
+            quote_spot_market_index: self.quote_spot_market_index,
+            fee_adjustment: self.fee_adjustment,
+            fuel_boost_position: self.fuel_boost_position,
+            fuel_boost_taker: self.fuel_boost_taker,
+            fuel_boost_maker: self.fuel_boost_maker,
+            oracle_slots_before_stale_for_amm: self.oracle_slots_before_stale_for_amm,
+            oracle_confidence_interval_max_size: self.oracle_confidence_interval_max_size,
+            oracle_slots_before_stale_for_margin: self.oracle_slots_before_stale_for_margin,
+            settlement_window: self.settlement_window,
+            rollover_period: self.rollover_period,
+            amm: AMM {
+                oracle: self.amm.oracle,
+                oracle_source: self.amm.oracle_source,
+                base_asset_reserve: sqrt_k,
+                quote_asset_reserve: sqrt_k,
+                terminal_quote_asset_reserve: sqrt_k,
+                ask_base_asset_reserve: sqrt_k,
+                ask_quote_asset_reserve: sqrt_k,
+                bid_base_asset_reserve: sqrt_k,
+                bid_quote_asset_reserve: sqrt_k,
+                sqrt_k,
+                peg_multiplier,
+                concentration_coef: self.amm.concentration_coef,
+                min_base_asset_reserve,
+                max_base_asset_reserve,
+                funding_period: self.amm.funding_period,
+                last_funding_rate_ts: now,
+                last_mark_price_twap: reserve_price,
+                last_mark_price_twap_5min: reserve_price,
+                last_mark_price_twap_ts: now,
+                last_bid_price_twap: reserve_price,
+                last_ask_price_twap: reserve_price,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap_ts: now,
+                    ..HistoricalOracleData::default_with_current_oracle(*oracle_price_data)
+                },
+                last_oracle_normalised_price: oracle_price,
+                order_step_size: self.amm.order_step_size,
+                order_tick_size: self.amm.order_tick_size,
+                min_order_size: self.amm.min_order_size,
+                max_position_size: self.amm.max_position_size,
+                max_slippage_ratio: self.amm.max_slippage_ratio,
+                max_fill_reserve_fraction: self.amm.max_fill_reserve_fraction,
+                base_spread: self.amm.base_spread,
+                max_spread: self.amm.max_spread,
+                max_open_interest: self.amm.max_open_interest,
+                curve_update_intensity: self.amm.curve_update_intensity,
+                amm_jit_intensity: self.amm.amm_jit_intensity,
+                target_base_asset_amount_per_lp: self.amm.target_base_asset_amount_per_lp,
+                per_lp_base: self.amm.per_lp_base,
+                funding_params: FundingParams {
+                    last_funding_period: 0,
+                    ..self.amm.funding_params
+                },
+                last_trade_ts: now,
+                last_update_slot: slot,
+                ..AMM::default()
+            },
+            ..PerpMarket::default()
+        })
+    }
+
     pub fn is_operation_paused(&self, operation: PerpOperation) -> bool {
         PerpOperation::is_operation_paused(self.paused_operations, operation)
     }
@@ -337,16 +701,45 @@ impl PerpMarket {
         Ok(false)
     }
 
+    /// The market's price in the oracle map, its oracle aggregate if it has one
+    pub fn oracle_id(&self) -> OracleId {
+        OracleId::new(
+            self.amm.oracle,
+            MarketType::Perp,
+            self.market_index,
+            self.has_oracle_aggregate,
+            self.get_validity_guard_rails_override(),
+            self.max_confidence_interval_multiplier(),
+        )
+    }
+
     pub fn get_max_confidence_interval_multiplier(self) -> DriftResult<u64> {
+        Ok(self.max_confidence_interval_multiplier())
+    }
+
+    fn max_confidence_interval_multiplier(&self) -> u64 {
+        // an overridden confidence interval max size isn't scaled by contract tier
+        if self.oracle_confidence_interval_max_size != 0 {
+            return 1;
+        }
+
         // assuming validity_guard_rails max confidence pct is 2%
-        Ok(match self.contract_tier {
+        match self.contract_tier {
             ContractTier::A => 1,                  // 2%
             ContractTier::B => 1,                  // 2%
             ContractTier::C => 2,                  // 4%
             ContractTier::Speculative => 10,       // 20%
             ContractTier::HighlySpeculative => 50, // 100%
             ContractTier::Isolated => 50,          // 100%
-        })
+        }
+    }
+
+    pub fn get_validity_guard_rails_override(&self) -> ValidityGuardRailsOverride {
+        ValidityGuardRailsOverride {
+            slots_before_stale_for_amm: self.oracle_slots_before_stale_for_amm,
+            slots_before_stale_for_margin: self.oracle_slots_before_stale_for_margin,
+            confidence_interval_max_size: self.oracle_confidence_interval_max_size,
+        }
     }
 
     pub fn get_sanitize_clamp_denominator(self) -> DriftResult<Option<i64>> {
@@ -668,6 +1061,63 @@ impl SpotBalance for PoolBalance {
     }
 }
 
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum FundingPremiumIndex {
+    /// the amm's mark twap against the oracle twap
+    #[default]
+    MarkTwap,
+    /// the amm's bid twap above or ask twap below the oracle twap, no premium in between
+    BidAskTwap,
+}
+
+#[zero_copy(unsafe)]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct FundingParams {
+    /// the funding period at the last funding rate update. funding accrues over the time since
+    /// that update up to the longer of it and the current funding period, so changing the
+    /// funding period doesn't drop or stretch the interval already running
+    pub last_funding_period: u32,
+    /// annualized interest rate added to the premium
+    /// precision: FUNDING_PARAMS_PRECISION
+    pub interest_rate: i16,
+    /// the premium, as a fraction of the oracle twap, that counts in full
+    /// precision: FUNDING_PARAMS_PRECISION
+    pub premium_clamp_threshold: u16,
+    /// the percentage of the premium beyond premium_clamp_threshold that counts. 0-100
+    pub premium_clamp_slope: u8,
+    pub premium_index: FundingPremiumIndex,
+    pub enabled: bool,
+    /// accrue funding every second the amm is updated instead of once per funding period. the
+    /// funding imbalance doesn't feed formulaic k updates then
+    pub continuous: bool,
+}
+
+impl FundingParams {
+    pub fn is_continuous(&self) -> bool {
+        self.enabled && self.continuous
+    }
+
+    pub fn validate(&self) -> DriftResult {
+        validate!(
+            self.premium_clamp_slope <= MAX_FUNDING_PREMIUM_CLAMP_SLOPE,
+            ErrorCode::InvalidFundingParams,
+            "premium_clamp_slope {} > {}",
+            self.premium_clamp_slope,
+            MAX_FUNDING_PREMIUM_CLAMP_SLOPE
+        )?;
+
+        validate!(
+            self.interest_rate.unsigned_abs().cast::<i64>()? <= FUNDING_PARAMS_PRECISION,
+            ErrorCode::InvalidFundingParams,
+            "interest_rate {} must be within +/-100%",
+            self.interest_rate
+        )?;
+
+        Ok(())
+    }
+}
+
 #[assert_no_slop]
 #[zero_copy(unsafe)]
 #[derive(Debug, PartialEq, Eq)]
@@ -884,7 +1334,8 @@ pub struct AMM {
     pub net_unsettled_funding_pnl: i64,
     pub quote_asset_amount_with_unsettled_lp: i64,
     pub reference_price_offset: i32,
-    pub padding: [u8; 12],
+    /// per market funding rate configuration, the default tier capped formula is used when disabled
+    pub funding_params: FundingParams,
 }
 
 impl Default for AMM {
@@ -973,7 +1424,7 @@ impl Default for AMM {
             net_unsettled_funding_pnl: 0,
             quote_asset_amount_with_unsettled_lp: 0,
             reference_price_offset: 0,
-            padding: [0; 12],
+            funding_params: FundingParams::default(),
         }
     }
 }
@@ -1306,6 +1757,12 @@ impl AMM {
             OracleSource::Pyth1MPull => {
                 Ok(Some(self.get_pyth_twap(price_oracle, 1000000, true)?))
             }
+            OracleSource::SwitchboardOnDemand => Ok(Some(
+                get_switchboard_on_demand_price(price_oracle, slot)?.price,
+            )),
+            OracleSource::EuropeanOption => {
+                Ok(Some(get_option_market_price(price_oracle, slot)?.price))
+            }
         }
     }
 
@@ -40,3 +40,393 @@ mod amm {
         assert_eq!(discount, 10000000); // $1
     }
 }
+
+mod dated_future {
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_I128, MAX_CONCENTRATION_COEFFICIENT,
+        PRICE_PRECISION_I64, PRICE_PRECISION_U64, QUOTE_PRECISION,
+    };
+    use crate::state::oracle::{HistoricalOracleData, OraclePriceData};
+    use crate::state::perp_market::{ContractType, FundingParams, MarketStatus, PerpMarket, AMM};
+    use anchor_lang::prelude::Pubkey;
+
+    #[test]
+    fn settlement_price_twap() {
+        let mut market = PerpMarket {
+            contract_type: ContractType::Future,
+            expiry_ts: 1000,
+            settlement_window: 100,
+            amm: AMM {
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: 90 * PRICE_PRECISION_I64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            ..PerpMarket::default()
+        };
+
+        // before the window
+        market
+            .update_settlement_price_twap(100 * PRICE_PRECISION_I64, 850)
+            .unwrap();
+        assert_eq!(market.settlement_price_twap_ts, 0);
+        assert_eq!(
+            market.get_target_expiry_price().unwrap(),
+            90 * PRICE_PRECISION_I64
+        );
+
+        market
+            .update_settlement_price_twap(100 * PRICE_PRECISION_I64, 910)
+            .unwrap();
+        assert_eq!(market.settlement_price_twap, 100 * PRICE_PRECISION_I64);
+        assert_eq!(market.settlement_price_twap_ts, 910);
+
+        market
+            .update_settlement_price_twap(130 * PRICE_PRECISION_I64, 940)
+            .unwrap();
+        assert_eq!(market.settlement_price_twap, 122_500_001);
+
+        // updates past expiry only count up to expiry
+        market
+            .update_settlement_price_twap(200 * PRICE_PRECISION_I64, 1100)
+            .unwrap();
+        assert_eq!(market.settlement_price_twap, 169_000_001);
+        assert_eq!(market.settlement_price_twap_ts, 1000);
+
+        market
+            .update_settlement_price_twap(300 * PRICE_PRECISION_I64, 1200)
+            .unwrap();
+        assert_eq!(market.settlement_price_twap, 169_000_001);
+        assert_eq!(market.get_target_expiry_price().unwrap(), 169_000_001);
+
+        // perpetuals settle at the oracle twap
+        market.contract_type = ContractType::Perpetual;
+        assert_eq!(
+            market.get_target_expiry_price().unwrap(),
+            90 * PRICE_PRECISION_I64
+        );
+    }
+
+    #[test]
+    fn margin_valuation_price() {
+        let mut market = PerpMarket {
+            contract_type: ContractType::Future,
+            margin_ratio_maintenance: 500,
+            amm: AMM {
+                last_mark_price_twap: 103 * PRICE_PRECISION_U64,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: 100 * PRICE_PRECISION_I64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            ..PerpMarket::default()
+        };
+
+        // futures are valued at the oracle
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        assert_eq!(
+            market.get_margin_valuation_price(oracle_price).unwrap(),
+            oracle_price
+        );
+
+        market.status = MarketStatus::Settlement;
+        market.expiry_price = 99 * PRICE_PRECISION_I64;
+        assert_eq!(
+            market.get_margin_valuation_price(oracle_price).unwrap(),
+            99 * PRICE_PRECISION_I64
+        );
+    }
+
+    #[test]
+    fn basis_margin_requirement() {
+        let mut market = PerpMarket {
+            contract_type: ContractType::Future,
+            margin_ratio_maintenance: 500,
+            amm: AMM {
+                last_mark_price_twap: 103 * PRICE_PRECISION_U64,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: 100 * PRICE_PRECISION_I64,
+                    ..HistoricalOracleData::default()
+                },
+                ..AMM::default()
+            },
+            ..PerpMarket::default()
+        };
+
+        let oracle_price = 100 * PRICE_PRECISION_I64;
+        let long = 2 * BASE_PRECISION_I128;
+        let short = -2 * BASE_PRECISION_I128;
+
+        // the future trades over the oracle, shorts are charged the basis
+        assert_eq!(
+            market
+                .get_basis_margin_requirement(short, oracle_price)
+                .unwrap(),
+            6 * QUOTE_PRECISION
+        );
+        assert_eq!(
+            market
+                .get_basis_margin_requirement(long, oracle_price)
+                .unwrap(),
+            0
+        );
+
+        // basis capped at the maintenance margin ratio
+        market.amm.last_mark_price_twap = 80 * PRICE_PRECISION_U64;
+        assert_eq!(
+            market
+                .get_basis_margin_requirement(long, oracle_price)
+                .unwrap(),
+            10 * QUOTE_PRECISION
+        );
+        assert_eq!(
+            market
+                .get_basis_margin_requirement(short, oracle_price)
+                .unwrap(),
+            0
+        );
+
+        market.contract_type = ContractType::Perpetual;
+        assert_eq!(
+            market
+                .get_basis_margin_requirement(long, oracle_price)
+                .unwrap(),
+            0
+        );
+
+        market.contract_type = ContractType::Future;
+        market.status = MarketStatus::Settlement;
+        assert_eq!(
+            market
+                .get_basis_margin_requirement(long, oracle_price)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn rollover_market() {
+        let market = PerpMarket {
+            market_index: 3,
+            status: MarketStatus::Settlement,
+            contract_type: ContractType::Future,
+            expiry_ts: 1000,
+            expiry_price: 48 * PRICE_PRECISION_I64,
+            settlement_window: 100,
+            rollover_period: 500,
+            margin_ratio_initial: 1000,
+            margin_ratio_maintenance: 500,
+            number_of_users: 10,
+            amm: AMM {
+                oracle: Pubkey::new_unique(),
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                base_asset_reserve: 90 * AMM_RESERVE_PRECISION,
+                concentration_coef: MAX_CONCENTRATION_COEFFICIENT,
+                base_asset_amount_with_amm: 10 * AMM_RESERVE_PRECISION as i128,
+                order_step_size: 1000,
+                order_tick_size: 100,
+                base_spread: 250,
+                funding_params: FundingParams {
+                    last_funding_period: 3600,
+                    interest_rate: 100,
+                    enabled: true,
+                    ..FundingParams::default()
+                },
+                ..AMM::default()
+            },
+            ..PerpMarket::default()
+        };
+
+        let oracle_price_data = OraclePriceData {
+            price: 50 * PRICE_PRECISION_I64,
+            confidence: 1,
+            delay: 2,
+            has_sufficient_number_of_data_points: true,
+        };
+
+        let rollover_market = market
+            .get_rollover_market(Pubkey::new_unique(), 7, &oracle_price_data, 1050, 20)
+            .unwrap();
+
+        assert_eq!(rollover_market.market_index, 7);
+        assert_eq!(rollover_market.status, MarketStatus::Active);
+        assert_eq!(rollover_market.contract_type, ContractType::Future);
+        assert_eq!(rollover_market.expiry_ts, 1500);
+        assert_eq!(rollover_market.expiry_price, 0);
+        assert_eq!(rollover_market.settlement_window, 100);
+        assert_eq!(rollover_market.rollover_period, 500);
+        assert_eq!(rollover_market.margin_ratio_initial, 1000);
+        assert_eq!(rollover_market.margin_ratio_maintenance, 500);
+        assert_eq!(rollover_market.number_of_users, 0);
+        assert_eq!(rollover_market.amm.oracle, market.amm.oracle);
+        assert_eq!(
+            rollover_market.amm.base_asset_reserve,
+            100 * AMM_RESERVE_PRECISION
+        );
+        assert_eq!(rollover_market.amm.base_asset_amount_with_amm, 0);
+        assert_eq!(rollover_market.amm.order_step_size, 1000);
+        assert_eq!(rollover_market.amm.base_spread, 250);
+        assert_eq!(
+            rollover_market.amm.funding_params,
+            FundingParams {
+                interest_rate: 100,
+                enabled: true,
+                ..FundingParams::default()
+            }
+        );
+        assert_eq!(
+            rollover_market.amm.reserve_price().unwrap(),
+            50 * PRICE_PRECISION_U64
+        );
+        assert_eq!(
+            rollover_market
+                .amm
+                .historical_oracle_data
+                .last_oracle_price_twap_ts,
+            1050
+        );
+
+        // a market reading an oracle aggregate waits for the rolled market's aggregate
+        let market_with_aggregate = PerpMarket {
+            has_oracle_aggregate: true,
+            ..market
+        };
+        let rollover_market = market_with_aggregate
+            .get_rollover_market(Pubkey::new_unique(), 7, &oracle_price_data, 1050, 20)
+            .unwrap();
+        assert_eq!(rollover_market.status, MarketStatus::Initialized);
+        assert!(!rollover_market.has_oracle_aggregate);
+
+        // rollover expiring in the past
+        assert!(market
+            .get_rollover_market(Pubkey::new_unique(), 7, &oracle_price_data, 1500, 20)
+            .is_err());
+    }
+}
+
+mod prediction_market {
+    use crate::controller::position::PositionDirection;
+    use crate::math::amm::calculate_price;
+    use crate::math::constants::{
+        AMM_RESERVE_PRECISION, MAX_PREDICTION_MARKET_PRICE, MAX_PREDICTION_MARKET_PRICE_I64,
+        PEG_PRECISION,
+    };
+    use crate::state::perp_market::{ContractType, PerpMarket, PredictionMarketResolution, AMM};
+
+    fn prediction_market() -> PerpMarket {
+        PerpMarket {
+            contract_type: ContractType::Prediction,
+            amm: AMM {
+                sqrt_k: 100 * AMM_RESERVE_PRECISION,
+                peg_multiplier: PEG_PRECISION / 2,
+                order_tick_size: 1000,
+                ..AMM::default()
+            },
+            ..PerpMarket::default()
+        }
+    }
+
+    #[test]
+    fn bounded_prices() {
+        let market = prediction_market();
+
+        assert_eq!(market.get_bounded_oracle_price(-1), 0);
+        assert_eq!(market.get_bounded_oracle_price(300_000), 300_000);
+        assert_eq!(
+            market.get_bounded_oracle_price(MAX_PREDICTION_MARKET_PRICE_I64 + 1),
+            MAX_PREDICTION_MARKET_PRICE_I64
+        );
+
+        assert_eq!(market.get_bounded_fill_price(1), 1000);
+        assert_eq!(
+            market.get_bounded_fill_price(2 * MAX_PREDICTION_MARKET_PRICE),
+            MAX_PREDICTION_MARKET_PRICE
+        );
+
+        assert_eq!(
+            market.get_bounded_limit_price(None, PositionDirection::Long),
+            Some(MAX_PREDICTION_MARKET_PRICE)
+        );
+        assert_eq!(
+            market.get_bounded_limit_price(None, PositionDirection::Short),
+            Some(1000)
+        );
+        assert_eq!(
+            market.get_bounded_limit_price(Some(400_000), PositionDirection::Short),
+            Some(400_000)
+        );
+
+        // other contracts are left alone
+        let perp_market = PerpMarket {
+            contract_type: ContractType::Perpetual,
+            ..market
+        };
+        assert_eq!(perp_market.get_bounded_oracle_price(-1), -1);
+        assert_eq!(
+            perp_market.get_bounded_limit_price(None, PositionDirection::Long),
+            None
+        );
+    }
+
+    #[test]
+    fn bounded_spread_reserves() {
+        let mut market = prediction_market();
+        // ask at 1.05, bid at 0.0005
+        market.amm.ask_base_asset_reserve = 50 * AMM_RESERVE_PRECISION;
+        market.amm.ask_quote_asset_reserve = 105 * AMM_RESERVE_PRECISION;
+        market.amm.bid_base_asset_reserve = 1000 * AMM_RESERVE_PRECISION;
+        market.amm.bid_quote_asset_reserve = AMM_RESERVE_PRECISION;
+
+        market.update_bounded_spread_reserves().unwrap();
+
+        let ask_price = calculate_price(
+            market.amm.ask_quote_asset_reserve,
+            market.amm.ask_base_asset_reserve,
+            market.amm.peg_multiplier,
+        )
+        .unwrap();
+        assert!(ask_price <= MAX_PREDICTION_MARKET_PRICE && ask_price >= 999_999);
+
+        let bid_price = calculate_price(
+            market.amm.bid_quote_asset_reserve,
+            market.amm.bid_base_asset_reserve,
+            market.amm.peg_multiplier,
+        )
+        .unwrap();
+        assert!((999..=1000).contains(&bid_price));
+    }
+
+    #[test]
+    fn settlement_price() {
+        let mut market = prediction_market();
+
+        market.prediction_market_resolution = PredictionMarketResolution::Yes;
+        assert_eq!(
+            market.get_prediction_market_settlement_price(0).unwrap(),
+            MAX_PREDICTION_MARKET_PRICE_I64
+        );
+
+        market.prediction_market_resolution = PredictionMarketResolution::No;
+        assert_eq!(
+            market
+                .get_prediction_market_settlement_price(MAX_PREDICTION_MARKET_PRICE_I64)
+                .unwrap(),
+            0
+        );
+
+        // unresolved markets settle at their oracle once it reports an outcome
+        market.prediction_market_resolution = PredictionMarketResolution::Unresolved;
+        assert_eq!(market.get_prediction_market_settlement_price(0).unwrap(), 0);
+        assert_eq!(
+            market
+                .get_prediction_market_settlement_price(MAX_PREDICTION_MARKET_PRICE_I64)
+                .unwrap(),
+            MAX_PREDICTION_MARKET_PRICE_I64
+        );
+        assert!(market
+            .get_prediction_market_settlement_price(MAX_PREDICTION_MARKET_PRICE_I64 / 2)
+            .is_err());
+    }
+}
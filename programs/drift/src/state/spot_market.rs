@@ -19,10 +19,12 @@ use crate::math::safe_math::SafeMath;
 use crate::math::spot_balance::{calculate_utilization, get_token_amount, get_token_value};
 
 use crate::math::stats::calculate_new_twap;
-use crate::state::oracle::{HistoricalIndexData, HistoricalOracleData, OracleSource};
+use crate::state::oracle::{HistoricalIndexData, HistoricalOracleData, OracleId, OracleSource};
 use crate::state::paused_operations::{InsuranceFundOperation, SpotOperation};
 use crate::state::perp_market::{MarketStatus, PoolBalance};
+use crate::state::state::ValidityGuardRailsOverride;
 use crate::state::traits::{MarketIndexOffset, Size};
+use crate::state::user::MarketType;
 use crate::{validate, PERCENTAGE_PRECISION};
 
 #[account(zero_copy(unsafe))]
@@ -197,7 +199,29 @@ pub struct SpotMarket {
     /// fuel multiplier for spot maker
     /// precision: 10
     pub fuel_boost_maker: u8,
-    pub padding: [u8; 43],
+    pub padding1: u8,
+    /// Overrides the state's oracle slots_before_stale_for_amm. 0 uses the state's
+    pub oracle_slots_before_stale_for_amm: u16,
+    /// Overrides the state's oracle confidence_interval_max_size. 0 uses the state's
+    /// precision: BID_ASK_SPREAD_PRECISION
+    pub oracle_confidence_interval_max_size: u32,
+    /// Overrides the state's oracle slots_before_stale_for_margin. 0 uses the state's
+    pub oracle_slots_before_stale_for_margin: u16,
+    pub padding2: [u8; 2],
+    /// The insurance fund shares minted for locked stakes and the junior tranche per unit of boost
+    /// weight, summed over every revenue settle
+    /// precision: IF_BOOST_SHARES_PER_WEIGHT_PRECISION
+    pub insurance_fund_cumulative_boost_shares_per_weight: u128,
+    /// The summed boost weight of the locked insurance fund stakes and the junior tranche
+    /// precision: if shares
+    pub insurance_fund_total_boost_weight: u64,
+    /// Whether insurance fund losses are taken from the market's InsuranceFundJuniorTranche first
+    pub has_insurance_fund_junior_tranche: bool,
+    /// Whether prices are read from the market's OracleAggregate
+    pub has_oracle_aggregate: bool,
+    /// Whether fills evaluate the market's OracleCircuitBreaker
+    pub has_oracle_circuit_breaker: bool,
+    pub padding: [u8; 5],
 }
 
 impl Default for SpotMarket {
@@ -263,7 +287,17 @@ impl Default for SpotMarket {
             fuel_boost_borrows: 0,
             fuel_boost_taker: 0,
             fuel_boost_maker: 0,
-            padding: [0; 43],
+            padding1: 0,
+            oracle_slots_before_stale_for_amm: 0,
+            oracle_confidence_interval_max_size: 0,
+            oracle_slots_before_stale_for_margin: 0,
+            padding2: [0; 2],
+            insurance_fund_cumulative_boost_shares_per_weight: 0,
+            insurance_fund_total_boost_weight: 0,
+            has_insurance_fund_junior_tranche: false,
+            has_oracle_aggregate: false,
+            has_oracle_circuit_breaker: false,
+            padding: [0; 5],
         }
     }
 }
@@ -303,14 +337,43 @@ impl SpotMarket {
             && !self.is_operation_paused(SpotOperation::Fill)
     }
 
+    /// The market's price in the oracle map, its oracle aggregate if it has one
+    pub fn oracle_id(&self) -> OracleId {
+        OracleId::new(
+            self.oracle,
+            MarketType::Spot,
+            self.market_index,
+            self.has_oracle_aggregate,
+            self.get_validity_guard_rails_override(),
+            self.max_confidence_interval_multiplier(),
+        )
+    }
+
     pub fn get_max_confidence_interval_multiplier(&self) -> DriftResult<u64> {
-        Ok(match self.asset_tier {
+        Ok(self.max_confidence_interval_multiplier())
+    }
+
+    fn max_confidence_interval_multiplier(&self) -> u64 {
+        // an overridden confidence interval max size isn't scaled by asset tier
+        if self.oracle_confidence_interval_max_size != 0 {
+            return 1;
+        }
+
+        match self.asset_tier {
             AssetTier::Collateral => 1, // 2%
             AssetTier::Protected => 1,  // 2%
             AssetTier::Cross => 5,      // 20%
             AssetTier::Isolated => 50,  // 100%
             AssetTier::Unlisted => 50,
-        })
+        }
+    }
+
+    pub fn get_validity_guard_rails_override(&self) -> ValidityGuardRailsOverride {
+        ValidityGuardRailsOverride {
+            slots_before_stale_for_amm: self.oracle_slots_before_stale_for_amm,
+            slots_before_stale_for_margin: self.oracle_slots_before_stale_for_margin,
+            confidence_interval_max_size: self.oracle_confidence_interval_max_size,
+        }
     }
 
     pub fn get_sanitize_clamp_denominator(&self) -> DriftResult<Option<i64>> {
@@ -1,13 +1,14 @@
 use anchor_lang::prelude::*;
 use enumflags2::BitFlags;
 
-use crate::error::DriftResult;
+use crate::error::{DriftResult, ErrorCode};
 use crate::math::constants::{
     FEE_DENOMINATOR, FEE_PERCENTAGE_DENOMINATOR, MAX_REFERRER_REWARD_EPOCH_UPPER_BOUND,
 };
 use crate::math::safe_math::SafeMath;
 use crate::math::safe_unwrap::SafeUnwrap;
 use crate::state::traits::Size;
+use crate::validate;
 use crate::{LAMPORTS_PER_SOL_U64, PERCENTAGE_PRECISION_U64};
 
 #[cfg(test)]
@@ -165,6 +166,101 @@ pub struct ValidityGuardRails {
     pub too_volatile_ratio: i64,
 }
 
+impl ValidityGuardRails {
+    /// The guard rails with a market's non-zero overrides applied
+    pub fn with_override(&self, guard_rails_override: ValidityGuardRailsOverride) -> Self {
+        ValidityGuardRails {
+            slots_before_stale_for_amm: if guard_rails_override.slots_before_stale_for_amm != 0 {
+                i64::from(guard_rails_override.slots_before_stale_for_amm)
+            } else {
+                self.slots_before_stale_for_amm
+            },
+            slots_before_stale_for_margin: if guard_rails_override.slots_before_stale_for_margin
+                != 0
+            {
+                i64::from(guard_rails_override.slots_before_stale_for_margin)
+            } else {
+                self.slots_before_stale_for_margin
+            },
+            confidence_interval_max_size: if guard_rails_override.confidence_interval_max_size != 0
+            {
+                u64::from(guard_rails_override.confidence_interval_max_size)
+            } else {
+                self.confidence_interval_max_size
+            },
+            too_volatile_ratio: self.too_volatile_ratio,
+        }
+    }
+}
+
+/// A market's overrides of the state's validity guard rails. 0 uses the state's
+#[derive(
+    Copy, AnchorSerialize, AnchorDeserialize, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct ValidityGuardRailsOverride {
+    pub slots_before_stale_for_amm: u16,
+    pub slots_before_stale_for_margin: u16,
+    /// precision: BID_ASK_SPREAD_PRECISION
+    pub confidence_interval_max_size: u32,
+}
+
+impl ValidityGuardRailsOverride {
+    /// How many times looser than the state's guard rails an override can be
+    pub const MAX_MULTIPLIER: u64 = 10;
+
+    pub fn validate(&self, validity_guard_rails: &ValidityGuardRails) -> DriftResult {
+        let overridden = validity_guard_rails.with_override(*self);
+
+        validate!(
+            overridden.slots_before_stale_for_amm <= overridden.slots_before_stale_for_margin,
+            ErrorCode::InvalidOracleGuardRailsOverride,
+            "slots_before_stale_for_amm ({}) must be <= slots_before_stale_for_margin ({})",
+            overridden.slots_before_stale_for_amm,
+            overridden.slots_before_stale_for_margin
+        )?;
+
+        validate!(
+            overridden.slots_before_stale_for_amm.unsigned_abs()
+                <= validity_guard_rails
+                    .slots_before_stale_for_amm
+                    .unsigned_abs()
+                    .safe_mul(Self::MAX_MULTIPLIER)?,
+            ErrorCode::InvalidOracleGuardRailsOverride,
+            "slots_before_stale_for_amm ({}) must be <= {}x the state's ({})",
+            overridden.slots_before_stale_for_amm,
+            Self::MAX_MULTIPLIER,
+            validity_guard_rails.slots_before_stale_for_amm
+        )?;
+
+        validate!(
+            overridden.slots_before_stale_for_margin.unsigned_abs()
+                <= validity_guard_rails
+                    .slots_before_stale_for_margin
+                    .unsigned_abs()
+                    .safe_mul(Self::MAX_MULTIPLIER)?,
+            ErrorCode::InvalidOracleGuardRailsOverride,
+            "slots_before_stale_for_margin ({}) must be <= {}x the state's ({})",
+            overridden.slots_before_stale_for_margin,
+            Self::MAX_MULTIPLIER,
+            validity_guard_rails.slots_before_stale_for_margin
+        )?;
+
+        validate!(
+            overridden.confidence_interval_max_size
+                <= validity_guard_rails
+                    .confidence_interval_max_size
+                    .safe_mul(Self::MAX_MULTIPLIER)?,
+            ErrorCode::InvalidOracleGuardRailsOverride,
+            "confidence_interval_max_size ({}) must be <= {}x the state's ({})",
+            overridden.confidence_interval_max_size,
+            Self::MAX_MULTIPLIER,
+            validity_guard_rails.confidence_interval_max_size
+        )?;
+
+        Ok(())
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct FeeStructure {
     pub fee_tiers: [FeeTier; 10],
@@ -51,3 +51,76 @@ mod get_init_user_fee {
         assert_eq!(init_user_fee, 1000000000);
     }
 }
+
+mod validity_guard_rails_override {
+    use crate::state::state::{OracleGuardRails, ValidityGuardRailsOverride};
+
+    #[test]
+    fn with_override() {
+        let validity = OracleGuardRails::default().validity;
+
+        let overridden = validity.with_override(ValidityGuardRailsOverride::default());
+        assert_eq!(overridden.slots_before_stale_for_amm, 10);
+        assert_eq!(overridden.slots_before_stale_for_margin, 120);
+        assert_eq!(overridden.confidence_interval_max_size, 20_000);
+
+        let overridden = validity.with_override(ValidityGuardRailsOverride {
+            slots_before_stale_for_amm: 0,
+            slots_before_stale_for_margin: 600,
+            confidence_interval_max_size: 100_000,
+        });
+        assert_eq!(overridden.slots_before_stale_for_amm, 10);
+        assert_eq!(overridden.slots_before_stale_for_margin, 600);
+        assert_eq!(overridden.confidence_interval_max_size, 100_000);
+        assert_eq!(overridden.too_volatile_ratio, validity.too_volatile_ratio);
+    }
+
+    #[test]
+    fn validate() {
+        let validity = OracleGuardRails::default().validity;
+
+        assert!(ValidityGuardRailsOverride::default()
+            .validate(&validity)
+            .is_ok());
+
+        assert!(ValidityGuardRailsOverride {
+            slots_before_stale_for_amm: 5,
+            slots_before_stale_for_margin: 1200,
+            confidence_interval_max_size: 200_000,
+        }
+        .validate(&validity)
+        .is_ok());
+
+        // amm stale threshold past margin's
+        assert!(ValidityGuardRailsOverride {
+            slots_before_stale_for_amm: 100,
+            slots_before_stale_for_margin: 50,
+            confidence_interval_max_size: 0,
+        }
+        .validate(&validity)
+        .is_err());
+
+        // amm override past margin's global
+        assert!(ValidityGuardRailsOverride {
+            slots_before_stale_for_amm: 121,
+            ..ValidityGuardRailsOverride::default()
+        }
+        .validate(&validity)
+        .is_err());
+
+        // more than 10x looser than the state's
+        assert!(ValidityGuardRailsOverride {
+            slots_before_stale_for_margin: 1201,
+            ..ValidityGuardRailsOverride::default()
+        }
+        .validate(&validity)
+        .is_err());
+
+        assert!(ValidityGuardRailsOverride {
+            confidence_interval_max_size: 200_001,
+            ..ValidityGuardRailsOverride::default()
+        }
+        .validate(&validity)
+        .is_err());
+    }
+}
@@ -1,12 +1,22 @@
 mod size {
-    use crate::state::events::OrderActionRecord;
+    use anchor_lang::prelude::*;
+
+    use crate::state::delegate_permissions::DelegatePermissions;
+    use crate::state::events::{OrderActionRecord, OrderRecord};
     use crate::state::fulfillment_params::serum::SerumV3FulfillmentConfig;
-    use crate::state::insurance_fund_stake::InsuranceFundStake;
+    use crate::state::insurance_fund_stake::{
+        InsuranceFundJuniorTranche, InsuranceFundStake, InsuranceFundStakeLockup,
+    };
+    use crate::state::option_market::OptionMarket;
+    use crate::state::oracle::OracleAggregate;
+    use crate::state::oracle_circuit_breaker::OracleCircuitBreaker;
     use crate::state::perp_market::PerpMarket;
     use crate::state::spot_market::SpotMarket;
     use crate::state::state::State;
     use crate::state::traits::Size;
-    use crate::state::user::{User, UserStats};
+    use crate::state::twap_order::UserTwapOrders;
+    use crate::state::user::{Order, User, UserStats};
+    use crate::state::withdraw_guard::WithdrawGuard;
 
     #[test]
     fn order_action_records() {
@@ -15,6 +25,20 @@ mod size {
         assert_eq!(actual_size, expected_size);
     }
 
+    #[test]
+    fn order_records() {
+        // emit_stack base64 encodes the discriminator and serialized record into SIZE bytes
+        let order_record = OrderRecord {
+            ts: 0,
+            user: Pubkey::default(),
+            order: Order::default(),
+        };
+        let serialized_size = 8 + order_record.try_to_vec().unwrap().len();
+        let expected_size = (serialized_size + 2) / 3 * 4;
+        let actual_size = OrderRecord::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
     #[test]
     fn perp_market() {
         let expected_size = std::mem::size_of::<PerpMarket>() + 8;
@@ -63,6 +87,62 @@ mod size {
         let actual_size = InsuranceFundStake::SIZE;
         assert_eq!(actual_size, expected_size);
     }
+
+    #[test]
+    fn insurance_fund_stake_lockup() {
+        let expected_size = std::mem::size_of::<InsuranceFundStakeLockup>() + 8;
+        let actual_size = InsuranceFundStakeLockup::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
+    #[test]
+    fn insurance_fund_junior_tranche() {
+        let expected_size = std::mem::size_of::<InsuranceFundJuniorTranche>() + 8;
+        let actual_size = InsuranceFundJuniorTranche::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
+    #[test]
+    fn user_twap_orders() {
+        let expected_size = std::mem::size_of::<UserTwapOrders>() + 8;
+        let actual_size = UserTwapOrders::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
+    #[test]
+    fn option_market() {
+        let expected_size = std::mem::size_of::<OptionMarket>() + 8;
+        let actual_size = OptionMarket::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
+    #[test]
+    fn delegate_permissions() {
+        let expected_size = std::mem::size_of::<DelegatePermissions>() + 8;
+        let actual_size = DelegatePermissions::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
+    #[test]
+    fn withdraw_guard() {
+        let expected_size = std::mem::size_of::<WithdrawGuard>() + 8;
+        let actual_size = WithdrawGuard::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
+    #[test]
+    fn oracle_aggregate() {
+        let expected_size = std::mem::size_of::<OracleAggregate>() + 8;
+        let actual_size = OracleAggregate::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
+
+    #[test]
+    fn oracle_circuit_breaker() {
+        let expected_size = std::mem::size_of::<OracleCircuitBreaker>() + 8;
+        let actual_size = OracleCircuitBreaker::SIZE;
+        assert_eq!(actual_size, expected_size);
+    }
 }
 
 mod market_index_offset {
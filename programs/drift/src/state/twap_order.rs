@@ -0,0 +1,125 @@
+use anchor_lang::prelude::*;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::controller::position::PositionDirection;
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::casting::Cast;
+use crate::math::orders::standardize_base_asset_amount;
+use crate::math::safe_math::SafeMath;
+use crate::state::traits::Size;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct UserTwapOrders {
+    /// The user account the twap orders release slices for
+    pub user: Pubkey,
+    pub twap_orders: [TwapOrder; 4],
+    pub next_twap_id: u32,
+    pub padding: [u8; 4],
+}
+
+impl Size for UserTwapOrders {
+    const SIZE: usize = 304;
+}
+
+impl UserTwapOrders {
+    pub fn get_twap_order_index(&self, twap_id: u32) -> DriftResult<usize> {
+        self.twap_orders
+            .iter()
+            .position(|twap_order| {
+                twap_order.twap_id == twap_id && twap_order.status == TwapOrderStatus::Open
+            })
+            .ok_or(ErrorCode::OrderDoesNotExist)
+    }
+
+    pub fn get_free_twap_order_index(&self) -> DriftResult<usize> {
+        self.twap_orders
+            .iter()
+            .position(|twap_order| twap_order.status == TwapOrderStatus::Init)
+            .ok_or(ErrorCode::MaxNumberOfOrders)
+    }
+}
+
+/// A parent order whose size is released as num_slices market orders, one every interval seconds
+#[zero_copy(unsafe)]
+#[repr(C)]
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Debug, Eq, Default)]
+pub struct TwapOrder {
+    /// The total size of the twap order
+    /// precision for perps: BASE_PRECISION
+    pub base_asset_amount: u64,
+    /// The size already released as slices
+    /// precision for perps: BASE_PRECISION
+    pub base_asset_amount_released: u64,
+    /// The worst price slices can fill at. 0 if slices have no limit
+    /// precision: PRICE_PRECISION
+    pub price: u64,
+    /// The unix timestamp at which the next slice can be released
+    pub next_slice_ts: i64,
+    /// The number of seconds between slices
+    pub interval: i64,
+    pub twap_id: u32,
+    /// The order id of the last released slice
+    pub last_slice_order_id: u32,
+    pub market_index: u16,
+    pub num_slices: u16,
+    pub slices_released: u16,
+    pub status: TwapOrderStatus,
+    pub direction: PositionDirection,
+    pub reduce_only: bool,
+    pub padding: [u8; 7],
+}
+
+impl TwapOrder {
+    pub fn is_complete(&self) -> bool {
+        self.slices_released >= self.num_slices
+    }
+
+    /// The remaining size split evenly across the remaining slices, the last slice takes the remainder
+    pub fn get_next_slice_base_asset_amount(&self, order_step_size: u64) -> DriftResult<u64> {
+        validate!(
+            !self.is_complete(),
+            ErrorCode::InvalidTwapOrder,
+            "all {} slices already released",
+            self.num_slices
+        )?;
+
+        let remaining_base_asset_amount = self
+            .base_asset_amount
+            .safe_sub(self.base_asset_amount_released)?;
+        let remaining_slices = self.num_slices.safe_sub(self.slices_released)?;
+
+        if remaining_slices == 1 {
+            return Ok(remaining_base_asset_amount);
+        }
+
+        standardize_base_asset_amount(
+            remaining_base_asset_amount.safe_div(remaining_slices.cast()?)?,
+            order_step_size,
+        )
+    }
+
+    pub fn record_slice(&mut self, base_asset_amount: u64, order_id: u32, now: i64) -> DriftResult {
+        self.base_asset_amount_released = self
+            .base_asset_amount_released
+            .safe_add(base_asset_amount)?;
+        self.slices_released = self.slices_released.safe_add(1)?;
+        self.last_slice_order_id = order_id;
+        self.next_slice_ts = now.safe_add(self.interval)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum TwapOrderStatus {
+    /// The twap order is not in use
+    #[default]
+    Init,
+    /// Slices are still being released
+    Open,
+}
@@ -0,0 +1,46 @@
+mod get_next_slice_base_asset_amount {
+    use crate::math::constants::BASE_PRECISION_U64;
+    use crate::state::twap_order::{TwapOrder, TwapOrderStatus};
+
+    #[test]
+    fn releases_even_slices_then_remainder() {
+        let order_step_size = BASE_PRECISION_U64 / 10;
+        let mut twap_order = TwapOrder {
+            status: TwapOrderStatus::Open,
+            base_asset_amount: 10 * BASE_PRECISION_U64,
+            num_slices: 3,
+            interval: 60,
+            ..TwapOrder::default()
+        };
+
+        let slice = twap_order
+            .get_next_slice_base_asset_amount(order_step_size)
+            .unwrap();
+        assert_eq!(slice, 3300000000);
+        twap_order.record_slice(slice, 1, 0).unwrap();
+        assert_eq!(twap_order.next_slice_ts, 60);
+
+        let slice = twap_order
+            .get_next_slice_base_asset_amount(order_step_size)
+            .unwrap();
+        assert_eq!(slice, 3300000000);
+        twap_order.record_slice(slice, 2, 60).unwrap();
+
+        // last slice takes what was rounded off the earlier slices
+        let slice = twap_order
+            .get_next_slice_base_asset_amount(order_step_size)
+            .unwrap();
+        assert_eq!(slice, 3400000000);
+        twap_order.record_slice(slice, 3, 120).unwrap();
+
+        assert!(twap_order.is_complete());
+        assert_eq!(twap_order.last_slice_order_id, 3);
+        assert_eq!(
+            twap_order.base_asset_amount_released,
+            twap_order.base_asset_amount
+        );
+        assert!(twap_order
+            .get_next_slice_base_asset_amount(order_step_size)
+            .is_err());
+    }
+}
@@ -54,6 +54,8 @@ pub enum UserStatus {
     Bankrupt = 0b00000010,
     ReduceOnly = 0b00000100,
     AdvancedLp = 0b00001000,
+    PortfolioMargin = 0b00010000,
+    WithdrawGuard = 0b00100000,
 }
 
 // implement SIZE const for User
@@ -126,12 +128,30 @@ pub struct User {
     pub open_auctions: u8,
     /// Whether or not user has open order with auction
     pub has_open_auction: bool,
-    pub padding1: [u8; 5],
+    /// Bitmask over perp_positions. A set bit means the position at that index is margined
+    /// in isolation with its own collateral instead of sharing the cross margin account
+    pub isolated_perp_positions: u8,
+    /// Seconds without a heartbeat after which keepers can cancel all the user's orders. 0 if the
+    /// dead man's switch is disarmed
+    pub heartbeat_timeout: u16,
+    pub padding1: [u8; 2],
     pub last_fuel_bonus_update_ts: i64,
-    pub padding: [u8; 8],
+    /// The last time the authority or delegate sent a heartbeat
+    pub last_heartbeat_ts: i64,
 }
 
 impl User {
+    pub fn is_heartbeat_expired(&self, now: i64) -> DriftResult<bool> {
+        if self.heartbeat_timeout == 0 {
+            return Ok(false);
+        }
+
+        Ok(now
+            > self
+                .last_heartbeat_ts
+                .safe_add(self.heartbeat_timeout.cast()?)?)
+    }
+
     pub fn is_being_liquidated(&self) -> bool {
         self.status & (UserStatus::BeingLiquidated as u8 | UserStatus::Bankrupt as u8) > 0
     }
@@ -148,6 +168,14 @@ impl User {
         self.status & (UserStatus::AdvancedLp as u8) > 0
     }
 
+    pub fn is_portfolio_margin_enabled(&self) -> bool {
+        self.status & (UserStatus::PortfolioMargin as u8) > 0
+    }
+
+    pub fn is_withdraw_guarded(&self) -> bool {
+        self.status & (UserStatus::WithdrawGuard as u8) > 0
+    }
+
     pub fn add_user_status(&mut self, status: UserStatus) {
         self.status |= status as u8;
     }
@@ -247,10 +275,44 @@ impl User {
         market_index: u16,
     ) -> DriftResult<&mut PerpPosition> {
         let position_index = get_position_index(&self.perp_positions, market_index)
-            .or_else(|_| add_new_position(&mut self.perp_positions, market_index))?;
+            .or_else(|_| self.add_perp_position(market_index))?;
         Ok(&mut self.perp_positions[position_index])
     }
 
+    pub fn add_perp_position(&mut self, market_index: u16) -> DriftResult<usize> {
+        let position_index = add_new_position(&mut self.perp_positions, market_index)?;
+        // slot may have previously held an isolated position
+        self.update_isolated_perp_position(position_index, false);
+        Ok(position_index)
+    }
+
+    pub fn is_isolated_perp_position(&self, position_index: usize) -> bool {
+        self.isolated_perp_positions & (1_u8 << position_index) > 0
+    }
+
+    pub fn is_isolated_perp_market(&self, market_index: u16) -> bool {
+        get_position_index(&self.perp_positions, market_index)
+            .map(|position_index| self.is_isolated_perp_position(position_index))
+            .unwrap_or(false)
+    }
+
+    pub fn has_isolated_perp_position(&self) -> bool {
+        self.perp_positions
+            .iter()
+            .enumerate()
+            .any(|(position_index, position)| {
+                !position.is_available() && self.is_isolated_perp_position(position_index)
+            })
+    }
+
+    pub fn update_isolated_perp_position(&mut self, position_index: usize, isolated: bool) {
+        if isolated {
+            self.isolated_perp_positions |= 1_u8 << position_index;
+        } else {
+            self.isolated_perp_positions &= !(1_u8 << position_index);
+        }
+    }
+
     pub fn get_order_index(&self, order_id: u32) -> DriftResult<usize> {
         self.orders
             .iter()
@@ -271,6 +333,17 @@ impl User {
         self.orders.iter().find(|order| order.order_id == order_id)
     }
 
+    pub fn get_free_bracket_id(&self) -> u8 {
+        // at most 32 orders are open, so an id is always free
+        (1..=u8::MAX)
+            .find(|bracket_id| {
+                !self.orders.iter().any(|order| {
+                    order.status == OrderStatus::Open && order.bracket_id == *bracket_id
+                })
+            })
+            .unwrap_or(u8::MAX)
+    }
+
     pub fn get_last_order_id(&self) -> u32 {
         if self.next_order_id == 1 {
             u32::MAX
@@ -422,6 +495,22 @@ impl User {
         Ok(())
     }
 
+    pub fn update_portfolio_margin_status(&mut self, portfolio_margin: bool) -> DriftResult {
+        if portfolio_margin {
+            validate!(
+                !self.perp_positions.iter().any(|position| position.is_lp()),
+                ErrorCode::PortfolioMarginLpNotSupported,
+                "user cant enable portfolio margin with lp shares"
+            )?;
+
+            self.add_user_status(UserStatus::PortfolioMargin);
+        } else {
+            self.remove_user_status(UserStatus::PortfolioMargin);
+        }
+
+        Ok(())
+    }
+
     pub fn has_room_for_new_order(&self) -> bool {
         for order in self.orders.iter() {
             if order.status == OrderStatus::Init {
@@ -1142,7 +1231,7 @@ pub struct Order {
     /// For orders with an auction, this price isn't used until the auction is complete
     /// precision: PRICE_PRECISION
     pub price: u64,
-    /// The size of the order
+    /// The size of the order. For iceberg orders, this grows by the filled amount until the hidden size runs out
     /// precision for perps: BASE_PRECISION
     /// precision for spot: token mint precision
     pub base_asset_amount: u64,
@@ -1160,12 +1249,14 @@ pub struct Order {
     /// precision: PRICE_PRECISION
     pub auction_start_price: i64,
     /// The end price for the auction. Only relevant for market/oracle orders
+    /// For iceberg orders, which are post only and never have an auction, the size not yet shown in base_asset_amount
     /// precision: PRICE_PRECISION
     pub auction_end_price: i64,
-    /// The time when the order will expire
+    /// The time when the order will expire. For good til slot orders, the slot the order will expire after
     pub max_ts: i64,
     /// If set, the order limit price is the oracle price + this offset
-    /// precision: PRICE_PRECISION
+    /// For trailing stops, the distance the trigger price trails the oracle price until triggered
+    /// precision: PRICE_PRECISION (PERCENTAGE_PRECISION for percentage trailing stops)
     pub oracle_price_offset: i32,
     /// The id for the order. Each users has their own order id space
     pub order_id: u32,
@@ -1193,7 +1284,12 @@ pub struct Order {
     pub trigger_condition: OrderTriggerCondition,
     /// How many slots the auction lasts
     pub auction_duration: u8,
-    pub padding: [u8; 3],
+    /// Bitmask of OrderBitFlag
+    pub bit_flags: u8,
+    /// Links a bracket parent with its take profit/stop loss legs. 0 if the order is not in a bracket
+    pub bracket_id: u8,
+    /// Whether max_ts is a unix timestamp or a slot
+    pub time_in_force: TimeInForce,
 }
 
 #[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug)]
@@ -1203,8 +1299,16 @@ pub enum AssetType {
 }
 
 impl Order {
-    pub fn seconds_til_expiry(self, now: i64) -> i64 {
-        (self.max_ts - now).max(0)
+    pub fn seconds_til_expiry(self, now: i64, slot: u64) -> i64 {
+        match self.time_in_force {
+            // slots are ~400ms
+            TimeInForce::GoodTilSlot => (self.max_ts - slot as i64).max(0) * 2 / 5,
+            _ => (self.max_ts - now).max(0),
+        }
+    }
+
+    pub fn is_expired(&self, now: i64, slot: u64) -> DriftResult<bool> {
+        self.time_in_force.is_expired(self.max_ts, now, slot)
     }
 
     pub fn has_oracle_price_offset(self) -> bool {
@@ -1419,6 +1523,85 @@ impl Order {
 
         Ok(self.post_only || self.is_auction_complete(slot)?)
     }
+
+    pub fn is_bit_flag_set(&self, flag: OrderBitFlag) -> bool {
+        (self.bit_flags & flag as u8) != 0
+    }
+
+    pub fn add_bit_flag(&mut self, flag: OrderBitFlag) {
+        self.bit_flags |= flag as u8;
+    }
+
+    pub fn remove_bit_flag(&mut self, flag: OrderBitFlag) {
+        self.bit_flags &= !(flag as u8);
+    }
+
+    pub fn is_bracket_parent(&self) -> bool {
+        self.is_bit_flag_set(OrderBitFlag::BracketParent)
+    }
+
+    pub fn is_bracket_leg(&self) -> bool {
+        self.is_bit_flag_set(OrderBitFlag::BracketLeg)
+    }
+
+    pub fn is_bracket_order(&self) -> bool {
+        self.bracket_id != 0
+    }
+
+    pub fn is_pending_bracket_leg(&self) -> bool {
+        self.is_bit_flag_set(OrderBitFlag::BracketPending)
+    }
+
+    pub fn is_trailing_stop(&self) -> bool {
+        self.is_bit_flag_set(OrderBitFlag::TrailingStop)
+    }
+
+    pub fn is_iceberg(&self) -> bool {
+        self.is_bit_flag_set(OrderBitFlag::Iceberg)
+    }
+
+    /// The size of an iceberg order that isn't shown in base_asset_amount yet
+    pub fn get_hidden_base_asset_amount(&self) -> DriftResult<u64> {
+        if self.is_iceberg() {
+            self.auction_end_price.cast()
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Moves up to base_asset_amount of the hidden size of an iceberg order into base_asset_amount
+    pub fn refresh_iceberg(&mut self, base_asset_amount: u64) -> DriftResult {
+        let hidden_base_asset_amount = self.get_hidden_base_asset_amount()?;
+        let refresh_base_asset_amount = base_asset_amount.min(hidden_base_asset_amount);
+        if refresh_base_asset_amount > 0 {
+            self.base_asset_amount = self.base_asset_amount.safe_add(refresh_base_asset_amount)?;
+            self.auction_end_price = hidden_base_asset_amount
+                .safe_sub(refresh_base_asset_amount)?
+                .cast()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+pub enum OrderBitFlag {
+    /// Order whose fill activates the take profit/stop loss legs sharing its bracket_id
+    BracketParent = 0b00000001,
+    /// Take profit or stop loss leg of a bracket
+    BracketLeg = 0b00000010,
+    /// Leg can not be triggered until the parent has filled
+    BracketPending = 0b00000100,
+    /// Leg was placed with a sibling leg, filling or canceling one cancels the other
+    BracketHasSibling = 0b00001000,
+    /// Trigger price ratchets with the oracle price until the order is triggered
+    TrailingStop = 0b00010000,
+    /// Trailing stop offset is a percentage of the oracle price
+    TrailingStopPercentage = 0b00100000,
+    /// Slice released by a twap order
+    TwapSlice = 0b01000000,
+    /// Only part of the order is shown, the rest is stored in auction_end_price
+    Iceberg = 0b10000000,
 }
 
 impl Default for Order {
@@ -1447,7 +1630,9 @@ impl Default for Order {
             auction_end_price: 0,
             auction_duration: 0,
             max_ts: 0,
-            padding: [0; 3],
+            bit_flags: 0,
+            bracket_id: 0,
+            time_in_force: TimeInForce::GoodTilTime,
         }
     }
 }
@@ -1484,7 +1669,32 @@ pub enum OrderTriggerCondition {
     TriggeredBelow, // below condition has been triggered
 }
 
-#[derive(Default, Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq)]
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum TimeInForce {
+    /// Expires once the unix timestamp passes max_ts
+    #[default]
+    GoodTilTime,
+    /// Expires once the slot passes max_ts
+    GoodTilSlot,
+}
+
+impl TimeInForce {
+    /// max_ts of 0 never expires
+    pub fn is_expired(&self, max_ts: i64, now: i64, slot: u64) -> DriftResult<bool> {
+        if max_ts == 0 {
+            return Ok(false);
+        }
+
+        match self {
+            TimeInForce::GoodTilSlot => Ok(slot > max_ts.cast::<u64>()?),
+            TimeInForce::GoodTilTime => Ok(now > max_ts),
+        }
+    }
+}
+
+#[derive(
+    Default, Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, PartialOrd, Ord,
+)]
 pub enum MarketType {
     #[default]
     Spot,
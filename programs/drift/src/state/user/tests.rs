@@ -1940,3 +1940,26 @@ mod get_user_stats_age_ts {
         assert_eq!(age, 0);
     }
 }
+
+mod is_heartbeat_expired {
+    use crate::state::user::User;
+
+    #[test]
+    fn test() {
+        let mut user = User {
+            last_heartbeat_ts: 100,
+            ..User::default()
+        };
+
+        // disarmed
+        assert!(!user.is_heartbeat_expired(1_000_000).unwrap());
+
+        user.heartbeat_timeout = 30;
+        assert!(!user.is_heartbeat_expired(120).unwrap());
+        assert!(!user.is_heartbeat_expired(130).unwrap());
+        assert!(user.is_heartbeat_expired(131).unwrap());
+
+        user.last_heartbeat_ts = 131;
+        assert!(!user.is_heartbeat_expired(131).unwrap());
+    }
+}
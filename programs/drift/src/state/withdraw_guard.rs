@@ -0,0 +1,295 @@
+use anchor_lang::prelude::*;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::error::{DriftResult, ErrorCode};
+use crate::math::safe_math::SafeMath;
+use crate::state::traits::Size;
+use crate::validate;
+
+#[cfg(test)]
+mod tests;
+
+pub const MAX_WITHDRAW_DESTINATIONS: usize = 8;
+
+#[derive(Clone, Copy, BorshSerialize, BorshDeserialize, PartialEq, Debug, Eq, Default)]
+pub enum WithdrawGuardAction {
+    #[default]
+    None,
+    /// Withdraw more than the withdraw threshold
+    Withdraw,
+    AddDestination,
+    RemoveDestination,
+    /// Delete the guard and its account
+    RemoveGuard,
+}
+
+/// Opt in security mode for a user: withdrawals can only go to allowlisted token accounts, and
+/// allowlist changes and withdrawals above the threshold have to be requested a timelock before
+/// they're executed
+#[account(zero_copy(unsafe))]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct WithdrawGuard {
+    /// The user account the guard is for
+    pub user: Pubkey,
+    /// The token accounts withdrawals can be sent to
+    pub destinations: [Pubkey; MAX_WITHDRAW_DESTINATIONS],
+    pub request: WithdrawGuardRequest,
+    /// The seconds between a request and when it can be executed
+    pub timelock: i64,
+    /// Withdrawals worth more than this per timelock have to be requested
+    /// precision: QUOTE_PRECISION
+    pub withdraw_threshold: u64,
+    /// The value withdrawn without a request since window_start_ts
+    /// precision: QUOTE_PRECISION
+    pub window_withdraw_value: u64,
+    /// unix_timestamp the current withdraw window started
+    pub window_start_ts: i64,
+    pub num_destinations: u8,
+    pub padding: [u8; 7],
+}
+
+impl Size for WithdrawGuard {
+    const SIZE: usize = 384 + 8;
+}
+
+#[zero_copy(unsafe)]
+#[derive(Default, Eq, PartialEq, Debug)]
+#[repr(C)]
+pub struct WithdrawGuardRequest {
+    /// The token account to withdraw to, add or remove
+    pub destination: Pubkey,
+    /// The amount to withdraw
+    /// precision: token mint precision
+    pub amount: u64,
+    /// unix_timestamp of the request
+    pub request_ts: i64,
+    /// The spot market to withdraw from
+    pub market_index: u16,
+    pub action: WithdrawGuardAction,
+    pub padding: [u8; 5],
+}
+
+impl WithdrawGuard {
+    pub fn get_destinations(&self) -> &[Pubkey] {
+        &self.destinations[..self.num_destinations as usize]
+    }
+
+    pub fn is_allowed_destination(&self, destination: &Pubkey) -> bool {
+        self.get_destinations().contains(destination)
+    }
+
+    pub fn has_request(&self) -> bool {
+        self.request.action != WithdrawGuardAction::None
+    }
+
+    pub fn add_destination(&mut self, destination: Pubkey) -> DriftResult {
+        validate!(
+            !self.is_allowed_destination(&destination),
+            ErrorCode::InvalidWithdrawGuardRequest,
+            "destination {} already allowlisted",
+            destination
+        )?;
+
+        validate!(
+            (self.num_destinations as usize) < MAX_WITHDRAW_DESTINATIONS,
+            ErrorCode::InvalidWithdrawGuardRequest,
+            "withdraw guard allowlist is full"
+        )?;
+
+        self.destinations[self.num_destinations as usize] = destination;
+        self.num_destinations += 1;
+
+        Ok(())
+    }
+
+    pub fn remove_destination(&mut self, destination: Pubkey) -> DriftResult {
+        let index = self
+            .get_destinations()
+            .iter()
+            .position(|allowed| *allowed == destination)
+            .ok_or_else(|| {
+                msg!("destination {} not allowlisted", destination);
+                ErrorCode::InvalidWithdrawGuardRequest
+            })?;
+
+        let last_index = self.num_destinations as usize - 1;
+        self.destinations[index] = self.destinations[last_index];
+        self.destinations[last_index] = Pubkey::default();
+        self.num_destinations -= 1;
+
+        Ok(())
+    }
+
+    pub fn request(
+        &mut self,
+        action: WithdrawGuardAction,
+        destination: Pubkey,
+        market_index: u16,
+        amount: u64,
+        now: i64,
+    ) -> DriftResult {
+        validate!(
+            !self.has_request(),
+            ErrorCode::InvalidWithdrawGuardRequest,
+            "{:?} request already in progress",
+            self.request.action
+        )?;
+
+        match action {
+            WithdrawGuardAction::None => {
+                msg!("withdraw guard request must have an action");
+                return Err(ErrorCode::InvalidWithdrawGuardRequest);
+            }
+            WithdrawGuardAction::Withdraw => {
+                validate!(
+                    self.is_allowed_destination(&destination),
+                    ErrorCode::WithdrawDestinationNotAllowlisted,
+                    "destination {} not allowlisted",
+                    destination
+                )?;
+
+                validate!(
+                    amount > 0,
+                    ErrorCode::InvalidWithdrawGuardRequest,
+                    "withdraw request amount == 0"
+                )?;
+            }
+            WithdrawGuardAction::AddDestination => {
+                validate!(
+                    !self.is_allowed_destination(&destination),
+                    ErrorCode::InvalidWithdrawGuardRequest,
+                    "destination {} already allowlisted",
+                    destination
+                )?;
+            }
+            WithdrawGuardAction::RemoveDestination => {
+                validate!(
+                    self.is_allowed_destination(&destination),
+                    ErrorCode::InvalidWithdrawGuardRequest,
+                    "destination {} not allowlisted",
+                    destination
+                )?;
+            }
+            WithdrawGuardAction::RemoveGuard => {}
+        }
+
+        self.request = WithdrawGuardRequest {
+            destination,
+            amount,
+            request_ts: now,
+            market_index,
+            action,
+            padding: [0; 5],
+        };
+
+        Ok(())
+    }
+
+    pub fn cancel_request(&mut self) -> DriftResult {
+        validate!(
+            self.has_request(),
+            ErrorCode::InvalidWithdrawGuardRequest,
+            "no withdraw guard request in progress"
+        )?;
+
+        self.request = WithdrawGuardRequest::default();
+
+        Ok(())
+    }
+
+    pub fn validate_request_unlocked(&self, now: i64) -> DriftResult {
+        let unlock_ts = self.request.request_ts.safe_add(self.timelock)?;
+
+        validate!(
+            now >= unlock_ts,
+            ErrorCode::WithdrawGuardRequestTimelocked,
+            "withdraw guard request can be executed at {}, now {}",
+            unlock_ts,
+            now
+        )?;
+
+        Ok(())
+    }
+
+    /// Applies a requested allowlist change once its timelock has passed
+    pub fn execute_request(&mut self, now: i64) -> DriftResult {
+        self.validate_request_unlocked(now)?;
+
+        let destination = self.request.destination;
+        match self.request.action {
+            WithdrawGuardAction::AddDestination => self.add_destination(destination)?,
+            WithdrawGuardAction::RemoveDestination => self.remove_destination(destination)?,
+            action => {
+                msg!("{:?} requests can't be executed on their own", action);
+                return Err(ErrorCode::InvalidWithdrawGuardRequest);
+            }
+        }
+
+        self.request = WithdrawGuardRequest::default();
+
+        Ok(())
+    }
+
+    /// Uses up a remove guard request once its timelock has passed
+    pub fn validate_remove(&mut self, now: i64) -> DriftResult {
+        validate!(
+            self.request.action == WithdrawGuardAction::RemoveGuard,
+            ErrorCode::InvalidWithdrawGuardRequest,
+            "no remove guard request in progress"
+        )?;
+
+        self.validate_request_unlocked(now)?;
+
+        self.request = WithdrawGuardRequest::default();
+
+        Ok(())
+    }
+
+    /// Withdrawals that take the value withdrawn within a timelock above the threshold use up the matching withdraw request once its timelock
+    /// has passed
+    pub fn validate_withdraw(
+        &mut self,
+        destination: &Pubkey,
+        market_index: u16,
+        amount: u64,
+        withdraw_value: u64,
+        now: i64,
+    ) -> DriftResult {
+        validate!(
+            self.is_allowed_destination(destination),
+            ErrorCode::WithdrawDestinationNotAllowlisted,
+            "destination {} not allowlisted",
+            destination
+        )?;
+
+        if now >= self.window_start_ts.safe_add(self.timelock)? {
+            self.window_start_ts = now;
+            self.window_withdraw_value = 0;
+        }
+
+        let window_withdraw_value = self.window_withdraw_value.safe_add(withdraw_value)?;
+        if window_withdraw_value <= self.withdraw_threshold {
+            self.window_withdraw_value = window_withdraw_value;
+            return Ok(());
+        }
+
+        validate!(
+            self.request.action == WithdrawGuardAction::Withdraw
+                && self.request.destination == *destination
+                && self.request.market_index == market_index
+                && self.request.amount == amount,
+            ErrorCode::InvalidWithdrawGuardRequest,
+            "withdraw value {} (window total {}) > threshold {} without a matching withdraw request",
+            withdraw_value,
+            window_withdraw_value,
+            self.withdraw_threshold
+        )?;
+
+        self.validate_request_unlocked(now)?;
+
+        self.request = WithdrawGuardRequest::default();
+
+        Ok(())
+    }
+}
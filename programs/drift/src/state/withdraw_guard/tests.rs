@@ -0,0 +1,223 @@
+use crate::math::constants::QUOTE_PRECISION_U64;
+use crate::state::withdraw_guard::{WithdrawGuard, WithdrawGuardAction, WithdrawGuardRequest};
+use anchor_lang::prelude::Pubkey;
+
+fn withdraw_guard(destinations: &[Pubkey]) -> WithdrawGuard {
+    let mut withdraw_guard = WithdrawGuard {
+        timelock: 100,
+        withdraw_threshold: 1000 * QUOTE_PRECISION_U64,
+        ..WithdrawGuard::default()
+    };
+
+    for destination in destinations {
+        withdraw_guard.add_destination(*destination).unwrap();
+    }
+
+    withdraw_guard
+}
+
+#[test]
+fn withdraw_below_threshold() {
+    let destination = Pubkey::new_unique();
+    let mut withdraw_guard = withdraw_guard(&[destination]);
+
+    assert!(withdraw_guard
+        .validate_withdraw(&destination, 0, 1, 1000 * QUOTE_PRECISION_U64, 0)
+        .is_ok());
+
+    // only allowlisted destinations
+    assert!(withdraw_guard
+        .validate_withdraw(&Pubkey::new_unique(), 0, 1, QUOTE_PRECISION_U64, 0)
+        .is_err());
+}
+
+#[test]
+fn withdraw_above_threshold() {
+    let destination = Pubkey::new_unique();
+    let mut withdraw_guard = withdraw_guard(&[destination]);
+    let value = 1000 * QUOTE_PRECISION_U64 + 1;
+
+    // needs a request
+    assert!(withdraw_guard
+        .validate_withdraw(&destination, 1, 50, value, 0)
+        .is_err());
+
+    assert!(withdraw_guard
+        .request(
+            WithdrawGuardAction::Withdraw,
+            Pubkey::new_unique(),
+            1,
+            50,
+            10
+        )
+        .is_err());
+
+    withdraw_guard
+        .request(WithdrawGuardAction::Withdraw, destination, 1, 50, 10)
+        .unwrap();
+
+    // one request at a time
+    assert!(withdraw_guard
+        .request(WithdrawGuardAction::Withdraw, destination, 1, 50, 10)
+        .is_err());
+
+    // timelocked
+    assert!(withdraw_guard
+        .validate_withdraw(&destination, 1, 50, value, 109)
+        .is_err());
+
+    // has to match the request
+    assert!(withdraw_guard
+        .validate_withdraw(&destination, 0, 50, value, 110)
+        .is_err());
+    assert!(withdraw_guard
+        .validate_withdraw(&destination, 1, 51, value, 110)
+        .is_err());
+
+    // withdraw requests aren't executed on their own
+    assert!(withdraw_guard.execute_request(110).is_err());
+
+    withdraw_guard
+        .validate_withdraw(&destination, 1, 50, value, 110)
+        .unwrap();
+    assert_eq!(withdraw_guard.request, WithdrawGuardRequest::default());
+
+    // the request is used up
+    assert!(withdraw_guard
+        .validate_withdraw(&destination, 1, 50, value, 110)
+        .is_err());
+}
+
+#[test]
+fn split_withdraws_above_threshold() {
+    let destination = Pubkey::new_unique();
+    let mut withdraw_guard = withdraw_guard(&[destination]);
+
+    withdraw_guard
+        .validate_withdraw(&destination, 0, 1, 600 * QUOTE_PRECISION_U64, 0)
+        .unwrap();
+
+    // the window total would be above the threshold
+    assert!(withdraw_guard
+        .validate_withdraw(&destination, 0, 1, 600 * QUOTE_PRECISION_U64, 99)
+        .is_err());
+
+    withdraw_guard
+        .validate_withdraw(&destination, 0, 1, 400 * QUOTE_PRECISION_U64, 99)
+        .unwrap();
+    assert_eq!(
+        withdraw_guard.window_withdraw_value,
+        1000 * QUOTE_PRECISION_U64
+    );
+
+    // new window once the timelock has passed
+    withdraw_guard
+        .validate_withdraw(&destination, 0, 1, 600 * QUOTE_PRECISION_U64, 100)
+        .unwrap();
+    assert_eq!(withdraw_guard.window_start_ts, 100);
+    assert_eq!(
+        withdraw_guard.window_withdraw_value,
+        600 * QUOTE_PRECISION_U64
+    );
+}
+
+#[test]
+fn remove_guard() {
+    let destination = Pubkey::new_unique();
+    let mut withdraw_guard = withdraw_guard(&[destination]);
+
+    // needs a request
+    assert!(withdraw_guard.validate_remove(100).is_err());
+
+    withdraw_guard
+        .request(WithdrawGuardAction::RemoveGuard, Pubkey::default(), 0, 0, 0)
+        .unwrap();
+
+    // remove guard requests aren't executed on their own
+    assert!(withdraw_guard.execute_request(100).is_err());
+
+    // timelocked
+    assert!(withdraw_guard.validate_remove(99).is_err());
+
+    withdraw_guard.validate_remove(100).unwrap();
+    assert!(!withdraw_guard.has_request());
+}
+
+#[test]
+fn update_allowlist() {
+    let destinations = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let mut withdraw_guard = withdraw_guard(&destinations);
+
+    let new_destination = Pubkey::new_unique();
+    assert!(withdraw_guard
+        .request(
+            WithdrawGuardAction::AddDestination,
+            destinations[0],
+            0,
+            0,
+            0
+        )
+        .is_err());
+
+    withdraw_guard
+        .request(
+            WithdrawGuardAction::AddDestination,
+            new_destination,
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+
+    assert!(withdraw_guard.execute_request(99).is_err());
+    withdraw_guard.execute_request(100).unwrap();
+    assert_eq!(
+        withdraw_guard.get_destinations(),
+        &[destinations[0], destinations[1], new_destination]
+    );
+    assert!(!withdraw_guard.has_request());
+
+    withdraw_guard
+        .request(
+            WithdrawGuardAction::RemoveDestination,
+            destinations[0],
+            0,
+            0,
+            200,
+        )
+        .unwrap();
+    withdraw_guard.execute_request(300).unwrap();
+    assert_eq!(
+        withdraw_guard.get_destinations(),
+        &[new_destination, destinations[1]]
+    );
+    assert!(!withdraw_guard.is_allowed_destination(&destinations[0]));
+    assert_eq!(withdraw_guard.destinations[2], Pubkey::default());
+}
+
+#[test]
+fn cancel_request() {
+    let destination = Pubkey::new_unique();
+    let mut withdraw_guard = withdraw_guard(&[destination]);
+
+    assert!(withdraw_guard.cancel_request().is_err());
+
+    withdraw_guard
+        .request(WithdrawGuardAction::RemoveDestination, destination, 0, 0, 0)
+        .unwrap();
+    withdraw_guard.cancel_request().unwrap();
+
+    assert!(!withdraw_guard.has_request());
+    assert!(withdraw_guard.execute_request(100).is_err());
+    assert!(withdraw_guard.is_allowed_destination(&destination));
+}
+
+#[test]
+fn allowlist_full() {
+    let destinations: Vec<Pubkey> = (0..8).map(|_| Pubkey::new_unique()).collect();
+    let mut withdraw_guard = withdraw_guard(&destinations);
+
+    assert!(withdraw_guard
+        .add_destination(Pubkey::new_unique())
+        .is_err());
+}
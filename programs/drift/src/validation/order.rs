@@ -4,6 +4,7 @@ use crate::controller::position::PositionDirection;
 use crate::error::{DriftResult, ErrorCode};
 
 use crate::math::casting::Cast;
+use crate::math::constants::MAX_PREDICTION_MARKET_PRICE;
 use crate::math::orders::{
     calculate_base_asset_amount_to_fill_up_to_limit_price, is_multiple_of_step_size,
 };
@@ -37,6 +38,33 @@ pub fn validate_order(
         }
     }
 
+    if market.is_prediction_market() {
+        validate_prediction_market_order(order)?;
+    }
+
+    Ok(())
+}
+
+/// Auction and oracle offset prices can't be known up front, they're bounded when filling
+fn validate_prediction_market_order(order: &Order) -> DriftResult {
+    validate!(
+        order.price <= MAX_PREDICTION_MARKET_PRICE,
+        ErrorCode::InvalidPredictionMarketOrder,
+        "order price {} must be <= {}",
+        order.price,
+        MAX_PREDICTION_MARKET_PRICE
+    )?;
+
+    if order.must_be_triggered() {
+        validate!(
+            order.trigger_price <= MAX_PREDICTION_MARKET_PRICE,
+            ErrorCode::InvalidPredictionMarketOrder,
+            "trigger price {} must be <= {}",
+            order.trigger_price,
+            MAX_PREDICTION_MARKET_PRICE
+        )?;
+    }
+
     Ok(())
 }
 
@@ -205,7 +233,7 @@ fn validate_limit_order_auction_params(order: &Order) -> DriftResult {
         )?;
 
         validate!(
-            order.auction_end_price == 0,
+            order.auction_end_price == 0 || order.is_iceberg(),
             ErrorCode::InvalidOrder,
             "limit order without auction can not have an auction end price"
         )?;
@@ -332,7 +360,7 @@ fn validate_trigger_market_order(
         return Err(ErrorCode::InvalidOrderPostOnly);
     }
 
-    if order.has_oracle_price_offset() {
+    if order.has_oracle_price_offset() && !order.is_trailing_stop() {
         msg!("Trigger market order can not have oracle offset");
         return Err(ErrorCode::InvalidOrderOracleOffset);
     }
@@ -28,6 +28,12 @@ pub fn validate_user_deletion(
         "user being liquidated"
     )?;
 
+    validate!(
+        !user.is_withdraw_guarded(),
+        ErrorCode::UserCantBeDeleted,
+        "user has a withdraw guard, remove it first"
+    )?;
+
     for perp_position in &user.perp_positions {
         validate!(
             perp_position.is_available(),
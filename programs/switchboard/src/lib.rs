@@ -3,7 +3,58 @@ use anchor_lang::prelude::*;
 declare_id!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
 
 #[program]
-pub mod switchboard {}
+pub mod switchboard {
+    use super::*;
+
+    pub fn initialize_pull_feed(
+        ctx: Context<InitializePullFeed>,
+        min_sample_size: u8,
+        max_staleness: u32,
+    ) -> Result<()> {
+        let mut pull_feed = ctx.accounts.pull_feed.load_init()?;
+
+        pull_feed.min_sample_size = min_sample_size;
+        pull_feed.max_staleness = max_staleness;
+        pull_feed.initialized_at = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+
+    pub fn set_pull_feed_result(
+        ctx: Context<SetPullFeedResult>,
+        value: i128,
+        std_dev: i128,
+        range: i128,
+        num_samples: u8,
+        slot: u64,
+    ) -> Result<()> {
+        let mut pull_feed = ctx.accounts.pull_feed.load_mut()?;
+
+        pull_feed.result.value = value;
+        pull_feed.result.mean = value;
+        pull_feed.result.std_dev = std_dev;
+        pull_feed.result.range = range;
+        pull_feed.result.min_value = value - range / 2;
+        pull_feed.result.max_value = value + range / 2;
+        pull_feed.result.num_samples = num_samples;
+        pull_feed.result.slot = slot;
+        pull_feed.result.min_slot = slot;
+        pull_feed.result.max_slot = slot;
+        pull_feed.last_update_timestamp = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializePullFeed<'info> {
+    #[account(zero)]
+    pub pull_feed: AccountLoader<'info, PullFeedAccountData>,
+}
+
+#[derive(Accounts)]
+pub struct SetPullFeedResult<'info> {
+    #[account(mut)]
+    pub pull_feed: AccountLoader<'info, PullFeedAccountData>,
+}
 
 #[zero_copy(unsafe)]
 #[repr(packed)]
@@ -137,6 +188,118 @@ pub struct AggregatorAccountData {
     pub _ebuf: [u8; 138],
 }
 
+/// On-demand results are fixed point numbers with 18 decimals.
+pub const PULL_FEED_PRECISION: i128 = 1_000_000_000_000_000_000;
+
+#[zero_copy(unsafe)]
+#[repr(packed)]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct OracleSubmission {
+    /// The public key of the oracle that submitted this value.
+    pub oracle: Pubkey,
+    /// The slot at which this value was signed.
+    pub slot: u64,
+    /// Reserved.
+    pub _padding1: [u8; 8],
+    /// The value that was submitted.
+    pub value: i128,
+}
+
+#[zero_copy(unsafe)]
+#[repr(packed)]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct CurrentResult {
+    /// The median value of the submissions needed for quorum size.
+    pub value: i128,
+    /// The standard deviation of the submissions needed for quorum size.
+    pub std_dev: i128,
+    /// The mean of the submissions needed for quorum size.
+    pub mean: i128,
+    /// The range of the submissions needed for quorum size.
+    pub range: i128,
+    /// The minimum value of the submissions needed for quorum size.
+    pub min_value: i128,
+    /// The maximum value of the submissions needed for quorum size.
+    pub max_value: i128,
+    /// The number of samples used to calculate this result.
+    pub num_samples: u8,
+    /// The index of the submission that was used to calculate this result.
+    pub submission_idx: u8,
+    /// Reserved.
+    pub _padding1: [u8; 6],
+    /// The slot at which this value was signed.
+    pub slot: u64,
+    /// The slot at which the first considered submission was made.
+    pub min_slot: u64,
+    /// The slot at which the last considered submission was made.
+    pub max_slot: u64,
+}
+
+#[zero_copy(unsafe)]
+#[repr(packed)]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct CompactResult {
+    /// The standard deviation of the submissions needed for quorum size.
+    pub std_dev: f32,
+    /// The mean of the submissions needed for quorum size.
+    pub mean: f32,
+    /// The slot at which this value was signed.
+    pub slot: u64,
+}
+
+/// A switchboard on-demand feed. Oracles post signed submissions to the feed and the result is
+/// the median of the submissions within the feed's max staleness.
+#[account(zero_copy(unsafe))]
+#[repr(packed)]
+#[derive(PartialEq)]
+pub struct PullFeedAccountData {
+    /// The oracle submissions for this feed.
+    pub submissions: [OracleSubmission; 32],
+    /// The public key of the authority that can update the feed hash that
+    /// this account will use for registering updates.
+    pub authority: Pubkey,
+    /// The public key of the queue which oracles must be bound to in order to
+    /// submit data to this feed.
+    pub queue: Pubkey,
+    /// SHA-256 hash of the job schema oracles will execute to produce data
+    /// for this feed.
+    pub feed_hash: [u8; 32],
+    /// Unix timestamp when the feed was initialized.
+    pub initialized_at: i64,
+    /// Permissions of the feed.
+    pub permissions: u64,
+    /// The maximum variance allowed between submissions, scaled by 1e9.
+    pub max_variance: u64,
+    /// The minimum number of oracle responses required before a value is accepted.
+    pub min_responses: u32,
+    /// The name of the feed.
+    pub name: [u8; 32],
+    /// Reserved.
+    pub _padding1: [u8; 2],
+    /// The index of the next historical result to be written.
+    pub historical_result_idx: u8,
+    /// The minimum number of samples required for a result to be valid.
+    pub min_sample_size: u8,
+    /// Unix timestamp of the last update.
+    pub last_update_timestamp: i64,
+    /// The slot of the address lookup table the feed uses.
+    pub lut_slot: u64,
+    /// Reserved.
+    pub _reserved1: [u8; 32],
+    /// The latest result of the feed.
+    pub result: CurrentResult,
+    /// The maximum number of slots a submission is considered valid for.
+    pub max_staleness: u32,
+    /// Reserved.
+    pub _padding2: [u8; 12],
+    /// The last results of the feed.
+    pub historical_results: [CompactResult; 32],
+    /// Reserved for future info.
+    pub _ebuf4: [u8; 8],
+    pub _ebuf3: [u8; 24],
+    pub _ebuf2: [u8; 256],
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,4 +334,9 @@ mod tests {
         let price = &aggregator.latest_confirmed_round.result;
         println!("price {:?}", price);
     }
+
+    #[test]
+    fn pull_feed_size() {
+        assert_eq!(std::mem::size_of::<PullFeedAccountData>(), 3200);
+    }
 }
@@ -553,6 +553,7 @@ export type OrderActionRecord = {
 	takerOrderBaseAssetAmount: BN | null;
 	takerOrderCumulativeBaseAssetAmountFilled: BN | null;
 	takerOrderCumulativeQuoteAssetAmountFilled: BN | null;
+	takerOrderTriggerPrice: BN | null;
 	maker: PublicKey | null;
 	makerOrderId: number | null;
 	makerOrderDirection: PositionDirection | null;